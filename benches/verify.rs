@@ -0,0 +1,57 @@
+//! Micro-benchmark for proof verification.
+//!
+//! Compares `MerkleProof::verify` against a fold that allocates a fresh
+//! `Vec` for every level, which is how verification used to work.
+//! Run with `cargo bench --bench verify`.
+
+use sha2::{Digest, Sha256};
+use simple_merkle_tree::MerkleTree;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const LEAVES: usize = 1 << 16;
+const ROUNDS: usize = 8;
+
+/// Folds a leaf through `depth` levels, allocating at every step
+fn allocating_fold(leaf: &[u8], sibling: &[u8], depth: usize) -> Vec<u8> {
+    let mut current_hash = leaf.to_vec();
+
+    for _ in 0..depth {
+        let mut hasher = Sha256::new();
+        hasher.update(&current_hash);
+        hasher.update(sibling);
+        current_hash = hasher.finalize().to_vec();
+    }
+
+    current_hash
+}
+
+fn report(name: &str, elapsed: Duration, count: usize) {
+    let per_op = elapsed.as_nanos() as f64 / count as f64;
+    println!("{:<24} {:>10.1} ns/proof {:>12.0} proofs/s", name, per_op, 1e9 / per_op);
+}
+
+fn main() {
+    let data: Vec<Vec<u8>> = (0..LEAVES).map(|i| format!("leaf {}", i).into_bytes()).collect();
+    let tree = MerkleTree::new(data);
+    let root = tree.root_hash().unwrap();
+    let proofs = tree.generate_all_proofs();
+    let depth = LEAVES.trailing_zeros() as usize;
+    let count = proofs.len() * ROUNDS;
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for proof in &proofs {
+            assert!(black_box(proof).verify(black_box(&root)));
+        }
+    }
+    report("verify (stack buffer)", start.elapsed(), count);
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for _ in &proofs {
+            black_box(allocating_fold(black_box(&root), black_box(&root), depth));
+        }
+    }
+    report("fold (allocating)", start.elapsed(), count);
+}
@@ -0,0 +1,178 @@
+//! A common interface over the crate's append-and-prove structures.
+//!
+//! Code written against [`Accumulator`] can switch between
+//! [`MerkleTree`] and [`HistoryTree`] without changing call sites.
+//! [`SparseMerkleTree`] reads and writes through an async [`NodeStore`], so
+//! it implements [`AsyncAccumulator`] instead; every [`Accumulator`] is an
+//! [`AsyncAccumulator`] too, so code that has to cover all three uses that.
+//!
+//! A Merkle mountain range was dropped from this interface rather than
+//! added: the history tree is this crate's append-only log accumulator,
+//! with the same append and prove costs and consistency proofs an MMR
+//! lacks, so a second log structure would only be another root format.
+
+use crate::history::hash_history_leaf;
+use crate::{
+    HistoryTree, MembershipProof, MerkleError, MerkleHasher, MerkleProof, MerkleTree, NodeStore,
+    SparseMerkleTree, SparseProof,
+};
+use std::future::{self, Future};
+
+/// A set commitment that elements can be added to and proven against
+pub trait Accumulator {
+    /// Identifies an element once added, and selects it for proving
+    type Key;
+    /// Proof that an element is committed to
+    type Proof;
+
+    /// Adds an element and returns the key to prove it by
+    fn add(&mut self, element: &[u8]) -> Self::Key;
+
+    /// Returns the current commitment, or `None` if there is none yet
+    fn commitment(&self) -> Option<Vec<u8>>;
+
+    /// Returns the number of elements committed to
+    fn size(&self) -> u64;
+
+    /// Proves the element stored under `key` against the current commitment
+    fn prove(&self, key: &Self::Key) -> Option<Self::Proof>;
+
+    /// Checks that `proof` shows `element` is committed to by `commitment`
+    fn verify(commitment: &[u8], element: &[u8], proof: &Self::Proof) -> bool;
+}
+
+impl<H: MerkleHasher> Accumulator for MerkleTree<H> {
+    type Key = usize;
+    type Proof = MerkleProof<H>;
+
+    fn add(&mut self, element: &[u8]) -> usize {
+        self.push(element)
+    }
+
+    fn commitment(&self) -> Option<Vec<u8>> {
+        self.root_hash()
+    }
+
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn prove(&self, key: &usize) -> Option<MerkleProof<H>> {
+        self.generate_proof_at(*key)
+    }
+
+    fn verify(commitment: &[u8], element: &[u8], proof: &MerkleProof<H>) -> bool {
+        proof.is_for(element) && proof.verify(commitment)
+    }
+}
+
+impl Accumulator for HistoryTree {
+    type Key = u64;
+    type Proof = MembershipProof;
+
+    fn add(&mut self, element: &[u8]) -> u64 {
+        self.append(element)
+    }
+
+    /// The empty history tree has a root too, so this is never `None`
+    fn commitment(&self) -> Option<Vec<u8>> {
+        Some(self.root_hash())
+    }
+
+    fn size(&self) -> u64 {
+        self.len()
+    }
+
+    fn prove(&self, key: &u64) -> Option<MembershipProof> {
+        self.prove_membership(*key, self.len())
+    }
+
+    fn verify(commitment: &[u8], element: &[u8], proof: &MembershipProof) -> bool {
+        proof.leaf_hash() == hash_history_leaf(element) && proof.verify(commitment)
+    }
+}
+
+/// An [`Accumulator`] whose elements live behind an async store
+///
+/// There is no `size`: a store-backed tree doesn't know how many entries
+/// sit under a root it was opened at without walking all of them.
+pub trait AsyncAccumulator {
+    /// Identifies an element once added, and selects it for proving
+    type Key;
+    /// Proof that an element is committed to
+    type Proof;
+
+    /// Adds an element and returns the key to prove it by
+    fn add(
+        &mut self,
+        element: &[u8],
+    ) -> impl Future<Output = Result<Self::Key, MerkleError>> + Send;
+
+    /// Returns the current commitment, or `None` if there is none yet
+    fn commitment(&self) -> Option<Vec<u8>>;
+
+    /// Proves the element stored under `key` against the current commitment
+    fn prove(
+        &self,
+        key: &Self::Key,
+    ) -> impl Future<Output = Result<Option<Self::Proof>, MerkleError>> + Send;
+
+    /// Checks that `proof` shows `element` is committed to by `commitment`
+    fn verify(commitment: &[u8], element: &[u8], proof: &Self::Proof) -> bool;
+}
+
+impl<A: Accumulator> AsyncAccumulator for A
+where
+    A::Key: Send,
+    A::Proof: Send,
+{
+    type Key = A::Key;
+    type Proof = A::Proof;
+
+    fn add(&mut self, element: &[u8]) -> impl Future<Output = Result<A::Key, MerkleError>> + Send {
+        future::ready(Ok(Accumulator::add(self, element)))
+    }
+
+    fn commitment(&self) -> Option<Vec<u8>> {
+        Accumulator::commitment(self)
+    }
+
+    fn prove(
+        &self,
+        key: &A::Key,
+    ) -> impl Future<Output = Result<Option<A::Proof>, MerkleError>> + Send {
+        future::ready(Ok(Accumulator::prove(self, key)))
+    }
+
+    fn verify(commitment: &[u8], element: &[u8], proof: &A::Proof) -> bool {
+        <A as Accumulator>::verify(commitment, element, proof)
+    }
+}
+
+/// The tree as a set: each element is a key holding an empty value, and
+/// keys are the elements themselves
+impl<S: NodeStore + Send> AsyncAccumulator for SparseMerkleTree<S> {
+    type Key = Vec<u8>;
+    type Proof = SparseProof;
+
+    async fn add(&mut self, element: &[u8]) -> Result<Vec<u8>, MerkleError> {
+        self.insert(element, Vec::new()).await?;
+        Ok(element.to_vec())
+    }
+
+    /// The empty tree has a root too, so this is never `None`
+    fn commitment(&self) -> Option<Vec<u8>> {
+        Some(self.root_hash().to_vec())
+    }
+
+    async fn prove(&self, key: &Vec<u8>) -> Result<Option<SparseProof>, MerkleError> {
+        let (value, proof) = self.get_with_proof(key).await?;
+        Ok(value.map(|_| proof))
+    }
+
+    fn verify(commitment: &[u8], element: &[u8], proof: &SparseProof) -> bool {
+        commitment
+            .try_into()
+            .is_ok_and(|root| proof.verify(root, element, Some(&[])))
+    }
+}
@@ -0,0 +1,231 @@
+//! Publishing roots to an Ethereum anchor contract.
+//!
+//! Anchoring a root on chain gives it a timestamp and an ordering nobody
+//! can rewrite. The contract this module targets does nothing but log:
+//!
+//! ```solidity
+//! contract RootAnchor {
+//!     event RootAnchored(uint64 indexed treeSize, bytes32 root);
+//!
+//!     function anchor(uint64 treeSize, bytes32 root) external {
+//!         emit RootAnchored(treeSize, root);
+//!     }
+//! }
+//! ```
+//!
+//! [`Anchor::calldata`] ABI-encodes a call to it and
+//! [`AnchorTransaction`] wraps that in an EIP-1559 transaction, producing
+//! the hash to sign and, given the signature, the raw transaction to send.
+//! Any secp256k1 signer will do. Going the other way,
+//! [`Anchor::decode_calldata`] and [`Anchor::decode_log`] read past anchors
+//! back out of transactions and event logs.
+
+use crate::{MerkleError, RootRecord};
+use sha3::{Digest, Keccak256};
+
+/// Signature of the contract's anchoring function
+pub const FUNCTION_SIGNATURE: &str = "anchor(uint64,bytes32)";
+/// Signature of the event the contract emits
+pub const EVENT_SIGNATURE: &str = "RootAnchored(uint64,bytes32)";
+
+fn keccak(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Returns the four-byte selector of [`FUNCTION_SIGNATURE`]
+pub fn selector() -> [u8; 4] {
+    let hash = keccak(FUNCTION_SIGNATURE.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// Returns the first log topic of every `RootAnchored` event
+pub fn event_topic() -> [u8; 32] {
+    keccak(EVENT_SIGNATURE.as_bytes())
+}
+
+/// A root as the contract records it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anchor {
+    pub tree_size: u64,
+    pub root: [u8; 32],
+}
+
+impl Anchor {
+    /// Takes the size and root of a published record
+    ///
+    /// Fails if the root is not 32 bytes.
+    pub fn from_record(record: &RootRecord) -> Result<Self, MerkleError> {
+        let invalid = || MerkleError::InvalidHashLength {
+            expected: 32,
+            actual: record.root_hash.len(),
+        };
+        let root = record
+            .root_hash
+            .as_slice()
+            .try_into()
+            .map_err(|_| invalid())?;
+        Ok(Anchor {
+            tree_size: record.tree_size,
+            root,
+        })
+    }
+
+    /// Returns the ABI-encoded call to `anchor`
+    pub fn calldata(&self) -> Vec<u8> {
+        let mut calldata = selector().to_vec();
+        calldata.extend_from_slice(&u64_word(self.tree_size));
+        calldata.extend_from_slice(&self.root);
+        calldata
+    }
+
+    /// Reads the anchor from a call to `anchor`
+    pub fn decode_calldata(calldata: &[u8]) -> Result<Self, MerkleError> {
+        let error = |message: &str| MerkleError::Parse(format!("anchor calldata: {}", message));
+
+        let args = calldata
+            .strip_prefix(&selector()[..])
+            .ok_or_else(|| error("not a call to anchor"))?;
+        let [size, root] = split_words(args).ok_or_else(|| error("expected two words"))?;
+        Ok(Anchor {
+            tree_size: word_u64(&size).ok_or_else(|| error("tree size out of range"))?,
+            root,
+        })
+    }
+
+    /// Reads the anchor from a `RootAnchored` log's topics and data
+    pub fn decode_log(topics: &[[u8; 32]], data: &[u8]) -> Result<Self, MerkleError> {
+        let error = |message: &str| MerkleError::Parse(format!("anchor event: {}", message));
+
+        let [topic, size] = topics else {
+            return Err(error("expected two topics"));
+        };
+        if *topic != event_topic() {
+            return Err(error("not a RootAnchored event"));
+        }
+        let root: [u8; 32] = data.try_into().map_err(|_| error("expected one word"))?;
+        Ok(Anchor {
+            tree_size: word_u64(size).ok_or_else(|| error("tree size out of range"))?,
+            root,
+        })
+    }
+}
+
+fn u64_word(value: u64) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[24..].copy_from_slice(&value.to_be_bytes());
+    word
+}
+
+fn word_u64(word: &[u8; 32]) -> Option<u64> {
+    if word[..24].iter().any(|&byte| byte != 0) {
+        return None;
+    }
+    Some(u64::from_be_bytes(word[24..].try_into().ok()?))
+}
+
+fn split_words(bytes: &[u8]) -> Option<[[u8; 32]; 2]> {
+    if bytes.len() != 64 {
+        return None;
+    }
+    Some([bytes[..32].try_into().ok()?, bytes[32..].try_into().ok()?])
+}
+
+/// An EIP-1559 transaction calling `anchor` on the contract at `to`
+///
+/// It sends no ether and has an empty access list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnchorTransaction {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub max_priority_fee_per_gas: u128,
+    pub max_fee_per_gas: u128,
+    pub gas_limit: u64,
+    /// Address of the anchor contract
+    pub to: [u8; 20],
+    pub anchor: Anchor,
+}
+
+/// Type byte of EIP-1559 transactions
+const DYNAMIC_FEE_TX_TYPE: u8 = 0x02;
+
+impl AnchorTransaction {
+    /// Returns the hash the sender signs
+    pub fn signing_hash(&self) -> [u8; 32] {
+        keccak(&self.envelope(&[]))
+    }
+
+    /// Returns the raw transaction to send, given the signature of
+    /// [`AnchorTransaction::signing_hash`]
+    ///
+    /// `y_parity` is the signature's recovery bit.
+    pub fn signed(&self, y_parity: bool, r: &[u8; 32], s: &[u8; 32]) -> Vec<u8> {
+        self.envelope(&[
+            rlp_uint(y_parity as u128),
+            rlp_bytes(strip_zeros(r)),
+            rlp_bytes(strip_zeros(s)),
+        ])
+    }
+
+    /// Returns the type byte followed by the RLP list of fields, with any
+    /// signature fields last
+    fn envelope(&self, signature: &[Vec<u8>]) -> Vec<u8> {
+        let mut fields = vec![
+            rlp_uint(self.chain_id.into()),
+            rlp_uint(self.nonce.into()),
+            rlp_uint(self.max_priority_fee_per_gas),
+            rlp_uint(self.max_fee_per_gas),
+            rlp_uint(self.gas_limit.into()),
+            rlp_bytes(&self.to),
+            rlp_uint(0),
+            rlp_bytes(&self.anchor.calldata()),
+            rlp_list(&[]),
+        ];
+        fields.extend_from_slice(signature);
+
+        let mut envelope = vec![DYNAMIC_FEE_TX_TYPE];
+        envelope.extend(rlp_list(&fields));
+        envelope
+    }
+}
+
+fn strip_zeros(bytes: &[u8]) -> &[u8] {
+    let start = bytes
+        .iter()
+        .position(|&byte| byte != 0)
+        .unwrap_or(bytes.len());
+    &bytes[start..]
+}
+
+fn rlp_uint(value: u128) -> Vec<u8> {
+    rlp_bytes(strip_zeros(&value.to_be_bytes()))
+}
+
+fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    if let [byte] = bytes {
+        if *byte < 0x80 {
+            return vec![*byte];
+        }
+    }
+    let mut encoded = rlp_length(bytes.len(), 0x80);
+    encoded.extend_from_slice(bytes);
+    encoded
+}
+
+fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    let mut encoded = rlp_length(payload.len(), 0xc0);
+    encoded.extend(payload);
+    encoded
+}
+
+/// Returns the prefix of an RLP string (`offset` 0x80) or list (0xc0)
+fn rlp_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let len_bytes = (len as u64).to_be_bytes();
+    let len_bytes = strip_zeros(&len_bytes);
+    let mut prefix = vec![offset + 55 + len_bytes.len() as u8];
+    prefix.extend_from_slice(len_bytes);
+    prefix
+}
@@ -0,0 +1,143 @@
+//! Trees allocated in a caller's bump arena.
+//!
+//! A [`MerkleTree`] allocates every hash separately on the global heap,
+//! which adds up when a node builds a tree per block in a hot loop.
+//! [`ArenaTree::new_in`] keeps a whole tree in a [`Bump`] the caller owns
+//! instead: each level is one contiguous run of 32-byte hashes, so a build
+//! makes one arena allocation per level, and resetting the arena frees
+//! every tree built in it at once.
+//!
+//! Proofs and [`ArenaTree::to_tree`] copy out to the global heap, so they
+//! outlive the arena.
+
+use crate::{domain_hasher, MerkleProof, MerkleTree, Side, TreeConfig};
+use bumpalo::collections::Vec as BumpVec;
+use bumpalo::Bump;
+use sha2::digest::generic_array::GenericArray;
+use sha2::Digest;
+
+const HASH_LEN: usize = 32;
+
+/// A Merkle tree whose hashes live in a [`Bump`] arena
+///
+/// Hashes as a [`MerkleTree`] with the same [`TreeConfig`] does, so roots
+/// and proofs are interchangeable.
+pub struct ArenaTree<'bump> {
+    domain: Option<&'bump [u8]>,
+    /// Hashes of every level, leaves first, each level back to back
+    levels: BumpVec<'bump, &'bump [u8]>,
+}
+
+impl<'bump> ArenaTree<'bump> {
+    /// Builds a tree over `data` in `bump`, hashed as `config` says
+    pub fn new_in<D: AsRef<[u8]>>(data: &[D], config: &TreeConfig, bump: &'bump Bump) -> Self {
+        let domain = config
+            .domain
+            .as_deref()
+            .map(|tag| &*bump.alloc_slice_copy(tag));
+        let mut levels = BumpVec::new_in(bump);
+        if data.is_empty() {
+            return ArenaTree { domain, levels };
+        }
+
+        let primed = domain_hasher(domain);
+        let leaves = bump.alloc_slice_fill_copy(data.len() * HASH_LEN, 0u8);
+        for (item, slot) in data.iter().zip(leaves.chunks_exact_mut(HASH_LEN)) {
+            let mut hasher = primed.clone();
+            hasher.update(item.as_ref());
+            hasher.finalize_into(GenericArray::from_mut_slice(slot));
+        }
+        levels.push(&*leaves);
+
+        // A single leaf is still paired with itself, so always hash at least once
+        while levels.len() == 1 || levels[levels.len() - 1].len() > HASH_LEN {
+            let nodes = levels[levels.len() - 1];
+            let len = nodes.len() / HASH_LEN;
+            let parents = bump.alloc_slice_fill_copy(len.div_ceil(2) * HASH_LEN, 0u8);
+            for (pair, slot) in nodes
+                .chunks(2 * HASH_LEN)
+                .zip(parents.chunks_exact_mut(HASH_LEN))
+            {
+                let (left, right) = match pair.len() {
+                    HASH_LEN => (pair, pair),
+                    _ => pair.split_at(HASH_LEN),
+                };
+                let mut hasher = primed.clone();
+                hasher.update(left);
+                hasher.update(right);
+                hasher.finalize_into(GenericArray::from_mut_slice(slot));
+            }
+            levels.push(&*parents);
+        }
+
+        ArenaTree { domain, levels }
+    }
+
+    /// Returns the number of leaves in the tree
+    pub fn len(&self) -> usize {
+        self.levels
+            .first()
+            .map_or(0, |leaves| leaves.len() / HASH_LEN)
+    }
+
+    /// Returns true if the tree has no leaves
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the Merkle root hash, if it exists
+    pub fn root_hash(&self) -> Option<[u8; 32]> {
+        (*self.levels.last()?).try_into().ok()
+    }
+
+    /// Returns the hash of the leaf at `index`
+    pub fn leaf_hash(&self, index: usize) -> Option<&'bump [u8]> {
+        self.node(0, index)
+    }
+
+    /// Generates a proof for the leaf at `index`
+    pub fn generate_proof_at(&self, index: usize) -> Option<MerkleProof> {
+        let leaf_hash = self.leaf_hash(index)?.to_vec();
+        let mut proof_hashes = Vec::with_capacity(self.levels.len() - 1);
+        let mut position = index;
+        for level in 0..self.levels.len() - 1 {
+            // A missing sibling means the node was paired with itself
+            let sibling = self
+                .node(level, position ^ 1)
+                .or_else(|| self.node(level, position))?;
+            proof_hashes.push((sibling.to_vec(), Side::of_sibling(position as u64)));
+            position /= 2;
+        }
+
+        Some(MerkleProof {
+            proof_hashes,
+            leaf_hash,
+            root_hash: self.root_hash()?.to_vec(),
+            config: self.config(),
+        })
+    }
+
+    /// Returns the configuration the tree was built with
+    pub fn config(&self) -> TreeConfig {
+        TreeConfig::from_domain(self.domain.map(<[u8]>::to_vec))
+    }
+
+    /// Copies the tree out of the arena
+    pub fn to_tree(&self) -> MerkleTree {
+        let levels = self
+            .levels
+            .iter()
+            .map(|level| level.chunks(HASH_LEN).map(<[u8]>::to_vec).collect())
+            .collect();
+        MerkleTree {
+            config: self.config(),
+            levels,
+        }
+    }
+
+    fn node(&self, level: usize, position: usize) -> Option<&'bump [u8]> {
+        self.levels
+            .get(level)?
+            .get(position * HASH_LEN..(position + 1) * HASH_LEN)
+    }
+}
@@ -0,0 +1,115 @@
+//! Conversions to and from `ark-crypto-primitives` Merkle trees.
+//!
+//! [`Sha256Config`] is the arkworks configuration that hashes the way this
+//! crate does: leaves with SHA-256 and parents as SHA-256 of the two child
+//! hashes. Under it a [`MerkleProof`] and an arkworks [`Path`] carry the
+//! same hashes and check against the same root, so a commitment built here
+//! can be opened by the arkworks path gadgets in a circuit.
+//!
+//! Arkworks only builds trees with a power-of-two number of leaves, at
+//! least two. Paths have no such limit: a proof from any tree converts, and
+//! the converted path verifies with arkworks against the same root.
+
+use crate::{MerkleError, MerkleHasher, MerkleProof, MerkleTree, Side, TreeConfig};
+use ark_crypto_primitives::crh::sha256::Sha256;
+use ark_crypto_primitives::merkle_tree::{self, Config, DigestConverter, Path};
+use ark_crypto_primitives::Error;
+
+/// Arkworks Merkle tree configuration matching this crate's hashing
+pub struct Sha256Config;
+
+/// Passes leaf hashes to the parent hash as their raw bytes
+///
+/// Arkworks' `ByteDigestConverter` would add a length prefix.
+pub struct RawBytes;
+
+impl DigestConverter<Vec<u8>, [u8]> for RawBytes {
+    type TargetType = Vec<u8>;
+
+    fn convert(item: Vec<u8>) -> Result<Vec<u8>, Error> {
+        Ok(item)
+    }
+}
+
+impl Config for Sha256Config {
+    type Leaf = [u8];
+    type LeafDigest = Vec<u8>;
+    type LeafInnerDigestConverter = RawBytes;
+    type InnerDigest = Vec<u8>;
+    type LeafHash = Sha256;
+    type TwoToOneHash = Sha256;
+}
+
+impl MerkleProof {
+    /// Converts the proof to an arkworks path
+    pub fn to_ark_path(&self) -> Path<Sha256Config> {
+        let leaf_index = self.proof_hashes.iter().rev().fold(0, |index, &(_, side)| {
+            (index << 1) | usize::from(side == Side::Left)
+        });
+
+        // Arkworks keeps the leaf-level sibling apart and orders the rest
+        // from the root down
+        Path {
+            leaf_sibling_hash: self.proof_hashes[0].0.clone(),
+            auth_path: self.proof_hashes[1..]
+                .iter()
+                .rev()
+                .map(|(hash, _)| hash.clone())
+                .collect(),
+            leaf_index,
+        }
+    }
+
+    /// Builds a proof from an arkworks path for the leaf with `leaf_hash`
+    ///
+    /// Paths do not carry their root, so the proof records `root_hash` as
+    /// the root it was generated from.
+    pub fn from_ark_path(
+        path: &Path<Sha256Config>,
+        leaf_hash: Vec<u8>,
+        root_hash: Vec<u8>,
+    ) -> Self {
+        let siblings = std::iter::once(&path.leaf_sibling_hash).chain(path.auth_path.iter().rev());
+        let proof_hashes = siblings
+            .enumerate()
+            .map(|(level, hash)| {
+                (
+                    hash.clone(),
+                    Side::of_sibling((path.leaf_index >> level) as u64),
+                )
+            })
+            .collect();
+
+        MerkleProof {
+            proof_hashes,
+            leaf_hash,
+            root_hash,
+            config: TreeConfig::default(),
+        }
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Builds the arkworks tree over the same leaves, which has the same root
+    ///
+    /// Fails unless the tree has a power-of-two number of leaves, at least two,
+    /// and hashes as the default [`TreeConfig`].
+    pub fn to_ark_tree(&self) -> Result<merkle_tree::MerkleTree<Sha256Config>, MerkleError> {
+        if self.config.config_id() != TreeConfig::default().config_id() {
+            return Err(MerkleError::Encode(
+                "arkworks trees only hash with untagged SHA-256".to_string(),
+            ));
+        }
+
+        let len = self.len();
+        if len < 2 || !len.is_power_of_two() {
+            return Err(MerkleError::Encode(format!(
+                "arkworks trees need a power-of-two number of leaves, not {}",
+                len
+            )));
+        }
+
+        merkle_tree::MerkleTree::new_with_leaf_digest(&(), &(), self.levels[0].clone())
+            .map_err(|e| MerkleError::Encode(e.to_string()))
+    }
+}
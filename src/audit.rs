@@ -0,0 +1,88 @@
+//! Consistency checks for trees loaded from disk or a third party.
+
+use crate::{MerkleHasher, MerkleTree, Side};
+use std::fmt;
+
+/// A stored node whose hash doesn't match the hash of its children
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditIssue {
+    /// Level of the node, with leaves at level 0
+    pub level: usize,
+    /// Position of the node within its level
+    pub index: usize,
+    /// Branches taken from the root down to the node
+    pub path: Vec<Side>,
+    /// Hash stored in the tree
+    pub stored: Vec<u8>,
+    /// Hash re-derived from the node's children
+    pub expected: Vec<u8>,
+}
+
+impl fmt::Display for AuditIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "level {} index {} (root", self.level, self.index)?;
+        for side in &self.path {
+            write!(f, "/{}", side)?;
+        }
+        write!(
+            f,
+            "): stored {} but children hash to {}",
+            hex::encode(&self.stored),
+            hex::encode(&self.expected)
+        )
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Re-derives every internal node from its children
+    ///
+    /// Returns every node whose stored hash doesn't match, lowest level
+    /// first. A bad node usually makes its ancestors mismatch too, so the
+    /// first issue is the one closest to the actual corruption.
+    pub fn audit(&self) -> Result<(), Vec<AuditIssue>> {
+        let depth = self.levels.len().saturating_sub(1);
+        let mut issues = Vec::new();
+
+        for level in 1..self.levels.len() {
+            let children = &self.levels[level - 1];
+
+            for (index, stored) in self.levels[level].iter().enumerate() {
+                let left = &children[index * 2];
+                let expected = match children.get(index * 2 + 1) {
+                    Some(right) => self.config.hash_node(left, right),
+                    None => self.config.hash_node(left, &self.config.pad(left)),
+                };
+
+                if *stored != expected {
+                    issues.push(AuditIssue {
+                        level,
+                        index,
+                        path: path_from_root(depth - level, index),
+                        stored: stored.clone(),
+                        expected,
+                    });
+                }
+            }
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+}
+
+/// Returns the branches leading to a node `steps` levels below the root
+fn path_from_root(steps: usize, index: usize) -> Vec<Side> {
+    (0..steps)
+        .rev()
+        .map(|bit| {
+            if index >> bit & 1 == 0 {
+                Side::Left
+            } else {
+                Side::Right
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,62 @@
+//! Runtime detection of the SHA-256 implementation in use.
+//!
+//! `sha2` picks its compression function at runtime: SHA-NI on x86 CPUs that
+//! have it, and the ARMv8 SHA-2 instructions on aarch64 when this crate's
+//! `asm` feature is enabled. Everything else falls back to portable code.
+
+use std::fmt;
+
+/// A SHA-256 implementation that hashing can be dispatched to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashBackend {
+    /// x86 SHA extensions
+    ShaNi,
+    /// ARMv8 cryptographic extensions
+    ArmSha2,
+    /// Portable software implementation
+    Software,
+}
+
+impl HashBackend {
+    /// Returns true if hashing runs on dedicated CPU instructions
+    pub fn is_accelerated(self) -> bool {
+        self != HashBackend::Software
+    }
+}
+
+impl fmt::Display for HashBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            HashBackend::ShaNi => "sha-ni",
+            HashBackend::ArmSha2 => "armv8-sha2",
+            HashBackend::Software => "software",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Returns the backend `sha2` dispatches to on this machine
+///
+/// The checks mirror the ones `sha2` performs, so the answer matches the
+/// code path that tree construction and verification actually take.
+pub fn active_backend() -> HashBackend {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if is_x86_feature_detected!("sha")
+            && is_x86_feature_detected!("sse2")
+            && is_x86_feature_detected!("ssse3")
+            && is_x86_feature_detected!("sse4.1")
+        {
+            return HashBackend::ShaNi;
+        }
+    }
+
+    #[cfg(all(target_arch = "aarch64", feature = "asm"))]
+    {
+        if std::arch::is_aarch64_feature_detected!("sha2") {
+            return HashBackend::ArmSha2;
+        }
+    }
+
+    HashBackend::Software
+}
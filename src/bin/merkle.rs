@@ -0,0 +1,122 @@
+//! Command-line tools for working with Merkle trees.
+//!
+//! ```text
+//! merkle vectors generate [FILE]   write test vectors to FILE or stdout
+//! merkle vectors verify FILE       check every vector in FILE
+//! merkle selftest [SEED [LEAVES]]  run a randomized cross-check
+//! merkle migrate IN OUT [DOMAIN]   upgrade IN to the current format version
+//! ```
+//!
+//! `migrate` takes the domain tag of a tagged tree as hex, for level exports
+//! and packed proofs written before tags were recorded.
+//!
+//! `selftest` needs the `selftest` feature. The tool builds for WASI with
+//! `cargo build --target wasm32-wasip1 --bin merkle`; the host must preopen
+//! the directories holding any files it reads or writes.
+
+use simple_merkle_tree::{migrate, vectors, MerkleError};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: merkle vectors generate [FILE]
+       merkle vectors verify FILE
+       merkle selftest [SEED [LEAVES]]
+       merkle migrate IN OUT [DOMAIN]";
+
+fn vectors_generate(path: Option<&str>) -> Result<(), MerkleError> {
+    let vectors = vectors::generate_all();
+
+    match path {
+        Some(path) => vectors::write_vectors(io::BufWriter::new(File::create(path)?), &vectors)?,
+        None => vectors::write_vectors(io::stdout().lock(), &vectors)?,
+    }
+
+    eprintln!("wrote {} vectors", vectors.len());
+    Ok(())
+}
+
+fn vectors_verify(path: &str) -> Result<(), MerkleError> {
+    let vectors = vectors::read_vectors(BufReader::new(File::open(path)?))?;
+
+    for vector in &vectors {
+        vectors::check(vector)?;
+    }
+    vectors::check_mainnet()?;
+
+    println!("{} vectors ok, bitcoin mode matches mainnet", vectors.len());
+    Ok(())
+}
+
+#[cfg(feature = "selftest")]
+fn selftest(seed: Option<&str>, leaves: Option<&str>) -> Result<(), MerkleError> {
+    let parse = |text: &str| {
+        text.parse::<u64>()
+            .map_err(|_| MerkleError::Parse(format!("invalid number {:?}", text)))
+    };
+    let seed = seed.map(parse).transpose()?.unwrap_or(0);
+    let leaves = leaves.map(parse).transpose()?.unwrap_or(1000) as usize;
+
+    let report = simple_merkle_tree::selftest::random_tree_roundtrip(seed, leaves)?;
+    println!(
+        "seed {}: {} leaves, {} proofs ok",
+        report.seed, report.leaves, report.proofs_checked
+    );
+    Ok(())
+}
+
+#[cfg(not(feature = "selftest"))]
+fn selftest(_seed: Option<&str>, _leaves: Option<&str>) -> Result<(), MerkleError> {
+    Err(MerkleError::Parse(
+        "selftest needs the `selftest` feature".to_string(),
+    ))
+}
+
+fn migrate_file(input: &str, output: &str, domain: Option<&str>) -> Result<(), MerkleError> {
+    let domain = domain
+        .map(|domain| {
+            hex::decode(domain)
+                .map_err(|_| MerkleError::Parse(format!("invalid hex domain {:?}", domain)))
+        })
+        .transpose()?;
+
+    let migration = migrate(&std::fs::read(input)?, domain.as_deref())?;
+    std::fs::write(output, &migration.bytes)?;
+
+    for note in &migration.notes {
+        eprintln!("{}", note);
+    }
+    println!(
+        "{}: version {} -> {}",
+        migration.format, migration.from_version, migration.to_version
+    );
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let result = match args.as_slice() {
+        ["vectors", "generate"] => vectors_generate(None),
+        ["vectors", "generate", path] => vectors_generate(Some(path)),
+        ["vectors", "verify", path] => vectors_verify(path),
+        ["selftest"] => selftest(None, None),
+        ["selftest", seed] => selftest(Some(seed), None),
+        ["selftest", seed, leaves] => selftest(Some(seed), Some(leaves)),
+        ["migrate", input, output] => migrate_file(input, output, None),
+        ["migrate", input, output, domain] => migrate_file(input, output, Some(domain)),
+        _ => {
+            eprintln!("{}", USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("merkle: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
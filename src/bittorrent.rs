@@ -0,0 +1,320 @@
+//! BitTorrent v2 (BEP 52) file hashing.
+//!
+//! Each file is cut into 16 KiB blocks, the last one possibly shorter, and
+//! each block's SHA-256 is a leaf. The leaves are padded with all-zero
+//! hashes up to a power of two and paired up without duplication, so a
+//! file of one block has that block's hash as its root. The root is the
+//! file's `pieces root`.
+//!
+//! The `piece layers` dictionary stores, for every file longer than one
+//! piece, the layer of the tree whose nodes each cover one piece. Clients
+//! check that layer against the pieces root once and then check each
+//! downloaded piece against its entry; single blocks can also be checked
+//! against the root with their uncle hashes.
+
+use crate::MerkleError;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::Read;
+
+/// Bytes hashed into each leaf
+pub const BLOCK_SIZE: usize = 16 * 1024;
+
+/// A SHA-256 node of a file tree
+pub type Hash = [u8; 32];
+
+fn parent_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Returns the root of a subtree of `2^height` padding leaves
+fn pad_hash(height: u32) -> Hash {
+    (0..height).fold([0; 32], |hash, _| parent_hash(&hash, &hash))
+}
+
+/// Returns the number of blocks per piece, or an error if `piece_length`
+/// is not a power of two of at least [`BLOCK_SIZE`]
+fn blocks_per_piece(piece_length: u64) -> Result<u64, MerkleError> {
+    if piece_length < BLOCK_SIZE as u64 || !piece_length.is_power_of_two() {
+        return Err(MerkleError::Encode(format!(
+            "piece length must be a power of two of at least 16 KiB, not {}",
+            piece_length
+        )));
+    }
+    Ok(piece_length / BLOCK_SIZE as u64)
+}
+
+/// Hashes `leaves` and padding up to `width` leaves, a power of two
+fn root_of(leaves: &[Hash], width: usize) -> Hash {
+    let mut level = leaves.to_vec();
+    let mut pad = [0; 32];
+    let mut width = width;
+    while width > 1 {
+        if level.len() % 2 == 1 {
+            level.push(pad);
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| parent_hash(&pair[0], &pair[1]))
+            .collect();
+        pad = parent_hash(&pad, &pad);
+        width /= 2;
+    }
+    level.first().copied().unwrap_or(pad)
+}
+
+/// The hash tree of one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentFile {
+    length: u64,
+    /// Levels from the block hashes up, without padding; a missing
+    /// right-hand node stands for a padding subtree
+    levels: Vec<Vec<Hash>>,
+}
+
+impl TorrentFile {
+    /// Hashes a file held in memory
+    pub fn new(data: &[u8]) -> Self {
+        let blocks = data
+            .chunks(BLOCK_SIZE)
+            .map(|block| Sha256::digest(block).into())
+            .collect();
+        Self::from_blocks(data.len() as u64, blocks)
+    }
+
+    /// Hashes a file as it is read
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, MerkleError> {
+        let mut reader = reader;
+        let mut length = 0;
+        let mut blocks = Vec::new();
+        let mut block = Vec::with_capacity(BLOCK_SIZE);
+        loop {
+            block.clear();
+            let read = reader
+                .by_ref()
+                .take(BLOCK_SIZE as u64)
+                .read_to_end(&mut block)?;
+            if read == 0 {
+                break;
+            }
+            length += read as u64;
+            blocks.push(Sha256::digest(&block).into());
+        }
+
+        Ok(Self::from_blocks(length, blocks))
+    }
+
+    fn from_blocks(length: u64, blocks: Vec<Hash>) -> Self {
+        let mut levels = vec![blocks];
+        let mut height = 0;
+        while levels.last().unwrap().len() > 1 {
+            let pad = pad_hash(height);
+            let next_level = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| parent_hash(&pair[0], pair.get(1).unwrap_or(&pad)))
+                .collect();
+            levels.push(next_level);
+            height += 1;
+        }
+
+        TorrentFile { length, levels }
+    }
+
+    /// Returns the file's length in bytes
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns true for an empty file
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Returns the number of 16 KiB blocks
+    pub fn blocks(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    /// Returns the `pieces root`, or `None` for an empty file, which has none
+    pub fn pieces_root(&self) -> Option<Hash> {
+        self.levels.last().unwrap().first().copied()
+    }
+
+    /// Returns the hashes of the piece layer, one per piece
+    ///
+    /// Files no longer than one piece have no piece layer, and this returns
+    /// an empty list for them.
+    pub fn piece_layer(&self, piece_length: u64) -> Result<Vec<Hash>, MerkleError> {
+        let height = blocks_per_piece(piece_length)?.ilog2() as usize;
+        if self.length <= piece_length {
+            return Ok(Vec::new());
+        }
+        Ok(self.levels[height].clone())
+    }
+
+    /// Returns the uncle hashes proving block `index`, lowest first
+    pub fn block_proof(&self, index: usize) -> Option<Vec<Hash>> {
+        if index >= self.blocks() {
+            return None;
+        }
+
+        let proof = self.levels[..self.levels.len() - 1]
+            .iter()
+            .enumerate()
+            .map(|(height, level)| {
+                let sibling = (index >> height) ^ 1;
+                level
+                    .get(sibling)
+                    .copied()
+                    .unwrap_or_else(|| pad_hash(height as u32))
+            })
+            .collect();
+        Some(proof)
+    }
+}
+
+/// Checks that `block` is block `index` of the file with `pieces_root`,
+/// given its uncle hashes from [`TorrentFile::block_proof`]
+pub fn verify_block(pieces_root: &Hash, index: usize, block: &[u8], proof: &[Hash]) -> bool {
+    if block.len() > BLOCK_SIZE || proof.len() >= usize::BITS as usize || index >> proof.len() != 0
+    {
+        return false;
+    }
+
+    let leaf: Hash = Sha256::digest(block).into();
+    let root = proof
+        .iter()
+        .enumerate()
+        .fold(leaf, |node, (height, uncle)| {
+            if (index >> height) & 1 == 0 {
+                parent_hash(&node, uncle)
+            } else {
+                parent_hash(uncle, &node)
+            }
+        });
+    root == *pieces_root
+}
+
+/// Checks a piece layer, as found in the `piece layers` dictionary, against
+/// the pieces root of a file of `file_length` bytes
+pub fn verify_piece_layer(
+    pieces_root: &Hash,
+    file_length: u64,
+    piece_length: u64,
+    layer: &[Hash],
+) -> Result<bool, MerkleError> {
+    let per_piece = blocks_per_piece(piece_length)?;
+    // Files that fit in one piece have no layer to check
+    if file_length <= piece_length {
+        return Ok(false);
+    }
+
+    let pieces = file_length.div_ceil(piece_length);
+    if layer.len() as u64 != pieces {
+        return Ok(false);
+    }
+
+    // Padding nodes at the piece layer cover whole pieces of zero leaves
+    let mut level = layer.to_vec();
+    let mut pad = pad_hash(per_piece.ilog2());
+    while level.len() > 1 {
+        if level.len() % 2 == 1 {
+            level.push(pad);
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| parent_hash(&pair[0], &pair[1]))
+            .collect();
+        pad = parent_hash(&pad, &pad);
+    }
+    Ok(level[0] == *pieces_root)
+}
+
+/// Checks a downloaded piece against its entry in the piece layer
+///
+/// The last piece of a file may be shorter than `piece_length`.
+pub fn verify_piece(
+    piece_hash: &Hash,
+    piece: &[u8],
+    piece_length: u64,
+) -> Result<bool, MerkleError> {
+    let per_piece = blocks_per_piece(piece_length)?;
+    if piece.is_empty() || piece.len() as u64 > piece_length {
+        return Ok(false);
+    }
+
+    let leaves: Vec<Hash> = piece
+        .chunks(BLOCK_SIZE)
+        .map(|block| Sha256::digest(block).into())
+        .collect();
+    Ok(root_of(&leaves, per_piece as usize) == *piece_hash)
+}
+
+/// The `piece layers` dictionary of a v2 torrent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PieceLayers {
+    piece_length: u64,
+    /// Piece layer keyed by pieces root, in the dictionary's byte order
+    layers: BTreeMap<Hash, Vec<Hash>>,
+}
+
+impl PieceLayers {
+    /// Creates an empty dictionary for a torrent with `piece_length`
+    pub fn new(piece_length: u64) -> Result<Self, MerkleError> {
+        blocks_per_piece(piece_length)?;
+        Ok(PieceLayers {
+            piece_length,
+            layers: BTreeMap::new(),
+        })
+    }
+
+    /// Returns the piece length the layers were taken at
+    pub fn piece_length(&self) -> u64 {
+        self.piece_length
+    }
+
+    /// Adds the file's piece layer, if it has one
+    pub fn add(&mut self, file: &TorrentFile) {
+        let layer = file
+            .piece_layer(self.piece_length)
+            .expect("piece length checked in new");
+        if let (Some(root), false) = (file.pieces_root(), layer.is_empty()) {
+            self.layers.insert(root, layer);
+        }
+    }
+
+    /// Returns the piece layer of the file with `pieces_root`
+    pub fn get(&self, pieces_root: &Hash) -> Option<&[Hash]> {
+        self.layers.get(pieces_root).map(Vec::as_slice)
+    }
+
+    /// Returns the number of files with a piece layer
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Encodes the dictionary as bencode, ready to go under `piece layers`
+    /// in the .torrent file
+    pub fn to_bencode(&self) -> Vec<u8> {
+        let mut out = vec![b'd'];
+        for (root, layer) in &self.layers {
+            out.extend_from_slice(format!("{}:", root.len()).as_bytes());
+            out.extend_from_slice(root);
+            out.extend_from_slice(format!("{}:", layer.len() * 32).as_bytes());
+            for hash in layer {
+                out.extend_from_slice(hash);
+            }
+        }
+        out.push(b'e');
+        out
+    }
+}
@@ -0,0 +1,96 @@
+//! Proofs bound to the tree size they were generated at.
+//!
+//! A plain [`MerkleProof`] verifies against whatever root the caller hands
+//! it, so a proof from a superseded root looks no different from a bad one.
+//! A [`BoundProof`] carries the leaf index and tree size, and checking it
+//! against a [`RootRecord`] reports a size mismatch as
+//! [`MerkleError::StaleProof`]. The size also fixes how long the path must
+//! be and the index fixes which side each sibling is on, so neither can be
+//! altered without the proof failing.
+//!
+//! [`MembershipProof`] already embeds its tree size and gets the same check.
+
+use crate::{proof_depth, MembershipProof, MerkleError, MerkleHasher, MerkleProof, MerkleTree};
+use crate::{RootRecord, Side, TreeConfig};
+
+/// A [`MerkleProof`] together with the leaf index and tree size it is for
+pub struct BoundProof<H: MerkleHasher = TreeConfig> {
+    index: u64,
+    tree_size: u64,
+    proof: MerkleProof<H>,
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Generates a proof for the leaf at `index`, bound to the current size
+    pub fn generate_bound_proof(&self, index: usize) -> Option<BoundProof<H>> {
+        Some(BoundProof {
+            index: index as u64,
+            tree_size: self.len() as u64,
+            proof: self.generate_proof_at(index)?,
+        })
+    }
+}
+
+impl<H: MerkleHasher> BoundProof<H> {
+    /// Returns the index of the proven leaf
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Returns the tree size the proof was generated at
+    pub fn tree_size(&self) -> u64 {
+        self.tree_size
+    }
+
+    /// Returns the unbound proof
+    pub fn proof(&self) -> &MerkleProof<H> {
+        &self.proof
+    }
+
+    /// Checks the proof against the root published for `entry.tree_size`
+    pub fn verify_against(&self, entry: &RootRecord) -> Result<(), MerkleError> {
+        if self.tree_size != entry.tree_size {
+            return Err(MerkleError::StaleProof {
+                proof_size: self.tree_size,
+                root_size: entry.tree_size,
+            });
+        }
+
+        if self.index >= self.tree_size
+            || self.proof.proof_hashes.len() != proof_depth(self.tree_size)
+        {
+            return Err(MerkleError::InvalidProof);
+        }
+
+        // Sibling sides must be the ones the index implies
+        let mut position = self.index;
+        for (_, side) in &self.proof.proof_hashes {
+            if *side != Side::of_sibling(position) {
+                return Err(MerkleError::InvalidProof);
+            }
+            position >>= 1;
+        }
+
+        if !self.proof.verify(&entry.root_hash) {
+            return Err(MerkleError::InvalidProof);
+        }
+        Ok(())
+    }
+}
+
+impl MembershipProof {
+    /// Checks the proof against the root published for `entry.tree_size`
+    pub fn verify_against(&self, entry: &RootRecord) -> Result<(), MerkleError> {
+        if self.tree_size() != entry.tree_size {
+            return Err(MerkleError::StaleProof {
+                proof_size: self.tree_size(),
+                root_size: entry.tree_size,
+            });
+        }
+
+        if !self.verify(&entry.root_hash) {
+            return Err(MerkleError::InvalidProof);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+//! Configuring a tree's hashing in one place.
+//!
+//! The hashing modes in [`crate::hasher`] combine by nesting adapters, which
+//! fixes the combination at compile time. [`MerkleTree::builder`] picks
+//! them with fluent calls instead, so the mode can come from a config file
+//! or a command-line flag:
+//!
+//! ```text
+//! MerkleTree::builder()
+//!     .hasher(DigestHasher::<Sha256>::new())
+//!     .leaf_prefix(0x00)
+//!     .padding(Padding::Zeros)
+//!     .sorted_pairs(true)
+//!     .build(leaves)?
+//! ```
+//!
+//! Layers apply in a fixed order whatever order they are set in: the base
+//! hasher, then prefixes, then pair sorting, then truncation. Settings that
+//! can't work together fail [`MerkleTreeBuilder::build`] with a
+//! [`BuilderError`].
+//!
+//! A builder left at its defaults hashes exactly as [`MerkleTree::new`].
+//! The tree it builds is a `MerkleTree<BuiltHasher>`, a full tree: it
+//! proves, updates, compares, audits and exports like any other. Formats
+//! that record only a domain tag take it when it hashes as a
+//! [`TreeConfig`], meaning at most a domain was set, and
+//! [`MerkleTree::into_config_tree`] converts such a tree for the few
+//! methods that need a plain [`MerkleTree`].
+//!
+//! On [`MerkleTree`] the builder replaces the `new_with_config` and
+//! `new_with_hasher` constructors, which are deprecated. The other tree
+//! types take their hasher in a `new_with_hasher` of their own;
+//! [`MerkleTreeBuilder::build_hasher`] makes one from the same settings.
+
+use crate::{MerkleHasher, MerkleTree, Prefixed, Side, SortedPairs, TreeConfig, TruncatedOutput};
+use std::error::Error;
+use std::fmt;
+use std::sync::Arc;
+
+/// What a node at the end of an odd-length level is hashed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    /// A copy of the node itself
+    Duplicate,
+    /// Zero bytes, as many as the node has
+    Zeros,
+    /// The hasher's [`MerkleHasher::empty_hash`]
+    EmptyHash,
+}
+
+/// Why a [`MerkleTreeBuilder`]'s settings don't make a hasher
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuilderError {
+    /// Leaves and nodes share a prefix, so a node could pass for a leaf
+    SamePrefixes(u8),
+    /// A domain tag was set along with a hasher; tags belong to the default
+    /// [`TreeConfig`] hasher
+    DomainWithHasher,
+    /// Truncation asked for fewer bytes than
+    /// [`MIN_HASH_LEN`](crate::hasher::MIN_HASH_LEN) or for more than the
+    /// hash has
+    OutputLength { requested: usize, available: usize },
+}
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuilderError::SamePrefixes(prefix) => write!(
+                f,
+                "leaves and nodes both have prefix {:#04x}; give them different prefixes",
+                prefix
+            ),
+            BuilderError::DomainWithHasher => write!(
+                f,
+                "a domain tag only applies to the default hasher; drop the tag or the hasher"
+            ),
+            BuilderError::OutputLength {
+                requested,
+                available,
+            } => write!(
+                f,
+                "can't truncate {}-byte hashes to {} bytes",
+                available, requested
+            ),
+        }
+    }
+}
+
+impl Error for BuilderError {}
+
+/// Object-safe view of a [`MerkleHasher`], so layers can be stacked at
+/// runtime
+trait DynHasher: Send + Sync {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8>;
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8>;
+    fn empty_hash(&self) -> Vec<u8>;
+    fn pad(&self, node: &[u8]) -> Vec<u8>;
+    fn hash_children(&self, children: &[&[u8]]) -> Vec<u8>;
+    fn verify_path(&self, leaf: &[u8], siblings: &[(Vec<u8>, Side)], root: &[u8]) -> bool;
+    fn domain_tag(&self) -> Option<&[u8]>;
+}
+
+impl<H: MerkleHasher + Send + Sync> DynHasher for H {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        MerkleHasher::hash_leaf(self, data)
+    }
+
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        MerkleHasher::hash_node(self, left, right)
+    }
+
+    fn empty_hash(&self) -> Vec<u8> {
+        MerkleHasher::empty_hash(self)
+    }
+
+    fn pad(&self, node: &[u8]) -> Vec<u8> {
+        MerkleHasher::pad(self, node)
+    }
+
+    fn hash_children(&self, children: &[&[u8]]) -> Vec<u8> {
+        MerkleHasher::hash_children(self, children)
+    }
+
+    fn verify_path(&self, leaf: &[u8], siblings: &[(Vec<u8>, Side)], root: &[u8]) -> bool {
+        MerkleHasher::verify_path(self, leaf, siblings, root)
+    }
+
+    fn domain_tag(&self) -> Option<&[u8]> {
+        MerkleHasher::domain_tag(self)
+    }
+}
+
+/// The hasher a [`MerkleTreeBuilder`] assembles
+///
+/// Clones share the layers underneath, so proofs stay cheap to copy.
+#[derive(Clone)]
+pub struct BuiltHasher {
+    inner: Arc<dyn DynHasher>,
+    /// Overrides the inner hasher's padding when set
+    padding: Option<Padding>,
+}
+
+impl BuiltHasher {
+    fn new<H: MerkleHasher + Send + Sync + 'static>(hasher: H) -> Self {
+        BuiltHasher {
+            inner: Arc::new(hasher),
+            padding: None,
+        }
+    }
+}
+
+impl fmt::Debug for BuiltHasher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BuiltHasher")
+            .field("config_id", &hex::encode(self.config_id()))
+            .field("padding", &self.padding)
+            .finish()
+    }
+}
+
+impl MerkleHasher for BuiltHasher {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        self.inner.hash_leaf(data)
+    }
+
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.inner.hash_node(left, right)
+    }
+
+    fn empty_hash(&self) -> Vec<u8> {
+        self.inner.empty_hash()
+    }
+
+    fn pad(&self, node: &[u8]) -> Vec<u8> {
+        match self.padding {
+            None => self.inner.pad(node),
+            Some(Padding::Duplicate) => node.to_vec(),
+            Some(Padding::Zeros) => vec![0; node.len()],
+            Some(Padding::EmptyHash) => self.inner.empty_hash(),
+        }
+    }
+
+    fn hash_children(&self, children: &[&[u8]]) -> Vec<u8> {
+        self.inner.hash_children(children)
+    }
+
+    fn verify_path(&self, leaf: &[u8], siblings: &[(Vec<u8>, Side)], root: &[u8]) -> bool {
+        self.inner.verify_path(leaf, siblings, root)
+    }
+
+    fn domain_tag(&self) -> Option<&[u8]> {
+        self.inner.domain_tag()
+    }
+}
+
+/// Fluent configuration for a [`MerkleTree`], from [`MerkleTree::builder`]
+#[derive(Debug, Clone, Default)]
+pub struct MerkleTreeBuilder {
+    hasher: Option<BuiltHasher>,
+    domain: Option<Vec<u8>>,
+    leaf_prefix: Option<u8>,
+    node_prefix: Option<u8>,
+    sorted_pairs: bool,
+    output_len: Option<usize>,
+    padding: Option<Padding>,
+}
+
+impl MerkleTreeBuilder {
+    /// Hashes with `hasher` instead of the default SHA-256 [`TreeConfig`]
+    pub fn hasher<H: MerkleHasher + Send + Sync + 'static>(mut self, hasher: H) -> Self {
+        self.hasher = Some(BuiltHasher::new(hasher));
+        self
+    }
+
+    /// Tags every hash with `domain`, as [`TreeConfig::domain`] does
+    pub fn domain(mut self, domain: impl Into<Vec<u8>>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Prepends `prefix` to every leaf before hashing
+    ///
+    /// Nodes get `0x01` unless [`MerkleTreeBuilder::node_prefix`] says
+    /// otherwise.
+    pub fn leaf_prefix(mut self, prefix: u8) -> Self {
+        self.leaf_prefix = Some(prefix);
+        self
+    }
+
+    /// Prepends `prefix` to every pair of children before hashing
+    ///
+    /// Leaves get `0x00` unless [`MerkleTreeBuilder::leaf_prefix`] says
+    /// otherwise.
+    pub fn node_prefix(mut self, prefix: u8) -> Self {
+        self.node_prefix = Some(prefix);
+        self
+    }
+
+    /// Orders each pair of children before hashing, as [`SortedPairs`] does
+    pub fn sorted_pairs(mut self, sorted: bool) -> Self {
+        self.sorted_pairs = sorted;
+        self
+    }
+
+    /// Keeps only the first `len` bytes of every hash, at least
+    /// [`MIN_HASH_LEN`](crate::hasher::MIN_HASH_LEN)
+    pub fn truncate(mut self, len: usize) -> Self {
+        self.output_len = Some(len);
+        self
+    }
+
+    /// Sets what a node without a sibling is hashed with; by default the
+    /// hasher's own choice, a copy of the node for the crate's hashers
+    pub fn padding(mut self, padding: Padding) -> Self {
+        self.padding = Some(padding);
+        self
+    }
+
+    /// Assembles the configured hasher without building a tree
+    pub fn build_hasher(self) -> Result<BuiltHasher, BuilderError> {
+        let mut hasher = match (self.hasher, self.domain) {
+            (Some(_), Some(_)) => return Err(BuilderError::DomainWithHasher),
+            (Some(hasher), None) => hasher,
+            (None, domain) => BuiltHasher::new(TreeConfig::from_domain(domain)),
+        };
+
+        if self.leaf_prefix.is_some() || self.node_prefix.is_some() {
+            let leaf_prefix = self.leaf_prefix.unwrap_or(0x00);
+            let node_prefix = self.node_prefix.unwrap_or(0x01);
+            if leaf_prefix == node_prefix {
+                return Err(BuilderError::SamePrefixes(leaf_prefix));
+            }
+            hasher = BuiltHasher::new(Prefixed {
+                inner: hasher,
+                leaf_prefix: vec![leaf_prefix],
+                node_prefix: vec![node_prefix],
+            });
+        }
+
+        if self.sorted_pairs {
+            hasher = BuiltHasher::new(SortedPairs(hasher));
+        }
+
+        if let Some(len) = self.output_len {
+            let available = MerkleHasher::empty_hash(&hasher).len();
+            let truncated =
+                TruncatedOutput::new(hasher, len).map_err(|_| BuilderError::OutputLength {
+                    requested: len,
+                    available,
+                })?;
+            hasher = BuiltHasher::new(truncated);
+        }
+
+        hasher.padding = self.padding;
+        Ok(hasher)
+    }
+
+    /// Builds a tree over `data` with the configured hashing
+    pub fn build(self, data: Vec<Vec<u8>>) -> Result<MerkleTree<BuiltHasher>, BuilderError> {
+        Ok(MerkleTree::hashed_by(data, self.build_hasher()?))
+    }
+
+    /// Builds a tree over leaves already hashed with the configured
+    /// hashing
+    pub fn build_from_leaf_hashes(
+        self,
+        leaves: Vec<Vec<u8>>,
+    ) -> Result<MerkleTree<BuiltHasher>, BuilderError> {
+        Ok(MerkleTree::from_leaf_hashes_by(
+            leaves,
+            self.build_hasher()?,
+        ))
+    }
+}
+
+impl MerkleTree {
+    /// Starts configuring a tree, see [`MerkleTreeBuilder`]
+    pub fn builder() -> MerkleTreeBuilder {
+        MerkleTreeBuilder::default()
+    }
+}
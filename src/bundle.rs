@@ -0,0 +1,149 @@
+//! Inclusion proofs packaged with the signed tree head they lead to.
+//!
+//! A [`ProofBundle`] carries a [`MembershipProof`], the [`TreeHead`] whose
+//! root it verifies against, the log's signature over that head and any
+//! witness cosignatures. A verifier holding only the log's key, and
+//! optionally some witness keys, checks all of it offline with one call to
+//! [`ProofBundle::verify`].
+//!
+//! Bundles travel as text, in sections separated by blank lines:
+//!
+//! ```text
+//! simple-merkle-tree proof bundle v1
+//! index <leaf index>
+//! <base64 leaf hash>
+//! <base64 sibling hash, one per line from the leaf up>
+//!
+//! <checkpoint body>
+//!
+//! — <key id> [<timestamp>] <base64 signature>
+//! ```
+//!
+//! with one signature line for the log and one per witness; only witness
+//! lines carry the timestamp they cosigned at. The proof's tree size is the
+//! checkpoint's.
+
+use crate::{
+    base64, Cosignature, HistoryTree, MembershipProof, MerkleError, Signer, TreeHead, Verifier,
+    VerifyPolicy, WitnessedHead,
+};
+
+const HEADER: &str = "simple-merkle-tree proof bundle v1";
+
+/// A proof with the signed tree head that vouches for its root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofBundle {
+    pub proof: MembershipProof,
+    /// The head with the log's signature and any witness cosignatures
+    pub head: WitnessedHead,
+}
+
+impl HistoryTree {
+    /// Proves leaf `index` against the current head of the log `origin`,
+    /// signed by `log`
+    ///
+    /// Witnesses can add their cosignatures to the bundle's head afterwards.
+    pub fn bundle_proof(
+        &self,
+        index: u64,
+        origin: impl Into<String>,
+        log: &dyn Signer,
+    ) -> Option<ProofBundle> {
+        let proof = self.prove_membership(index, self.len())?;
+        let mut head = WitnessedHead::new(TreeHead::from_history(origin, self));
+        head.sign(log);
+        Some(ProofBundle { proof, head })
+    }
+}
+
+impl ProofBundle {
+    /// Checks the log's signature, the proof against the signed root, and
+    /// that at least `threshold` of `witnesses` cosigned the head
+    ///
+    /// Returns the number of valid witnesses on success.
+    pub fn verify(
+        &self,
+        log: &dyn Verifier,
+        witnesses: &[&dyn Verifier],
+        threshold: usize,
+    ) -> Result<usize, MerkleError> {
+        let policy = VerifyPolicy {
+            min_witnesses: threshold,
+            ..VerifyPolicy::default()
+        };
+        self.verify_with_policy(&policy, log, witnesses)
+    }
+
+    /// Encodes the bundle in the text format described in the module docs
+    ///
+    /// Fails if a key id is empty or contains whitespace.
+    pub fn to_text(&self) -> Result<String, MerkleError> {
+        let mut text = format!("{}\nindex {}\n", HEADER, self.proof.index());
+        for hash in std::iter::once(self.proof.leaf_hash())
+            .chain(self.proof.path().iter().map(Vec::as_slice))
+        {
+            text.push_str(&base64::encode(hash));
+            text.push('\n');
+        }
+
+        text.push('\n');
+        text.push_str(&self.head.head.to_checkpoint());
+        text.push('\n');
+        for signature in &self.head.cosignatures {
+            let line = signature.to_line().ok_or_else(|| {
+                MerkleError::Encode(format!(
+                    "key id {:?} can't be written to a bundle",
+                    signature.key_id
+                ))
+            })?;
+            text.push_str(&line);
+            text.push('\n');
+        }
+
+        Ok(text)
+    }
+
+    /// Parses a bundle written by [`ProofBundle::to_text`]
+    ///
+    /// Nothing is verified; call [`ProofBundle::verify`] on the result.
+    pub fn parse(text: &str) -> Result<Self, MerkleError> {
+        let error = |message: &str| MerkleError::Parse(format!("proof bundle: {}", message));
+
+        let body = text
+            .strip_suffix('\n')
+            .ok_or_else(|| error("missing final newline"))?;
+        let sections: Vec<&str> = body.split("\n\n").collect();
+        let [proof, checkpoint, signatures] = sections[..] else {
+            return Err(error("expected proof, checkpoint and signature sections"));
+        };
+
+        let mut lines = proof.split('\n');
+        if lines.next() != Some(HEADER) {
+            return Err(error("not a supported proof bundle"));
+        }
+        let index = lines
+            .next()
+            .and_then(|line| line.strip_prefix("index "))
+            .and_then(|index| index.parse().ok())
+            .ok_or_else(|| error("invalid index line"))?;
+        let mut hashes = lines
+            .map(|line| base64::decode(line).ok_or_else(|| error("invalid hash")))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter();
+        let leaf_hash = hashes.next().ok_or_else(|| error("missing leaf hash"))?;
+
+        let head = TreeHead::parse_checkpoint(&format!("{}\n", checkpoint))?;
+
+        let cosignatures = signatures
+            .split('\n')
+            .map(|line| {
+                Cosignature::parse_line(line).ok_or_else(|| error("invalid signature line"))
+            })
+            .collect::<Result<_, MerkleError>>()?;
+
+        Ok(ProofBundle {
+            proof: MembershipProof::from_parts(index, head.size, leaf_hash, hashes.collect()),
+            head: WitnessedHead { head, cosignatures },
+        })
+    }
+}
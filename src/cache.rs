@@ -0,0 +1,154 @@
+//! An LRU cache of generated proofs for skewed access patterns.
+//!
+//! Proofs are cached per leaf index for one root and tree size. Looking up a
+//! proof against a tree with a different root or size drops everything
+//! cached, so an updated tree never gets stale proofs. The size matters
+//! because duplicating the last node gives `[a, b, c]` and `[a, b, c, c]`
+//! the same root, and only the second has a leaf 3.
+
+use crate::{MerkleHasher, MerkleProof, MerkleTree, TreeConfig};
+use std::collections::{BTreeMap, HashMap};
+
+/// How much a [`ProofCache`] may hold before evicting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheLimit {
+    /// At most this many proofs
+    Entries(usize),
+    /// At most this many bytes of hashes across all cached proofs
+    Bytes(usize),
+}
+
+/// Counters for tuning a [`ProofCache`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+struct CacheEntry<H: MerkleHasher> {
+    proof: MerkleProof<H>,
+    size: usize,
+    last_used: u64,
+}
+
+/// Least-recently-used cache of proofs keyed by leaf index
+pub struct ProofCache<H: MerkleHasher = TreeConfig> {
+    limit: CacheLimit,
+    /// Root and size of the tree the cached proofs belong to
+    tree: Option<(Vec<u8>, usize)>,
+    entries: HashMap<usize, CacheEntry<H>>,
+    /// Leaf indices by the tick they were last used at, oldest first
+    recency: BTreeMap<u64, usize>,
+    tick: u64,
+    bytes: usize,
+    stats: CacheStats,
+}
+
+/// Bytes of hashes a proof holds
+fn proof_size<H: MerkleHasher>(proof: &MerkleProof<H>) -> usize {
+    proof.leaf_hash.len()
+        + proof.root_hash.len()
+        + proof
+            .proof_hashes
+            .iter()
+            .map(|(hash, _)| hash.len() + 1)
+            .sum::<usize>()
+}
+
+impl<H: MerkleHasher> ProofCache<H> {
+    /// Creates an empty cache
+    pub fn new(limit: CacheLimit) -> Self {
+        ProofCache {
+            limit,
+            tree: None,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+            bytes: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Returns the proof for leaf `index` of `tree`, generating it on a miss
+    pub fn proof(&mut self, tree: &MerkleTree<H>, index: usize) -> Option<&MerkleProof<H>> {
+        let key = (tree.root_hash()?, tree.len());
+        if self.tree.as_ref() != Some(&key) {
+            self.clear();
+            self.tree = Some(key);
+        }
+
+        self.tick += 1;
+        if let Some(entry) = self.entries.get_mut(&index) {
+            self.recency.remove(&entry.last_used);
+            self.recency.insert(self.tick, index);
+            entry.last_used = self.tick;
+            self.stats.hits += 1;
+            return self.entries.get(&index).map(|entry| &entry.proof);
+        }
+
+        self.stats.misses += 1;
+        let proof = tree.generate_proof_at(index)?;
+        let size = proof_size(&proof);
+        self.bytes += size;
+        self.recency.insert(self.tick, index);
+        self.entries.insert(
+            index,
+            CacheEntry {
+                proof,
+                size,
+                last_used: self.tick,
+            },
+        );
+        self.evict();
+
+        self.entries.get(&index).map(|entry| &entry.proof)
+    }
+
+    /// Drops least recently used proofs until the cache is within its limit
+    ///
+    /// The newest proof is always kept, even if it alone is over a byte limit.
+    fn evict(&mut self) {
+        while self.over_limit() && self.entries.len() > 1 {
+            let (_, index) = self.recency.pop_first().unwrap();
+            let entry = self.entries.remove(&index).unwrap();
+            self.bytes -= entry.size;
+            self.stats.evictions += 1;
+        }
+    }
+
+    fn over_limit(&self) -> bool {
+        match self.limit {
+            CacheLimit::Entries(max) => self.entries.len() > max,
+            CacheLimit::Bytes(max) => self.bytes > max,
+        }
+    }
+
+    /// Drops every cached proof, keeping the counters
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.bytes = 0;
+        self.tree = None;
+    }
+
+    /// Returns the number of cached proofs
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if nothing is cached
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the bytes of hashes currently cached
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Returns hit, miss and eviction counts since the cache was created
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+}
@@ -0,0 +1,498 @@
+//! Deterministic leaf encodings for structured records.
+//!
+//! Two services hashing "the same record" only agree on the root if they
+//! agree on the leaf bytes, so records are serialized with serde into one of
+//! two canonical forms before hashing:
+//!
+//! - [`Encoding::Json`] writes compact JSON with object keys sorted by their
+//!   UTF-16 code units, in the style of RFC 8785. Floats are written the way
+//!   `serde_json` formats them, so cross-language records should avoid them.
+//! - [`Encoding::Binary`] writes a fixed layout: big-endian integers of their
+//!   declared width, `u32` length prefixes for strings, bytes, sequences and
+//!   maps, and `u32` variant indices. A struct is the `u32` count of the
+//!   fields it serializes, then each field's name as a string followed by
+//!   its value, in declaration order, so structs that skip different fields
+//!   never share an encoding. Map entries are sorted by their encoded keys.
+//!
+//! In both forms NaN and infinite floats are rejected and negative zero is
+//! written as zero. JSON has no literal for them and `serde_json` would
+//! write `null`, so a JSON record is walked by the binary serializer first
+//! to reject them, and `Some(f64::NAN)` can't share a leaf with `None`.
+//!
+//! Either way a `HashMap` field produces the same bytes regardless of
+//! iteration order.
+//!
+//! Needs the `canonical` feature.
+
+use crate::{MerkleError, MerkleTree};
+use serde::ser::{self, Serialize};
+use serde_json::Value;
+
+/// Canonical form records are serialized into before hashing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    #[default]
+    Json,
+    Binary,
+}
+
+/// Serializes `value` into its canonical leaf bytes
+pub fn encode<T: Serialize + ?Sized>(
+    value: &T,
+    encoding: Encoding,
+) -> Result<Vec<u8>, MerkleError> {
+    match encoding {
+        Encoding::Json => to_canonical_json(value),
+        Encoding::Binary => to_canonical_binary(value),
+    }
+}
+
+/// Serializes every record into its canonical leaf bytes
+pub fn leaves<T: Serialize>(
+    records: &[T],
+    encoding: Encoding,
+) -> Result<Vec<Vec<u8>>, MerkleError> {
+    records
+        .iter()
+        .map(|record| encode(record, encoding))
+        .collect()
+}
+
+impl MerkleTree {
+    /// Creates a tree whose leaves are the canonical encodings of `records`
+    pub fn from_records<T: Serialize>(
+        records: &[T],
+        encoding: Encoding,
+    ) -> Result<Self, MerkleError> {
+        Ok(MerkleTree::new(leaves(records, encoding)?))
+    }
+}
+
+/// Serializes `value` as canonical JSON
+pub fn to_canonical_json<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, MerkleError> {
+    to_canonical_binary(value)?;
+    let value = serde_json::to_value(value).map_err(|e| MerkleError::Encode(e.to_string()))?;
+    let mut out = Vec::new();
+    write_json(&value, &mut out);
+    Ok(out)
+}
+
+fn write_json(value: &Value, out: &mut Vec<u8>) {
+    match value {
+        Value::Number(number) if number.as_f64() == Some(0.0) && number.is_f64() => {
+            out.extend_from_slice(b"0.0")
+        }
+        Value::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_json(item, out);
+            }
+            out.push(b']');
+        }
+        Value::Object(fields) => {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+
+            out.push(b'{');
+            for (i, (key, field)) in fields.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                out.extend_from_slice(Value::String(key.clone()).to_string().as_bytes());
+                out.push(b':');
+                write_json(field, out);
+            }
+            out.push(b'}');
+        }
+        scalar => out.extend_from_slice(scalar.to_string().as_bytes()),
+    }
+}
+
+/// Serializes `value` in the fixed binary layout
+pub fn to_canonical_binary<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, MerkleError> {
+    let mut serializer = BinarySerializer { out: Vec::new() };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.out)
+}
+
+impl ser::Error for MerkleError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        MerkleError::Encode(msg.to_string())
+    }
+}
+
+fn non_finite(v: impl std::fmt::Display) -> MerkleError {
+    MerkleError::Encode(format!("{} has no canonical encoding", v))
+}
+
+struct BinarySerializer {
+    out: Vec<u8>,
+}
+
+impl BinarySerializer {
+    fn write_len(&mut self, len: usize) -> Result<(), MerkleError> {
+        let len = u32::try_from(len)
+            .map_err(|_| MerkleError::Encode(format!("length {} exceeds u32", len)))?;
+        self.out.extend_from_slice(&len.to_be_bytes());
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), MerkleError> {
+        self.write_len(bytes.len())?;
+        self.out.extend_from_slice(bytes);
+        Ok(())
+    }
+}
+
+/// Collects the elements of a sequence, tuple, map or struct
+///
+/// Sequences and maps get their length prefix once every element has been
+/// written, since serde doesn't always know it up front. Map entries are
+/// buffered separately so they can be sorted.
+struct Compound<'a> {
+    serializer: &'a mut BinarySerializer,
+    kind: CompoundKind,
+}
+
+enum CompoundKind {
+    /// Elements are written in place with no prefix
+    Fixed,
+    /// Elements, or struct fields, are written after a count placeholder at
+    /// this offset
+    Seq { start: usize, len: usize },
+    /// Encoded (key, value) entries waiting to be sorted
+    Map {
+        entries: Vec<(Vec<u8>, Vec<u8>)>,
+        key: Option<Vec<u8>>,
+    },
+}
+
+impl Compound<'_> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), MerkleError> {
+        if let CompoundKind::Seq { len, .. } = &mut self.kind {
+            *len += 1;
+        }
+        value.serialize(&mut *self.serializer)
+    }
+
+    /// Writes a struct field, its name first
+    fn field<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), MerkleError> {
+        if let CompoundKind::Seq { len, .. } = &mut self.kind {
+            *len += 1;
+        }
+        self.serializer.write_bytes(key.as_bytes())?;
+        value.serialize(&mut *self.serializer)
+    }
+
+    fn finish(self) -> Result<(), MerkleError> {
+        match self.kind {
+            CompoundKind::Fixed => Ok(()),
+            CompoundKind::Seq { start, len } => {
+                let len = u32::try_from(len)
+                    .map_err(|_| MerkleError::Encode(format!("length {} exceeds u32", len)))?;
+                self.serializer.out[start..start + 4].copy_from_slice(&len.to_be_bytes());
+                Ok(())
+            }
+            CompoundKind::Map { mut entries, .. } => {
+                entries.sort();
+                self.serializer.write_len(entries.len())?;
+                for (key, value) in entries {
+                    self.serializer.out.extend_from_slice(&key);
+                    self.serializer.out.extend_from_slice(&value);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+macro_rules! serialize_be {
+    ($($method:ident: $ty:ty),*) => {
+        $(fn $method(self, v: $ty) -> Result<(), MerkleError> {
+            self.out.extend_from_slice(&v.to_be_bytes());
+            Ok(())
+        })*
+    };
+}
+
+impl<'a> ser::Serializer for &'a mut BinarySerializer {
+    type Ok = ();
+    type Error = MerkleError;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    serialize_be!(
+        serialize_i8: i8, serialize_i16: i16, serialize_i32: i32, serialize_i64: i64,
+        serialize_i128: i128, serialize_u8: u8, serialize_u16: u16, serialize_u32: u32,
+        serialize_u64: u64, serialize_u128: u128
+    );
+
+    fn serialize_bool(self, v: bool) -> Result<(), MerkleError> {
+        self.out.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), MerkleError> {
+        if !v.is_finite() {
+            return Err(non_finite(v));
+        }
+        // Adding zero turns -0.0 into 0.0 and leaves everything else alone
+        self.out
+            .extend_from_slice(&(v + 0.0).to_bits().to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), MerkleError> {
+        if !v.is_finite() {
+            return Err(non_finite(v));
+        }
+        // Adding zero turns -0.0 into 0.0 and leaves everything else alone
+        self.out
+            .extend_from_slice(&(v + 0.0).to_bits().to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), MerkleError> {
+        self.serialize_u32(v as u32)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), MerkleError> {
+        self.write_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), MerkleError> {
+        self.write_bytes(v)
+    }
+
+    fn serialize_none(self) -> Result<(), MerkleError> {
+        self.out.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), MerkleError> {
+        self.out.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), MerkleError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), MerkleError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), MerkleError> {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), MerkleError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), MerkleError> {
+        self.out.extend_from_slice(&variant_index.to_be_bytes());
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a>, MerkleError> {
+        let start = self.out.len();
+        self.out.extend_from_slice(&[0; 4]);
+        Ok(Compound {
+            serializer: self,
+            kind: CompoundKind::Seq { start, len: 0 },
+        })
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Compound<'a>, MerkleError> {
+        Ok(Compound {
+            serializer: self,
+            kind: CompoundKind::Fixed,
+        })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, MerkleError> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, MerkleError> {
+        self.out.extend_from_slice(&variant_index.to_be_bytes());
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a>, MerkleError> {
+        Ok(Compound {
+            serializer: self,
+            kind: CompoundKind::Map {
+                entries: Vec::new(),
+                key: None,
+            },
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, MerkleError> {
+        self.serialize_seq(None)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Compound<'a>, MerkleError> {
+        self.out.extend_from_slice(&variant_index.to_be_bytes());
+        self.serialize_seq(None)
+    }
+}
+
+impl ser::SerializeSeq for Compound<'_> {
+    type Ok = ();
+    type Error = MerkleError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), MerkleError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), MerkleError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for Compound<'_> {
+    type Ok = ();
+    type Error = MerkleError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), MerkleError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), MerkleError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for Compound<'_> {
+    type Ok = ();
+    type Error = MerkleError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), MerkleError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), MerkleError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for Compound<'_> {
+    type Ok = ();
+    type Error = MerkleError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), MerkleError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), MerkleError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeMap for Compound<'_> {
+    type Ok = ();
+    type Error = MerkleError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), MerkleError> {
+        let encoded = to_canonical_binary(key)?;
+        if let CompoundKind::Map { key, .. } = &mut self.kind {
+            *key = Some(encoded);
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), MerkleError> {
+        let encoded = to_canonical_binary(value)?;
+        if let CompoundKind::Map { entries, key } = &mut self.kind {
+            let key = key
+                .take()
+                .ok_or_else(|| MerkleError::Encode("map value without a key".to_string()))?;
+            entries.push((key, encoded));
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), MerkleError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for Compound<'_> {
+    type Ok = ();
+    type Error = MerkleError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), MerkleError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), MerkleError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for Compound<'_> {
+    type Ok = ();
+    type Error = MerkleError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), MerkleError> {
+        self.field(key, value)
+    }
+
+    fn end(self) -> Result<(), MerkleError> {
+        self.finish()
+    }
+}
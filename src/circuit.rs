@@ -0,0 +1,155 @@
+//! Inclusion proofs as witness inputs for zero-knowledge circuits.
+//!
+//! A SHA-256 hash is wider than the scalar field of the usual proving
+//! curves, so each hash becomes several field elements: 128-bit limbs fit
+//! BN254, BLS12-381 and the Pasta curves, 32-bit limbs fit Goldilocks, and
+//! single bits suit gadgets that hash bit arrays, like circomlib's
+//! `Sha256`. The path follows the circom `MerkleTreeInclusionProof`
+//! convention: `pathElements[i]` is the sibling at level `i`, and
+//! `pathIndices[i]` is 1 when the node being proven is the right child.
+//!
+//! [`CircuitWitness::to_json`] writes the input file circom and the
+//! halo2 examples read, with every element as a decimal string:
+//!
+//! ```text
+//! {"leaf":[...],"root":[...],"pathElements":[[...],...],"pathIndices":[0,1,...]}
+//! ```
+
+use crate::{MerkleHasher, MerkleProof, Side};
+use std::fmt::Write;
+
+/// How each hash is split into field elements
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FieldEncoding {
+    /// One element per bit
+    Bits,
+    Limbs32,
+    Limbs64,
+    #[default]
+    Limbs128,
+}
+
+impl FieldEncoding {
+    fn bits(self) -> usize {
+        match self {
+            FieldEncoding::Bits => 1,
+            FieldEncoding::Limbs32 => 32,
+            FieldEncoding::Limbs64 => 64,
+            FieldEncoding::Limbs128 => 128,
+        }
+    }
+}
+
+/// Order of the elements making up one hash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endianness {
+    /// Most significant first, in hash byte order, as SHA-256 gadgets expect
+    #[default]
+    Big,
+    /// Least significant first, as circom's `Num2Bits` produces
+    Little,
+}
+
+/// Layout for [`MerkleProof::to_circuit_witness_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CircuitConfig {
+    pub encoding: FieldEncoding,
+    pub endianness: Endianness,
+}
+
+/// A proof laid out as circuit inputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitWitness {
+    pub leaf: Vec<u128>,
+    pub root: Vec<u128>,
+    /// Sibling hashes, leaf level first
+    pub path_elements: Vec<Vec<u128>>,
+    /// 1 where the proven node is the right child
+    pub path_indices: Vec<u8>,
+}
+
+/// Splits `hash`, read as a big-endian number, into field elements
+fn elements(hash: &[u8], config: &CircuitConfig) -> Vec<u128> {
+    let bits = config.encoding.bits();
+    let mut elements: Vec<u128> = if bits == 1 {
+        hash.iter()
+            .flat_map(|byte| (0..8).rev().map(move |bit| u128::from((byte >> bit) & 1)))
+            .collect()
+    } else {
+        hash.chunks(bits / 8)
+            .map(|limb| {
+                limb.iter()
+                    .fold(0, |acc, &byte| (acc << 8) | u128::from(byte))
+            })
+            .collect()
+    };
+
+    if config.endianness == Endianness::Little {
+        elements.reverse();
+    }
+    elements
+}
+
+fn write_array(out: &mut String, elements: &[u128]) {
+    out.push('[');
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "\"{}\"", element);
+    }
+    out.push(']');
+}
+
+impl<H: MerkleHasher> MerkleProof<H> {
+    /// Lays the proof out as circuit inputs with 128-bit big-endian limbs
+    pub fn to_circuit_witness(&self) -> CircuitWitness {
+        self.to_circuit_witness_with(&CircuitConfig::default())
+    }
+
+    /// Lays the proof out as circuit inputs using `config`
+    pub fn to_circuit_witness_with(&self, config: &CircuitConfig) -> CircuitWitness {
+        CircuitWitness {
+            leaf: elements(&self.leaf_hash, config),
+            root: elements(&self.root_hash, config),
+            path_elements: self
+                .proof_hashes
+                .iter()
+                .map(|(hash, _)| elements(hash, config))
+                .collect(),
+            path_indices: self
+                .proof_hashes
+                .iter()
+                .map(|&(_, side)| u8::from(side == Side::Left))
+                .collect(),
+        }
+    }
+}
+
+impl CircuitWitness {
+    /// Returns the witness as a circom input file
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("{\"leaf\":");
+        write_array(&mut out, &self.leaf);
+        out.push_str(",\"root\":");
+        write_array(&mut out, &self.root);
+
+        out.push_str(",\"pathElements\":[");
+        for (i, sibling) in self.path_elements.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            write_array(&mut out, sibling);
+        }
+
+        out.push_str("],\"pathIndices\":[");
+        for (i, index) in self.path_indices.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}", index);
+        }
+        out.push_str("]}");
+        out
+    }
+}
@@ -0,0 +1,198 @@
+//! Reclaiming space from sparse tree stores.
+//!
+//! A [`SparseMerkleTree`] never deletes a replaced node, so its store grows
+//! with every write and keeps every root the tree ever had. [`compact`]
+//! garbage-collects it: it walks down from the roots a [`Retention`] keeps,
+//! marking every node they reach, then scans the store in batches and
+//! deletes the rest. Roots compacted away can no longer be opened.
+//!
+//! Compaction takes one store round trip per level of the retained trees
+//! and one per batch scanned, so on a large store it runs for a good while;
+//! spawn it on its own task. Writes to the store must wait until it
+//! returns, though: nodes are content addressed, and a node written
+//! meanwhile may match one already judged unreachable, which would then be
+//! deleted under the new root.
+
+use crate::sparse::{NodeHash, SparseNode, EMPTY_HASH};
+use crate::{MemoryStore, MerkleError, NodeStore, SparseMerkleTree};
+use std::collections::HashSet;
+use std::future::Future;
+
+/// Node store that can list and delete its nodes
+///
+/// Key-value databases implement [`CompactStore::scan_hashes`] with an
+/// ordered iterator starting just past `after`.
+pub trait CompactStore: NodeStore {
+    /// Returns up to `limit` stored hashes greater than `after`, in
+    /// ascending order; `None` starts from the lowest
+    fn scan_hashes(
+        &self,
+        after: Option<NodeHash>,
+        limit: usize,
+    ) -> impl Future<Output = Result<Vec<NodeHash>, MerkleError>> + Send;
+
+    /// Deletes nodes by hash, skipping any that aren't stored
+    fn delete_nodes(
+        &self,
+        hashes: &[NodeHash],
+    ) -> impl Future<Output = Result<(), MerkleError>> + Send;
+}
+
+impl CompactStore for MemoryStore {
+    async fn scan_hashes(
+        &self,
+        after: Option<NodeHash>,
+        limit: usize,
+    ) -> Result<Vec<NodeHash>, MerkleError> {
+        let nodes = self.nodes.lock().unwrap();
+        let mut hashes: Vec<NodeHash> = nodes
+            .keys()
+            .filter(|hash| after.is_none_or(|after| **hash > after))
+            .copied()
+            .collect();
+        hashes.sort_unstable();
+        hashes.truncate(limit);
+        Ok(hashes)
+    }
+
+    async fn delete_nodes(&self, hashes: &[NodeHash]) -> Result<(), MerkleError> {
+        let mut nodes = self.nodes.lock().unwrap();
+        for hash in hashes {
+            nodes.remove(hash);
+        }
+        Ok(())
+    }
+}
+
+/// Which historical roots survive a compaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Retention {
+    /// Every root given, deleting only nodes none of them reach
+    #[default]
+    All,
+    /// The given number of most recent roots
+    Last(usize),
+}
+
+/// Settings for [`compact`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionConfig {
+    pub retention: Retention,
+    /// Nodes read, scanned or deleted per store call
+    pub batch_size: usize,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        CompactionConfig {
+            retention: Retention::All,
+            batch_size: 1024,
+        }
+    }
+}
+
+/// What a compaction kept and reclaimed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompactionReport {
+    pub roots_kept: usize,
+    pub nodes_kept: u64,
+    pub nodes_deleted: u64,
+}
+
+/// Deletes every node of `store` that no retained root reaches
+///
+/// `roots` runs oldest first, so [`Retention::Last`] keeps its tail. Fails
+/// with [`MerkleError::MissingNode`] before deleting anything if a retained
+/// root refers to a node the store doesn't have, which usually means a
+/// tree wasn't flushed.
+pub async fn compact<S: CompactStore>(
+    store: &S,
+    roots: &[NodeHash],
+    config: &CompactionConfig,
+) -> Result<CompactionReport, MerkleError> {
+    let retained = match config.retention {
+        Retention::All => roots,
+        Retention::Last(count) => &roots[roots.len().saturating_sub(count)..],
+    };
+    let batch_size = config.batch_size.max(1);
+
+    // Mark: walk the retained trees a level at a time
+    let mut reachable = HashSet::new();
+    let mut frontier: Vec<NodeHash> = retained
+        .iter()
+        .filter(|root| **root != EMPTY_HASH && reachable.insert(**root))
+        .copied()
+        .collect();
+    while !frontier.is_empty() {
+        let mut next = Vec::new();
+        for batch in frontier.chunks(batch_size) {
+            let nodes = store.get_nodes(batch).await?;
+            for (hash, node) in batch.iter().zip(nodes) {
+                match node {
+                    Some(SparseNode::Internal { left, right }) => {
+                        for child in [left, right] {
+                            if child != EMPTY_HASH && reachable.insert(child) {
+                                next.push(child);
+                            }
+                        }
+                    }
+                    Some(SparseNode::Leaf { .. }) => {}
+                    None => return Err(MerkleError::MissingNode(*hash)),
+                }
+            }
+        }
+        frontier = next;
+    }
+
+    // Sweep: delete everything unmarked, a batch at a time
+    let mut report = CompactionReport {
+        roots_kept: retained.len(),
+        ..CompactionReport::default()
+    };
+    let mut after = None;
+    loop {
+        let hashes = store.scan_hashes(after, batch_size).await?;
+        let Some(&last) = hashes.last() else {
+            break;
+        };
+        after = Some(last);
+
+        let garbage: Vec<NodeHash> = hashes
+            .iter()
+            .filter(|hash| !reachable.contains(*hash))
+            .copied()
+            .collect();
+        report.nodes_kept += (hashes.len() - garbage.len()) as u64;
+        report.nodes_deleted += garbage.len() as u64;
+        if !garbage.is_empty() {
+            store.delete_nodes(&garbage).await?;
+        }
+    }
+    Ok(report)
+}
+
+impl<S: CompactStore> SparseMerkleTree<S> {
+    /// Flushes the tree and compacts its store, always keeping the current
+    /// root
+    ///
+    /// `earlier_roots` are the tree's past roots, oldest first; the
+    /// retention counts the current root as the newest.
+    pub async fn compact(
+        &mut self,
+        earlier_roots: &[NodeHash],
+        config: &CompactionConfig,
+    ) -> Result<CompactionReport, MerkleError> {
+        self.flush().await?;
+
+        let mut roots = earlier_roots.to_vec();
+        roots.push(self.root_hash());
+        let config = CompactionConfig {
+            retention: match config.retention {
+                Retention::Last(count) => Retention::Last(count.max(1)),
+                retention => retention,
+            },
+            ..*config
+        };
+        compact(self.store(), &roots, &config).await
+    }
+}
@@ -0,0 +1,214 @@
+//! Optional zstd and lz4 compression for files this crate writes.
+//!
+//! Hashes themselves barely compress, but level exports, tree files and
+//! proof bundles repeat a lot around them: hex encodings, line prefixes and
+//! the upper-path hashes every proof in a claims file shares. Compressing
+//! with the `zstd` or `lz4` feature shrinks those considerably.
+//!
+//! Loading is transparent: [`MerkleTree::import_levels`] and
+//! [`ProofBundle::from_bytes`] recognize the zstd and lz4 frame magics and
+//! decompress before parsing. A tree file is borrowed by
+//! [`TreeView`](crate::TreeView), so decompress it with [`decompress`] first
+//! and view the result. Compressed input whose codec wasn't compiled in is
+//! rejected with a [`MerkleError::Parse`] naming the feature.
+//!
+//! A small frame can expand to gigabytes, so decompression stops with
+//! [`MerkleError::DecompressedTooLarge`] past
+//! [`DEFAULT_DECOMPRESSED_LIMIT`] bytes, or past the limit given to
+//! [`decompress_with_limit`].
+
+use crate::{ExportFormat, MerkleError, MerkleHasher, MerkleTree, ProofBundle};
+use std::borrow::Cow;
+use std::io::{BufRead, Read, Write};
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const LZ4_MAGIC: [u8; 4] = [0x04, 0x22, 0x4d, 0x18];
+
+/// Most bytes compressed input may expand to unless a limit is given
+pub const DEFAULT_DECOMPRESSED_LIMIT: usize = 1 << 30;
+
+/// How a file is compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    /// A zstd frame, needing the `zstd` feature
+    Zstd,
+    /// An lz4 frame, needing the `lz4` feature
+    Lz4,
+}
+
+impl Compression {
+    /// Works out from its first bytes how a file is compressed
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else if bytes.starts_with(&LZ4_MAGIC) {
+            Compression::Lz4
+        } else {
+            Compression::None
+        }
+    }
+}
+
+fn missing_feature(compression: Compression) -> String {
+    let feature = match compression {
+        Compression::Zstd => "zstd",
+        _ => "lz4",
+    };
+    format!(
+        "{:?} compression needs the `{}` feature",
+        compression, feature
+    )
+}
+
+/// Compresses `bytes` as a single frame
+pub fn compress(bytes: &[u8], compression: Compression) -> Result<Vec<u8>, MerkleError> {
+    let mut compressed = Vec::new();
+    write_compressed(&mut compressed, compression, |writer| {
+        Ok(writer.write_all(bytes)?)
+    })?;
+    Ok(compressed)
+}
+
+/// Decompresses `bytes` if they start with a zstd or lz4 frame, and
+/// borrows them unchanged otherwise
+///
+/// Fails past [`DEFAULT_DECOMPRESSED_LIMIT`] bytes of output.
+pub fn decompress(bytes: &[u8]) -> Result<Cow<'_, [u8]>, MerkleError> {
+    decompress_with_limit(bytes, DEFAULT_DECOMPRESSED_LIMIT)
+}
+
+/// Decompresses like [`decompress`], failing with
+/// [`MerkleError::DecompressedTooLarge`] past `limit` bytes of output
+pub fn decompress_with_limit(bytes: &[u8], limit: usize) -> Result<Cow<'_, [u8]>, MerkleError> {
+    if Compression::detect(bytes) == Compression::None {
+        return Ok(Cow::Borrowed(bytes));
+    }
+
+    let mut decompressed = Vec::new();
+    decompressing(bytes, limit)?.read_to_end(&mut decompressed)?;
+    Ok(Cow::Owned(decompressed))
+}
+
+/// Reads through a decoder, failing once it has produced more than `limit`
+/// bytes
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+struct Capped<R> {
+    inner: R,
+    remaining: usize,
+    limit: usize,
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+impl<R> Capped<R> {
+    fn new(inner: R, limit: usize) -> Self {
+        Capped {
+            inner,
+            remaining: limit,
+            limit,
+        }
+    }
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+impl<R: Read> Read for Capped<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Ask for one byte past the limit so reaching it exactly still succeeds
+        let wanted = buf.len().min(self.remaining.saturating_add(1));
+        let read = self.inner.read(&mut buf[..wanted])?;
+        if read > self.remaining {
+            return Err(std::io::Error::other(MerkleError::DecompressedTooLarge {
+                limit: self.limit,
+            }));
+        }
+        self.remaining -= read;
+        Ok(read)
+    }
+}
+
+/// Runs `write` against a writer that compresses into `writer`, then ends
+/// the frame
+pub(crate) fn write_compressed<W: Write>(
+    mut writer: W,
+    compression: Compression,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), MerkleError>,
+) -> Result<(), MerkleError> {
+    match compression {
+        Compression::None => write(&mut writer)?,
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(writer, 0)?;
+            write(&mut encoder)?;
+            encoder.finish()?.flush()?;
+        }
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(writer);
+            write(&mut encoder)?;
+            encoder
+                .finish()
+                .map_err(|e| MerkleError::Io(e.to_string()))?
+                .flush()?;
+        }
+        #[allow(unreachable_patterns)]
+        compression => return Err(MerkleError::Encode(missing_feature(compression))),
+    }
+    Ok(())
+}
+
+/// Wraps `reader` in a decoder if its input starts with a zstd or lz4
+/// frame, producing at most `limit` bytes
+#[cfg_attr(not(any(feature = "zstd", feature = "lz4")), allow(unused_variables))]
+pub(crate) fn decompressing<'a, R: BufRead + 'a>(
+    mut reader: R,
+    limit: usize,
+) -> Result<Box<dyn BufRead + 'a>, MerkleError> {
+    // Peeks without consuming; a first buffer shorter than a magic reads as uncompressed
+    let compression = Compression::detect(reader.fill_buf()?);
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(std::io::BufReader::new(Capped::new(
+            zstd::Decoder::with_buffer(reader)?,
+            limit,
+        ))),
+        #[cfg(feature = "lz4")]
+        Compression::Lz4 => Box::new(std::io::BufReader::new(Capped::new(
+            lz4_flex::frame::FrameDecoder::new(reader),
+            limit,
+        ))),
+        #[allow(unreachable_patterns)]
+        compression => return Err(MerkleError::Parse(missing_feature(compression))),
+    })
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Streams the levels like [`MerkleTree::export_levels`], compressed
+    pub fn export_levels_compressed<W: Write>(
+        &self,
+        writer: W,
+        format: ExportFormat,
+        compression: Compression,
+    ) -> Result<(), MerkleError> {
+        write_compressed(writer, compression, |writer| {
+            self.export_levels(writer, format)
+        })
+    }
+}
+
+impl ProofBundle {
+    /// Encodes the bundle as [`ProofBundle::to_text`] does, compressed
+    pub fn to_bytes(&self, compression: Compression) -> Result<Vec<u8>, MerkleError> {
+        compress(self.to_text()?.as_bytes(), compression)
+    }
+
+    /// Parses a bundle written by [`ProofBundle::to_bytes`] or
+    /// [`ProofBundle::to_text`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let text = decompress(bytes)?;
+        let text = std::str::from_utf8(&text)
+            .map_err(|_| MerkleError::Parse("proof bundle: not UTF-8".to_string()))?;
+        ProofBundle::parse(text)
+    }
+}
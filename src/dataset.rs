@@ -0,0 +1,141 @@
+//! Checking a whole dataset against a root.
+//!
+//! [`verify_dataset`] rebuilds the root from a stream with the pipelined
+//! builder, hashing as the given [`TreeConfig`] says, and compares it,
+//! which says whether the data matches but not where. When the original
+//! tree is at hand, [`MerkleTree::verify_dataset`] compares leaf hashes as
+//! the data streams in and names the first leaf that differs. Both hash on
+//! every core (or on the calling thread in WebAssembly builds) and hold
+//! only a few batches of the dataset at a time. The `_with_progress`
+//! variants report leaves hashed and bytes read to a [`Progress`].
+
+use crate::pipeline::pipelined_root_for;
+use crate::{MerkleError, MerkleHasher, MerkleTree, PipelineConfig, Progress, TreeConfig};
+use std::thread;
+
+/// Items compared per thread before the next batch is read
+const BATCH_PER_THREAD: usize = 4096;
+
+/// Returns the number of leaves in `data` if a tree hashed as `config`
+/// says over it has root `root_hash`
+pub fn verify_dataset<I>(root_hash: &[u8], data: I, config: &TreeConfig) -> Result<u64, MerkleError>
+where
+    I: IntoIterator<Item = Vec<u8>>,
+{
+    verify_dataset_with_progress(root_hash, data, config, &())
+}
+
+/// Checks a dataset like [`verify_dataset`], reporting to `progress`
+pub fn verify_dataset_with_progress<I>(
+    root_hash: &[u8],
+    data: I,
+    config: &TreeConfig,
+    progress: &dyn Progress,
+) -> Result<u64, MerkleError>
+where
+    I: IntoIterator<Item = Vec<u8>>,
+{
+    let mut leaves = 0;
+    let root = pipelined_root_for(
+        data.into_iter().inspect(|_| leaves += 1),
+        config,
+        &PipelineConfig::default(),
+        progress,
+    );
+
+    if root.as_deref() != Some(root_hash) {
+        return Err(MerkleError::DatasetMismatch {
+            index: None,
+            leaves,
+        });
+    }
+    Ok(leaves)
+}
+
+impl<H: MerkleHasher + Sync> MerkleTree<H> {
+    /// Checks that `data` is exactly the data the tree was built from
+    ///
+    /// Stops at the first leaf that differs. A dataset that is too short or
+    /// too long is reported at the first index where the two disagree on
+    /// whether a leaf exists.
+    pub fn verify_dataset<I>(&self, data: I) -> Result<(), MerkleError>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+    {
+        self.verify_dataset_with_progress(data, &())
+    }
+
+    /// Checks a dataset like [`MerkleTree::verify_dataset`], reporting to
+    /// `progress` after each batch
+    pub fn verify_dataset_with_progress<I>(
+        &self,
+        data: I,
+        progress: &dyn Progress,
+    ) -> Result<(), MerkleError>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+    {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let leaves = self.levels.first().map_or(&[][..], Vec::as_slice);
+        let mismatch = |index: usize| MerkleError::DatasetMismatch {
+            index: Some(index as u64),
+            leaves: self.len() as u64,
+        };
+
+        let mut data = data.into_iter();
+        let mut offset = 0;
+        loop {
+            let batch: Vec<Vec<u8>> = data.by_ref().take(threads * BATCH_PER_THREAD).collect();
+            if batch.is_empty() {
+                break;
+            }
+            progress.bytes_read(batch.iter().map(|item| item.len() as u64).sum());
+
+            // Leaves past the end of the tree are compared as missing
+            let overlap = leaves.len().saturating_sub(offset).min(batch.len());
+            let expected = &leaves[offset..offset + overlap];
+            let chunk_size = overlap.div_ceil(threads).max(1);
+            let differs = |(item, hash): (&Vec<u8>, &Vec<u8>)| self.config.hash_leaf(item) != *hash;
+            let first_difference = if cfg!(target_family = "wasm") {
+                batch[..overlap].iter().zip(expected).position(differs)
+            } else {
+                thread::scope(|scope| {
+                    let workers: Vec<_> = batch[..overlap]
+                        .chunks(chunk_size)
+                        .zip(expected.chunks(chunk_size))
+                        .enumerate()
+                        .map(|(chunk, (items, hashes))| {
+                            scope.spawn(move || {
+                                items
+                                    .iter()
+                                    .zip(hashes)
+                                    .position(differs)
+                                    .map(|position| chunk * chunk_size + position)
+                            })
+                        })
+                        .collect();
+
+                    // Workers are joined in order, so the first hit is the lowest
+                    workers
+                        .into_iter()
+                        .find_map(|worker| worker.join().expect("dataset worker panicked"))
+                })
+            };
+
+            progress.leaves_hashed(overlap as u64);
+
+            if let Some(position) = first_difference {
+                return Err(mismatch(offset + position));
+            }
+            if overlap < batch.len() {
+                return Err(mismatch(leaves.len()));
+            }
+            offset += batch.len();
+        }
+
+        if offset != leaves.len() {
+            return Err(mismatch(offset));
+        }
+        Ok(())
+    }
+}
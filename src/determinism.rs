@@ -0,0 +1,60 @@
+//! Checks that parallel construction can't change a root.
+//!
+//! The pipelined builder spreads leaves over worker threads in batches and
+//! folds the results back in turn order, so its root is fixed by the data
+//! alone. [`check_determinism`] holds it to that: it builds the root on the
+//! sequential path, then through the pipeline under each configuration it
+//! is given, and fails on the first that disagrees.
+//! [`MerkleTree::build_deterministic`] runs the check over a spread of
+//! worker counts and batch sizes, for reproducibility audits and CI.
+
+use crate::{hash_leaf, pipelined_root_with, MerkleError, MerkleTree, PipelineConfig};
+
+/// Returns a spread of pipeline configurations covering one and several
+/// workers and batches from a single leaf up to the default size
+pub fn determinism_configs() -> Vec<PipelineConfig> {
+    let default = PipelineConfig::default();
+    let mut configs = Vec::new();
+    for workers in [1, 3, default.workers.max(2)] {
+        for batch_size in [1, 64, default.batch_size] {
+            configs.push(PipelineConfig {
+                workers,
+                batch_size,
+                queue_depth: 1,
+            });
+        }
+    }
+    configs
+}
+
+/// Checks that every configuration in `configs` yields the sequential root
+/// over `data`, and returns that root
+///
+/// Fails with [`MerkleError::NondeterministicRoot`] naming the first
+/// configuration that disagrees.
+pub fn check_determinism(
+    data: &[Vec<u8>],
+    configs: &[PipelineConfig],
+) -> Result<Option<Vec<u8>>, MerkleError> {
+    let leaves = data.iter().map(|item| hash_leaf(item)).collect();
+    let root = MerkleTree::from_leaf_hashes(leaves).root_hash();
+
+    for config in configs {
+        if pipelined_root_with(data.iter().cloned(), config) != root {
+            return Err(MerkleError::NondeterministicRoot {
+                workers: config.workers,
+                batch_size: config.batch_size,
+            });
+        }
+    }
+    Ok(root)
+}
+
+impl MerkleTree {
+    /// Builds the tree sequentially after checking that the pipelined
+    /// builder computes the same root under [`determinism_configs`]
+    pub fn build_deterministic(data: Vec<Vec<u8>>) -> Result<Self, MerkleError> {
+        check_determinism(&data, &determinism_configs())?;
+        Ok(MerkleTree::new(data))
+    }
+}
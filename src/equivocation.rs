@@ -0,0 +1,273 @@
+//! Portable proof that a log showed two incompatible histories.
+//!
+//! A log equivocates when it signs tree heads that can't both belong to one
+//! append-only log. That shows up one of two ways:
+//!
+//! - a split view: two signed heads with the same size and different roots
+//! - a fork: the log's own consistency proof shows its later head extends a
+//!   root other than the one it signed for the earlier size
+//!
+//! [`EquivocationEvidence`] captures either with the log's signatures, so
+//! anyone holding the log's key can check it with
+//! [`EquivocationEvidence::verify`] without trusting whoever reported it.
+//! It travels as text, in sections separated by blank lines:
+//!
+//! ```text
+//! simple-merkle-tree equivocation v1
+//! split-view | fork
+//!
+//! <checkpoint body>
+//!
+//! — <log key id> <base64 signature>
+//!
+//! <checkpoint body>
+//!
+//! — <log key id> <base64 signature>
+//! ```
+//!
+//! A fork adds a last section: `prefix <base64 root>` on its first line,
+//! then the consistency proof hashes, one per line.
+
+use crate::{
+    base64, Cosignature, IncrementalProof, MerkleError, TreeHead, Verifier, WitnessedHead,
+};
+
+const HEADER: &str = "simple-merkle-tree equivocation v1";
+
+/// A tree head with the log's signature over it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedHead {
+    pub head: TreeHead,
+    pub signature: Cosignature,
+}
+
+impl SignedHead {
+    /// Takes `head` with the log's signature from among its cosignatures
+    ///
+    /// Returns `None` if `log` hasn't validly signed it.
+    pub fn from_witnessed(head: &WitnessedHead, log: &dyn Verifier) -> Option<Self> {
+        let signature = head
+            .cosignatures
+            .iter()
+            .find(|cosignature| cosignature.verify_log(&head.head, log))?;
+        Some(SignedHead {
+            head: head.head.clone(),
+            signature: signature.clone(),
+        })
+    }
+
+    fn check(&self, log: &dyn Verifier) -> Result<(), MerkleError> {
+        if !self.signature.verify_log(&self.head, log) {
+            return Err(MerkleError::InvalidSignature {
+                key_id: self.signature.key_id.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Signed heads that no honest log could have produced together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EquivocationEvidence {
+    /// Two heads for the same size with different roots
+    SplitView {
+        first: SignedHead,
+        second: SignedHead,
+    },
+    /// A later head whose consistency proof leads back to `prefix_root`,
+    /// not the root signed for the earlier size
+    Fork {
+        old: SignedHead,
+        new: SignedHead,
+        prefix_root: Vec<u8>,
+        proof: IncrementalProof,
+    },
+}
+
+fn invalid(message: &str) -> MerkleError {
+    MerkleError::InvalidEvidence(message.to_string())
+}
+
+impl EquivocationEvidence {
+    /// Returns evidence if `first` and `second` are both signed by `log`
+    /// for the same origin and size but commit to different roots
+    pub fn from_heads(
+        first: &WitnessedHead,
+        second: &WitnessedHead,
+        log: &dyn Verifier,
+    ) -> Option<Self> {
+        let evidence = EquivocationEvidence::SplitView {
+            first: SignedHead::from_witnessed(first, log)?,
+            second: SignedHead::from_witnessed(second, log)?,
+        };
+        evidence.verify(log).is_ok().then_some(evidence)
+    }
+
+    /// Returns evidence if `proof`, the log's consistency proof from `old`
+    /// to `new`, shows `new` extending a root other than `old`'s
+    ///
+    /// A failing proof from an old size that is a power of two proves
+    /// nothing on its own, as the old root is one of its inputs: `None` is
+    /// returned, as it is when either head isn't signed by `log` or the proof
+    /// doesn't lead to `new`'s root at all.
+    pub fn from_consistency(
+        old: &WitnessedHead,
+        new: &WitnessedHead,
+        proof: &IncrementalProof,
+        log: &dyn Verifier,
+    ) -> Option<Self> {
+        let evidence = EquivocationEvidence::Fork {
+            old: SignedHead::from_witnessed(old, log)?,
+            new: SignedHead::from_witnessed(new, log)?,
+            prefix_root: proof.implied_old_root(&new.head.root_hash)?,
+            proof: proof.clone(),
+        };
+        evidence.verify(log).is_ok().then_some(evidence)
+    }
+
+    /// Checks that the log behind `log` really signed both heads and that
+    /// they conflict
+    pub fn verify(&self, log: &dyn Verifier) -> Result<(), MerkleError> {
+        let (first, second) = self.heads();
+        first.check(log)?;
+        second.check(log)?;
+        if first.head.origin != second.head.origin {
+            return Err(invalid("heads are from different logs"));
+        }
+
+        match self {
+            EquivocationEvidence::SplitView { first, second } => {
+                if first.head.size != second.head.size {
+                    return Err(invalid("heads are for different sizes"));
+                }
+                if first.head.root_hash == second.head.root_hash {
+                    return Err(invalid("heads agree"));
+                }
+            }
+            EquivocationEvidence::Fork {
+                old,
+                new,
+                prefix_root,
+                proof,
+            } => {
+                if proof.old_size() != old.head.size || proof.new_size() != new.head.size {
+                    return Err(invalid("consistency proof is for other sizes"));
+                }
+                if old.head.size >= new.head.size {
+                    return Err(invalid("old head is not older"));
+                }
+                if !proof.verify(prefix_root, &new.head.root_hash) {
+                    return Err(invalid("consistency proof doesn't lead to the new root"));
+                }
+                if *prefix_root == old.head.root_hash {
+                    return Err(invalid("new head extends the old one"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the two conflicting heads, earlier first for a fork
+    pub fn heads(&self) -> (&SignedHead, &SignedHead) {
+        match self {
+            EquivocationEvidence::SplitView { first, second } => (first, second),
+            EquivocationEvidence::Fork { old, new, .. } => (old, new),
+        }
+    }
+
+    /// Encodes the evidence in the text format described in the module docs
+    ///
+    /// Fails if a key id is empty or contains whitespace.
+    pub fn to_text(&self) -> Result<String, MerkleError> {
+        let kind = match self {
+            EquivocationEvidence::SplitView { .. } => "split-view",
+            EquivocationEvidence::Fork { .. } => "fork",
+        };
+        let mut text = format!("{}\n{}\n", HEADER, kind);
+
+        let (first, second) = self.heads();
+        for signed in [first, second] {
+            let line = signed.signature.to_line().ok_or_else(|| {
+                MerkleError::Encode(format!(
+                    "key id {:?} can't be written to evidence",
+                    signed.signature.key_id
+                ))
+            })?;
+            text.push('\n');
+            text.push_str(&signed.head.to_checkpoint());
+            text.push_str(&format!("\n{}\n", line));
+        }
+
+        if let EquivocationEvidence::Fork {
+            prefix_root, proof, ..
+        } = self
+        {
+            text.push_str(&format!("\nprefix {}\n", base64::encode(prefix_root)));
+            for hash in proof.path() {
+                text.push_str(&base64::encode(hash));
+                text.push('\n');
+            }
+        }
+        Ok(text)
+    }
+
+    /// Parses evidence written by [`EquivocationEvidence::to_text`]
+    ///
+    /// Nothing is verified; call [`EquivocationEvidence::verify`] on the
+    /// result.
+    pub fn parse(text: &str) -> Result<Self, MerkleError> {
+        let error = |message: &str| MerkleError::Parse(format!("equivocation: {}", message));
+
+        let body = text
+            .strip_suffix('\n')
+            .ok_or_else(|| error("missing final newline"))?;
+        let sections: Vec<&str> = body.split("\n\n").collect();
+        let (header, heads, rest) = match sections[..] {
+            [header, first, first_sig, second, second_sig] => {
+                (header, [(first, first_sig), (second, second_sig)], None)
+            }
+            [header, first, first_sig, second, second_sig, fork] => (
+                header,
+                [(first, first_sig), (second, second_sig)],
+                Some(fork),
+            ),
+            _ => return Err(error("expected header, two heads and their signatures")),
+        };
+        let kind = match header.split_once('\n') {
+            Some((HEADER, kind)) => kind,
+            _ => return Err(error("not supported equivocation evidence")),
+        };
+
+        let signed = |(checkpoint, signature): (&str, &str)| {
+            let head = TreeHead::parse_checkpoint(&format!("{}\n", checkpoint))?;
+            let signature = Cosignature::parse_line(signature)
+                .ok_or_else(|| error("invalid signature line"))?;
+            Ok::<_, MerkleError>(SignedHead { head, signature })
+        };
+        let [first, second] = heads;
+        let (first, second) = (signed(first)?, signed(second)?);
+
+        match (kind, rest) {
+            ("split-view", None) => Ok(EquivocationEvidence::SplitView { first, second }),
+            ("fork", Some(fork)) => {
+                let mut lines = fork.split('\n');
+                let prefix_root = lines
+                    .next()
+                    .and_then(|line| line.strip_prefix("prefix "))
+                    .and_then(base64::decode)
+                    .ok_or_else(|| error("invalid prefix line"))?;
+                let path = lines
+                    .map(|line| base64::decode(line).ok_or_else(|| error("invalid hash")))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let proof = IncrementalProof::from_parts(first.head.size, second.head.size, path);
+                Ok(EquivocationEvidence::Fork {
+                    old: first,
+                    new: second,
+                    prefix_root,
+                    proof,
+                })
+            }
+            _ => Err(error("invalid kind line")),
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! Why a proof failed, in a form support tooling can read.
+//!
+//! [`MerkleProof::verify`] says `false` and nothing more.
+//! [`MerkleProof::verify_explain`] says what went wrong instead, as a
+//! [`VerifyFailure`] that prints as a sentence and serializes with
+//! [`VerifyFailure::to_json`] for logs and tickets.
+//!
+//! A root and the expected hasher's [`MerkleHasher::config_id`] only show
+//! whether a proof was hashed the same way, whether its path is intact and
+//! which root it leads to. An altered hash changes every hash above it, so
+//! where a path went wrong can't be told from the root. When the tree is
+//! at hand, [`MerkleTree::verify_proof_explain`] compares the path node by
+//! node and names the first level where it leaves the tree.
+
+use crate::{ConfigMismatch, MerkleHasher, MerkleProof, MerkleTree, Side};
+use std::error::Error;
+use std::fmt::{self, Write};
+
+/// What made a proof fail to verify
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyFailure {
+    /// The tree has no leaves to prove
+    EmptyTree,
+    /// The leaf hash is not as long as the root
+    LeafLength { expected: usize, actual: usize },
+    /// The sibling at `level`, counting from the leaf, is not as long as
+    /// the root
+    SiblingLength {
+        level: usize,
+        expected: usize,
+        actual: usize,
+    },
+    /// The proof was made by a hasher with another
+    /// [`MerkleHasher::config_id`], such as a different algorithm or domain
+    /// tag; `tree` is the id the tree, or the caller, expected
+    ConfigDiffers { tree: Vec<u8>, proof: Vec<u8> },
+    /// The proof has a different number of levels than the tree
+    Depth { expected: usize, actual: usize },
+    /// The sibling sides put the leaf past the end of the tree
+    Position { index: u64, tree_size: usize },
+    /// The leaf hash is not the tree's leaf at the proof's position
+    Leaf { expected: Vec<u8>, actual: Vec<u8> },
+    /// The sibling at `level` is not the tree's node there
+    Sibling {
+        level: usize,
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    /// Recomputing the path gave neither the root nor the proof's own root:
+    /// a hash was altered or the proof was hashed differently
+    ///
+    /// Which hash was altered can't be told without the tree;
+    /// [`MerkleTree::verify_proof_explain`] reports it as
+    /// [`VerifyFailure::Leaf`] or [`VerifyFailure::Sibling`] instead.
+    Diverged {
+        expected: Vec<u8>,
+        computed: Vec<u8>,
+    },
+    /// The path is intact but leads to the root the proof was made
+    /// against, not the one it was checked against: a stale proof or one
+    /// from another tree
+    OtherRoot { proof_root: Vec<u8>, root: Vec<u8> },
+}
+
+impl VerifyFailure {
+    /// Returns a short, stable name for the failure, the `reason` field of
+    /// its JSON
+    pub fn reason(&self) -> &'static str {
+        match self {
+            VerifyFailure::EmptyTree => "empty_tree",
+            VerifyFailure::LeafLength { .. } => "leaf_length",
+            VerifyFailure::SiblingLength { .. } => "sibling_length",
+            VerifyFailure::ConfigDiffers { .. } => "config_differs",
+            VerifyFailure::Depth { .. } => "depth",
+            VerifyFailure::Position { .. } => "position",
+            VerifyFailure::Leaf { .. } => "leaf",
+            VerifyFailure::Sibling { .. } => "sibling",
+            VerifyFailure::Diverged { .. } => "diverged",
+            VerifyFailure::OtherRoot { .. } => "other_root",
+        }
+    }
+
+    /// Returns the failure as a JSON object, its fields alongside `reason`
+    /// and hashes in hex
+    pub fn to_json(&self) -> String {
+        let mut out = format!("{{\"reason\":\"{}\"", self.reason());
+        let mut number = |name: &str, value: u64| {
+            let _ = write!(out, ",\"{}\":{}", name, value);
+        };
+        match self {
+            VerifyFailure::EmptyTree => {}
+            VerifyFailure::LeafLength { expected, actual } => {
+                number("expected", *expected as u64);
+                number("actual", *actual as u64);
+            }
+            VerifyFailure::SiblingLength {
+                level,
+                expected,
+                actual,
+            } => {
+                number("level", *level as u64);
+                number("expected", *expected as u64);
+                number("actual", *actual as u64);
+            }
+            VerifyFailure::Depth { expected, actual } => {
+                number("expected", *expected as u64);
+                number("actual", *actual as u64);
+            }
+            VerifyFailure::Position { index, tree_size } => {
+                number("index", *index);
+                number("tree_size", *tree_size as u64);
+            }
+            VerifyFailure::Sibling { level, .. } => {
+                number("level", *level as u64);
+            }
+            _ => {}
+        }
+
+        let hashes: &[(&str, &[u8])] = match self {
+            VerifyFailure::ConfigDiffers { tree, proof } => &[("tree", tree), ("proof", proof)],
+            VerifyFailure::Leaf { expected, actual }
+            | VerifyFailure::Sibling {
+                expected, actual, ..
+            } => &[("expected", expected), ("actual", actual)],
+            VerifyFailure::Diverged { expected, computed } => {
+                &[("expected", expected), ("computed", computed)]
+            }
+            VerifyFailure::OtherRoot { proof_root, root } => {
+                &[("proof_root", proof_root), ("root", root)]
+            }
+            _ => &[],
+        };
+        for (name, hash) in hashes {
+            let _ = write!(out, ",\"{}\":\"{}\"", name, hex::encode(hash));
+        }
+        out.push('}');
+        out
+    }
+}
+
+impl fmt::Display for VerifyFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyFailure::EmptyTree => write!(f, "tree has no leaves"),
+            VerifyFailure::LeafLength { expected, actual } => write!(
+                f,
+                "leaf hash is {} bytes but the root is {}",
+                actual, expected
+            ),
+            VerifyFailure::SiblingLength {
+                level,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "sibling at level {} is {} bytes but the root is {}",
+                level, actual, expected
+            ),
+            VerifyFailure::ConfigDiffers { tree, proof } => write!(
+                f,
+                "proof was made with hasher {} but the tree uses {}",
+                hex::encode(proof),
+                hex::encode(tree)
+            ),
+            VerifyFailure::Depth { expected, actual } => write!(
+                f,
+                "proof has {} levels but the tree has {}",
+                actual, expected
+            ),
+            VerifyFailure::Position { index, tree_size } => write!(
+                f,
+                "proof is for leaf {} but the tree has {} leaves",
+                index, tree_size
+            ),
+            VerifyFailure::Leaf { expected, actual } => write!(
+                f,
+                "leaf hash {} is not the tree's {}",
+                hex::encode(actual),
+                hex::encode(expected)
+            ),
+            VerifyFailure::Sibling {
+                level,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "sibling at level {} is {} but the tree has {}",
+                level,
+                hex::encode(actual),
+                hex::encode(expected)
+            ),
+            VerifyFailure::Diverged { expected, computed } => write!(
+                f,
+                "path recomputes to {}, expected {}",
+                hex::encode(computed),
+                hex::encode(expected)
+            ),
+            VerifyFailure::OtherRoot { proof_root, root } => write!(
+                f,
+                "proof leads to root {}, not {}",
+                hex::encode(proof_root),
+                hex::encode(root)
+            ),
+        }
+    }
+}
+
+impl Error for VerifyFailure {}
+
+/// The tree-side checks of [`MerkleTree::verify_proof_explain`] are those of
+/// [`MerkleTree::owns_proof`], so their failures carry over
+impl From<ConfigMismatch> for VerifyFailure {
+    fn from(mismatch: ConfigMismatch) -> Self {
+        match mismatch {
+            ConfigMismatch::EmptyTree => VerifyFailure::EmptyTree,
+            // Which hash has the wrong length isn't kept; the leaf is
+            // checked first
+            ConfigMismatch::HashLength { expected, actual } => {
+                VerifyFailure::LeafLength { expected, actual }
+            }
+            ConfigMismatch::ConfigId { tree, proof } => {
+                VerifyFailure::ConfigDiffers { tree, proof }
+            }
+            ConfigMismatch::Depth {
+                expected, actual, ..
+            } => VerifyFailure::Depth { expected, actual },
+            ConfigMismatch::Position { index, tree_size } => {
+                VerifyFailure::Position { index, tree_size }
+            }
+            ConfigMismatch::Root { tree, proof } => VerifyFailure::OtherRoot {
+                proof_root: proof,
+                root: tree,
+            },
+        }
+    }
+}
+
+impl<H: MerkleHasher> MerkleProof<H> {
+    /// Verifies the proof against `root_hash` like [`MerkleProof::verify`],
+    /// saying why it fails
+    ///
+    /// `config_id` is the [`MerkleHasher::config_id`] of the hasher the
+    /// proof should have been made with; a proof from another is
+    /// [`VerifyFailure::ConfigDiffers`]. Hashes of the wrong length are
+    /// reported next. Otherwise the path is recomputed: if it reaches the
+    /// proof's own root instead of `root_hash` the failure is
+    /// [`VerifyFailure::OtherRoot`], and if it reaches neither it is
+    /// [`VerifyFailure::Diverged`].
+    pub fn verify_explain(&self, root_hash: &[u8], config_id: &[u8]) -> Result<(), VerifyFailure> {
+        let proof_id = self.config.config_id();
+        if proof_id != config_id {
+            return Err(VerifyFailure::ConfigDiffers {
+                tree: config_id.to_vec(),
+                proof: proof_id,
+            });
+        }
+
+        let expected = root_hash.len();
+        if self.leaf_hash.len() != expected {
+            return Err(VerifyFailure::LeafLength {
+                expected,
+                actual: self.leaf_hash.len(),
+            });
+        }
+        for (level, (sibling, _)) in self.proof_hashes.iter().enumerate() {
+            if sibling.len() != expected {
+                return Err(VerifyFailure::SiblingLength {
+                    level,
+                    expected,
+                    actual: sibling.len(),
+                });
+            }
+        }
+
+        let computed = self
+            .proof_hashes
+            .iter()
+            .fold(self.leaf_hash.clone(), |current, (sibling, side)| {
+                parent(&self.config, &current, sibling, *side)
+            });
+        if computed == root_hash {
+            Ok(())
+        } else if computed == self.root_hash {
+            Err(VerifyFailure::OtherRoot {
+                proof_root: computed,
+                root: root_hash.to_vec(),
+            })
+        } else {
+            Err(VerifyFailure::Diverged {
+                expected: root_hash.to_vec(),
+                computed,
+            })
+        }
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Verifies a proof against this tree like [`MerkleTree::verify_proof`],
+    /// naming the first hash that differs from the tree's
+    ///
+    /// The hasher, depth and position are checked as by
+    /// [`MerkleTree::owns_proof`] before any hash is compared.
+    pub fn verify_proof_explain(&self, proof: &MerkleProof<H>) -> Result<(), VerifyFailure> {
+        let root = self.root_hash().ok_or(VerifyFailure::EmptyTree)?;
+
+        let mut position = self.proof_position(proof)? as usize;
+        let leaf = &self.levels[0][position];
+        if proof.leaf_hash != *leaf {
+            return Err(VerifyFailure::Leaf {
+                expected: leaf.clone(),
+                actual: proof.leaf_hash.clone(),
+            });
+        }
+        for (level, (sibling, _)) in proof.proof_hashes.iter().enumerate() {
+            let nodes = &self.levels[level];
+            let expected = match nodes.get(position ^ 1) {
+                Some(sibling) => sibling.clone(),
+                None => self.config.pad(&nodes[position]),
+            };
+            if *sibling != expected {
+                return Err(VerifyFailure::Sibling {
+                    level,
+                    expected,
+                    actual: sibling.clone(),
+                });
+            }
+            position /= 2;
+        }
+
+        // Every hash is the tree's, so this only fails if the tree itself is
+        // inconsistent
+        proof.verify_explain(&root, &self.config.config_id())
+    }
+}
+
+fn parent<H: MerkleHasher>(hasher: &H, current: &[u8], sibling: &[u8], side: Side) -> Vec<u8> {
+    match side {
+        Side::Left => hasher.hash_node(sibling, current),
+        Side::Right => hasher.hash_node(current, sibling),
+    }
+}
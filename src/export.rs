@@ -0,0 +1,308 @@
+//! Streaming export and import of every node in a tree.
+//!
+//! Two formats are supported:
+//!
+//! - [`ExportFormat::Lines`] writes a `smt-levels 3` header line, a
+//!   `domain <hex tag>` line for tagged trees, a `config <hex id>` line,
+//!   then one node per line as `<level> <index> <hex hash>`, leaves first.
+//! - [`ExportFormat::Json`] writes
+//!   `{"version":3,"domain":"<hex tag>","config":"<hex id>","levels":[["<hex>",...],...]}`
+//!   with the leaf level first and the root level last; `domain` is left
+//!   out for untagged trees.
+//!
+//! The config id is the tree's [`MerkleHasher::config_id`], so a tree
+//! exported with one hasher can't be imported as if it had another:
+//! [`MerkleTree::import_levels`] reads [`TreeConfig`] trees and
+//! [`MerkleTree::import_levels_with_hasher`] trees with any other hasher.
+//!
+//! Version 1 files, written before trees had domain tags, have no header
+//! or `version` field, and neither version 1 nor 2 records a config id.
+//! They still import, as trees hashed by the [`TreeConfig`] their domain
+//! tag, if any, gives; [`crate::migrate`] upgrades them.
+//!
+//! Both are written node by node, so exporting never holds more than one
+//! hash of output in memory. Importing JSON needs the `json` feature.
+//! [`MerkleTree::export_levels_compressed`] writes either format compressed.
+//!
+//! Importing checks the shape of the levels but does not recompute any
+//! hashes; run [`MerkleTree::audit`] before trusting an imported tree.
+
+use crate::compress::{decompressing, DEFAULT_DECOMPRESSED_LIMIT};
+use crate::{MerkleError, MerkleHasher, MerkleTree, TreeConfig};
+use std::io::{BufRead, Write};
+
+/// Version written by [`MerkleTree::export_levels`]
+pub(crate) const LEVELS_VERSION: u32 = 3;
+const LINES_HEADER: &str = "smt-levels";
+
+/// Text format used by [`MerkleTree::export_levels`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Lines,
+    Json,
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Streams every level's hashes to `writer`
+    pub fn export_levels<W: Write>(
+        &self,
+        mut writer: W,
+        format: ExportFormat,
+    ) -> Result<(), MerkleError> {
+        let domain = self.config.domain_tag();
+        let config_id = hex::encode(self.config.config_id());
+        match format {
+            ExportFormat::Lines => {
+                writeln!(writer, "{} {}", LINES_HEADER, LEVELS_VERSION)?;
+                if let Some(domain) = domain {
+                    writeln!(writer, "domain {}", hex::encode(domain))?;
+                }
+                writeln!(writer, "config {}", config_id)?;
+                for (level, nodes) in self.levels.iter().enumerate() {
+                    for (index, hash) in nodes.iter().enumerate() {
+                        writeln!(writer, "{} {} {}", level, index, hex::encode(hash))?;
+                    }
+                }
+            }
+            ExportFormat::Json => {
+                write!(writer, "{{\"version\":{},", LEVELS_VERSION)?;
+                if let Some(domain) = domain {
+                    write!(writer, "\"domain\":\"{}\",", hex::encode(domain))?;
+                }
+                write!(writer, "\"config\":\"{}\",\"levels\":[", config_id)?;
+                for (level, nodes) in self.levels.iter().enumerate() {
+                    if level > 0 {
+                        write!(writer, ",")?;
+                    }
+                    write!(writer, "[")?;
+                    for (index, hash) in nodes.iter().enumerate() {
+                        if index > 0 {
+                            write!(writer, ",")?;
+                        }
+                        write!(writer, "\"{}\"", hex::encode(hash))?;
+                    }
+                    write!(writer, "]")?;
+                }
+                writeln!(writer, "]}}")?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a tree written by [`MerkleTree::export_levels`] from a tree
+    /// hashed by `hasher`
+    ///
+    /// Fails with [`MerkleError::ConfigMismatch`] if the file was written
+    /// with a hasher of another [`MerkleHasher::config_id`]. Compressed
+    /// exports are decompressed on the way in, up to
+    /// [`DEFAULT_DECOMPRESSED_LIMIT`] bytes.
+    pub fn import_levels_with_hasher<R: BufRead>(
+        reader: R,
+        format: ExportFormat,
+        hasher: H,
+    ) -> Result<Self, MerkleError> {
+        read_levels(decompressing(reader, DEFAULT_DECOMPRESSED_LIMIT)?, format)?.into_tree(hasher)
+    }
+}
+
+impl MerkleTree {
+    /// Reads a tree written by [`MerkleTree::export_levels`], taking its
+    /// domain tag from the file
+    ///
+    /// Fails with [`MerkleError::ConfigMismatch`] if the file was written
+    /// by a tree with another hasher than a [`TreeConfig`]. Compressed
+    /// exports are decompressed on the way in, up to
+    /// [`DEFAULT_DECOMPRESSED_LIMIT`] bytes.
+    pub fn import_levels<R: BufRead>(reader: R, format: ExportFormat) -> Result<Self, MerkleError> {
+        let imported = read_levels(decompressing(reader, DEFAULT_DECOMPRESSED_LIMIT)?, format)?;
+        let config = TreeConfig::from_domain(imported.domain.clone());
+        imported.into_tree(config)
+    }
+}
+
+/// The contents of an export, with the format version it was in
+pub(crate) struct ImportedLevels {
+    pub version: u32,
+    pub domain: Option<Vec<u8>>,
+    /// Recorded from version 3 on
+    pub config_id: Option<Vec<u8>>,
+    pub levels: Vec<Vec<Vec<u8>>>,
+}
+
+impl ImportedLevels {
+    /// Makes the tree, checking that `hasher` is the one the file records
+    ///
+    /// Files without a config id were written by a [`TreeConfig`] tree in
+    /// the recorded domain.
+    pub fn into_tree<H: MerkleHasher>(self, hasher: H) -> Result<MerkleTree<H>, MerkleError> {
+        let config_id = match self.config_id {
+            Some(config_id) => config_id,
+            None => TreeConfig::from_domain(self.domain).config_id(),
+        };
+        if hasher.config_id() != config_id {
+            return Err(MerkleError::ConfigMismatch);
+        }
+        MerkleTree::from_levels(self.levels, hasher)
+    }
+}
+
+pub(crate) fn read_levels<R: BufRead>(
+    reader: R,
+    format: ExportFormat,
+) -> Result<ImportedLevels, MerkleError> {
+    let imported = match format {
+        ExportFormat::Lines => read_lines(reader)?,
+        ExportFormat::Json => read_json(reader)?,
+    };
+    if imported.version > LEVELS_VERSION {
+        return Err(MerkleError::Parse(format!(
+            "levels format version {} is newer than this crate supports",
+            imported.version
+        )));
+    }
+    Ok(imported)
+}
+
+fn read_lines<R: BufRead>(reader: R) -> Result<ImportedLevels, MerkleError> {
+    let mut version = 1;
+    let mut domain = None;
+    let mut config_id = None;
+    let mut levels: Vec<Vec<Vec<u8>>> = Vec::new();
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let error =
+            |message: &str| MerkleError::Parse(format!("line {}: {}", line_number + 1, message));
+
+        // The header, domain and config lines come before any node
+        if levels.is_empty() {
+            if let Some(number) = line.strip_prefix(LINES_HEADER) {
+                if version > 1 || line_number > 0 {
+                    return Err(error("unexpected header"));
+                }
+                version = number
+                    .trim()
+                    .parse()
+                    .map_err(|_| error("invalid version"))?;
+                continue;
+            }
+            if let Some(tag) = line.strip_prefix("domain ") {
+                if version < 2 || domain.is_some() || config_id.is_some() {
+                    return Err(error("unexpected domain line"));
+                }
+                domain = Some(hex::decode(tag.trim()).map_err(|_| error("invalid hex domain"))?);
+                continue;
+            }
+            if let Some(id) = line.strip_prefix("config ") {
+                if version < 3 || config_id.is_some() {
+                    return Err(error("unexpected config line"));
+                }
+                config_id = Some(hex::decode(id.trim()).map_err(|_| error("invalid hex config"))?);
+                continue;
+            }
+        }
+
+        let mut fields = line.split_whitespace();
+        let (Some(level), Some(index), Some(hash), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(error("expected `<level> <index> <hash>`"));
+        };
+
+        let level: usize = level.parse().map_err(|_| error("invalid level"))?;
+        let index: usize = index.parse().map_err(|_| error("invalid index"))?;
+        let hash = hex::decode(hash).map_err(|_| error("invalid hex hash"))?;
+
+        // Nodes have to arrive in export order: level by level, left to right
+        if level == levels.len() {
+            levels.push(Vec::new());
+        }
+        if level + 1 != levels.len() || index != levels[level].len() {
+            return Err(error("node out of order"));
+        }
+        levels[level].push(hash);
+    }
+
+    if version >= 3 && config_id.is_none() {
+        return Err(MerkleError::Parse("missing config line".to_string()));
+    }
+    Ok(ImportedLevels {
+        version,
+        domain,
+        config_id,
+        levels,
+    })
+}
+
+#[cfg(feature = "json")]
+fn read_json<R: BufRead>(reader: R) -> Result<ImportedLevels, MerkleError> {
+    let document: serde_json::Value =
+        serde_json::from_reader(reader).map_err(|e| MerkleError::Parse(e.to_string()))?;
+    let error = |message: &str| MerkleError::Parse(message.to_string());
+
+    let version = match document.get("version") {
+        None => 1,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| error("invalid `version`"))?,
+    };
+    let domain = match document.get("domain") {
+        None => None,
+        Some(domain) => {
+            let domain = domain
+                .as_str()
+                .ok_or_else(|| error("`domain` is not a string"))?;
+            Some(hex::decode(domain).map_err(|_| error("invalid hex domain"))?)
+        }
+    };
+    let config_id = match document.get("config") {
+        None if version >= 3 => return Err(error("missing `config`")),
+        None => None,
+        Some(config_id) => {
+            let config_id = config_id
+                .as_str()
+                .ok_or_else(|| error("`config` is not a string"))?;
+            Some(hex::decode(config_id).map_err(|_| error("invalid hex config"))?)
+        }
+    };
+
+    let levels = document
+        .get("levels")
+        .and_then(|levels| levels.as_array())
+        .ok_or_else(|| error("missing `levels` array"))?
+        .iter()
+        .map(|nodes| {
+            nodes
+                .as_array()
+                .ok_or_else(|| error("level is not an array"))?
+                .iter()
+                .map(|hash| {
+                    let hash = hash.as_str().ok_or_else(|| error("hash is not a string"))?;
+                    hex::decode(hash).map_err(|_| error("invalid hex hash"))
+                })
+                .collect()
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(ImportedLevels {
+        version,
+        domain,
+        config_id,
+        levels,
+    })
+}
+
+#[cfg(not(feature = "json"))]
+fn read_json<R: BufRead>(_reader: R) -> Result<ImportedLevels, MerkleError> {
+    Err(MerkleError::Parse(
+        "importing JSON needs the `json` feature".to_string(),
+    ))
+}
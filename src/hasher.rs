@@ -0,0 +1,594 @@
+//! The tree's hashing rules on their own.
+//!
+//! Storage engines that keep nodes in their own tables can use a
+//! [`NodeHasher`] to derive leaves, parents and roots exactly as
+//! [`MerkleTree`] does, domain tag included, without building one. The
+//! rules are: a leaf is the hash of its data, a parent is the hash of its
+//! two children in order, and the last node of an odd-length level (or a
+//! lone leaf) is combined with itself.
+//!
+//! For trees rebuilt at high frequency, such as one per block,
+//! [`NodeHasher::hash_leaves_into`] and [`NodeHasher::root_in_place`] work
+//! on a flat buffer of 32-byte hashes the caller allocates once and reuses.
+//!
+//! Other hashing schemes plug in through [`MerkleHasher`], which
+//! [`MerkleTree`] and [`MerkleProof`](crate::MerkleProof) are generic
+//! over. [`DigestHasher`] adapts any RustCrypto [`Digest`] and
+//! [`HmacHasher`] keys one; [`Prefixed`], [`SortedPairs`] and
+//! [`TruncatedOutput`] wrap another hasher to add leaf and node prefixes,
+//! order-independent pairs or shorter hashes. Modes combine by nesting
+//! rather than by constructor flags:
+//! `SortedPairs(Prefixed::rfc6962(DigestHasher::<Sha256>::new()))` hashes
+//! with Certificate Transparency's prefixes and order-independent pairs.
+//! [`MerkleTree::builder`] stacks the same adapters from runtime settings.
+//!
+//! Field-friendly hashes such as Poseidon are one more implementation of
+//! the trait. Structures whose hashing is fixed by an outside
+//! specification, such as the history tree or the Solana and BitTorrent
+//! models, keep their own.
+
+use crate::{domain_hasher, MerkleError, MerkleTree, Side, TreeConfig};
+use sha2::digest::core_api::BlockSizeUser;
+use sha2::digest::generic_array::GenericArray;
+use sha2::Digest;
+use std::marker::PhantomData;
+
+/// Length of every hash in a flat buffer
+const HASH_LEN: usize = 32;
+
+/// Hashes leaves and nodes the way a tree with a given [`TreeConfig`] does
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeHasher {
+    config: TreeConfig,
+}
+
+impl NodeHasher {
+    /// Creates a hasher following `config`
+    pub fn new(config: TreeConfig) -> Self {
+        NodeHasher { config }
+    }
+
+    /// Returns the configuration the hasher follows
+    pub fn config(&self) -> &TreeConfig {
+        &self.config
+    }
+
+    /// Hashes a data item into its leaf hash
+    pub fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        self.config.hash_leaf(data)
+    }
+
+    /// Hashes two sibling nodes into their parent
+    pub fn combine(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.config.hash_pair(left, right)
+    }
+
+    /// Hashes the parent of a node that has no sibling
+    pub fn promote(&self, node: &[u8]) -> Vec<u8> {
+        self.config.hash_pair(node, node)
+    }
+
+    /// Folds `leaf` up through its siblings, lowest first, and returns the
+    /// root they lead to
+    ///
+    /// Each [`Side`] says which side of the running hash the sibling sits
+    /// on; a node without a sibling is listed as its own right sibling.
+    pub fn fold_path<'a>(
+        &self,
+        leaf: &[u8],
+        siblings: impl IntoIterator<Item = (&'a [u8], Side)>,
+    ) -> Vec<u8> {
+        siblings
+            .into_iter()
+            .fold(leaf.to_vec(), |current, (sibling, side)| match side {
+                Side::Left => self.combine(sibling, &current),
+                Side::Right => self.combine(&current, sibling),
+            })
+    }
+
+    /// Hashes one level into the level above it
+    pub fn parent_level(&self, nodes: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        nodes
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => self.combine(left, right),
+                [node] => self.promote(node),
+                _ => unreachable!("chunks of two"),
+            })
+            .collect()
+    }
+
+    /// Writes the leaf hash of each item into consecutive 32-byte slots of
+    /// `out`
+    ///
+    /// Nothing is allocated, so one buffer can serve every build. Fails
+    /// with [`MerkleError::Encode`] unless `out` holds exactly one hash per
+    /// item.
+    pub fn hash_leaves_into<D: AsRef<[u8]>>(
+        &self,
+        data: &[D],
+        out: &mut [u8],
+    ) -> Result<(), MerkleError> {
+        if out.len() != data.len() * HASH_LEN {
+            return Err(MerkleError::Encode(format!(
+                "{} leaves need a {}-byte buffer, got {} bytes",
+                data.len(),
+                data.len() * HASH_LEN,
+                out.len()
+            )));
+        }
+
+        // Absorb the domain prefix once and start every leaf from a copy
+        let primed = domain_hasher(self.config.domain.as_deref());
+        for (item, slot) in data.iter().zip(out.chunks_exact_mut(HASH_LEN)) {
+            let mut hasher = primed.clone();
+            hasher.update(item.as_ref());
+            slot.copy_from_slice(&hasher.finalize());
+        }
+        Ok(())
+    }
+
+    /// Reduces a buffer of consecutive 32-byte leaf hashes to the tree's
+    /// root, overwriting the buffer as it goes
+    ///
+    /// Returns `None` if the buffer is empty or not a whole number of
+    /// hashes.
+    pub fn root_in_place(&self, hashes: &mut [u8]) -> Option<[u8; 32]> {
+        if hashes.is_empty() || !hashes.len().is_multiple_of(HASH_LEN) {
+            return None;
+        }
+
+        let primed = domain_hasher(self.config.domain.as_deref());
+        let mut len = hashes.len() / HASH_LEN;
+        // A single leaf is still paired with itself, so always hash at least once
+        loop {
+            for parent in 0..len.div_ceil(2) {
+                let left = parent * 2 * HASH_LEN;
+                let right = if parent * 2 + 1 < len {
+                    left + HASH_LEN
+                } else {
+                    left
+                };
+
+                let mut hasher = primed.clone();
+                hasher.update(&hashes[left..left + HASH_LEN]);
+                hasher.update(&hashes[right..right + HASH_LEN]);
+                // Parent slots trail the children they are read from
+                hashes[parent * HASH_LEN..(parent + 1) * HASH_LEN]
+                    .copy_from_slice(&hasher.finalize());
+            }
+
+            len = len.div_ceil(2);
+            if len == 1 {
+                break;
+            }
+        }
+
+        hashes[..HASH_LEN].try_into().ok()
+    }
+}
+
+impl From<TreeConfig> for NodeHasher {
+    fn from(config: TreeConfig) -> Self {
+        NodeHasher::new(config)
+    }
+}
+
+impl MerkleTree {
+    /// Returns a hasher that derives nodes exactly as this tree does
+    pub fn hasher(&self) -> NodeHasher {
+        NodeHasher::new(self.config.clone())
+    }
+}
+
+/// How a tree hashes its leaves and nodes
+///
+/// Implementations are cloned into every proof, so keep them cheap to
+/// clone.
+pub trait MerkleHasher: Clone {
+    /// Hashes a data item into its leaf hash
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8>;
+
+    /// Hashes two sibling nodes, left first, into their parent
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8>;
+
+    /// Returns the hash standing in for an empty subtree
+    fn empty_hash(&self) -> Vec<u8>;
+
+    /// Returns the sibling a node is hashed with when its level has none
+    /// for it
+    ///
+    /// By default the node is paired with a copy of itself.
+    fn pad(&self, node: &[u8]) -> Vec<u8> {
+        node.to_vec()
+    }
+
+    /// Hashes any number of children, in order, into their parent
+    ///
+    /// Trees with more than two children per node use this. By default
+    /// every child but the last is joined into the left input of
+    /// [`MerkleHasher::hash_node`], which hashes the concatenation of the
+    /// children for hashers that hash `left || right`; two children hash
+    /// exactly as `hash_node` does.
+    fn hash_children(&self, children: &[&[u8]]) -> Vec<u8> {
+        match children.split_last() {
+            Some((last, rest)) => self.hash_node(&rest.concat(), last),
+            None => self.empty_hash(),
+        }
+    }
+
+    /// Returns true if folding `leaf` up through `siblings`, lowest first,
+    /// reaches `root`
+    ///
+    /// Each [`Side`] says which side of the running hash the sibling sits
+    /// on. Override this when the hash allows a faster fold than one
+    /// [`MerkleHasher::hash_node`] call per level.
+    fn verify_path(&self, leaf: &[u8], siblings: &[(Vec<u8>, Side)], root: &[u8]) -> bool {
+        let folded = siblings
+            .iter()
+            .fold(leaf.to_vec(), |current, (sibling, side)| match side {
+                Side::Left => self.hash_node(sibling, &current),
+                Side::Right => self.hash_node(&current, sibling),
+            });
+        folded == root
+    }
+
+    /// Returns a fingerprint of how this hasher hashes
+    ///
+    /// It is a node hashed from a fixed leaf, that leaf's padding and the
+    /// empty hash, taken in both orders, so hashers that differ in
+    /// algorithm, domain tag, prefixes, padding or pair ordering get
+    /// different ids without having to be compared directly.
+    fn config_id(&self) -> Vec<u8> {
+        let probe = self.hash_leaf(CONFIG_PROBE);
+        let padded = self.hash_node(&probe, &self.pad(&probe));
+        let empty = self.empty_hash();
+        self.hash_node(
+            &self.hash_node(&padded, &empty),
+            &self.hash_node(&empty, &padded),
+        )
+    }
+
+    /// Returns the domain tag serialized trees record for this hasher
+    ///
+    /// Only a tagged [`TreeConfig`] has one. Formats store it so the tree
+    /// reads back in the same domain; which hasher wrote a file is told by
+    /// its [`MerkleHasher::config_id`] instead.
+    fn domain_tag(&self) -> Option<&[u8]> {
+        None
+    }
+}
+
+const CONFIG_PROBE: &[u8] = b"simple-merkle-tree config probe";
+
+impl MerkleHasher for TreeConfig {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        TreeConfig::hash_leaf(self, data)
+    }
+
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.hash_pair(left, right)
+    }
+
+    fn empty_hash(&self) -> Vec<u8> {
+        TreeConfig::hash_leaf(self, &[])
+    }
+
+    fn domain_tag(&self) -> Option<&[u8]> {
+        self.domain.as_deref()
+    }
+
+    /// Keeps the running hash in a stack buffer and copies a hasher primed
+    /// with the domain for each level, so nothing is allocated
+    fn verify_path(&self, leaf: &[u8], siblings: &[(Vec<u8>, Side)], root: &[u8]) -> bool {
+        let mut current_hash = [0u8; 32];
+        if leaf.len() != current_hash.len() {
+            return false;
+        }
+        current_hash.copy_from_slice(leaf);
+
+        let domain = domain_hasher(self.domain.as_deref());
+        for (sibling_hash, side) in siblings {
+            let mut hasher = domain.clone();
+            match side {
+                Side::Left => {
+                    hasher.update(sibling_hash);
+                    hasher.update(current_hash);
+                }
+                Side::Right => {
+                    hasher.update(current_hash);
+                    hasher.update(sibling_hash);
+                }
+            }
+
+            hasher.finalize_into(GenericArray::from_mut_slice(&mut current_hash));
+        }
+
+        current_hash[..] == *root
+    }
+}
+
+impl MerkleHasher for NodeHasher {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        self.config.hash_leaf(data)
+    }
+
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.config.hash_pair(left, right)
+    }
+
+    fn empty_hash(&self) -> Vec<u8> {
+        self.config.empty_hash()
+    }
+
+    fn verify_path(&self, leaf: &[u8], siblings: &[(Vec<u8>, Side)], root: &[u8]) -> bool {
+        self.config.verify_path(leaf, siblings, root)
+    }
+
+    fn domain_tag(&self) -> Option<&[u8]> {
+        self.config.domain.as_deref()
+    }
+}
+
+/// Hashes leaves as `D(data)` and nodes as `D(left || right)`
+pub struct DigestHasher<D>(PhantomData<fn() -> D>);
+
+impl<D> DigestHasher<D> {
+    pub fn new() -> Self {
+        DigestHasher(PhantomData)
+    }
+}
+
+impl<D> Default for DigestHasher<D> {
+    fn default() -> Self {
+        DigestHasher::new()
+    }
+}
+
+impl<D> Clone for DigestHasher<D> {
+    fn clone(&self) -> Self {
+        DigestHasher::new()
+    }
+}
+
+impl<D: Digest> MerkleHasher for DigestHasher<D> {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        D::digest(data).to_vec()
+    }
+
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        D::new()
+            .chain_update(left)
+            .chain_update(right)
+            .finalize()
+            .to_vec()
+    }
+
+    fn empty_hash(&self) -> Vec<u8> {
+        D::digest([]).to_vec()
+    }
+}
+
+/// Hashes leaves and nodes with HMAC under a secret key
+///
+/// Only holders of the key can build or check the tree, which keeps leaves
+/// drawn from a small space, such as account numbers, from being guessed
+/// from their hashes.
+pub struct HmacHasher<D> {
+    inner_pad: Vec<u8>,
+    outer_pad: Vec<u8>,
+    digest: PhantomData<fn() -> D>,
+}
+
+impl<D: Digest + BlockSizeUser> HmacHasher<D> {
+    /// Creates a hasher keyed with `key`, per RFC 2104
+    pub fn new(key: &[u8]) -> Self {
+        let block_size = D::block_size();
+        let mut block = if key.len() > block_size {
+            D::digest(key).to_vec()
+        } else {
+            key.to_vec()
+        };
+        block.resize(block_size, 0);
+
+        HmacHasher {
+            inner_pad: block.iter().map(|byte| byte ^ 0x36).collect(),
+            outer_pad: block.iter().map(|byte| byte ^ 0x5c).collect(),
+            digest: PhantomData,
+        }
+    }
+
+    fn mac(&self, parts: &[&[u8]]) -> Vec<u8> {
+        let mut inner = D::new().chain_update(&self.inner_pad);
+        for part in parts {
+            inner.update(part);
+        }
+        D::new()
+            .chain_update(&self.outer_pad)
+            .chain_update(inner.finalize())
+            .finalize()
+            .to_vec()
+    }
+}
+
+impl<D> Clone for HmacHasher<D> {
+    fn clone(&self) -> Self {
+        HmacHasher {
+            inner_pad: self.inner_pad.clone(),
+            outer_pad: self.outer_pad.clone(),
+            digest: PhantomData,
+        }
+    }
+}
+
+impl<D: Digest + BlockSizeUser> MerkleHasher for HmacHasher<D> {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        self.mac(&[data])
+    }
+
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.mac(&[left, right])
+    }
+
+    fn empty_hash(&self) -> Vec<u8> {
+        self.mac(&[])
+    }
+}
+
+/// Prepends a prefix to every leaf and node before hashing with `inner`
+///
+/// Both prefixed inputs go through the inner hasher's leaf hash, so with
+/// [`Prefixed::rfc6962`] around a [`DigestHasher`] a leaf is
+/// `D(0x00 || data)` and a node `D(0x01 || left || right)`, as in
+/// Certificate Transparency.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prefixed<H> {
+    pub inner: H,
+    pub leaf_prefix: Vec<u8>,
+    pub node_prefix: Vec<u8>,
+}
+
+impl<H> Prefixed<H> {
+    /// Uses the RFC 6962 prefixes, `0x00` for leaves and `0x01` for nodes
+    pub fn rfc6962(inner: H) -> Self {
+        Prefixed {
+            inner,
+            leaf_prefix: vec![0x00],
+            node_prefix: vec![0x01],
+        }
+    }
+}
+
+impl<H: MerkleHasher> MerkleHasher for Prefixed<H> {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        self.inner
+            .hash_leaf(&[&self.leaf_prefix[..], data].concat())
+    }
+
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.inner
+            .hash_leaf(&[&self.node_prefix[..], left, right].concat())
+    }
+
+    fn hash_children(&self, children: &[&[u8]]) -> Vec<u8> {
+        let mut input = self.node_prefix.clone();
+        input.extend(children.concat());
+        self.inner.hash_leaf(&input)
+    }
+
+    fn empty_hash(&self) -> Vec<u8> {
+        self.inner.empty_hash()
+    }
+
+    fn pad(&self, node: &[u8]) -> Vec<u8> {
+        self.inner.pad(node)
+    }
+}
+
+/// Orders each pair of children before hashing them with `inner`
+///
+/// A parent no longer depends on which side a child is on, so proofs need
+/// no side information, as with OpenZeppelin's `MerkleProof`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortedPairs<H>(pub H);
+
+impl<H: MerkleHasher> MerkleHasher for SortedPairs<H> {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        self.0.hash_leaf(data)
+    }
+
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        if left <= right {
+            self.0.hash_node(left, right)
+        } else {
+            self.0.hash_node(right, left)
+        }
+    }
+
+    fn hash_children(&self, children: &[&[u8]]) -> Vec<u8> {
+        let mut sorted = children.to_vec();
+        sorted.sort_unstable();
+        self.0.hash_children(&sorted)
+    }
+
+    fn empty_hash(&self) -> Vec<u8> {
+        self.0.empty_hash()
+    }
+
+    fn pad(&self, node: &[u8]) -> Vec<u8> {
+        self.0.pad(node)
+    }
+}
+
+/// Shortest hash length a [`TruncatedOutput`] may keep
+pub const MIN_HASH_LEN: usize = 16;
+
+/// Keeps only the first bytes of every hash from `inner`
+///
+/// Shorter hashes shrink stored levels and proofs at the cost of collision
+/// resistance: an `N`-byte hash offers about `4N` bits against collisions,
+/// so 16 bytes is 64 bits, within reach of a determined attacker. The
+/// length is part of the hasher, so every [`MerkleProof`](crate::MerkleProof)
+/// from a truncated tree carries it, and a proof made at another length has
+/// a different [`MerkleHasher::config_id`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruncatedOutput<H> {
+    inner: H,
+    len: usize,
+}
+
+impl<H: MerkleHasher> TruncatedOutput<H> {
+    /// Truncates the hashes of `inner` to `len` bytes
+    ///
+    /// Fails with [`MerkleError::InvalidLimit`] unless `len` is at least
+    /// [`MIN_HASH_LEN`] and no longer than the hashes `inner` makes.
+    pub fn new(inner: H, len: usize) -> Result<Self, MerkleError> {
+        let available = inner.empty_hash().len();
+        if len < MIN_HASH_LEN || len > available {
+            return Err(MerkleError::InvalidLimit(format!(
+                "can't truncate {}-byte hashes to {} bytes; keep at least {} and at most all",
+                available, len, MIN_HASH_LEN
+            )));
+        }
+        Ok(TruncatedOutput { inner, len })
+    }
+
+    /// Returns the hasher whose output is truncated
+    pub fn inner(&self) -> &H {
+        &self.inner
+    }
+
+    /// Returns the number of bytes kept from each hash
+    pub fn output_len(&self) -> usize {
+        self.len
+    }
+}
+
+impl<H: MerkleHasher> MerkleHasher for TruncatedOutput<H> {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        self.truncate(self.inner.hash_leaf(data))
+    }
+
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.truncate(self.inner.hash_node(left, right))
+    }
+
+    fn hash_children(&self, children: &[&[u8]]) -> Vec<u8> {
+        self.truncate(self.inner.hash_children(children))
+    }
+
+    fn empty_hash(&self) -> Vec<u8> {
+        self.truncate(self.inner.empty_hash())
+    }
+
+    fn pad(&self, node: &[u8]) -> Vec<u8> {
+        self.truncate(self.inner.pad(node))
+    }
+}
+
+impl<H> TruncatedOutput<H> {
+    fn truncate(&self, mut hash: Vec<u8>) -> Vec<u8> {
+        hash.truncate(self.len);
+        hash
+    }
+}
@@ -0,0 +1,411 @@
+//! An append-only history tree with proofs against any past version.
+//!
+//! Version `n` of the tree commits to the first `n` leaves. The layout is
+//! the Crosby–Wallach history tree with empty right subtrees collapsed,
+//! which is exactly the RFC 6962 tree: the left child of every node covers
+//! the largest power of two strictly smaller than its leaf count. Leaves and
+//! nodes are domain separated as in RFC 6962, as `H(0x00 || data)` and
+//! `H(0x01 || left || right)`.
+//!
+//! Two kinds of proof are supported:
+//!
+//! - [`MembershipProof`]: leaf `i` was present when the tree had `n` leaves.
+//! - [`IncrementalProof`]: the version `m` root is a prefix of the version
+//!   `n` root, for any `m <= n`.
+
+use crate::HashAlgorithm;
+use sha2::{Digest, Sha256};
+
+/// Hashes a data item into an RFC 6962 leaf hash
+pub(crate) fn hash_history_leaf(data: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(data);
+    hasher.finalize().to_vec()
+}
+
+/// Hashes two child hashes into an RFC 6962 node hash
+pub(crate) fn hash_history_node(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().to_vec()
+}
+
+/// Returns the largest power of two strictly smaller than `n`, for `n >= 2`
+fn split_point(n: u64) -> u64 {
+    1 << (63 - (n - 1).leading_zeros())
+}
+
+/// An append-only Merkle tree that can prove statements about past versions
+#[derive(Debug, Clone, Default)]
+pub struct HistoryTree {
+    /// `levels[j][i]` is the root of the complete subtree over leaves
+    /// `i * 2^j .. (i + 1) * 2^j`; partial subtrees are derived on demand
+    levels: Vec<Vec<Vec<u8>>>,
+}
+
+impl HistoryTree {
+    /// Creates an empty history tree
+    pub fn new() -> Self {
+        HistoryTree { levels: Vec::new() }
+    }
+
+    /// Appends a data item and returns its leaf index
+    pub fn append(&mut self, data: &[u8]) -> u64 {
+        self.append_leaf_hash(hash_history_leaf(data))
+    }
+
+    /// Appends an already-hashed leaf and returns its leaf index
+    pub fn append_leaf_hash(&mut self, leaf_hash: Vec<u8>) -> u64 {
+        if self.levels.is_empty() {
+            self.levels.push(Vec::new());
+        }
+        self.levels[0].push(leaf_hash);
+        let index = self.len() - 1;
+
+        // Complete every subtree the new leaf finishes
+        let mut level = 0;
+        while self.levels[level].len().is_multiple_of(2) {
+            let nodes = &self.levels[level];
+            let parent = hash_history_node(&nodes[nodes.len() - 2], &nodes[nodes.len() - 1]);
+
+            if self.levels.len() == level + 1 {
+                self.levels.push(Vec::new());
+            }
+            self.levels[level + 1].push(parent);
+            level += 1;
+        }
+
+        index
+    }
+
+    /// Returns the number of leaves, which is also the current version
+    pub fn len(&self) -> u64 {
+        self.levels.first().map_or(0, |leaves| leaves.len() as u64)
+    }
+
+    /// Returns true if nothing has been appended yet
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the hash of the leaf at `index`
+    pub fn leaf_hash(&self, index: u64) -> Option<&[u8]> {
+        self.levels
+            .first()?
+            .get(usize::try_from(index).ok()?)
+            .map(Vec::as_slice)
+    }
+
+    /// Returns the current root hash
+    pub fn root_hash(&self) -> Vec<u8> {
+        self.subtree_hash(0, self.len())
+    }
+
+    /// Returns the root hash the tree had when it held `size` leaves
+    pub fn root_at(&self, size: u64) -> Option<Vec<u8>> {
+        if size > self.len() {
+            return None;
+        }
+
+        Some(self.subtree_hash(0, size))
+    }
+
+    /// Proves that leaf `index` was in the tree when it held `size` leaves
+    pub fn prove_membership(&self, index: u64, size: u64) -> Option<MembershipProof> {
+        if index >= size || size > self.len() {
+            return None;
+        }
+
+        let mut path = Vec::new();
+        self.build_path(index, 0, size, &mut path);
+
+        Some(MembershipProof {
+            index,
+            tree_size: size,
+            leaf_hash: self.leaf_hash(index)?.to_vec(),
+            path,
+        })
+    }
+
+    /// Proves that the version `old_size` root is a prefix of the version
+    /// `new_size` root
+    pub fn prove_incremental(&self, old_size: u64, new_size: u64) -> Option<IncrementalProof> {
+        if old_size > new_size || new_size > self.len() {
+            return None;
+        }
+
+        let mut path = Vec::new();
+        if old_size > 0 {
+            self.build_subproof(old_size, 0, new_size, true, &mut path);
+        }
+
+        Some(IncrementalProof {
+            old_size,
+            new_size,
+            path,
+        })
+    }
+
+    /// Returns the root of the subtree over leaves `start..end`
+    fn subtree_hash(&self, start: u64, end: u64) -> Vec<u8> {
+        let n = end - start;
+        if n == 0 {
+            return Sha256::digest([]).to_vec();
+        }
+
+        // Complete, aligned subtrees are already stored
+        if n.is_power_of_two() && start.is_multiple_of(n) {
+            let level = n.trailing_zeros() as usize;
+            return self.levels[level][(start >> level) as usize].clone();
+        }
+
+        let k = split_point(n);
+        hash_history_node(
+            &self.subtree_hash(start, start + k),
+            &self.subtree_hash(start + k, end),
+        )
+    }
+
+    /// Collects the audit path for `index` within leaves `start..end`
+    fn build_path(&self, index: u64, start: u64, end: u64, path: &mut Vec<Vec<u8>>) {
+        let n = end - start;
+        if n <= 1 {
+            return;
+        }
+
+        let k = split_point(n);
+        if index - start < k {
+            self.build_path(index, start, start + k, path);
+            path.push(self.subtree_hash(start + k, end));
+        } else {
+            self.build_path(index, start + k, end, path);
+            path.push(self.subtree_hash(start, start + k));
+        }
+    }
+
+    /// Collects the consistency path between the first `m` leaves of
+    /// `start..end` and the whole range
+    fn build_subproof(
+        &self,
+        m: u64,
+        start: u64,
+        end: u64,
+        complete: bool,
+        path: &mut Vec<Vec<u8>>,
+    ) {
+        let n = end - start;
+        if m == n {
+            if !complete {
+                path.push(self.subtree_hash(start, end));
+            }
+            return;
+        }
+
+        let k = split_point(n);
+        if m <= k {
+            self.build_subproof(m, start, start + k, complete, path);
+            path.push(self.subtree_hash(start + k, end));
+        } else {
+            self.build_subproof(m - k, start + k, end, false, path);
+            path.push(self.subtree_hash(start, start + k));
+        }
+    }
+}
+
+/// Right-shifts both values until the low bit of `first` is set or it is zero
+fn shift_while_even(first: &mut u64, second: &mut u64) {
+    while *first != 0 && *first & 1 == 0 {
+        *first >>= 1;
+        *second >>= 1;
+    }
+}
+
+/// A proof that a leaf was present in a particular version of a [`HistoryTree`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MembershipProof {
+    index: u64,
+    tree_size: u64,
+    leaf_hash: Vec<u8>,
+    path: Vec<Vec<u8>>,
+}
+
+impl MembershipProof {
+    /// Assembles a proof read back from storage
+    pub(crate) fn from_parts(
+        index: u64,
+        tree_size: u64,
+        leaf_hash: Vec<u8>,
+        path: Vec<Vec<u8>>,
+    ) -> Self {
+        MembershipProof {
+            index,
+            tree_size,
+            leaf_hash,
+            path,
+        }
+    }
+
+    /// Returns the index of the proven leaf
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Returns the hash the proof was computed with; history trees always
+    /// hash with SHA-256
+    pub fn hash_algorithm(&self) -> HashAlgorithm {
+        HashAlgorithm::Sha256
+    }
+
+    /// Returns the version (tree size) the proof is for
+    pub fn tree_size(&self) -> u64 {
+        self.tree_size
+    }
+
+    /// Returns the hash of the proven leaf
+    pub fn leaf_hash(&self) -> &[u8] {
+        &self.leaf_hash
+    }
+
+    /// Returns the sibling hashes from the leaf up
+    pub fn path(&self) -> &[Vec<u8>] {
+        &self.path
+    }
+
+    /// Verifies the proof against the root of version `tree_size`
+    pub fn verify(&self, root_hash: &[u8]) -> bool {
+        if self.index >= self.tree_size {
+            return false;
+        }
+
+        let mut position = self.index;
+        let mut last = self.tree_size - 1;
+        let mut current_hash = self.leaf_hash.clone();
+
+        for sibling in &self.path {
+            if last == 0 {
+                return false;
+            }
+
+            if position & 1 == 1 || position == last {
+                current_hash = hash_history_node(sibling, &current_hash);
+                shift_while_even(&mut position, &mut last);
+            } else {
+                current_hash = hash_history_node(&current_hash, sibling);
+            }
+
+            position >>= 1;
+            last >>= 1;
+        }
+
+        last == 0 && current_hash == root_hash
+    }
+}
+
+/// A proof that one version of a [`HistoryTree`] extends an earlier one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncrementalProof {
+    old_size: u64,
+    new_size: u64,
+    path: Vec<Vec<u8>>,
+}
+
+impl IncrementalProof {
+    /// Assembles a proof read back from storage
+    pub(crate) fn from_parts(old_size: u64, new_size: u64, path: Vec<Vec<u8>>) -> Self {
+        IncrementalProof {
+            old_size,
+            new_size,
+            path,
+        }
+    }
+
+    /// Returns the earlier version
+    pub fn old_size(&self) -> u64 {
+        self.old_size
+    }
+
+    /// Returns the later version
+    pub fn new_size(&self) -> u64 {
+        self.new_size
+    }
+
+    /// Returns the proof hashes
+    pub fn path(&self) -> &[Vec<u8>] {
+        &self.path
+    }
+
+    /// Verifies that `new_root` extends `old_root`
+    pub fn verify(&self, old_root: &[u8], new_root: &[u8]) -> bool {
+        if self.old_size > self.new_size {
+            return false;
+        }
+        if self.old_size == self.new_size {
+            return self.path.is_empty() && old_root == new_root;
+        }
+        // Every tree extends the empty tree
+        if self.old_size == 0 {
+            return self.path.is_empty();
+        }
+
+        self.fold(old_root)
+            .is_some_and(|(old_hash, new_hash)| old_hash == old_root && new_hash == new_root)
+    }
+
+    /// Returns the earlier root the proof shows `new_root` extends, without
+    /// being told it
+    ///
+    /// Only a proof from an old size that isn't a power of two carries that
+    /// root; otherwise the old root is an input to the proof and `None` is
+    /// returned, as it is when the proof doesn't lead to `new_root`.
+    pub fn implied_old_root(&self, new_root: &[u8]) -> Option<Vec<u8>> {
+        if self.old_size == 0 || self.old_size >= self.new_size || self.old_size.is_power_of_two() {
+            return None;
+        }
+
+        let (old_hash, new_hash) = self.fold(&[])?;
+        (new_hash == new_root).then_some(old_hash)
+    }
+
+    /// Recomputes the old and new roots from the path, for
+    /// `0 < old_size < new_size`
+    fn fold(&self, old_root: &[u8]) -> Option<(Vec<u8>, Vec<u8>)> {
+        let mut path = self.path.iter().map(Vec::as_slice);
+        let seed = if self.old_size.is_power_of_two() {
+            old_root
+        } else {
+            path.next()?
+        };
+
+        let mut position = self.old_size - 1;
+        let mut last = self.new_size - 1;
+        while position & 1 == 1 {
+            position >>= 1;
+            last >>= 1;
+        }
+
+        let mut old_hash = seed.to_vec();
+        let mut new_hash = seed.to_vec();
+
+        for sibling in path {
+            if last == 0 {
+                return None;
+            }
+
+            if position & 1 == 1 || position == last {
+                old_hash = hash_history_node(sibling, &old_hash);
+                new_hash = hash_history_node(sibling, &new_hash);
+                shift_while_even(&mut position, &mut last);
+            } else {
+                new_hash = hash_history_node(&new_hash, sibling);
+            }
+
+            position >>= 1;
+            last >>= 1;
+        }
+
+        (last == 0).then_some((old_hash, new_hash))
+    }
+}
@@ -0,0 +1,106 @@
+//! Proofs whose directions come from the leaf index.
+//!
+//! A [`MerkleProof`] stores a [`Side`] with every sibling. An
+//! [`IndexedProof`] stores the leaf index instead: bit `i` of the index is
+//! set when the node at level `i` is a right child, so its sibling is on
+//! the left. This is the layout most on-chain verifiers and circuits take.
+//! The two convert losslessly for any proof a [`MerkleTree`] generates.
+//!
+//! [`MerkleTree`]: crate::MerkleTree
+
+use crate::{MerkleError, MerkleHasher, MerkleProof, Side, TreeConfig};
+
+/// A proof with its sibling sides implied by `index`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedProof {
+    /// Position of the leaf in its tree
+    pub index: u64,
+    pub leaf_hash: Vec<u8>,
+    /// Sibling hashes from the leaf up
+    pub siblings: Vec<Vec<u8>>,
+    pub root_hash: Vec<u8>,
+    /// Domain tag of the tree, as in [`MerkleProof::domain`]
+    pub domain: Option<Vec<u8>>,
+}
+
+impl IndexedProof {
+    /// Returns the side each sibling sits on, from the leaf up
+    pub fn sides(&self) -> impl Iterator<Item = Side> + '_ {
+        (0..self.siblings.len() as u32)
+            .map(|level| Side::of_sibling(self.index.checked_shr(level).unwrap_or(0)))
+    }
+
+    /// Converts to a proof with an explicit side for every sibling
+    ///
+    /// Fails if `index` has bits set above the proof's depth.
+    pub fn to_proof(&self) -> Result<MerkleProof, MerkleError> {
+        let depth = self.siblings.len() as u32;
+        if depth < u64::BITS && self.index >> depth != 0 {
+            return Err(MerkleError::InvalidProof);
+        }
+
+        Ok(MerkleProof {
+            proof_hashes: self.siblings.iter().cloned().zip(self.sides()).collect(),
+            leaf_hash: self.leaf_hash.clone(),
+            root_hash: self.root_hash.clone(),
+            config: TreeConfig::from_domain(self.domain.clone()),
+        })
+    }
+
+    /// Verifies the proof against the given root hash
+    pub fn verify(&self, root_hash: &[u8]) -> bool {
+        self.to_proof().is_ok_and(|proof| proof.verify(root_hash))
+    }
+}
+
+impl<H: MerkleHasher> MerkleProof<H> {
+    /// Converts to a proof that records the leaf index instead of sides
+    ///
+    /// Fails if the proof is deeper than 64 levels, leaving sides the index
+    /// can't hold, and with [`MerkleError::ConfigMismatch`] unless it hashes
+    /// as a [`TreeConfig`] does, since only the domain tag is kept.
+    pub fn to_indexed(&self) -> Result<IndexedProof, MerkleError> {
+        if TreeConfig::matching(self.merkle_hasher()).is_none() {
+            return Err(MerkleError::ConfigMismatch);
+        }
+        if self.proof_hashes.len() > u64::BITS as usize {
+            return Err(MerkleError::InvalidProof);
+        }
+
+        let index = self
+            .proof_hashes
+            .iter()
+            .enumerate()
+            .fold(0, |index, (level, (_, side))| {
+                index | (u64::from(*side == Side::Left) << level)
+            });
+
+        Ok(IndexedProof {
+            index,
+            leaf_hash: self.leaf_hash.clone(),
+            siblings: self
+                .proof_hashes
+                .iter()
+                .map(|(hash, _)| hash.clone())
+                .collect(),
+            root_hash: self.root_hash.clone(),
+            domain: self.domain().map(<[u8]>::to_vec),
+        })
+    }
+}
+
+impl<H: MerkleHasher> TryFrom<&MerkleProof<H>> for IndexedProof {
+    type Error = MerkleError;
+
+    fn try_from(proof: &MerkleProof<H>) -> Result<Self, MerkleError> {
+        proof.to_indexed()
+    }
+}
+
+impl TryFrom<&IndexedProof> for MerkleProof {
+    type Error = MerkleError;
+
+    fn try_from(proof: &IndexedProof) -> Result<Self, MerkleError> {
+        proof.to_proof()
+    }
+}
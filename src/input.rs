@@ -0,0 +1,215 @@
+//! Building trees straight from CSV and JSON exports.
+//!
+//! Every loader reads one value per record, runs it through a
+//! [`Canonicalize`] step and hashes the resulting UTF-8 text as a leaf, so
+//! two exports that only differ in formatting produce the same root. The
+//! CSV loader needs the `csv` feature and the JSON loaders need `json`.
+
+#[cfg(any(feature = "csv", feature = "json"))]
+use crate::{MerkleError, MerkleTree};
+#[cfg(any(feature = "csv", feature = "json"))]
+use std::path::Path;
+
+/// Normalisation applied to each value before it is hashed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Canonicalize {
+    /// Strip leading and trailing whitespace
+    pub trim: bool,
+    /// Lowercase the value
+    pub lowercase: bool,
+    /// Rewrite plain decimal numbers in a single form, e.g. `+007.50` to `7.5`
+    pub normalize_numbers: bool,
+}
+
+impl Canonicalize {
+    /// Applies every enabled rule to `value`
+    pub fn apply(&self, value: &str) -> String {
+        let mut value = if self.trim { value.trim() } else { value }.to_string();
+
+        if self.lowercase {
+            value = value.to_lowercase();
+        }
+
+        if self.normalize_numbers {
+            if let Some(number) = normalize_number(&value) {
+                value = number;
+            }
+        }
+
+        value
+    }
+}
+
+/// Rewrites a plain decimal number without redundant signs or zeros
+fn normalize_number(value: &str) -> Option<String> {
+    let (negative, digits) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if integer.is_empty() && fraction.is_empty() {
+        return None;
+    }
+    if !integer
+        .bytes()
+        .chain(fraction.bytes())
+        .all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let integer = integer.trim_start_matches('0');
+    let fraction = fraction.trim_end_matches('0');
+
+    let mut number = String::new();
+    // Negative zero is still zero
+    if negative && !(integer.is_empty() && fraction.is_empty()) {
+        number.push('-');
+    }
+    number.push_str(if integer.is_empty() { "0" } else { integer });
+    if !fraction.is_empty() {
+        number.push('.');
+        number.push_str(fraction);
+    }
+
+    Some(number)
+}
+
+/// Which CSV column supplies the leaf values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    /// Zero-based column position
+    Index(usize),
+    /// Column name from the header row
+    Name(String),
+}
+
+impl From<usize> for Column {
+    fn from(index: usize) -> Self {
+        Column::Index(index)
+    }
+}
+
+impl From<&str> for Column {
+    fn from(name: &str) -> Self {
+        Column::Name(name.to_string())
+    }
+}
+
+#[cfg(feature = "csv")]
+impl MerkleTree {
+    /// Builds a tree from one column of a CSV file
+    ///
+    /// The first row is always treated as a header and is not hashed.
+    pub fn from_csv(
+        path: impl AsRef<Path>,
+        column: impl Into<Column>,
+        canonicalize: &Canonicalize,
+    ) -> Result<Self, MerkleError> {
+        let parse_error = |e: csv::Error| MerkleError::Parse(e.to_string());
+        let mut reader = csv::Reader::from_path(path).map_err(parse_error)?;
+
+        let position = match column.into() {
+            Column::Index(index) => index,
+            Column::Name(name) => reader
+                .headers()
+                .map_err(parse_error)?
+                .iter()
+                .position(|header| header == name)
+                .ok_or_else(|| MerkleError::Parse(format!("no column named {:?}", name)))?,
+        };
+
+        let mut data = Vec::new();
+        for (row, record) in reader.records().enumerate() {
+            let record = record.map_err(parse_error)?;
+            let value = record.get(position).ok_or_else(|| {
+                MerkleError::Parse(format!("row {} has no column {}", row + 1, position))
+            })?;
+
+            data.push(canonicalize.apply(value).into_bytes());
+        }
+
+        Ok(MerkleTree::new(data))
+    }
+}
+
+#[cfg(feature = "json")]
+impl MerkleTree {
+    /// Builds a tree from a file holding a single JSON array
+    ///
+    /// `pointer` is a JSON pointer such as `/address` selecting the value
+    /// inside each element; `None` uses the whole element.
+    pub fn from_json_array(
+        path: impl AsRef<Path>,
+        pointer: Option<&str>,
+        canonicalize: &Canonicalize,
+    ) -> Result<Self, MerkleError> {
+        let file = std::fs::File::open(path)?;
+        let elements: Vec<serde_json::Value> =
+            serde_json::from_reader(std::io::BufReader::new(file))
+                .map_err(|e| MerkleError::Parse(e.to_string()))?;
+
+        let data = elements
+            .iter()
+            .enumerate()
+            .map(|(index, element)| json_leaf(element, pointer, index, canonicalize))
+            .collect::<Result<_, _>>()?;
+
+        Ok(MerkleTree::new(data))
+    }
+
+    /// Builds a tree from newline-delimited JSON, one record per line
+    ///
+    /// Blank lines are skipped. `pointer` works as in [`MerkleTree::from_json_array`].
+    pub fn from_ndjson(
+        path: impl AsRef<Path>,
+        pointer: Option<&str>,
+        canonicalize: &Canonicalize,
+    ) -> Result<Self, MerkleError> {
+        use std::io::BufRead;
+
+        let file = std::fs::File::open(path)?;
+        let mut data = Vec::new();
+
+        for (line_number, line) in std::io::BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let record: serde_json::Value = serde_json::from_str(&line)
+                .map_err(|e| MerkleError::Parse(format!("line {}: {}", line_number + 1, e)))?;
+            data.push(json_leaf(&record, pointer, line_number, canonicalize)?);
+        }
+
+        Ok(MerkleTree::new(data))
+    }
+}
+
+/// Extracts and canonicalizes the leaf value from one JSON record
+#[cfg(feature = "json")]
+fn json_leaf(
+    record: &serde_json::Value,
+    pointer: Option<&str>,
+    position: usize,
+    canonicalize: &Canonicalize,
+) -> Result<Vec<u8>, MerkleError> {
+    let value = match pointer {
+        Some(pointer) => record.pointer(pointer).ok_or_else(|| {
+            MerkleError::Parse(format!(
+                "record {} has no value at {}",
+                position + 1,
+                pointer
+            ))
+        })?,
+        None => record,
+    };
+
+    let text = match value {
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    };
+
+    Ok(canonicalize.apply(&text).into_bytes())
+}
@@ -0,0 +1,178 @@
+//! Joining two trees under a common parent.
+//!
+//! A join commits to `H(left_root || right_root)`, so proofs from either
+//! side only need one more sibling to verify against the joined root. Both
+//! sides must share a hasher, a [`TreeConfig`] unless another
+//! [`MerkleHasher`] is given, which the parent is hashed with.
+
+use crate::{MerkleError, MerkleHasher, MerkleProof, MerkleTree, Side, TreeConfig};
+
+/// One side of a [`JoinedTree`]
+pub enum Subtree<H: MerkleHasher = TreeConfig> {
+    Tree(MerkleTree<H>),
+    Joined(Box<JoinedTree<H>>),
+}
+
+impl<H: MerkleHasher> Subtree<H> {
+    /// Returns the root hash of this side
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        match self {
+            Subtree::Tree(tree) => tree.root_hash(),
+            Subtree::Joined(joined) => Some(joined.root_hash().to_vec()),
+        }
+    }
+
+    /// Returns the number of leaves on this side
+    pub fn len(&self) -> usize {
+        match self {
+            Subtree::Tree(tree) => tree.len(),
+            Subtree::Joined(joined) => joined.len(),
+        }
+    }
+
+    /// Returns true if this side has no leaves
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the configuration this side was hashed with
+    pub fn config(&self) -> &H {
+        match self {
+            Subtree::Tree(tree) => tree.merkle_hasher(),
+            Subtree::Joined(joined) => &joined.config,
+        }
+    }
+
+    fn generate_proof(&self, data: &[u8]) -> Option<MerkleProof<H>> {
+        match self {
+            Subtree::Tree(tree) => tree.generate_proof(data),
+            Subtree::Joined(joined) => joined.generate_proof(data),
+        }
+    }
+
+    fn generate_proof_at(&self, index: usize) -> Option<MerkleProof<H>> {
+        match self {
+            Subtree::Tree(tree) => tree.generate_proof_at(index),
+            Subtree::Joined(joined) => joined.generate_proof_at(index),
+        }
+    }
+}
+
+impl<H: MerkleHasher> From<MerkleTree<H>> for Subtree<H> {
+    fn from(tree: MerkleTree<H>) -> Self {
+        Subtree::Tree(tree)
+    }
+}
+
+impl<H: MerkleHasher> From<JoinedTree<H>> for Subtree<H> {
+    fn from(joined: JoinedTree<H>) -> Self {
+        Subtree::Joined(Box::new(joined))
+    }
+}
+
+/// Two trees committed to under a single parent node
+///
+/// Leaves are numbered left to right across both sides, so index 0 is the
+/// first leaf of `left` and `left.len()` is the first leaf of `right`.
+pub struct JoinedTree<H: MerkleHasher = TreeConfig> {
+    left: Subtree<H>,
+    right: Subtree<H>,
+    root_hash: Vec<u8>,
+    config: H,
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Joins two trees (or earlier joins) under a new parent node
+    ///
+    /// Fails with [`MerkleError::ConfigMismatch`] if the sides were hashed
+    /// with hashers of different [`MerkleHasher::config_id`]s.
+    pub fn join(
+        left: impl Into<Subtree<H>>,
+        right: impl Into<Subtree<H>>,
+    ) -> Result<JoinedTree<H>, MerkleError> {
+        let left = left.into();
+        let right = right.into();
+        let (Some(left_root), Some(right_root)) = (left.root_hash(), right.root_hash()) else {
+            return Err(MerkleError::EmptyTree);
+        };
+        if left.config().config_id() != right.config().config_id() {
+            return Err(MerkleError::ConfigMismatch);
+        }
+
+        let config = left.config().clone();
+        Ok(JoinedTree {
+            root_hash: config.hash_node(&left_root, &right_root),
+            left,
+            right,
+            config,
+        })
+    }
+}
+
+impl<H: MerkleHasher> JoinedTree<H> {
+    /// Returns the joined root hash
+    pub fn root_hash(&self) -> &[u8] {
+        &self.root_hash
+    }
+
+    /// Returns the joined root hash as a hex string
+    pub fn root_hash_hex(&self) -> String {
+        hex::encode(&self.root_hash)
+    }
+
+    /// Returns the number of leaves across both sides
+    pub fn len(&self) -> usize {
+        self.left.len() + self.right.len()
+    }
+
+    /// Always false, since both sides of a join hold at least one leaf
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Returns the left and right sides of the join
+    pub fn parts(&self) -> (&Subtree<H>, &Subtree<H>) {
+        (&self.left, &self.right)
+    }
+
+    /// Generates a proof for data on either side that verifies against the joined root
+    pub fn generate_proof(&self, data: &[u8]) -> Option<MerkleProof<H>> {
+        if let Some(proof) = self.left.generate_proof(data) {
+            return Some(self.extend_left(proof));
+        }
+
+        self.right
+            .generate_proof(data)
+            .map(|proof| self.extend_right(proof))
+    }
+
+    /// Generates a proof for the leaf at `index`, counting across both sides
+    pub fn generate_proof_at(&self, index: usize) -> Option<MerkleProof<H>> {
+        let left_len = self.left.len();
+        if index < left_len {
+            return self
+                .left
+                .generate_proof_at(index)
+                .map(|proof| self.extend_left(proof));
+        }
+
+        self.right
+            .generate_proof_at(index - left_len)
+            .map(|proof| self.extend_right(proof))
+    }
+
+    /// Verifies a proof against the joined root
+    pub fn verify_proof(&self, proof: &MerkleProof<H>) -> bool {
+        proof.verify(&self.root_hash)
+    }
+
+    fn extend_left(&self, proof: MerkleProof<H>) -> MerkleProof<H> {
+        let sibling = self.right.root_hash().unwrap();
+        proof.extend(sibling, Side::Right, self.root_hash.clone())
+    }
+
+    fn extend_right(&self, proof: MerkleProof<H>) -> MerkleProof<H> {
+        let sibling = self.left.root_hash().unwrap();
+        proof.extend(sibling, Side::Left, self.root_hash.clone())
+    }
+}
@@ -0,0 +1,1165 @@
+//! Merkle trees over SHA-256.
+//!
+//! Everything except [`verify_proof_in_place`], the borrowed views
+//! ([`ManifestView`], [`TreeView`]) and the plain enums needs the default
+//! `std` feature. Without it the crate is `no_std`, does not allocate and
+//! can verify proofs on bare-metal targets.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use core::fmt;
+use sha2::digest::generic_array::GenericArray;
+use sha2::{Digest, Sha256};
+#[cfg(feature = "std")]
+use std::error::Error;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+#[cfg(feature = "std")]
+pub mod accumulator;
+#[cfg(feature = "anchor")]
+pub mod anchor;
+#[cfg(feature = "arena")]
+pub mod arena;
+#[cfg(feature = "arkworks")]
+pub mod arkworks;
+#[cfg(feature = "std")]
+pub mod audit;
+#[cfg(feature = "std")]
+pub mod backend;
+#[cfg(feature = "std")]
+mod base64;
+#[cfg(feature = "bittorrent")]
+pub mod bittorrent;
+#[cfg(feature = "std")]
+pub mod bound;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod bundle;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "canonical")]
+pub mod canonical;
+#[cfg(feature = "std")]
+pub mod circuit;
+#[cfg(feature = "std")]
+pub mod compact;
+#[cfg(feature = "std")]
+pub mod compress;
+#[cfg(feature = "std")]
+pub mod dataset;
+#[cfg(feature = "std")]
+pub mod determinism;
+#[cfg(feature = "std")]
+pub mod equivocation;
+#[cfg(feature = "std")]
+pub mod explain;
+#[cfg(feature = "std")]
+pub mod export;
+#[cfg(feature = "std")]
+pub mod hasher;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(feature = "std")]
+pub mod indexed;
+#[cfg(feature = "std")]
+pub mod input;
+#[cfg(feature = "std")]
+pub mod join;
+#[cfg(feature = "std")]
+pub mod limits;
+pub mod manifest;
+#[cfg(feature = "std")]
+pub mod map;
+#[cfg(feature = "std")]
+pub mod migrate;
+#[cfg(feature = "openzeppelin")]
+pub mod openzeppelin;
+#[cfg(feature = "std")]
+pub mod ownership;
+#[cfg(feature = "std")]
+pub mod packed;
+#[cfg(feature = "parallel")]
+pub mod parallel;
+#[cfg(feature = "std")]
+pub mod pipeline;
+#[cfg(feature = "std")]
+pub mod policy;
+#[cfg(feature = "std")]
+pub mod progress;
+#[cfg(feature = "std")]
+pub mod quorum;
+#[cfg(feature = "std")]
+pub mod redact;
+#[cfg(feature = "std")]
+pub mod rolling;
+#[cfg(feature = "std")]
+pub mod roots;
+#[cfg(feature = "selftest")]
+pub mod selftest;
+#[cfg(feature = "std")]
+pub mod shard;
+#[cfg(feature = "std")]
+pub mod shuffle;
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[cfg(feature = "solana")]
+pub mod solana;
+#[cfg(feature = "std")]
+pub mod sparse;
+#[cfg(feature = "std")]
+pub mod subtree;
+#[cfg(feature = "std")]
+pub mod sync;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "std")]
+pub mod timestamp;
+#[cfg(feature = "std")]
+pub mod tombstone;
+#[cfg(feature = "std")]
+pub mod typed;
+#[cfg(feature = "std")]
+pub mod utreexo;
+#[cfg(feature = "std")]
+pub mod vectors;
+pub mod view;
+#[cfg(feature = "std")]
+pub mod watch;
+#[cfg(feature = "std")]
+pub mod wide;
+#[cfg(feature = "std")]
+pub mod witness;
+
+#[cfg(feature = "std")]
+pub use accumulator::{Accumulator, AsyncAccumulator};
+#[cfg(feature = "std")]
+pub use audit::AuditIssue;
+#[cfg(feature = "std")]
+pub use backend::{active_backend, HashBackend};
+#[cfg(feature = "std")]
+pub use bound::BoundProof;
+#[cfg(feature = "std")]
+pub use builder::{BuilderError, BuiltHasher, MerkleTreeBuilder, Padding};
+#[cfg(feature = "std")]
+pub use bundle::ProofBundle;
+#[cfg(feature = "std")]
+pub use cache::{CacheLimit, CacheStats, ProofCache};
+#[cfg(feature = "std")]
+pub use circuit::{CircuitConfig, CircuitWitness, Endianness, FieldEncoding};
+#[cfg(feature = "std")]
+pub use compact::{compact, CompactStore, CompactionConfig, CompactionReport, Retention};
+#[cfg(feature = "std")]
+pub use compress::{compress, decompress, decompress_with_limit, Compression};
+#[cfg(feature = "std")]
+pub use dataset::{verify_dataset, verify_dataset_with_progress};
+#[cfg(feature = "std")]
+pub use determinism::{check_determinism, determinism_configs};
+#[cfg(feature = "std")]
+pub use equivocation::{EquivocationEvidence, SignedHead};
+#[cfg(feature = "std")]
+pub use explain::VerifyFailure;
+#[cfg(feature = "std")]
+pub use export::ExportFormat;
+#[cfg(feature = "std")]
+pub use hasher::{
+    DigestHasher, HmacHasher, MerkleHasher, NodeHasher, Prefixed, SortedPairs, TruncatedOutput,
+};
+#[cfg(feature = "std")]
+pub use history::{HistoryTree, IncrementalProof, MembershipProof};
+#[cfg(feature = "std")]
+pub use indexed::IndexedProof;
+#[cfg(feature = "std")]
+pub use input::{Canonicalize, Column};
+#[cfg(feature = "std")]
+pub use join::{JoinedTree, Subtree};
+#[cfg(feature = "std")]
+pub use limits::{LimitsConfig, RateLimiter, RequestGuard};
+#[cfg(feature = "std")]
+pub use manifest::FirmwareManifest;
+pub use manifest::ManifestView;
+#[cfg(feature = "std")]
+pub use map::{AuthenticatedMap, Mutation};
+#[cfg(feature = "std")]
+pub use migrate::{detect_format, migrate, FormatInfo, Migration};
+#[cfg(feature = "std")]
+pub use ownership::ConfigMismatch;
+#[cfg(feature = "std")]
+pub use packed::PackedProofs;
+#[cfg(feature = "std")]
+pub use pipeline::{
+    pipelined_root, pipelined_root_with, pipelined_root_with_progress, PipelineConfig,
+};
+#[cfg(feature = "std")]
+pub use policy::VerifyPolicy;
+#[cfg(feature = "std")]
+pub use progress::Progress;
+#[cfg(feature = "std")]
+pub use quorum::{Quorum, QuorumReport, ReportedRoot};
+#[cfg(feature = "std")]
+pub use redact::{Disclosure, RedactedTree};
+#[cfg(feature = "std")]
+pub use rolling::{RollingTree, Window};
+#[cfg(feature = "std")]
+pub use roots::{RootHistory, RootRecord};
+#[cfg(feature = "std")]
+pub use shard::{ComposedProof, ShardedTree};
+#[cfg(feature = "std")]
+pub use shuffle::ShuffledTree;
+#[cfg(feature = "std")]
+pub use snapshot::{ChangeKind, PathChange, Snapshot, SnapshotEntry};
+#[cfg(feature = "std")]
+pub use sparse::{MemoryStore, NodeStore, SparseMerkleTree, SparseProof};
+#[cfg(feature = "std")]
+pub use subtree::SubtreeProof;
+#[cfg(feature = "std")]
+pub use sync::SyncChunk;
+#[cfg(feature = "std")]
+pub use timestamp::{TimestampAuthority, TimestampToken, TimestampVerifier};
+#[cfg(feature = "std")]
+pub use tombstone::TOMBSTONE;
+#[cfg(feature = "std")]
+pub use typed::{LeafEncode, TypedMerkleTree, TypedProof};
+#[cfg(feature = "std")]
+pub use utreexo::{BatchProof, Forest, Stump};
+#[cfg(feature = "std")]
+pub use vectors::{TestVector, VectorMode};
+pub use view::TreeView;
+#[cfg(feature = "std")]
+pub use watch::{TreeUpdate, Watched};
+#[cfg(feature = "std")]
+pub use wide::{Arity, WideMerkleTree, WideProof, WideProofStep};
+#[cfg(feature = "std")]
+pub use witness::{Cosignature, Signer, TreeHead, Verifier, WitnessedHead};
+
+/// Hashes a data item into a leaf hash
+#[cfg(feature = "std")]
+fn hash_leaf(data: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(data);
+    hasher.finalize().to_vec()
+}
+
+/// Builds every level of the tree bottom-up from the leaf hashes
+#[cfg(feature = "std")]
+fn build_levels(leaves: Vec<Vec<u8>>) -> Vec<Vec<Vec<u8>>> {
+    build_levels_reporting(leaves, &TreeConfig::default(), &())
+}
+
+/// Builds every level like [`build_levels`] with `hasher`, reporting each
+/// one above the leaves to `progress` as it is finished
+#[cfg(feature = "std")]
+fn build_levels_reporting<H: MerkleHasher>(
+    leaves: Vec<Vec<u8>>,
+    hasher: &H,
+    progress: &dyn Progress,
+) -> Vec<Vec<Vec<u8>>> {
+    if leaves.is_empty() {
+        return Vec::new();
+    }
+
+    let mut levels = vec![leaves];
+
+    // A single leaf is still padded, so always hash at least once
+    while levels.len() == 1 || levels.last().unwrap().len() > 1 {
+        let nodes = levels.last().unwrap();
+        let next_level = nodes
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => hasher.hash_node(left, right),
+                [node] => hasher.hash_node(node, &hasher.pad(node)),
+                _ => unreachable!("chunks of two"),
+            })
+            .collect::<Vec<_>>();
+
+        progress.level_built(levels.len(), next_level.len());
+        levels.push(next_level);
+    }
+
+    levels
+}
+
+/// Errors returned by fallible tree operations
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MerkleError {
+    /// A leaf index was past the end of the tree
+    IndexOutOfBounds { index: usize, len: usize },
+    /// The operation needs a tree with at least one leaf
+    EmptyTree,
+    /// Reading input failed
+    Io(String),
+    /// Input could not be parsed
+    Parse(String),
+    /// A value could not be encoded
+    Encode(String),
+    /// Too few trusted witnesses signed a tree head
+    InsufficientCosignatures { valid: usize, required: usize },
+    /// A test vector disagrees with what this crate computes
+    VectorMismatch(String),
+    /// A randomized self-test found a disagreement
+    SelfTestFailed { seed: u64, reason: String },
+    /// A node store did not have a node the tree refers to
+    MissingNode([u8; 32]),
+    /// A proof was made for a different tree size than the root it was
+    /// checked against
+    StaleProof { proof_size: u64, root_size: u64 },
+    /// A proof does not lead to the root it was checked against
+    InvalidProof,
+    /// A client has used up its request budget for now
+    RateLimited { retry_after: Duration },
+    /// A request asked for more than a configured limit allows
+    RequestTooLarge {
+        what: &'static str,
+        size: usize,
+        limit: usize,
+    },
+    /// A fixed-size tree has no free leaf slots left
+    TreeFull { capacity: u64 },
+    /// A write raced with an earlier one to the same leaf
+    LeafModified { index: u32 },
+    /// A dataset does not match the tree or root it was checked against;
+    /// `index` is the first differing leaf when it can be known
+    DatasetMismatch { index: Option<u64>, leaves: u64 },
+    /// A hash was not the length the tree is configured for
+    InvalidHashLength { expected: usize, actual: usize },
+    /// Trees that must hash alike were built with different configurations
+    ConfigMismatch,
+    /// The pipelined builder disagreed with the sequential one under the
+    /// given configuration
+    NondeterministicRoot { workers: usize, batch_size: usize },
+    /// A signature that must be valid, such as a log's over its tree head,
+    /// is not
+    InvalidSignature { key_id: String },
+    /// A proof was sound but fell short of a [`VerifyPolicy`]
+    PolicyViolation(String),
+    /// A file can't be brought up to the current format version
+    CannotMigrate(String),
+    /// Too few independent sources reported the same root
+    NoQuorum { agreeing: usize, required: usize },
+    /// Evidence of log misbehavior doesn't show what it claims to
+    InvalidEvidence(String),
+    /// Building a tree would take more memory than it was allowed
+    BudgetExceeded { needed: usize, budget: usize },
+    /// A configured limit is outside the range it can take
+    InvalidLimit(String),
+    /// Compressed input expands to more than the limit it was read with
+    DecompressedTooLarge { limit: usize },
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MerkleError::IndexOutOfBounds { index, len } => {
+                write!(f, "leaf index {} out of bounds for tree of {} leaves", index, len)
+            }
+            MerkleError::EmptyTree => write!(f, "tree has no leaves"),
+            MerkleError::Io(message) => write!(f, "I/O error: {}", message),
+            MerkleError::Parse(message) => write!(f, "parse error: {}", message),
+            MerkleError::Encode(message) => write!(f, "encoding error: {}", message),
+            MerkleError::InsufficientCosignatures { valid, required } => write!(
+                f,
+                "{} valid witness cosignatures, {} required",
+                valid, required
+            ),
+            MerkleError::VectorMismatch(message) => write!(f, "vector mismatch: {}", message),
+            MerkleError::SelfTestFailed { seed, reason } => {
+                write!(f, "self-test failed for seed {}: {}", seed, reason)
+            }
+            MerkleError::MissingNode(hash) => {
+                write!(f, "node {} is missing from the store", hex::encode(hash))
+            }
+            MerkleError::StaleProof {
+                proof_size,
+                root_size,
+            } => write!(
+                f,
+                "proof is for a tree of {} leaves but the root is for {}",
+                proof_size, root_size
+            ),
+            MerkleError::InvalidProof => write!(f, "proof does not match the root"),
+            MerkleError::RateLimited { retry_after } => {
+                write!(f, "rate limited, retry after {:?}", retry_after)
+            }
+            MerkleError::RequestTooLarge { what, size, limit } => {
+                write!(f, "{} {} exceeds the limit of {}", what, size, limit)
+            }
+            MerkleError::TreeFull { capacity } => {
+                write!(f, "tree is full at {} leaves", capacity)
+            }
+            MerkleError::LeafModified { index } => {
+                write!(f, "leaf {} was modified since the proof was made", index)
+            }
+            MerkleError::DatasetMismatch {
+                index: Some(index), ..
+            } => write!(f, "dataset differs from the tree at leaf {}", index),
+            MerkleError::DatasetMismatch {
+                index: None,
+                leaves,
+            } => write!(f, "dataset of {} leaves does not match the root", leaves),
+            MerkleError::InvalidHashLength { expected, actual } => {
+                write!(f, "expected a {}-byte hash, got {} bytes", expected, actual)
+            }
+            MerkleError::ConfigMismatch => {
+                write!(f, "trees were built with different configurations")
+            }
+            MerkleError::NondeterministicRoot {
+                workers,
+                batch_size,
+            } => write!(
+                f,
+                "pipelined root with {} workers and batches of {} differs from the sequential root",
+                workers, batch_size
+            ),
+            MerkleError::InvalidSignature { key_id } => {
+                write!(f, "signature by {} is not valid", key_id)
+            }
+            MerkleError::PolicyViolation(message) => {
+                write!(f, "rejected by verification policy: {}", message)
+            }
+            MerkleError::CannotMigrate(message) => write!(f, "cannot migrate: {}", message),
+            MerkleError::NoQuorum { agreeing, required } => write!(
+                f,
+                "{} sources agree on the root, {} required",
+                agreeing, required
+            ),
+            MerkleError::InvalidEvidence(message) => {
+                write!(f, "not evidence of equivocation: {}", message)
+            }
+            MerkleError::BudgetExceeded { needed, budget } => write!(
+                f,
+                "building the tree needs about {} bytes but the budget is {}; compute just \
+                 the root in bounded memory with pipelined_root, or serve proofs from a tree \
+                 file with TreeView",
+                needed, budget
+            ),
+            MerkleError::InvalidLimit(message) => write!(f, "invalid limit: {}", message),
+            MerkleError::DecompressedTooLarge { limit } => {
+                write!(f, "input decompresses to more than {} bytes", limit)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for MerkleError {}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for MerkleError {
+    fn from(error: io::Error) -> Self {
+        // Readers such as the decompression cap fail with a crate error inside
+        match error.get_ref().and_then(|inner| inner.downcast_ref::<MerkleError>()) {
+            Some(inner) => inner.clone(),
+            None => MerkleError::Io(error.to_string()),
+        }
+    }
+}
+
+/// Hash function a proof was computed with, as a [`VerifyPolicy`] names it
+///
+/// Trees built from a [`TreeConfig`] always hash with SHA-256.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    /// Keccak-256, as hashed by the OpenZeppelin and Solana trees
+    Keccak256,
+}
+
+/// Settings that determine how a tree derives its hashes
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TreeConfig {
+    /// Application tag hashed into every leaf and node, so trees built for
+    /// different purposes never share a root even over the same data
+    ///
+    /// Each hash input is prefixed with the tag's length as a big-endian
+    /// `u64` followed by the tag itself.
+    #[cfg(feature = "std")]
+    pub domain: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "std")]
+impl TreeConfig {
+    /// Returns the default configuration with `tag` as its domain
+    pub fn with_domain(tag: impl Into<Vec<u8>>) -> Self {
+        TreeConfig {
+            domain: Some(tag.into()),
+        }
+    }
+
+    /// Returns the default configuration in `domain`, tagged or not
+    pub(crate) fn from_domain(domain: Option<Vec<u8>>) -> Self {
+        TreeConfig { domain }
+    }
+
+    /// Returns the configuration that hashes exactly as `hasher` does, if
+    /// there is one
+    ///
+    /// Formats and APIs that only record a domain tag use this to accept
+    /// other hashers, such as a [`BuiltHasher`] in its defaults.
+    pub(crate) fn matching<H: MerkleHasher>(hasher: &H) -> Option<Self> {
+        let config = TreeConfig::from_domain(hasher.domain_tag().map(<[u8]>::to_vec));
+        (config.config_id() == hasher.config_id()).then_some(config)
+    }
+
+    /// Hashes a data item into a leaf hash in this configuration's domain
+    pub(crate) fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        let mut hasher = domain_hasher(self.domain.as_deref());
+        hasher.update(data);
+        hasher.finalize().to_vec()
+    }
+
+    /// Hashes two child hashes into their parent in this configuration's
+    /// domain
+    pub(crate) fn hash_pair(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        let mut hasher = domain_hasher(self.domain.as_deref());
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().to_vec()
+    }
+}
+
+/// Returns a hasher that has already absorbed the domain prefix, if any
+#[cfg(feature = "std")]
+fn domain_hasher(domain: Option<&[u8]>) -> Sha256 {
+    let mut hasher = Sha256::new();
+    if let Some(tag) = domain {
+        hasher.update((tag.len() as u64).to_be_bytes());
+        hasher.update(tag);
+    }
+    hasher
+}
+
+/// Which child of its parent a node is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    /// Returns the side the sibling of the node at `position` sits on
+    ///
+    /// Even positions are left children, so their siblings are on the right.
+    pub fn of_sibling(position: u64) -> Side {
+        if position & 1 == 1 {
+            Side::Left
+        } else {
+            Side::Right
+        }
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Side::Left => write!(f, "L"),
+            Side::Right => write!(f, "R"),
+        }
+    }
+}
+
+/// Outcome of [`MerkleTree::compare`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TreeComparison {
+    /// Same configuration and same leaves
+    Equal,
+    /// The trees hash differently, so their contents can't be compared
+    DifferentConfig,
+    /// The trees disagree starting at this leaf index
+    DifferentContent(usize),
+}
+
+/// A Merkle tree structure
+///
+/// Hashing follows `H`, a [`TreeConfig`] unless another [`MerkleHasher`] is
+/// given, usually through [`MerkleTree::builder`]. Building, proving,
+/// updating, comparing, auditing and exporting work with any hasher; the
+/// binary formats that record a domain tag take any hasher that matches a
+/// [`TreeConfig`], and [`MerkleTree::into_config_tree`] converts such a
+/// tree for the rest.
+#[cfg(feature = "std")]
+pub struct MerkleTree<H: MerkleHasher = TreeConfig> {
+    config: H,
+    /// Hashes of every level, leaves first; the last level holds the root
+    levels: Vec<Vec<Vec<u8>>>,
+}
+
+#[cfg(feature = "std")]
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Creates a new Merkle tree hashed by `hasher`
+    #[deprecated(note = "use `MerkleTree::builder()` with `MerkleTreeBuilder::hasher`")]
+    pub fn new_with_hasher(data: Vec<Vec<u8>>, hasher: H) -> Self {
+        Self::hashed_by(data, hasher)
+    }
+
+    /// Creates a new Merkle tree over leaves already hashed by `hasher`
+    #[deprecated(note = "use `MerkleTree::builder()` with `MerkleTreeBuilder::hasher`")]
+    pub fn from_leaf_hashes_with_hasher(leaves: Vec<Vec<u8>>, hasher: H) -> Self {
+        Self::from_leaf_hashes_by(leaves, hasher)
+    }
+
+    /// Creates a new Merkle tree over `data` hashed by `hasher`
+    pub(crate) fn hashed_by(data: Vec<Vec<u8>>, hasher: H) -> Self {
+        let leaves = data.iter().map(|item| hasher.hash_leaf(item)).collect();
+        Self::from_leaf_hashes_by(leaves, hasher)
+    }
+
+    /// Creates a new Merkle tree over leaves already hashed by `hasher`
+    pub(crate) fn from_leaf_hashes_by(leaves: Vec<Vec<u8>>, hasher: H) -> Self {
+        let levels = build_levels_reporting(leaves, &hasher, &());
+
+        MerkleTree {
+            config: hasher,
+            levels,
+        }
+    }
+
+    /// Creates a tree hashed by `hasher` from stored levels, checking only
+    /// their shape
+    pub(crate) fn from_levels(levels: Vec<Vec<Vec<u8>>>, hasher: H) -> Result<Self, MerkleError> {
+        let shape_error = |message: &str| Err(MerkleError::Parse(message.to_string()));
+
+        if let Some(leaves) = levels.first() {
+            if levels.len() < 2 || leaves.is_empty() || levels.last().unwrap().len() != 1 {
+                return shape_error("levels must run from the leaves up to a single root");
+            }
+
+            for (level, pair) in levels.windows(2).enumerate() {
+                if pair[1].len() != pair[0].len().div_ceil(2) || (level > 0 && pair[0].len() == 1) {
+                    return shape_error("each level must pair up the level below it");
+                }
+            }
+
+            let hash_len = leaves[0].len();
+            if levels.iter().flatten().any(|hash| hash.len() != hash_len) {
+                return shape_error("hashes must all have the same length");
+            }
+        }
+
+        Ok(MerkleTree {
+            config: hasher,
+            levels,
+        })
+    }
+
+    /// Returns the hasher the tree was built with
+    pub fn merkle_hasher(&self) -> &H {
+        &self.config
+    }
+
+    /// Converts to a [`TreeConfig`] tree, for the parts of the API that
+    /// only take one, such as [`MerkleTree::hasher`]
+    ///
+    /// Fails with [`MerkleError::ConfigMismatch`] unless the hasher hashes
+    /// exactly as the default configuration in its
+    /// [`MerkleHasher::domain_tag`] does, as that of a
+    /// [`MerkleTree::builder`] setting at most a domain does.
+    pub fn into_config_tree(self) -> Result<MerkleTree, MerkleError> {
+        let config = TreeConfig::matching(&self.config).ok_or(MerkleError::ConfigMismatch)?;
+        Ok(MerkleTree {
+            config,
+            levels: self.levels,
+        })
+    }
+
+    /// Returns the number of leaves in the tree
+    pub fn len(&self) -> usize {
+        self.levels.first().map_or(0, |leaves| leaves.len())
+    }
+
+    /// Returns true if the tree has no leaves
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the Merkle root hash, if it exists
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.levels.last().map(|root| root[0].clone())
+    }
+
+    /// Returns the Merkle root hash as a hex string
+    pub fn root_hash_hex(&self) -> Option<String> {
+        self.root_hash().map(hex::encode)
+    }
+
+    /// Returns true if both trees commit to the same root hash
+    pub fn same_root(&self, other: &MerkleTree<H>) -> bool {
+        self.root_hash() == other.root_hash()
+    }
+
+    /// Generates a proof that a leaf with given data exists in the tree
+    pub fn generate_proof(&self, data: &[u8]) -> Option<MerkleProof<H>> {
+        let leaf_hash = self.config.hash_leaf(data);
+        let index = self.levels.first()?.iter().position(|leaf| *leaf == leaf_hash)?;
+
+        Some(self.build_proof(index))
+    }
+
+    /// Generates a proof for the leaf at `index`
+    pub fn generate_proof_at(&self, index: usize) -> Option<MerkleProof<H>> {
+        if index >= self.len() {
+            return None;
+        }
+
+        Some(self.build_proof(index))
+    }
+
+    /// Builds the proof for the leaf at `index`, which must be in bounds
+    fn build_proof(&self, index: usize) -> MerkleProof<H> {
+        self.build_proof_from(0, index)
+    }
+
+    /// Builds the proof for node `index` of `level`, which must be in bounds
+    fn build_proof_from(&self, level: usize, index: usize) -> MerkleProof<H> {
+        let mut proof = Vec::new();
+        let mut position = index;
+
+        // Walk up every level below the root, collecting the sibling of each node
+        for nodes in &self.levels[level..self.levels.len() - 1] {
+            // A missing sibling means the node was padded
+            let hash = match nodes.get(position ^ 1) {
+                Some(sibling) => sibling.clone(),
+                None => self.config.pad(&nodes[position]),
+            };
+            proof.push((hash, Side::of_sibling(position as u64)));
+            position /= 2;
+        }
+
+        MerkleProof {
+            proof_hashes: proof,
+            leaf_hash: self.levels[level][index].clone(),
+            root_hash: self.root_hash().unwrap(),
+            config: self.config.clone(),
+        }
+    }
+
+    /// Verifies whether data is included in the tree using a proof
+    pub fn verify_proof(&self, proof: &MerkleProof<H>) -> bool {
+        if let Some(root) = self.root_hash() {
+            proof.verify(&root)
+        } else {
+            false
+        }
+    }
+
+    /// Compares two trees, locating the first leaf where they diverge
+    pub fn compare(&self, other: &MerkleTree<H>) -> TreeComparison {
+        if self.config.config_id() != other.config.config_id() {
+            return TreeComparison::DifferentConfig;
+        }
+
+        if self.len() != other.len() {
+            // Different shapes, so fall back to scanning the leaves
+            let leaves = self.levels.first().into_iter().flatten();
+            let other_leaves = other.levels.first().into_iter().flatten();
+            let index = leaves
+                .zip(other_leaves)
+                .position(|(leaf, other_leaf)| leaf != other_leaf)
+                .unwrap_or(self.len().min(other.len()));
+
+            return TreeComparison::DifferentContent(index);
+        }
+
+        if self.same_root(other) {
+            return TreeComparison::Equal;
+        }
+
+        // Same shape: follow the differing child down from the root
+        let mut position = 0;
+        for level in (0..self.levels.len() - 1).rev() {
+            let left = position * 2;
+            position = if self.levels[level][left] != other.levels[level][left] {
+                left
+            } else {
+                left + 1
+            };
+        }
+
+        TreeComparison::DifferentContent(position)
+    }
+
+    /// Generates a proof for every leaf, in leaf order
+    ///
+    /// Proofs are read straight off the stored levels and the leaves are
+    /// split into contiguous chunks, one per available core. WebAssembly
+    /// builds, which can't count on threads, generate them in turn instead.
+    pub fn generate_all_proofs(&self) -> Vec<MerkleProof<H>>
+    where
+        H: Send + Sync,
+    {
+        let len = self.len();
+        if cfg!(target_family = "wasm") {
+            return (0..len).map(|index| self.build_proof(index)).collect();
+        }
+
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = len.div_ceil(threads).max(1);
+
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..len)
+                .step_by(chunk_size)
+                .map(|start| {
+                    let end = (start + chunk_size).min(len);
+                    scope.spawn(move || {
+                        (start..end)
+                            .map(|index| self.build_proof(index))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("proof worker panicked"))
+                .collect()
+        })
+    }
+
+    /// Stages leaf updates through `f` and applies them together.
+    ///
+    /// The new root is returned once every staged change has been applied.
+    /// If `f` returns an error the tree is left untouched and the error is
+    /// passed back to the caller.
+    pub fn transaction<F, E>(&mut self, f: F) -> Result<Option<Vec<u8>>, E>
+    where
+        F: FnOnce(&mut Transaction<H>) -> Result<(), E>,
+    {
+        let mut txn = Transaction {
+            len: self.len(),
+            config: self.config.clone(),
+            updates: Vec::new(),
+        };
+        f(&mut txn)?;
+
+        for (index, leaf_hash) in txn.updates {
+            self.levels[0][index] = leaf_hash;
+            self.update_path(index);
+        }
+
+        Ok(self.root_hash())
+    }
+
+    /// Appends a data item and returns its leaf index
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        self.push_leaf_hash(self.config.hash_leaf(data))
+    }
+
+    /// Appends an already-hashed leaf and returns its leaf index
+    ///
+    /// Only the right edge of the tree changes, so this rehashes one node
+    /// per level rather than rebuilding.
+    pub fn push_leaf_hash(&mut self, leaf_hash: Vec<u8>) -> usize {
+        if self.levels.is_empty() {
+            self.levels = build_levels_reporting(vec![leaf_hash], &self.config, &());
+            return 0;
+        }
+
+        self.levels[0].push(leaf_hash);
+
+        let mut level = 0;
+        while level == 0 || self.levels[level].len() > 1 {
+            let parent = (self.levels[level].len() - 1) / 2;
+            let hash = {
+                let nodes = &self.levels[level];
+                let left = &nodes[parent * 2];
+                match nodes.get(parent * 2 + 1) {
+                    Some(right) => self.config.hash_node(left, right),
+                    None => self.config.hash_node(left, &self.config.pad(left)),
+                }
+            };
+
+            if self.levels.len() == level + 1 {
+                self.levels.push(Vec::new());
+            }
+            // The parent is either the current last node or a new one
+            let parents = &mut self.levels[level + 1];
+            parents.truncate(parent);
+            parents.push(hash);
+            level += 1;
+        }
+
+        self.len() - 1
+    }
+
+    /// Recomputes every ancestor of the leaf at `index`
+    fn update_path(&mut self, index: usize) {
+        let mut position = index;
+
+        for level in 0..self.levels.len() - 1 {
+            let parent = position / 2;
+            let hash = {
+                let nodes = &self.levels[level];
+                let left = &nodes[parent * 2];
+                match nodes.get(parent * 2 + 1) {
+                    Some(right) => self.config.hash_node(left, right),
+                    None => self.config.hash_node(left, &self.config.pad(left)),
+                }
+            };
+
+            self.levels[level + 1][parent] = hash;
+            position = parent;
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl MerkleTree {
+    /// Creates a new Merkle tree from a list of data items
+    pub fn new(data: Vec<Vec<u8>>) -> Self {
+        let leaves = data.iter().map(|item| hash_leaf(item)).collect();
+
+        Self::from_leaf_hashes(leaves)
+    }
+
+    /// Creates a new Merkle tree from a list of data items, failing with
+    /// [`MerkleError::BudgetExceeded`] instead of allocating more than
+    /// `max_bytes`
+    ///
+    /// The check uses [`MerkleTree::estimated_peak_bytes`], which counts
+    /// `data` as well as the tree, and happens before anything is hashed.
+    /// For inputs too large to hold,
+    /// [`pipelined_root`] computes the root in bounded memory and
+    /// [`TreeView`] serves proofs from a tree file.
+    pub fn try_new_with_budget(data: Vec<Vec<u8>>, max_bytes: usize) -> Result<Self, MerkleError> {
+        let needed = Self::estimated_peak_bytes(&data);
+        if needed > max_bytes {
+            return Err(MerkleError::BudgetExceeded {
+                needed,
+                budget: max_bytes,
+            });
+        }
+
+        Ok(Self::new(data))
+    }
+
+    /// Returns roughly how many heap bytes a tree of `leaves` leaves takes,
+    /// not counting the data it is built from
+    ///
+    /// Every node is a 32-byte hash in its own allocation; allocator
+    /// overhead is not included. The leaf hashes are collected straight
+    /// into the bottom level, so they are counted once, as part of it.
+    pub fn estimated_bytes(leaves: usize) -> usize {
+        let node = 32 + size_of::<Vec<u8>>();
+        view::level_lengths(leaves as u64).fold(0usize, |total, len| {
+            let level = (len as usize)
+                .saturating_mul(node)
+                .saturating_add(size_of::<Vec<Vec<u8>>>());
+            total.saturating_add(level)
+        })
+    }
+
+    /// Returns roughly how many heap bytes building a tree over `data`
+    /// takes at its peak
+    ///
+    /// `data` is held until every level is built, so this is
+    /// [`MerkleTree::estimated_bytes`] plus the bytes of `data` itself.
+    pub fn estimated_peak_bytes(data: &[Vec<u8>]) -> usize {
+        let data_bytes = data
+            .iter()
+            .fold(size_of_val(data), |total, item| total.saturating_add(item.capacity()));
+        Self::estimated_bytes(data.len()).saturating_add(data_bytes)
+    }
+
+    /// Creates a new Merkle tree from leaves that have already been hashed
+    pub fn from_leaf_hashes(leaves: Vec<Vec<u8>>) -> Self {
+        MerkleTree {
+            config: TreeConfig::default(),
+            levels: build_levels(leaves),
+        }
+    }
+
+    /// Creates a new Merkle tree whose hashes are derived as `config` says
+    #[deprecated(note = "use `MerkleTree::builder()` with `MerkleTreeBuilder::domain`")]
+    pub fn new_with_config(data: Vec<Vec<u8>>, config: TreeConfig) -> Self {
+        Self::hashed_by(data, config)
+    }
+
+    /// Returns the configuration the tree was built with
+    pub fn config(&self) -> &TreeConfig {
+        &self.config
+    }
+}
+
+/// Trees are equal when they share a hasher, leaf count and root.
+///
+/// Hashers are told apart by [`MerkleHasher::config_id`]. The leaf count
+/// matters because duplicating the last node means `[a, b, c]` and
+/// `[a, b, c, c]` produce the same root.
+#[cfg(feature = "std")]
+impl<H: MerkleHasher> PartialEq for MerkleTree<H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.config.config_id() == other.config.config_id()
+            && self.len() == other.len()
+            && self.same_root(other)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H: MerkleHasher> Eq for MerkleTree<H> {}
+
+/// Leaf changes staged inside [`MerkleTree::transaction`]
+#[cfg(feature = "std")]
+pub struct Transaction<H: MerkleHasher = TreeConfig> {
+    len: usize,
+    config: H,
+    updates: Vec<(usize, Vec<u8>)>,
+}
+
+#[cfg(feature = "std")]
+impl<H: MerkleHasher> Transaction<H> {
+    /// Stages replacing the leaf at `index` with `data`
+    pub fn update(&mut self, index: usize, data: &[u8]) -> Result<(), MerkleError> {
+        if index >= self.len {
+            return Err(MerkleError::IndexOutOfBounds { index, len: self.len });
+        }
+
+        self.updates.push((index, self.config.hash_leaf(data)));
+        Ok(())
+    }
+}
+
+/// A proof that a particular data item is in the Merkle tree
+///
+/// The proof carries the hasher of the tree it came from and verifies with
+/// it.
+#[cfg(feature = "std")]
+pub struct MerkleProof<H: MerkleHasher = TreeConfig> {
+    /// Siblings from the leaf up, each with the side it sits on
+    proof_hashes: Vec<(Vec<u8>, Side)>,
+    leaf_hash: Vec<u8>,
+    root_hash: Vec<u8>,
+    config: H,
+}
+
+#[cfg(feature = "std")]
+impl MerkleProof {
+    /// Assembles a proof from its parts, for proofs made elsewhere
+    ///
+    /// `siblings` runs from the leaf up. The proof has no domain tag.
+    pub fn new(leaf_hash: Vec<u8>, siblings: Vec<(Vec<u8>, Side)>, root_hash: Vec<u8>) -> Self {
+        MerkleProof {
+            proof_hashes: siblings,
+            leaf_hash,
+            root_hash,
+            config: TreeConfig::default(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H: MerkleHasher> MerkleProof<H> {
+    /// Returns the hasher the proof verifies with
+    pub fn merkle_hasher(&self) -> &H {
+        &self.config
+    }
+
+    /// Returns the domain tag of the tree the proof was generated from
+    ///
+    /// Verification hashes in this domain, so a verifier expecting a
+    /// particular application should check it.
+    pub fn domain(&self) -> Option<&[u8]> {
+        self.config.domain_tag()
+    }
+
+    /// Converts to a proof with a [`TreeConfig`], as
+    /// [`MerkleTree::into_config_tree`] does for trees
+    pub fn into_config_proof(self) -> Result<MerkleProof, MerkleError> {
+        let config = TreeConfig::matching(&self.config).ok_or(MerkleError::ConfigMismatch)?;
+        Ok(MerkleProof {
+            proof_hashes: self.proof_hashes,
+            leaf_hash: self.leaf_hash,
+            root_hash: self.root_hash,
+            config,
+        })
+    }
+
+    /// Returns the sibling hashes from the leaf up, each with the side of
+    /// the running hash it sits on
+    pub fn siblings(&self) -> &[(Vec<u8>, Side)] {
+        &self.proof_hashes
+    }
+
+    /// Returns the hash of the leaf the proof starts from
+    pub fn leaf_hash(&self) -> &[u8] {
+        &self.leaf_hash
+    }
+
+    /// Returns the root hash of the tree the proof was generated from
+    pub fn root_hash(&self) -> &[u8] {
+        &self.root_hash
+    }
+
+    /// Returns true if the proof's leaf is `data` hashed by the proof's
+    /// hasher
+    pub(crate) fn is_for(&self, data: &[u8]) -> bool {
+        self.config.hash_leaf(data) == self.leaf_hash
+    }
+
+    /// Adds one more level above the proof's current root
+    pub(crate) fn extend(mut self, sibling: Vec<u8>, side: Side, root_hash: Vec<u8>) -> Self {
+        self.proof_hashes.push((sibling, side));
+        self.root_hash = root_hash;
+        self
+    }
+
+    /// Verifies the proof against the given root hash
+    ///
+    /// With the default [`TreeConfig`] hasher the running hash lives in a
+    /// stack buffer, so verification does not allocate.
+    pub fn verify(&self, root_hash: &[u8]) -> bool {
+        self.config
+            .verify_path(&self.leaf_hash, &self.proof_hashes, root_hash)
+    }
+}
+
+/// Number of proof hashes for any leaf of a tree with `leaves` leaves
+fn proof_depth(leaves: u64) -> usize {
+    // A lone leaf is still paired with itself once
+    (u64::BITS - leaves.saturating_sub(1).leading_zeros()).max(1) as usize
+}
+
+/// Verifies a proof using fixed-size buffers only
+///
+/// `siblings` runs from the leaf up and each [`Side`] says which side of the
+/// running hash the sibling sits on. Nothing is allocated, so this works
+/// without `std` or an allocator.
+pub fn verify_proof_in_place(
+    root: &[u8; 32],
+    leaf: &[u8; 32],
+    siblings: &[([u8; 32], Side)],
+) -> bool {
+    fold_path_in_place(leaf, siblings.iter().map(|(hash, side)| (hash, *side))) == *root
+}
+
+/// Folds a leaf hash up through `(sibling, side)` pairs without allocating
+fn fold_path_in_place<'a>(
+    leaf: &[u8; 32],
+    siblings: impl IntoIterator<Item = (&'a [u8; 32], Side)>,
+) -> [u8; 32] {
+    let mut current_hash = *leaf;
+    let mut hasher = Sha256::new();
+
+    for (sibling_hash, side) in siblings {
+        match side {
+            Side::Left => {
+                hasher.update(sibling_hash);
+                hasher.update(current_hash);
+            }
+            Side::Right => {
+                hasher.update(current_hash);
+                hasher.update(sibling_hash);
+            }
+        }
+
+        hasher.finalize_into_reset(GenericArray::from_mut_slice(&mut current_hash));
+    }
+
+    current_hash
+}
@@ -0,0 +1,284 @@
+//! Admission control for services that hand out proofs.
+//!
+//! The crate ships no server, and wiring these limits into one is left to
+//! the service that embeds the crate; these are the pieces its handler
+//! needs to keep an internet-facing proof endpoint from being overwhelmed. [`RequestGuard`]
+//! bounds the encoded size of each request and the number of proofs it asks
+//! for, then charges one token per proof to the client's bucket, so a large
+//! multiproof request costs as much as that many single requests.
+//!
+//! Limits can be read from a config file of `key = value` lines:
+//!
+//! ```text
+//! # proofs per second each client earns, and how many it can save up
+//! requests_per_second = 50
+//! burst = 256
+//! max_request_bytes = 65536
+//! max_proofs_per_request = 256
+//! # clients tracked at once; idle clients are forgotten first
+//! max_clients = 10000
+//! ```
+//!
+//! Missing keys keep their defaults. `burst` must be at least 1 and at
+//! least `max_proofs_per_request`, or some request within the size limits
+//! could never be admitted.
+
+use crate::MerkleError;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// Limits applied by a [`RequestGuard`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LimitsConfig {
+    /// Tokens each client earns per second
+    pub requests_per_second: f64,
+    /// Most tokens a client can hold
+    pub burst: u32,
+    pub max_request_bytes: usize,
+    pub max_proofs_per_request: usize,
+    /// Most clients with a bucket at once
+    pub max_clients: usize,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            requests_per_second: 50.0,
+            burst: 256,
+            max_request_bytes: 64 * 1024,
+            max_proofs_per_request: 256,
+            max_clients: 10_000,
+        }
+    }
+}
+
+/// Parses a config number, allowing `_` separators
+fn number<T: FromStr>(value: &str) -> Option<T> {
+    value.replace('_', "").parse().ok()
+}
+
+impl LimitsConfig {
+    /// Parses a config file's contents
+    pub fn parse(text: &str) -> Result<Self, MerkleError> {
+        let mut config = LimitsConfig::default();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: &str| {
+                MerkleError::Parse(format!("line {}: {}", line_number + 1, message))
+            };
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `key = value`"))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            let invalid = || error(&format!("invalid value for {}", key));
+
+            match key {
+                "requests_per_second" => {
+                    config.requests_per_second = number(value)
+                        .filter(|rate: &f64| rate.is_finite() && *rate > 0.0)
+                        .ok_or_else(invalid)?
+                }
+                "burst" => config.burst = number(value).ok_or_else(invalid)?,
+                "max_request_bytes" => {
+                    config.max_request_bytes = number(value).ok_or_else(invalid)?
+                }
+                "max_proofs_per_request" => {
+                    config.max_proofs_per_request = number(value).ok_or_else(invalid)?
+                }
+                "max_clients" => config.max_clients = number(value).ok_or_else(invalid)?,
+                _ => return Err(error(&format!("unknown key {}", key))),
+            }
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Fails with [`MerkleError::InvalidLimit`] if the limits can't all be
+    /// met: a zero `burst` admits nothing, and a `burst` below
+    /// `max_proofs_per_request` refuses requests the size limit allows
+    pub fn validate(&self) -> Result<(), MerkleError> {
+        if self.burst == 0 {
+            return Err(MerkleError::InvalidLimit(
+                "burst must be at least 1".to_string(),
+            ));
+        }
+        if self.max_proofs_per_request > self.burst as usize {
+            return Err(MerkleError::InvalidLimit(format!(
+                "max_proofs_per_request {} is above the burst of {}",
+                self.max_proofs_per_request, self.burst
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reads and parses a config file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MerkleError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Per-client token buckets
+pub struct RateLimiter<K> {
+    rate: f64,
+    burst: f64,
+    max_clients: usize,
+    buckets: HashMap<K, Bucket>,
+}
+
+impl<K: Hash + Eq + Clone> RateLimiter<K> {
+    /// Creates a limiter where each client earns `rate` tokens a second up
+    /// to `burst`, tracking at most `max_clients` clients
+    ///
+    /// Fails with [`MerkleError::InvalidLimit`] unless `rate` is finite and
+    /// positive.
+    pub fn new(rate: f64, burst: u32, max_clients: usize) -> Result<Self, MerkleError> {
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(MerkleError::InvalidLimit(format!(
+                "requests_per_second must be a positive number, not {}",
+                rate
+            )));
+        }
+
+        Ok(RateLimiter {
+            rate,
+            burst: burst as f64,
+            max_clients: max_clients.max(1),
+            buckets: HashMap::new(),
+        })
+    }
+
+    /// Takes `cost` tokens from `client`'s bucket as of `now`
+    ///
+    /// New clients start with a full bucket. On rejection nothing is taken
+    /// and the error says how long until the request would be admitted.
+    pub fn check(&mut self, client: &K, cost: u32, now: Instant) -> Result<(), MerkleError> {
+        let cost = cost as f64;
+        if cost > self.burst {
+            return Err(MerkleError::RequestTooLarge {
+                what: "request cost",
+                size: cost as usize,
+                limit: self.burst as usize,
+            });
+        }
+
+        if !self.buckets.contains_key(client) && self.buckets.len() >= self.max_clients {
+            self.forget_idle(now);
+        }
+
+        let (rate, burst) = (self.rate, self.burst);
+        let bucket = self.buckets.entry(client.clone()).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens < cost {
+            let wait = (cost - bucket.tokens) / rate;
+            return Err(MerkleError::RateLimited {
+                retry_after: Duration::try_from_secs_f64(wait).unwrap_or(Duration::MAX),
+            });
+        }
+
+        bucket.tokens -= cost;
+        Ok(())
+    }
+
+    /// Returns the number of clients being tracked
+    pub fn clients(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Drops clients whose buckets have refilled, or failing that the one
+    /// seen least recently
+    fn forget_idle(&mut self, now: Instant) {
+        let (rate, burst) = (self.rate, self.burst);
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * rate < burst
+        });
+
+        if self.buckets.len() >= self.max_clients {
+            let oldest = self
+                .buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.updated)
+                .map(|(client, _)| client.clone());
+            if let Some(client) = oldest {
+                self.buckets.remove(&client);
+            }
+        }
+    }
+}
+
+/// Size bounds plus rate limiting for proof requests
+pub struct RequestGuard<K> {
+    config: LimitsConfig,
+    limiter: RateLimiter<K>,
+}
+
+impl<K: Hash + Eq + Clone> RequestGuard<K> {
+    /// Creates a guard enforcing `config`
+    ///
+    /// Fails as [`LimitsConfig::validate`] does, and as
+    /// [`RateLimiter::new`] does on a rate that isn't finite and positive.
+    pub fn new(config: LimitsConfig) -> Result<Self, MerkleError> {
+        config.validate()?;
+        let limiter =
+            RateLimiter::new(config.requests_per_second, config.burst, config.max_clients)?;
+
+        Ok(RequestGuard { config, limiter })
+    }
+
+    /// Returns the limits being enforced
+    pub fn config(&self) -> &LimitsConfig {
+        &self.config
+    }
+
+    /// Decides whether `client` may have `proofs` proofs for a request of
+    /// `request_bytes` bytes
+    ///
+    /// Size checks run first, so oversized requests never cost tokens.
+    pub fn admit(
+        &mut self,
+        client: &K,
+        request_bytes: usize,
+        proofs: usize,
+        now: Instant,
+    ) -> Result<(), MerkleError> {
+        if request_bytes > self.config.max_request_bytes {
+            return Err(MerkleError::RequestTooLarge {
+                what: "request bytes",
+                size: request_bytes,
+                limit: self.config.max_request_bytes,
+            });
+        }
+        if proofs > self.config.max_proofs_per_request {
+            return Err(MerkleError::RequestTooLarge {
+                what: "proofs requested",
+                size: proofs,
+                limit: self.config.max_proofs_per_request,
+            });
+        }
+
+        let cost = u32::try_from(proofs.max(1)).unwrap_or(u32::MAX);
+        self.limiter.check(client, cost, now)
+    }
+}
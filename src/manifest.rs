@@ -0,0 +1,256 @@
+//! Signed update manifests over chunked firmware images.
+//!
+//! The build side splits an image into fixed-size chunks and commits to
+//! them in a [`MerkleTree`] whose leaf 0 is the manifest metadata and leaf
+//! `i + 1` is chunk `i`. The manifest carries the root and is signed as a
+//! whole, so a device only has to check one signature and can then accept
+//! chunks one at a time, in any order, as their proofs arrive.
+//!
+//! Manifests are encoded big-endian:
+//!
+//! ```text
+//! "SMTM" | version: u8 | chunk_size: u32 | image_len: u64
+//!        | metadata_len: u16 | metadata | root: [u8; 32]
+//!        | signature_len: u16 | signature
+//! ```
+//!
+//! The signature covers everything before `signature_len`. Building and
+//! signing need the `std` feature; [`ManifestView`] does not allocate and
+//! is what devices should use.
+
+use crate::{fold_path_in_place, proof_depth, Side};
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "std")]
+use crate::{MerkleError, MerkleTree, Signer};
+
+pub(crate) const MAGIC: &[u8; 4] = b"SMTM";
+pub(crate) const FORMAT_VERSION: u8 = 1;
+
+/// A manifest being built or published
+#[cfg(feature = "std")]
+pub struct FirmwareManifest {
+    chunk_size: u32,
+    image_len: u64,
+    metadata: Vec<u8>,
+    tree: MerkleTree,
+    signature: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl FirmwareManifest {
+    /// Chunks `image` and builds the tree over the metadata and chunks
+    pub fn build(image: &[u8], chunk_size: u32, metadata: &[u8]) -> Result<Self, MerkleError> {
+        if chunk_size == 0 {
+            return Err(MerkleError::Encode(
+                "chunk size must be non-zero".to_string(),
+            ));
+        }
+        if metadata.len() > u16::MAX as usize {
+            return Err(MerkleError::Encode(
+                "manifest metadata is too long".to_string(),
+            ));
+        }
+
+        let mut leaves = vec![metadata.to_vec()];
+        leaves.extend(image.chunks(chunk_size as usize).map(<[u8]>::to_vec));
+
+        Ok(FirmwareManifest {
+            chunk_size,
+            image_len: image.len() as u64,
+            metadata: metadata.to_vec(),
+            tree: MerkleTree::new(leaves),
+            signature: Vec::new(),
+        })
+    }
+
+    /// Returns the root committing to the metadata and every chunk
+    pub fn root_hash(&self) -> [u8; 32] {
+        self.tree.root_hash().unwrap().try_into().unwrap()
+    }
+
+    /// Returns the number of image chunks
+    pub fn chunk_count(&self) -> u64 {
+        self.image_len.div_ceil(self.chunk_size as u64)
+    }
+
+    /// Returns the sibling hashes a device needs to accept chunk `index`
+    pub fn chunk_proof(&self, index: u64) -> Option<Vec<[u8; 32]>> {
+        let proof = self
+            .tree
+            .generate_proof_at(usize::try_from(index).ok()?.checked_add(1)?)?;
+
+        Some(
+            proof
+                .proof_hashes
+                .iter()
+                .map(|(hash, _)| hash.as_slice().try_into().unwrap())
+                .collect(),
+        )
+    }
+
+    /// Returns the bytes the signature covers
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(53 + self.metadata.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&self.chunk_size.to_be_bytes());
+        bytes.extend_from_slice(&self.image_len.to_be_bytes());
+        bytes.extend_from_slice(&(self.metadata.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.metadata);
+        bytes.extend_from_slice(&self.root_hash());
+        bytes
+    }
+
+    /// Signs the manifest, replacing any earlier signature
+    pub fn sign(&mut self, signer: &dyn Signer) -> Result<(), MerkleError> {
+        let signature = signer.sign(&self.signed_bytes());
+        if signature.len() > u16::MAX as usize {
+            return Err(MerkleError::Encode(
+                "manifest signature is too long".to_string(),
+            ));
+        }
+
+        self.signature = signature;
+        Ok(())
+    }
+
+    /// Encodes the signed manifest
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.signed_bytes();
+        bytes.extend_from_slice(&(self.signature.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&self.signature);
+        bytes
+    }
+}
+
+/// Reads big-endian fields off the front of a byte slice
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+}
+
+/// A decoded manifest borrowing from its encoding
+///
+/// Parsing does not check the signature; call
+/// [`ManifestView::verify_signature`] before trusting any field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ManifestView<'a> {
+    chunk_size: u32,
+    image_len: u64,
+    metadata: &'a [u8],
+    root: [u8; 32],
+    signed: &'a [u8],
+    signature: &'a [u8],
+}
+
+impl<'a> ManifestView<'a> {
+    /// Decodes a manifest, returning `None` if it is malformed
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        let mut reader = Reader(bytes);
+
+        if reader.take(4)? != MAGIC || reader.array::<1>()?[0] != FORMAT_VERSION {
+            return None;
+        }
+
+        let chunk_size = u32::from_be_bytes(reader.array()?);
+        let image_len = u64::from_be_bytes(reader.array()?);
+        let metadata_len = u16::from_be_bytes(reader.array()?);
+        let metadata = reader.take(metadata_len as usize)?;
+        let root = reader.array()?;
+        let signed = &bytes[..bytes.len() - reader.0.len()];
+
+        let signature_len = u16::from_be_bytes(reader.array()?);
+        let signature = reader.take(signature_len as usize)?;
+
+        if chunk_size == 0 || !reader.0.is_empty() {
+            return None;
+        }
+
+        Some(ManifestView {
+            chunk_size,
+            image_len,
+            metadata,
+            root,
+            signed,
+            signature,
+        })
+    }
+
+    /// Returns the size of every chunk but the last
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+
+    /// Returns the length of the whole image
+    pub fn image_len(&self) -> u64 {
+        self.image_len
+    }
+
+    /// Returns the number of image chunks
+    pub fn chunk_count(&self) -> u64 {
+        self.image_len.div_ceil(self.chunk_size as u64)
+    }
+
+    /// Returns the metadata the manifest was built with
+    pub fn metadata(&self) -> &'a [u8] {
+        self.metadata
+    }
+
+    /// Returns the root committing to the metadata and every chunk
+    pub fn root_hash(&self) -> &[u8; 32] {
+        &self.root
+    }
+
+    /// Checks the signature with `verify(message, signature)`
+    pub fn verify_signature(&self, verify: impl FnOnce(&[u8], &[u8]) -> bool) -> bool {
+        verify(self.signed, self.signature)
+    }
+
+    /// Checks chunk `index` against the manifest root
+    ///
+    /// `siblings` is the list from [`FirmwareManifest::chunk_proof`]. The
+    /// side of each sibling is derived from the index, and the chunk must
+    /// have exactly the length its position in the image implies.
+    pub fn verify_chunk(&self, index: u64, chunk: &[u8], siblings: &[[u8; 32]]) -> bool {
+        let chunks = self.chunk_count();
+        if index >= chunks {
+            return false;
+        }
+
+        let expected_len = if index == chunks - 1 {
+            self.image_len - index * self.chunk_size as u64
+        } else {
+            self.chunk_size as u64
+        };
+        if chunk.len() as u64 != expected_len || siblings.len() != proof_depth(chunks + 1) {
+            return false;
+        }
+
+        let leaf: [u8; 32] = Sha256::digest(chunk).into();
+        let mut position = index + 1;
+        let path = siblings.iter().map(|sibling| {
+            let side = if position & 1 == 1 {
+                Side::Left
+            } else {
+                Side::Right
+            };
+            position >>= 1;
+            (sibling, side)
+        });
+
+        fold_path_in_place(&leaf, path) == self.root
+    }
+}
@@ -0,0 +1,131 @@
+//! A map that can prove what it holds.
+//!
+//! [`AuthenticatedMap`] keeps its entries in a [`HashMap`] for reads and
+//! mirrors every write into a [`SparseMerkleTree`] over a [`MemoryStore`],
+//! so it behaves like a `HashMap` but each mutation yields the new root and
+//! any lookup can come with a [`SparseProof`], of presence or of absence.
+//! Keys and values go into the tree as their [`LeafEncode`] bytes.
+//!
+//! The memory store never waits, so the tree's async operations are run to
+//! completion in place and the map's API is synchronous. Replaced tree nodes
+//! are kept, as in any sparse tree, so memory grows with every write.
+
+use crate::sparse::NodeHash;
+use crate::{LeafEncode, MemoryStore, SparseMerkleTree, SparseProof};
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+
+/// Runs a future that never waits, as none over a [`MemoryStore`] do
+fn ready<F: Future>(future: F) -> F::Output {
+    match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("memory store operations complete immediately"),
+    }
+}
+
+/// What a mutation of an [`AuthenticatedMap`] replaced, and the root after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mutation<V> {
+    /// The value the key held before
+    pub previous: Option<V>,
+    pub root: NodeHash,
+}
+
+/// A key-value map committed to by a sparse Merkle root
+pub struct AuthenticatedMap<K, V> {
+    entries: HashMap<K, V>,
+    tree: SparseMerkleTree<MemoryStore>,
+}
+
+impl<K: LeafEncode + Eq + Hash, V: LeafEncode> AuthenticatedMap<K, V> {
+    /// Creates an empty map
+    pub fn new() -> Self {
+        AuthenticatedMap {
+            entries: HashMap::new(),
+            tree: SparseMerkleTree::new(MemoryStore::new()),
+        }
+    }
+
+    /// Returns the root committing to every entry
+    pub fn root_hash(&self) -> NodeHash {
+        self.tree.root_hash()
+    }
+
+    /// Returns the number of entries
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if the map has no entries
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the value stored under `key`
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.entries.get(key)
+    }
+
+    /// Returns the value stored under `key` with a proof of the answer
+    /// against [`AuthenticatedMap::root_hash`]
+    pub fn get_with_proof(&self, key: &K) -> (Option<&V>, SparseProof) {
+        let (_, proof) = ready(self.tree.get_with_proof(&key.encode_leaf()))
+            .expect("memory store holds every node");
+        (self.entries.get(key), proof)
+    }
+
+    /// Returns true if `key` has a value
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Stores `value` under `key`
+    pub fn insert(&mut self, key: K, value: V) -> Mutation<V> {
+        ready(self.tree.insert(&key.encode_leaf(), value.encode_leaf()))
+            .expect("memory store holds every node");
+        Mutation {
+            previous: self.entries.insert(key, value),
+            root: self.root_hash(),
+        }
+    }
+
+    /// Removes `key` and its value
+    pub fn remove(&mut self, key: &K) -> Mutation<V> {
+        ready(self.tree.remove(&key.encode_leaf())).expect("memory store holds every node");
+        Mutation {
+            previous: self.entries.remove(key),
+            root: self.root_hash(),
+        }
+    }
+
+    /// Returns every entry, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.entries.iter()
+    }
+
+    /// Checks that `proof` shows `key` holding `value`, or nothing when
+    /// `value` is `None`, under `root`
+    pub fn verify(root: &NodeHash, key: &K, value: Option<&V>, proof: &SparseProof) -> bool {
+        let value = value.map(LeafEncode::encode_leaf);
+        proof.verify(root, &key.encode_leaf(), value.as_deref())
+    }
+}
+
+impl<K: LeafEncode + Eq + Hash, V: LeafEncode> Default for AuthenticatedMap<K, V> {
+    fn default() -> Self {
+        AuthenticatedMap::new()
+    }
+}
+
+impl<K: LeafEncode + Eq + Hash, V: LeafEncode> FromIterator<(K, V)> for AuthenticatedMap<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let mut map = AuthenticatedMap::new();
+        for (key, value) in entries {
+            map.insert(key, value);
+        }
+        map
+    }
+}
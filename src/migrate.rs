@@ -0,0 +1,255 @@
+//! Format versions of serialized trees and proofs, and upgrades between them.
+//!
+//! Every format this crate writes carries a version: the binary ones in the
+//! byte after their magic, level exports in their header line or `version`
+//! field, and proof bundles in their first line. [`detect_format`] reads it
+//! back and [`migrate`] rewrites a file in the current version of its
+//! format.
+//!
+//! The upgrades so far are for level exports and packed proofs. Version 1
+//! of both predates domain tags, so a tree built in a tagged domain lost
+//! its tag on export; migrating re-audits the tree, or re-verifies the
+//! proofs, to tell which it was. One whose hashes only add up under a
+//! domain tag the caller doesn't supply can't be recovered and fails with
+//! [`MerkleError::CannotMigrate`]. Version 2 level exports have their tag
+//! and only gain the config id of the [`TreeConfig`](crate::TreeConfig) it
+//! gives.
+
+use crate::compress::decompress;
+use crate::export::{read_levels, LEVELS_VERSION};
+use crate::{manifest, packed, shard, sync, view};
+use crate::{ExportFormat, MerkleError, TreeConfig};
+
+/// Magic, name and current version of each binary format
+const BINARY_FORMATS: [(&[u8; 4], &str, u8); 5] = [
+    (packed::MAGIC, "packed proofs", packed::FORMAT_VERSION),
+    (view::MAGIC, "tree view", view::FORMAT_VERSION),
+    (shard::MAGIC, "composed proof", shard::FORMAT_VERSION),
+    (sync::MAGIC, "sync chunk", sync::FORMAT_VERSION),
+    (
+        manifest::MAGIC,
+        "firmware manifest",
+        manifest::FORMAT_VERSION,
+    ),
+];
+
+/// Proof bundles start with this and their version on one line
+const BUNDLE_HEADER_PREFIX: &str = "simple-merkle-tree proof bundle v";
+const BUNDLE_VERSION: u32 = 1;
+
+/// Which format a file is in, and which version of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatInfo {
+    pub name: &'static str,
+    pub version: u32,
+    /// Version this crate writes
+    pub current_version: u32,
+}
+
+impl FormatInfo {
+    /// Returns true if the file is already in the version this crate writes
+    pub fn is_current(&self) -> bool {
+        self.version == self.current_version
+    }
+}
+
+/// A file rewritten in the current version of its format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    pub bytes: Vec<u8>,
+    pub format: &'static str,
+    pub from_version: u32,
+    pub to_version: u32,
+    /// What was re-derived or assumed along the way
+    pub notes: Vec<String>,
+}
+
+/// Works out the format and version of a serialized tree or proof
+pub fn detect_format(bytes: &[u8]) -> Result<FormatInfo, MerkleError> {
+    Ok(detect(&decompress(bytes)?)?.0)
+}
+
+/// Detects the format, with the export format too for level exports
+fn detect(bytes: &[u8]) -> Result<(FormatInfo, Option<ExportFormat>), MerkleError> {
+    if let Some(&(_, name, current)) = BINARY_FORMATS
+        .iter()
+        .find(|(magic, _, _)| bytes.starts_with(&magic[..]))
+    {
+        let version = *bytes
+            .get(4)
+            .ok_or_else(|| MerkleError::Parse(format!("{}: missing version", name)))?;
+        let info = FormatInfo {
+            name,
+            version: version.into(),
+            current_version: current.into(),
+        };
+        return Ok((info, None));
+    }
+
+    let text = std::str::from_utf8(bytes)
+        .map_err(|_| MerkleError::Parse("not a recognized format".to_string()))?;
+    if let Some(rest) = text.strip_prefix(BUNDLE_HEADER_PREFIX) {
+        let version = rest
+            .split('\n')
+            .next()
+            .and_then(|version| version.parse().ok())
+            .ok_or_else(|| MerkleError::Parse("proof bundle: invalid header".to_string()))?;
+        let info = FormatInfo {
+            name: "proof bundle",
+            version,
+            current_version: BUNDLE_VERSION,
+        };
+        return Ok((info, None));
+    }
+
+    let format = if text.trim_start().starts_with('{') {
+        ExportFormat::Json
+    } else {
+        ExportFormat::Lines
+    };
+    let info = FormatInfo {
+        name: match format {
+            ExportFormat::Lines => "level export",
+            ExportFormat::Json => "JSON level export",
+        },
+        version: read_levels(bytes, format)?.version,
+        current_version: LEVELS_VERSION,
+    };
+    Ok((info, Some(format)))
+}
+
+/// Rewrites a serialized tree or proof in the current version of its format
+///
+/// `domain` is the tag to try if a version 1 level export only audits under
+/// one; without it, or if the tree doesn't audit under it either, the
+/// migration fails. Compressed files are decompressed first and written
+/// back uncompressed; otherwise files already in the current version come
+/// back unchanged.
+pub fn migrate(bytes: &[u8], domain: Option<&[u8]>) -> Result<Migration, MerkleError> {
+    let bytes = &decompress(bytes)?[..];
+    let (info, format) = detect(bytes)?;
+    if info.version > info.current_version {
+        return Err(MerkleError::CannotMigrate(format!(
+            "{} version {} is newer than this crate supports",
+            info.name, info.version
+        )));
+    }
+
+    if info.is_current() {
+        return Ok(Migration {
+            bytes: bytes.to_vec(),
+            format: info.name,
+            from_version: info.version,
+            to_version: info.current_version,
+            notes: vec![format!("already {} version {}", info.name, info.version)],
+        });
+    }
+    if bytes.starts_with(packed::MAGIC) {
+        return migrate_packed(bytes, domain, info);
+    }
+    let Some(format) = format else {
+        return Err(MerkleError::CannotMigrate(format!(
+            "no upgrade from {} version {}",
+            info.name, info.version
+        )));
+    };
+
+    // Only version 1 and 2 level exports get here, both from TreeConfig trees
+    let imported = read_levels(bytes, format)?;
+    let version = imported.version;
+    let config = TreeConfig::from_domain(imported.domain.clone());
+    let mut tree = imported.into_tree(config)?;
+    let mut notes = Vec::new();
+    if version >= 2 {
+        notes.push("recorded the config id of the domain tag in the file".to_string());
+        if domain.is_some() {
+            notes.push("ignored the domain tag given".to_string());
+        }
+    } else if tree.levels.len() == 1 {
+        // No internal node to audit, so the tag can't be re-derived
+        if let Some(domain) = domain {
+            tree.config.domain = Some(domain.to_vec());
+            notes.push("single-leaf tree: took the domain tag on trust".to_string());
+        } else {
+            notes.push("single-leaf tree: assumed untagged".to_string());
+        }
+    } else if tree.audit().is_ok() {
+        notes.push("tree audits untagged".to_string());
+        if domain.is_some() {
+            notes.push("ignored the domain tag given".to_string());
+        }
+    } else {
+        let Some(domain) = domain else {
+            return Err(MerkleError::CannotMigrate(
+                "tree doesn't audit untagged; pass the domain tag it was built with".to_string(),
+            ));
+        };
+        tree.config.domain = Some(domain.to_vec());
+        if let Err(issues) = tree.audit() {
+            return Err(MerkleError::CannotMigrate(format!(
+                "tree doesn't audit untagged or under the given domain tag: {}",
+                issues[0]
+            )));
+        }
+        notes.push("tree audits under the given domain tag".to_string());
+    }
+
+    let mut migrated = Vec::new();
+    tree.export_levels(&mut migrated, format)?;
+    Ok(Migration {
+        bytes: migrated,
+        format: info.name,
+        from_version: info.version,
+        to_version: info.current_version,
+        notes,
+    })
+}
+
+/// Upgrades version 1 packed proofs by checking which tag they verify under
+fn migrate_packed(
+    bytes: &[u8],
+    domain: Option<&[u8]>,
+    info: FormatInfo,
+) -> Result<Migration, MerkleError> {
+    let (_, mut proofs) = packed::read_packed(bytes)?;
+    let root = proofs.root_hash().to_vec();
+    let mut notes = Vec::new();
+    if proofs.is_empty() {
+        // No proof to verify, so the tag can't be re-derived
+        if let Some(domain) = domain {
+            proofs.config.domain = Some(domain.to_vec());
+            notes.push("no proofs: took the domain tag on trust".to_string());
+        } else {
+            notes.push("no proofs: assumed untagged".to_string());
+        }
+    } else if proofs.verify_all(&root) {
+        notes.push("proofs verify untagged".to_string());
+        if domain.is_some() {
+            notes.push("ignored the domain tag given".to_string());
+        }
+    } else {
+        let Some(domain) = domain else {
+            return Err(MerkleError::CannotMigrate(
+                "proofs don't verify untagged; pass the domain tag their tree was built with"
+                    .to_string(),
+            ));
+        };
+        proofs.config.domain = Some(domain.to_vec());
+        if !proofs.verify_all(&root) {
+            return Err(MerkleError::CannotMigrate(
+                "proofs don't verify untagged or under the given domain tag".to_string(),
+            ));
+        }
+        notes.push("proofs verify under the given domain tag".to_string());
+    }
+
+    let mut migrated = Vec::new();
+    proofs.write_to(&mut migrated)?;
+    Ok(Migration {
+        bytes: migrated,
+        format: info.name,
+        from_version: info.version,
+        to_version: info.current_version,
+        notes,
+    })
+}
@@ -0,0 +1,537 @@
+//! Trees compatible with OpenZeppelin's JavaScript `StandardMerkleTree`.
+//!
+//! `@openzeppelin/merkle-tree` is the usual way Solidity projects build
+//! allowlist and airdrop trees, checked on chain by `MerkleProof.verify`.
+//! [`StandardMerkleTree`] reproduces it exactly so generation can move off
+//! Node.js without the root changing:
+//!
+//! - a leaf is `keccak256(keccak256(abi.encode(values...)))`, the double
+//!   hash keeping leaves from ever being read as internal nodes;
+//! - leaves are sorted by hash before the tree is built;
+//! - a parent is the Keccak-256 of its two children in sorted order, so
+//!   proofs carry no left/right flags;
+//! - nodes are laid out as a complete binary tree in one array, root first,
+//!   leaves at the end in reverse order.
+//!
+//! Values are JSON, like the library's own: addresses, hex strings for
+//! `bytes` and `bytesN`, decimal or `0x` hex strings or plain numbers for
+//! integers, booleans and strings. Array types such as `uint256[]` and
+//! `address[3]` are supported; tuples are not. [`StandardMerkleTree::dump`]
+//! and [`StandardMerkleTree::load`] read and write the library's
+//! `standard-v1` JSON tree description.
+
+use crate::MerkleError;
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+
+/// A 32-byte leaf or internal node
+pub type Hash = [u8; 32];
+
+const DUMP_FORMAT: &str = "standard-v1";
+
+fn keccak(data: &[u8]) -> Hash {
+    Keccak256::digest(data).into()
+}
+
+/// Hashes two nodes into their parent, smaller first
+pub fn hash_pair(a: &Hash, b: &Hash) -> Hash {
+    let (low, high) = if a <= b { (a, b) } else { (b, a) };
+    let mut hasher = Keccak256::new();
+    hasher.update(low);
+    hasher.update(high);
+    hasher.finalize().into()
+}
+
+/// Returns the leaf hash of `values` ABI-encoded as `types`
+pub fn leaf_hash<T: AsRef<str>>(types: &[T], values: &[Value]) -> Result<Hash, MerkleError> {
+    Ok(keccak(&keccak(&abi_encode(types, values)?)))
+}
+
+/// Returns the root `proof` leads to from `leaf`, as `MerkleProof.processProof`
+/// computes it on chain
+pub fn process_proof(leaf: &Hash, proof: &[Hash]) -> Hash {
+    proof
+        .iter()
+        .fold(*leaf, |node, sibling| hash_pair(&node, sibling))
+}
+
+/// Returns true if `proof` leads from `leaf` to `root`
+pub fn verify(root: &Hash, leaf: &Hash, proof: &[Hash]) -> bool {
+    process_proof(leaf, proof) == *root
+}
+
+/// A tree built the way `StandardMerkleTree.of` builds one
+#[derive(Debug, Clone, PartialEq)]
+pub struct StandardMerkleTree {
+    /// Every node, root first
+    tree: Vec<Hash>,
+    /// Each value in the order given, with the position of its leaf
+    values: Vec<(Vec<Value>, usize)>,
+    leaf_encoding: Vec<String>,
+}
+
+impl StandardMerkleTree {
+    /// Builds a tree over `values`, each ABI-encoded as `leaf_encoding`
+    pub fn of<T: AsRef<str>>(
+        values: Vec<Vec<Value>>,
+        leaf_encoding: &[T],
+    ) -> Result<Self, MerkleError> {
+        let leaf_encoding: Vec<String> = leaf_encoding
+            .iter()
+            .map(|t| t.as_ref().to_string())
+            .collect();
+        if values.is_empty() {
+            return Err(MerkleError::EmptyTree);
+        }
+
+        let hashes = values
+            .iter()
+            .map(|value| leaf_hash(&leaf_encoding, value))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut order: Vec<usize> = (0..values.len()).collect();
+        order.sort_by_key(|&index| hashes[index]);
+
+        // Leaves fill the end of the array in reverse, smallest hash last
+        let len = 2 * values.len() - 1;
+        let mut tree = vec![[0u8; 32]; len];
+        let mut tree_index = vec![0; values.len()];
+        for (position, &index) in order.iter().enumerate() {
+            tree[len - 1 - position] = hashes[index];
+            tree_index[index] = len - 1 - position;
+        }
+        for node in (0..len - values.len()).rev() {
+            tree[node] = hash_pair(&tree[2 * node + 1], &tree[2 * node + 2]);
+        }
+
+        Ok(StandardMerkleTree {
+            tree,
+            values: values.into_iter().zip(tree_index).collect(),
+            leaf_encoding,
+        })
+    }
+
+    /// Returns the root hash
+    pub fn root(&self) -> Hash {
+        self.tree[0]
+    }
+
+    /// Returns the number of values
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns true if the tree holds no values
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the ABI types each value is encoded as
+    pub fn leaf_encoding(&self) -> &[String] {
+        &self.leaf_encoding
+    }
+
+    /// Returns the value at `index`, in the order the tree was built from
+    pub fn value(&self, index: usize) -> Option<&[Value]> {
+        self.values.get(index).map(|(value, _)| value.as_slice())
+    }
+
+    /// Returns the leaf hash of the value at `index`
+    pub fn leaf(&self, index: usize) -> Option<Hash> {
+        let &(_, tree_index) = self.values.get(index)?;
+        Some(self.tree[tree_index])
+    }
+
+    /// Returns the proof for the value at `index`, leaf level first
+    pub fn proof(&self, index: usize) -> Option<Vec<Hash>> {
+        let &(_, mut node) = self.values.get(index)?;
+        let mut proof = Vec::new();
+        while node > 0 {
+            let sibling = if node % 2 == 1 { node + 1 } else { node - 1 };
+            proof.push(self.tree[sibling]);
+            node = (node - 1) / 2;
+        }
+        Some(proof)
+    }
+
+    /// Returns the index of the first value equal to `value`
+    pub fn position(&self, value: &[Value]) -> Option<usize> {
+        self.values
+            .iter()
+            .position(|(candidate, _)| candidate.as_slice() == value)
+    }
+
+    /// Writes the `standard-v1` JSON tree description
+    pub fn dump(&self) -> String {
+        let values: Vec<Value> = self
+            .values
+            .iter()
+            .map(|(value, tree_index)| json!({ "value": value, "treeIndex": tree_index }))
+            .collect();
+        let tree: Vec<String> = self
+            .tree
+            .iter()
+            .map(|node| format!("0x{}", hex::encode(node)))
+            .collect();
+        json!({
+            "format": DUMP_FORMAT,
+            "leafEncoding": self.leaf_encoding,
+            "tree": tree,
+            "values": values,
+        })
+        .to_string()
+    }
+
+    /// Reads a tree description written by [`StandardMerkleTree::dump`] or
+    /// by the JavaScript library
+    ///
+    /// Every leaf is re-derived from its value and every parent from its
+    /// children, so a loaded tree is as trustworthy as a built one.
+    pub fn load(json: &str) -> Result<Self, MerkleError> {
+        let error = |message: &str| MerkleError::Parse(format!("standard tree: {}", message));
+        let document: Value = serde_json::from_str(json).map_err(|e| error(&e.to_string()))?;
+
+        if document.get("format").and_then(Value::as_str) != Some(DUMP_FORMAT) {
+            return Err(error("unknown format"));
+        }
+        let leaf_encoding = document
+            .get("leafEncoding")
+            .and_then(Value::as_array)
+            .and_then(|types| {
+                types
+                    .iter()
+                    .map(|t| t.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>()
+            })
+            .ok_or_else(|| error("invalid `leafEncoding`"))?;
+        let tree = document
+            .get("tree")
+            .and_then(Value::as_array)
+            .ok_or_else(|| error("missing `tree` array"))?
+            .iter()
+            .map(|node| {
+                node.as_str()
+                    .and_then(|node| node.strip_prefix("0x"))
+                    .and_then(|node| hex::decode(node).ok())
+                    .and_then(|node| node.try_into().ok())
+                    .ok_or_else(|| error("invalid node"))
+            })
+            .collect::<Result<Vec<Hash>, _>>()?;
+        let values = document
+            .get("values")
+            .and_then(Value::as_array)
+            .ok_or_else(|| error("missing `values` array"))?
+            .iter()
+            .map(|entry| {
+                let value = entry.get("value").and_then(Value::as_array);
+                let tree_index = entry
+                    .get("treeIndex")
+                    .and_then(Value::as_u64)
+                    .and_then(|index| usize::try_from(index).ok());
+                match (value, tree_index) {
+                    (Some(value), Some(tree_index)) => Ok((value.clone(), tree_index)),
+                    _ => Err(error("invalid value entry")),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if tree.is_empty() || values.is_empty() {
+            return Err(MerkleError::EmptyTree);
+        }
+        // One leaf per value, so every leaf is accounted for
+        if tree.len() != 2 * values.len() - 1 {
+            return Err(error("`tree` does not have one leaf per value"));
+        }
+        let first_leaf = tree.len() / 2;
+        let mut seen = vec![false; tree.len()];
+        for (value, tree_index) in &values {
+            if *tree_index < first_leaf || *tree_index >= tree.len() {
+                return Err(error("`treeIndex` is not a leaf"));
+            }
+            if std::mem::replace(&mut seen[*tree_index], true) {
+                return Err(error("`treeIndex` is used twice"));
+            }
+            if leaf_hash(&leaf_encoding, value)? != tree[*tree_index] {
+                return Err(error("leaf does not match its value"));
+            }
+        }
+        for node in 0..first_leaf {
+            if tree[node] != hash_pair(&tree[2 * node + 1], &tree[2 * node + 2]) {
+                return Err(error("node does not match its children"));
+            }
+        }
+
+        Ok(StandardMerkleTree {
+            tree,
+            values,
+            leaf_encoding,
+        })
+    }
+}
+
+/// An ABI type a leaf value can be encoded as
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum AbiType {
+    Address,
+    Bool,
+    Uint(usize),
+    Int(usize),
+    FixedBytes(usize),
+    Bytes,
+    String,
+    Array(Box<AbiType>),
+    FixedArray(Box<AbiType>, usize),
+}
+
+impl AbiType {
+    fn parse(name: &str) -> Option<Self> {
+        if let Some(element) = name.strip_suffix("[]") {
+            return Some(AbiType::Array(Box::new(AbiType::parse(element)?)));
+        }
+        if let Some(rest) = name.strip_suffix(']') {
+            let (element, len) = rest.rsplit_once('[')?;
+            return Some(AbiType::FixedArray(
+                Box::new(AbiType::parse(element)?),
+                len.parse().ok()?,
+            ));
+        }
+
+        let bits = |suffix: &str| -> Option<usize> {
+            let bits = if suffix.is_empty() {
+                256
+            } else {
+                suffix.parse().ok()?
+            };
+            (bits > 0 && bits <= 256 && bits % 8 == 0).then_some(bits)
+        };
+        Some(match name {
+            "address" => AbiType::Address,
+            "bool" => AbiType::Bool,
+            "bytes" => AbiType::Bytes,
+            "string" => AbiType::String,
+            _ => {
+                if let Some(suffix) = name.strip_prefix("uint") {
+                    AbiType::Uint(bits(suffix)?)
+                } else if let Some(suffix) = name.strip_prefix("int") {
+                    AbiType::Int(bits(suffix)?)
+                } else {
+                    let len: usize = name.strip_prefix("bytes")?.parse().ok()?;
+                    (1..=32)
+                        .contains(&len)
+                        .then_some(AbiType::FixedBytes(len))?
+                }
+            }
+        })
+    }
+
+    fn is_dynamic(&self) -> bool {
+        match self {
+            AbiType::Bytes | AbiType::String | AbiType::Array(_) => true,
+            AbiType::FixedArray(element, _) => element.is_dynamic(),
+            _ => false,
+        }
+    }
+}
+
+/// Returns `abi.encode(values...)` for `values` of the given types
+pub fn abi_encode<T: AsRef<str>>(types: &[T], values: &[Value]) -> Result<Vec<u8>, MerkleError> {
+    if types.len() != values.len() {
+        return Err(MerkleError::Encode(format!(
+            "{} values for {} types",
+            values.len(),
+            types.len()
+        )));
+    }
+    let types = types
+        .iter()
+        .map(|name| {
+            let name = name.as_ref();
+            AbiType::parse(name)
+                .ok_or_else(|| MerkleError::Encode(format!("unsupported ABI type {}", name)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let values: Vec<&Value> = values.iter().collect();
+    encode_tuple(&types.iter().collect::<Vec<_>>(), &values)
+}
+
+/// Encodes a sequence of values as heads followed by the tails of the
+/// dynamic ones
+fn encode_tuple(types: &[&AbiType], values: &[&Value]) -> Result<Vec<u8>, MerkleError> {
+    let head_len: usize = types.iter().map(|t| head_size(t)).sum();
+    let mut head = Vec::with_capacity(head_len);
+    let mut tail = Vec::new();
+
+    for (abi_type, value) in types.iter().zip(values) {
+        let encoded = encode_value(abi_type, value)?;
+        if abi_type.is_dynamic() {
+            head.extend_from_slice(&word_from_u64((head_len + tail.len()) as u64));
+            tail.extend(encoded);
+        } else {
+            head.extend(encoded);
+        }
+    }
+
+    head.extend(tail);
+    Ok(head)
+}
+
+/// Bytes a value of `abi_type` takes in the head of its enclosing tuple
+fn head_size(abi_type: &AbiType) -> usize {
+    match abi_type {
+        AbiType::FixedArray(element, len) if !element.is_dynamic() => head_size(element) * len,
+        _ => 32,
+    }
+}
+
+fn encode_value(abi_type: &AbiType, value: &Value) -> Result<Vec<u8>, MerkleError> {
+    let invalid = || MerkleError::Encode(format!("invalid {:?} value {}", abi_type, value));
+
+    Ok(match abi_type {
+        AbiType::Address => {
+            let address = hex_value(value)
+                .filter(|a| a.len() == 20)
+                .ok_or_else(invalid)?;
+            left_pad(&address).to_vec()
+        }
+        AbiType::Bool => word_from_u64(value.as_bool().ok_or_else(invalid)?.into()).to_vec(),
+        AbiType::Uint(bits) => {
+            let (negative, magnitude) = parse_integer(value).ok_or_else(invalid)?;
+            if negative || !fits(&magnitude, *bits) {
+                return Err(invalid());
+            }
+            magnitude.to_vec()
+        }
+        AbiType::Int(bits) => {
+            let (negative, magnitude) = parse_integer(value).ok_or_else(invalid)?;
+            let limit = power_of_two(bits - 1);
+            let in_range = if negative {
+                magnitude <= limit
+            } else {
+                magnitude < limit
+            };
+            if !in_range {
+                return Err(invalid());
+            }
+            if negative {
+                negate(magnitude).to_vec()
+            } else {
+                magnitude.to_vec()
+            }
+        }
+        AbiType::FixedBytes(len) => {
+            let bytes = hex_value(value)
+                .filter(|b| b.len() == *len)
+                .ok_or_else(invalid)?;
+            right_pad(&bytes)
+        }
+        AbiType::Bytes => encode_bytes(&hex_value(value).ok_or_else(invalid)?),
+        AbiType::String => encode_bytes(value.as_str().ok_or_else(invalid)?.as_bytes()),
+        AbiType::Array(element) => {
+            let elements = value.as_array().ok_or_else(invalid)?;
+            let mut encoded = word_from_u64(elements.len() as u64).to_vec();
+            encoded.extend(encode_tuple(
+                &vec![&**element; elements.len()],
+                &elements.iter().collect::<Vec<_>>(),
+            )?);
+            encoded
+        }
+        AbiType::FixedArray(element, len) => {
+            let elements = value
+                .as_array()
+                .filter(|elements| elements.len() == *len)
+                .ok_or_else(invalid)?;
+            encode_tuple(
+                &vec![&**element; *len],
+                &elements.iter().collect::<Vec<_>>(),
+            )?
+        }
+    })
+}
+
+/// Encodes a length word followed by `bytes` padded to whole words
+fn encode_bytes(bytes: &[u8]) -> Vec<u8> {
+    let mut encoded = word_from_u64(bytes.len() as u64).to_vec();
+    encoded.extend(right_pad(bytes));
+    encoded
+}
+
+fn hex_value(value: &Value) -> Option<Vec<u8>> {
+    hex::decode(value.as_str()?.strip_prefix("0x")?).ok()
+}
+
+fn word_from_u64(value: u64) -> Hash {
+    left_pad(&value.to_be_bytes())
+}
+
+fn left_pad(bytes: &[u8]) -> Hash {
+    let mut word = [0u8; 32];
+    word[32 - bytes.len()..].copy_from_slice(bytes);
+    word
+}
+
+fn right_pad(bytes: &[u8]) -> Vec<u8> {
+    let mut padded = bytes.to_vec();
+    padded.resize(bytes.len().div_ceil(32) * 32, 0);
+    padded
+}
+
+/// Parses a JSON number or a decimal or `0x` hex string into a sign and a
+/// 256-bit big-endian magnitude
+fn parse_integer(value: &Value) -> Option<(bool, Hash)> {
+    if let Some(number) = value.as_u64() {
+        return Some((false, word_from_u64(number)));
+    }
+    if let Some(number) = value.as_i64() {
+        return Some((true, word_from_u64(number.unsigned_abs())));
+    }
+
+    let text = value.as_str()?;
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let (radix, digits) = match digits.strip_prefix("0x") {
+        Some(digits) => (16, digits),
+        None => (10, digits),
+    };
+    if digits.is_empty() {
+        return None;
+    }
+
+    let mut magnitude = [0u8; 32];
+    for digit in digits.chars() {
+        let mut carry = digit.to_digit(radix)?;
+        for byte in magnitude.iter_mut().rev() {
+            let product = *byte as u32 * radix + carry;
+            *byte = product as u8;
+            carry = product >> 8;
+        }
+        if carry != 0 {
+            return None;
+        }
+    }
+    Some((negative, magnitude))
+}
+
+/// Returns true if `magnitude` is below `2^bits`
+fn fits(magnitude: &Hash, bits: usize) -> bool {
+    magnitude[..32 - bits / 8].iter().all(|&byte| byte == 0)
+}
+
+fn power_of_two(bits: usize) -> Hash {
+    let mut word = [0u8; 32];
+    word[31 - bits / 8] = 1 << (bits % 8);
+    word
+}
+
+/// Returns the two's complement of `magnitude`
+fn negate(magnitude: Hash) -> Hash {
+    let mut word = magnitude.map(|byte| !byte);
+    for byte in word.iter_mut().rev() {
+        let (sum, overflow) = byte.overflowing_add(1);
+        *byte = sum;
+        if !overflow {
+            break;
+        }
+    }
+    word
+}
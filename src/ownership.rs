@@ -0,0 +1,146 @@
+//! Telling why a proof doesn't belong to a tree.
+//!
+//! [`MerkleTree::verify_proof`] answers with a bare `false` whether the
+//! proof is for another leaf, another version of the tree or a tree hashed
+//! some other way entirely. [`MerkleTree::owns_proof`] checks the shape of
+//! the proof against the tree first, hash lengths, hasher, depth and leaf
+//! position, and says which of them is off.
+
+use crate::{MerkleHasher, MerkleProof, MerkleTree, Side};
+use std::error::Error;
+use std::fmt;
+
+/// Why a proof can't have come from a tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigMismatch {
+    /// The tree has no leaves, so no proof belongs to it
+    EmptyTree,
+    /// A hash in the proof is not the length the tree's hasher produces
+    HashLength { expected: usize, actual: usize },
+    /// The proof was made by a hasher with a different
+    /// [`MerkleHasher::config_id`], such as another algorithm or domain tag
+    ConfigId { tree: Vec<u8>, proof: Vec<u8> },
+    /// The proof has a different number of levels than every path in the
+    /// tree
+    Depth {
+        expected: usize,
+        actual: usize,
+        tree_size: usize,
+    },
+    /// The sibling sides put the leaf past the end of the tree
+    Position { index: u64, tree_size: usize },
+    /// The proof fits the tree but was made against another root, typically
+    /// before the tree last changed
+    Root { tree: Vec<u8>, proof: Vec<u8> },
+}
+
+impl fmt::Display for ConfigMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigMismatch::EmptyTree => write!(f, "tree has no leaves"),
+            ConfigMismatch::HashLength { expected, actual } => write!(
+                f,
+                "proof holds {}-byte hashes but the tree hashes to {} bytes",
+                actual, expected
+            ),
+            ConfigMismatch::ConfigId { tree, proof } => write!(
+                f,
+                "proof was made with hasher {} but the tree uses {}; check the algorithm and \
+                 domain tag",
+                hex::encode(proof),
+                hex::encode(tree)
+            ),
+            ConfigMismatch::Depth {
+                expected,
+                actual,
+                tree_size,
+            } => write!(
+                f,
+                "proof has {} levels but a tree of {} leaves needs {}",
+                actual, tree_size, expected
+            ),
+            ConfigMismatch::Position { index, tree_size } => write!(
+                f,
+                "proof is for leaf {} but the tree has {} leaves",
+                index, tree_size
+            ),
+            ConfigMismatch::Root { .. } => {
+                write!(f, "proof was made against a different root of the tree")
+            }
+        }
+    }
+}
+
+impl Error for ConfigMismatch {}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Checks that `proof` could have come from this tree
+    ///
+    /// The checks only compare the proof's shape and configuration with the
+    /// tree's; a proof that passes still has to [`MerkleProof::verify`].
+    pub fn owns_proof(&self, proof: &MerkleProof<H>) -> Result<(), ConfigMismatch> {
+        let root = self.root_hash().ok_or(ConfigMismatch::EmptyTree)?;
+
+        let hashes = std::iter::once(proof.leaf_hash())
+            .chain(std::iter::once(proof.root_hash()))
+            .chain(proof.siblings().iter().map(|(hash, _)| hash.as_slice()));
+        for hash in hashes {
+            if hash.len() != root.len() {
+                return Err(ConfigMismatch::HashLength {
+                    expected: root.len(),
+                    actual: hash.len(),
+                });
+            }
+        }
+
+        self.proof_position(proof)?;
+
+        if proof.root_hash() != root {
+            return Err(ConfigMismatch::Root {
+                tree: root,
+                proof: proof.root_hash().to_vec(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the leaf index `proof` is for, after checking that its hasher
+    /// and depth are the tree's and that the index is inside the tree
+    ///
+    /// [`MerkleTree::owns_proof`] and [`MerkleTree::verify_proof_explain`]
+    /// both start from these checks.
+    pub(crate) fn proof_position(&self, proof: &MerkleProof<H>) -> Result<u64, ConfigMismatch> {
+        let tree_id = self.merkle_hasher().config_id();
+        let proof_id = proof.merkle_hasher().config_id();
+        if tree_id != proof_id {
+            return Err(ConfigMismatch::ConfigId {
+                tree: tree_id,
+                proof: proof_id,
+            });
+        }
+
+        let depth = self.levels.len() - 1;
+        if proof.siblings().len() != depth {
+            return Err(ConfigMismatch::Depth {
+                expected: depth,
+                actual: proof.siblings().len(),
+                tree_size: self.len(),
+            });
+        }
+
+        // A sibling on the left means the node at that level is a right child
+        let index = proof
+            .siblings()
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, side))| *side == Side::Left)
+            .fold(0u64, |index, (level, _)| index | 1 << level);
+        if index >= self.len() as u64 {
+            return Err(ConfigMismatch::Position {
+                index,
+                tree_size: self.len(),
+            });
+        }
+        Ok(index)
+    }
+}
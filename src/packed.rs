@@ -0,0 +1,331 @@
+//! Many proofs from one tree, with every shared hash stored once.
+//!
+//! Proofs for neighbouring leaves repeat most of their upper path. A
+//! [`PackedProofs`] stores the leaf hashes of the proven leaves and then
+//! each distinct sibling node once. Which nodes are needed, and in what
+//! order, follows from the tree size and the leaf indices alone, so node
+//! positions are never written down. A claims file for every leaf of a
+//! tree holds about two hashes per leaf instead of one per level per leaf.
+//! Leaves that are proven themselves are not repeated as siblings.
+//!
+//! The encoding is big-endian:
+//!
+//! ```text
+//! "SMTP" | version: u8 | tagged: u8 | [domain_len: u16 | domain]
+//!        | tree_size: u64 | root: [u8; 32] | count: u64
+//!        | index: u64 * count | leaf hash: [u8; 32] * count
+//!        | node hash: [u8; 32] * (number implied by the indices)
+//! ```
+//!
+//! `tagged` is 1 when the tree has a domain tag, which then follows, and 0
+//! otherwise. Indices are strictly increasing and the node hashes are
+//! ordered by level, then by position within the level. Version 1 files
+//! have no `tagged` byte or domain, so they read back untagged;
+//! [`migrate`](crate::migrate()) upgrades them given the tag.
+//!
+//! Packing works for trees with any [`MerkleHasher`], but the encoding only
+//! has room for a [`TreeConfig`]'s tag, so only proofs that hash as a
+//! `TreeConfig` can be written, and they read back with one.
+
+use crate::{MerkleError, MerkleHasher, MerkleProof, MerkleTree, Side, TreeConfig};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+pub(crate) const MAGIC: &[u8; 4] = b"SMTP";
+pub(crate) const FORMAT_VERSION: u8 = 2;
+const HASH_LEN: usize = 32;
+
+/// Proofs for a set of leaves of one tree, sharing common hashes
+///
+/// The proofs carry the hasher of the tree they came from and verify with
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedProofs<H: MerkleHasher = TreeConfig> {
+    pub(crate) tree_size: u64,
+    root_hash: Vec<u8>,
+    pub(crate) indices: Vec<u64>,
+    pub(crate) leaf_hashes: Vec<Vec<u8>>,
+    /// Sibling hashes keyed by (level, position)
+    pub(crate) nodes: BTreeMap<(usize, u64), Vec<u8>>,
+    pub(crate) config: H,
+}
+
+/// Returns the (level, position) of every sibling the proofs for `indices`
+/// need, in encoding order
+///
+/// A node paired with itself is its own sibling. Leaves that are proven
+/// themselves are left out, since their hashes are stored already.
+fn sibling_positions(tree_size: u64, indices: &[u64]) -> Vec<(usize, u64)> {
+    let mut positions = Vec::new();
+    let mut level_nodes: Vec<u64> = indices.to_vec();
+    let mut level_len = tree_size;
+    let mut level = 0;
+
+    // Walk up level by level until only the root is left
+    while level == 0 || level_len > 1 {
+        let mut siblings: Vec<u64> = level_nodes
+            .iter()
+            .map(|&position| {
+                let sibling = position ^ 1;
+                if sibling < level_len {
+                    sibling
+                } else {
+                    position
+                }
+            })
+            .collect();
+        siblings.sort_unstable();
+        siblings.dedup();
+        if level == 0 {
+            siblings.retain(|position| indices.binary_search(position).is_err());
+        }
+        positions.extend(siblings.into_iter().map(|position| (level, position)));
+
+        level_nodes = level_nodes.iter().map(|position| position / 2).collect();
+        level_nodes.dedup();
+        level_len = level_len.div_ceil(2);
+        level += 1;
+    }
+
+    positions
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Packs proofs for the leaves at `indices`
+    ///
+    /// Returns `None` if the tree is empty or an index is out of bounds.
+    /// Duplicate indices are proven once.
+    pub fn pack_proofs(&self, indices: &[usize]) -> Option<PackedProofs<H>> {
+        let root_hash = self.root_hash()?;
+        let mut indices: Vec<u64> = indices.iter().map(|&index| index as u64).collect();
+        indices.sort_unstable();
+        indices.dedup();
+        if indices
+            .last()
+            .is_some_and(|&index| index >= self.len() as u64)
+        {
+            return None;
+        }
+
+        let tree_size = self.len() as u64;
+        let nodes = sibling_positions(tree_size, &indices)
+            .into_iter()
+            .map(|(level, position)| {
+                let hash = self.levels[level][position as usize].clone();
+                ((level, position), hash)
+            })
+            .collect();
+
+        Some(PackedProofs {
+            tree_size,
+            root_hash,
+            leaf_hashes: indices
+                .iter()
+                .map(|&index| self.levels[0][index as usize].clone())
+                .collect(),
+            indices,
+            nodes,
+            config: self.config.clone(),
+        })
+    }
+}
+
+impl<H: MerkleHasher> PackedProofs<H> {
+    /// Returns the size of the tree the proofs are from
+    pub fn tree_size(&self) -> u64 {
+        self.tree_size
+    }
+
+    /// Returns the root hash the proofs lead to
+    pub fn root_hash(&self) -> &[u8] {
+        &self.root_hash
+    }
+
+    /// Returns the proven leaf indices in increasing order
+    pub fn indices(&self) -> &[u64] {
+        &self.indices
+    }
+
+    /// Returns the number of proofs
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Returns true if no leaves are proven
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Returns the hasher the proofs verify with
+    pub fn merkle_hasher(&self) -> &H {
+        &self.config
+    }
+
+    /// Rebuilds the proof for leaf `index`, if it is in the set
+    pub fn proof(&self, index: u64) -> Option<MerkleProof<H>> {
+        let slot = self.indices.binary_search(&index).ok()?;
+        let mut proof_hashes = Vec::new();
+        let mut position = index;
+        let mut level_len = self.tree_size;
+        let mut level = 0;
+
+        while level == 0 || level_len > 1 {
+            let sibling = if position ^ 1 < level_len {
+                position ^ 1
+            } else {
+                position
+            };
+            let hash = match self.nodes.get(&(level, sibling)) {
+                Some(hash) => hash,
+                None => &self.leaf_hashes[self.indices.binary_search(&sibling).ok()?],
+            };
+            proof_hashes.push((hash.clone(), Side::of_sibling(position)));
+
+            position /= 2;
+            level_len = level_len.div_ceil(2);
+            level += 1;
+        }
+
+        Some(MerkleProof {
+            proof_hashes,
+            leaf_hash: self.leaf_hashes[slot].clone(),
+            root_hash: self.root_hash.clone(),
+            config: self.config.clone(),
+        })
+    }
+
+    /// Rebuilds every proof, in index order
+    pub fn proofs(&self) -> impl Iterator<Item = MerkleProof<H>> + '_ {
+        self.indices.iter().map(|&index| self.proof(index).unwrap())
+    }
+
+    /// Returns true if every proof leads to `root_hash`
+    pub fn verify_all(&self, root_hash: &[u8]) -> bool {
+        self.proofs().all(|proof| proof.verify(root_hash))
+    }
+
+    /// Writes the packed encoding described in the module docs
+    ///
+    /// Fails with [`MerkleError::ConfigMismatch`] unless the proofs hash as
+    /// a [`TreeConfig`] does, since only its domain tag is recorded.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), MerkleError> {
+        let config = TreeConfig::matching(&self.config).ok_or(MerkleError::ConfigMismatch)?;
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION])?;
+        match &config.domain {
+            Some(domain) => {
+                let len = u16::try_from(domain.len()).map_err(|_| {
+                    MerkleError::Encode("packed proofs: domain tag is too long".to_string())
+                })?;
+                writer.write_all(&[1])?;
+                writer.write_all(&len.to_be_bytes())?;
+                writer.write_all(domain)?;
+            }
+            None => writer.write_all(&[0])?,
+        }
+        writer.write_all(&self.tree_size.to_be_bytes())?;
+        writer.write_all(&self.root_hash)?;
+        writer.write_all(&(self.indices.len() as u64).to_be_bytes())?;
+        for index in &self.indices {
+            writer.write_all(&index.to_be_bytes())?;
+        }
+        for hash in self.leaf_hashes.iter().chain(self.nodes.values()) {
+            writer.write_all(hash)?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl PackedProofs {
+    /// Reads proofs written by [`PackedProofs::write_to`]
+    ///
+    /// Version 1 files read back untagged.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, MerkleError> {
+        Ok(read_packed(reader)?.1)
+    }
+}
+
+/// Reads packed proofs of any supported version, returning the version too
+pub(crate) fn read_packed<R: Read>(mut reader: R) -> Result<(u8, PackedProofs), MerkleError> {
+    let error = |message: &str| MerkleError::Parse(format!("packed proofs: {}", message));
+
+    let mut header = [0u8; 5];
+    reader.read_exact(&mut header)?;
+    let version = header[4];
+    if &header[..4] != MAGIC || !(1..=FORMAT_VERSION).contains(&version) {
+        return Err(error("not a supported packed proof file"));
+    }
+
+    let domain = match version {
+        1 => None,
+        _ => {
+            let mut tagged = [0u8];
+            reader.read_exact(&mut tagged)?;
+            match tagged[0] {
+                0 => None,
+                1 => {
+                    let mut len = [0u8; 2];
+                    reader.read_exact(&mut len)?;
+                    let mut domain = vec![0u8; u16::from_be_bytes(len).into()];
+                    reader.read_exact(&mut domain)?;
+                    Some(domain)
+                }
+                _ => return Err(error("invalid domain flag")),
+            }
+        }
+    };
+
+    let tree_size = read_u64(&mut reader)?;
+    let root_hash = read_hash(&mut reader)?;
+    let count = read_u64(&mut reader)?;
+    if count > tree_size {
+        return Err(error("more proofs than leaves"));
+    }
+
+    let mut indices = Vec::new();
+    for _ in 0..count {
+        let index = read_u64(&mut reader)?;
+        if index >= tree_size || indices.last().is_some_and(|&last| index <= last) {
+            return Err(error("indices must be increasing and within the tree"));
+        }
+        indices.push(index);
+    }
+
+    let leaf_hashes = (0..count)
+        .map(|_| read_hash(&mut reader))
+        .collect::<Result<_, _>>()?;
+    let nodes = sibling_positions(tree_size, &indices)
+        .into_iter()
+        .map(|position| Ok((position, read_hash(&mut reader)?)))
+        .collect::<Result<_, MerkleError>>()?;
+
+    if reader.read(&mut [0u8])? != 0 {
+        return Err(error("trailing bytes"));
+    }
+
+    Ok((
+        version,
+        PackedProofs {
+            tree_size,
+            root_hash,
+            indices,
+            leaf_hashes,
+            nodes,
+            config: TreeConfig::from_domain(domain),
+        },
+    ))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, MerkleError> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_be_bytes(bytes))
+}
+
+fn read_hash<R: Read>(reader: &mut R) -> Result<Vec<u8>, MerkleError> {
+    let mut hash = vec![0u8; HASH_LEN];
+    reader.read_exact(&mut hash)?;
+    Ok(hash)
+}
@@ -0,0 +1,90 @@
+//! Verifying wide packed proofs across threads.
+//!
+//! [`PackedProofs::verify_all`] rebuilds and folds every proof on its own,
+//! rehashing the upper levels the proofs share once per leaf. For a set
+//! covering thousands of leaves, such as every transaction of a block,
+//! [`PackedProofs::verify_parallel`] reconstructs the tree a level at a
+//! time instead: the frontier of known nodes on one level is hashed into
+//! the next with rayon, so each shared node is computed once and each
+//! level's hashes are spread over the thread pool.
+
+use crate::{MerkleHasher, PackedProofs};
+use rayon::prelude::*;
+
+impl<H: MerkleHasher + Sync> PackedProofs<H> {
+    /// Returns true if every proof leads to `root_hash`, as
+    /// [`PackedProofs::verify_all`] does, hashing each level in parallel
+    ///
+    /// Stored siblings that the frontier also computes must match, so the
+    /// answer is the same as checking proof by proof.
+    pub fn verify_parallel(&self, root_hash: &[u8]) -> bool {
+        if self.indices.is_empty() {
+            return true;
+        }
+
+        let config = &self.config;
+        // Known nodes of the current level, by increasing position
+        let mut frontier: Vec<(u64, Vec<u8>)> = self
+            .indices
+            .iter()
+            .copied()
+            .zip(self.leaf_hashes.iter().cloned())
+            .collect();
+        let mut level_len = self.tree_size;
+        let mut level = 0;
+
+        while level == 0 || level_len > 1 {
+            // Split the frontier into the children of each parent, one or two
+            let mut groups: Vec<&[(u64, Vec<u8>)]> = Vec::new();
+            let mut rest = &frontier[..];
+            while let Some((first, tail)) = rest.split_first() {
+                let pair = tail.first().is_some_and(|next| next.0 == first.0 ^ 1);
+                let (group, tail) = rest.split_at(if pair { 2 } else { 1 });
+                groups.push(group);
+                rest = tail;
+            }
+
+            let next: Option<Vec<(u64, Vec<u8>)>> = groups
+                .par_iter()
+                .map(|group| {
+                    let mismatched = group.iter().any(|(position, hash)| {
+                        let stored = self.nodes.get(&(level, *position));
+                        stored.is_some_and(|stored| stored != hash)
+                    });
+                    if mismatched {
+                        return None;
+                    }
+
+                    let (position, hash) = &group[0];
+                    let parent = match group {
+                        [left, right] => config.hash_node(&left.1, &right.1),
+                        _ => {
+                            let sibling = position ^ 1;
+                            let sibling_hash = if sibling < level_len {
+                                self.nodes.get(&(level, sibling))?
+                            } else {
+                                // Padded
+                                &config.pad(hash)
+                            };
+                            if position % 2 == 0 {
+                                config.hash_node(hash, sibling_hash)
+                            } else {
+                                config.hash_node(sibling_hash, hash)
+                            }
+                        }
+                    };
+                    Some((position / 2, parent))
+                })
+                .collect();
+            let Some(next) = next else {
+                return false;
+            };
+
+            frontier = next;
+            level_len = level_len.div_ceil(2);
+            level += 1;
+        }
+
+        frontier[0].1 == root_hash
+    }
+}
@@ -0,0 +1,259 @@
+//! Root computation over a stream, in bounded memory.
+//!
+//! [`pipelined_root`] runs two stages at once. Worker threads hash batches
+//! of leaves and reduce each full batch to the root of its subtree, and a
+//! reducer folds those roots into a frontier of at most one pending node
+//! per level. Stages are joined by bounded channels, so a slow stage holds
+//! up the ones before it instead of letting work pile up: memory stays at
+//! the frontier plus a few batches in flight, however long the stream.
+//!
+//! Only the root comes out, and it is the root
+//! [`MerkleTree::new`](crate::MerkleTree::new) would produce from the same
+//! items. WebAssembly builds, where threads can't be relied on, run both
+//! stages on the calling thread instead.
+
+use crate::{Progress, TreeConfig};
+use std::sync::mpsc::sync_channel;
+use std::thread;
+
+/// Tuning for [`pipelined_root_with`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineConfig {
+    /// Threads hashing leaves
+    pub workers: usize,
+    /// Leaves per batch, rounded up to a power of two
+    pub batch_size: usize,
+    /// Batches each channel holds before its sender waits
+    pub queue_depth: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig {
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            batch_size: 1024,
+            queue_depth: 4,
+        }
+    }
+}
+
+/// What a worker hands the reducer for one batch
+enum Batch {
+    /// Root of a full batch, a perfect subtree
+    Full(Vec<u8>),
+    /// Leaf hashes of the final, short batch
+    Partial(Vec<Vec<u8>>),
+}
+
+/// Pending right edge of a tree being built left to right
+struct Frontier<'a> {
+    config: &'a TreeConfig,
+    /// `levels[l]` is a node at level `l` still waiting for its right sibling
+    levels: Vec<Option<Vec<u8>>>,
+}
+
+impl<'a> Frontier<'a> {
+    fn new(config: &'a TreeConfig) -> Self {
+        Frontier {
+            config,
+            levels: Vec::new(),
+        }
+    }
+
+    /// Adds the next node at `level`, combining with waiting left siblings
+    ///
+    /// Every level below `level` must be empty.
+    fn push(&mut self, mut level: usize, mut hash: Vec<u8>) {
+        loop {
+            if self.levels.len() <= level {
+                self.levels.resize(level + 1, None);
+            }
+            match self.levels[level].take() {
+                Some(left) => {
+                    hash = self.config.hash_pair(&left, &hash);
+                    level += 1;
+                }
+                None => {
+                    self.levels[level] = Some(hash);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Closes off the right edge, pairing odd last nodes with themselves
+    fn finish(mut self) -> Option<Vec<u8>> {
+        let mut carry: Option<Vec<u8>> = None;
+
+        for level in 0..self.levels.len() {
+            let pending = self.levels[level].take();
+            let above = self.levels[level + 1..].iter().any(Option::is_some);
+
+            carry = match (pending, carry) {
+                (Some(left), Some(right)) => Some(self.config.hash_pair(&left, &right)),
+                // The last node of its level; alone on top it is the root,
+                // except that a single leaf is still hashed once
+                (Some(last), None) | (None, Some(last)) => {
+                    if above || level == 0 {
+                        Some(self.config.hash_pair(&last, &last))
+                    } else {
+                        return Some(last);
+                    }
+                }
+                (None, None) => None,
+            };
+        }
+
+        carry
+    }
+}
+
+/// Reduces a power-of-two run of hashes to the root over them
+fn reduce_perfect(mut nodes: Vec<Vec<u8>>, config: &TreeConfig) -> Vec<u8> {
+    while nodes.len() > 1 {
+        nodes = nodes
+            .chunks(2)
+            .map(|pair| config.hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    nodes.pop().unwrap()
+}
+
+/// Computes the root over `data` with the default [`PipelineConfig`]
+pub fn pipelined_root<I>(data: I) -> Option<Vec<u8>>
+where
+    I: IntoIterator<Item = Vec<u8>>,
+{
+    pipelined_root_with(data, &PipelineConfig::default())
+}
+
+/// Computes the root over `data`, returning `None` if it yields nothing
+pub fn pipelined_root_with<I>(data: I, config: &PipelineConfig) -> Option<Vec<u8>>
+where
+    I: IntoIterator<Item = Vec<u8>>,
+{
+    pipelined_root_with_progress(data, config, &())
+}
+
+/// Computes the root like [`pipelined_root_with`], reporting bytes taken
+/// from `data` and leaves hashed to `progress`
+pub fn pipelined_root_with_progress<I>(
+    data: I,
+    config: &PipelineConfig,
+    progress: &dyn Progress,
+) -> Option<Vec<u8>>
+where
+    I: IntoIterator<Item = Vec<u8>>,
+{
+    pipelined_root_for(data, &TreeConfig::default(), config, progress)
+}
+
+/// Computes the root a tree hashed as `tree` would have over `data`
+pub(crate) fn pipelined_root_for<I>(
+    data: I,
+    tree: &TreeConfig,
+    config: &PipelineConfig,
+    progress: &dyn Progress,
+) -> Option<Vec<u8>>
+where
+    I: IntoIterator<Item = Vec<u8>>,
+{
+    let workers = config.workers.max(1);
+    let batch_size = config.batch_size.max(1).next_power_of_two();
+    let batch_level = batch_size.trailing_zeros() as usize;
+    let queue_depth = config.queue_depth.max(1);
+    if cfg!(target_family = "wasm") {
+        return sequential_root(data, tree, batch_size, progress);
+    }
+
+    thread::scope(|scope| {
+        // Batches go to workers in turn, and the reducer reads results back
+        // in the same turn order, so no reordering is needed
+        let mut inputs = Vec::new();
+        let mut outputs = Vec::new();
+        for _ in 0..workers {
+            let (input, batches) = sync_channel::<Vec<Vec<u8>>>(queue_depth);
+            let (results, output) = sync_channel::<Batch>(queue_depth);
+
+            scope.spawn(move || {
+                for items in batches {
+                    let leaves: Vec<Vec<u8>> =
+                        items.iter().map(|item| tree.hash_leaf(item)).collect();
+                    progress.leaves_hashed(leaves.len() as u64);
+                    let batch = if leaves.len() == batch_size {
+                        Batch::Full(reduce_perfect(leaves, tree))
+                    } else {
+                        Batch::Partial(leaves)
+                    };
+                    if results.send(batch).is_err() {
+                        return;
+                    }
+                }
+            });
+
+            inputs.push(input);
+            outputs.push(output);
+        }
+
+        let reducer = scope.spawn(move || {
+            let mut frontier = Frontier::new(tree);
+
+            // A closed channel on the next worker in turn means the stream ended
+            for turn in 0.. {
+                match outputs[turn % workers].recv() {
+                    Ok(Batch::Full(root)) => frontier.push(batch_level, root),
+                    Ok(Batch::Partial(leaves)) => {
+                        for leaf in leaves {
+                            frontier.push(0, leaf);
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+
+            frontier.finish()
+        });
+
+        let mut data = data.into_iter();
+        for turn in 0.. {
+            let batch: Vec<Vec<u8>> = data.by_ref().take(batch_size).collect();
+            if batch.is_empty() {
+                break;
+            }
+            progress.bytes_read(batch.iter().map(|item| item.len() as u64).sum());
+            if inputs[turn % workers].send(batch).is_err() {
+                break;
+            }
+        }
+        drop(inputs);
+
+        reducer.join().expect("pipeline reducer panicked")
+    })
+}
+
+/// Computes the root on the calling thread, for targets without threads
+fn sequential_root<I>(
+    data: I,
+    tree: &TreeConfig,
+    batch_size: usize,
+    progress: &dyn Progress,
+) -> Option<Vec<u8>>
+where
+    I: IntoIterator<Item = Vec<u8>>,
+{
+    let mut frontier = Frontier::new(tree);
+    let mut data = data.into_iter();
+    loop {
+        let batch: Vec<Vec<u8>> = data.by_ref().take(batch_size).collect();
+        if batch.is_empty() {
+            break;
+        }
+        progress.bytes_read(batch.iter().map(|item| item.len() as u64).sum());
+        progress.leaves_hashed(batch.len() as u64);
+        for item in &batch {
+            frontier.push(0, tree.hash_leaf(item));
+        }
+    }
+
+    frontier.finish()
+}
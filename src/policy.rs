@@ -0,0 +1,213 @@
+//! What a proof must show, configured in one place.
+//!
+//! A [`VerifyPolicy`] collects the requirements a deployment puts on proofs
+//! beyond their hashes adding up: a signed root, enough witnesses, a
+//! minimum tree size and an allow-list of hash algorithms. The composed
+//! checks [`ProofBundle::verify_with_policy`] and
+//! [`BoundProof::verify_with_policy`] enforce it, so call sites don't each
+//! decide what counts as valid.
+//!
+//! Policies can be read from a config file of `key = value` lines:
+//!
+//! ```text
+//! require_signed_root = true
+//! min_witnesses = 2
+//! min_tree_size = 1_000
+//! # comma-separated: sha256, keccak256
+//! allowed_hashes = sha256
+//! ```
+//!
+//! Missing keys keep their defaults, which accept any size and witness
+//! count but only SHA-256 proofs under a signed root. The composed checks
+//! work out the algorithm from how the proof hashes, never from a label it
+//! carries: a proof counts as SHA-256 when its hasher hashes as a
+//! [`TreeConfig`] does, and proofs with any other hasher are refused.
+//! Keccak-256 proofs from the OpenZeppelin and Solana trees go through
+//! [`VerifyPolicy::check_hash`] directly.
+
+use crate::{BoundProof, HashAlgorithm, MerkleError, MerkleHasher, ProofBundle, RootRecord};
+use crate::{TreeConfig, Verifier, WitnessedHead};
+use std::fs;
+use std::path::Path;
+
+/// Requirements a proof must meet to be accepted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyPolicy {
+    /// Only accept roots the log has signed
+    pub require_signed_root: bool,
+    /// Trusted witnesses that must have cosigned the root
+    pub min_witnesses: usize,
+    /// Smallest tree a root may commit to
+    pub min_tree_size: u64,
+    pub allowed_hashes: Vec<HashAlgorithm>,
+}
+
+impl Default for VerifyPolicy {
+    fn default() -> Self {
+        VerifyPolicy {
+            require_signed_root: true,
+            min_witnesses: 0,
+            min_tree_size: 0,
+            allowed_hashes: vec![HashAlgorithm::Sha256],
+        }
+    }
+}
+
+fn violation(message: String) -> MerkleError {
+    MerkleError::PolicyViolation(message)
+}
+
+impl VerifyPolicy {
+    /// Parses a config file's contents
+    pub fn parse(text: &str) -> Result<Self, MerkleError> {
+        let mut policy = VerifyPolicy::default();
+
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = |message: &str| {
+                MerkleError::Parse(format!("line {}: {}", line_number + 1, message))
+            };
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `key = value`"))?;
+            let (key, value) = (key.trim(), value.trim());
+
+            let invalid = || error(&format!("invalid value for {}", key));
+
+            match key {
+                "require_signed_root" => {
+                    policy.require_signed_root = value.parse().map_err(|_| invalid())?
+                }
+                "min_witnesses" => {
+                    policy.min_witnesses = value.replace('_', "").parse().map_err(|_| invalid())?
+                }
+                "min_tree_size" => {
+                    policy.min_tree_size = value.replace('_', "").parse().map_err(|_| invalid())?
+                }
+                "allowed_hashes" => {
+                    policy.allowed_hashes = value
+                        .split(',')
+                        .map(|name| match name.trim() {
+                            "sha256" => Ok(HashAlgorithm::Sha256),
+                            "keccak256" => Ok(HashAlgorithm::Keccak256),
+                            _ => Err(invalid()),
+                        })
+                        .collect::<Result<_, _>>()?
+                }
+                _ => return Err(error(&format!("unknown key {}", key))),
+            }
+        }
+
+        Ok(policy)
+    }
+
+    /// Reads and parses a config file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MerkleError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Fails unless proofs hashed with `hash` are allowed
+    pub fn check_hash(&self, hash: HashAlgorithm) -> Result<(), MerkleError> {
+        if !self.allowed_hashes.contains(&hash) {
+            return Err(violation(format!(
+                "hash algorithm {:?} is not allowed",
+                hash
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fails if a root for `tree_size` leaves is too small to accept
+    pub fn check_tree_size(&self, tree_size: u64) -> Result<(), MerkleError> {
+        if tree_size < self.min_tree_size {
+            return Err(violation(format!(
+                "tree size {} is below the minimum of {}",
+                tree_size, self.min_tree_size
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks a tree head's size, the log's signature if one is required,
+    /// and that at least `min_witnesses` of `witnesses` cosigned it
+    ///
+    /// Returns the number of valid witnesses.
+    pub fn check_head(
+        &self,
+        head: &WitnessedHead,
+        log: &dyn Verifier,
+        witnesses: &[&dyn Verifier],
+    ) -> Result<usize, MerkleError> {
+        self.check_tree_size(head.head.size)?;
+
+        if self.require_signed_root
+            && !head
+                .cosignatures
+                .iter()
+                .any(|signature| signature.verify_log(&head.head, log))
+        {
+            return Err(MerkleError::InvalidSignature {
+                key_id: log.key_id().to_string(),
+            });
+        }
+
+        head.verify(witnesses, self.min_witnesses)
+    }
+}
+
+impl ProofBundle {
+    /// Verifies the bundle as far as `policy` demands
+    ///
+    /// Returns the number of valid witnesses on success.
+    pub fn verify_with_policy(
+        &self,
+        policy: &VerifyPolicy,
+        log: &dyn Verifier,
+        witnesses: &[&dyn Verifier],
+    ) -> Result<usize, MerkleError> {
+        policy.check_hash(self.proof.hash_algorithm())?;
+
+        let head = &self.head.head;
+        if self.proof.tree_size() != head.size {
+            return Err(MerkleError::StaleProof {
+                proof_size: self.proof.tree_size(),
+                root_size: head.size,
+            });
+        }
+        if !self.proof.verify(&head.root_hash) {
+            return Err(MerkleError::InvalidProof);
+        }
+
+        policy.check_head(&self.head, log, witnesses)
+    }
+}
+
+impl<H: MerkleHasher> BoundProof<H> {
+    /// Checks the proof against `entry` as [`BoundProof::verify_against`]
+    /// does, then holds it to `policy`
+    ///
+    /// A root record carries no signature, so a policy that requires a
+    /// signed root rejects every bound proof.
+    pub fn verify_with_policy(
+        &self,
+        entry: &RootRecord,
+        policy: &VerifyPolicy,
+    ) -> Result<(), MerkleError> {
+        if TreeConfig::matching(self.proof().merkle_hasher()).is_none() {
+            return Err(violation(
+                "proof hashes with an algorithm the policy can't name".to_string(),
+            ));
+        }
+        policy.check_hash(HashAlgorithm::Sha256)?;
+        if policy.require_signed_root || policy.min_witnesses > 0 {
+            return Err(violation("root record is not signed".to_string()));
+        }
+        policy.check_tree_size(entry.tree_size)?;
+
+        self.verify_against(entry)
+    }
+}
@@ -0,0 +1,98 @@
+//! Progress reporting for long-running operations.
+//!
+//! Operations named `*_with_progress` call a [`Progress`] as they go, with
+//! counts of work done since the previous call, so a CLI can drive a
+//! progress bar or a service can export throughput. Calls may come from
+//! worker threads, hence the `Sync` bound; implementations typically add
+//! the counts to atomics. `()` reports nowhere.
+
+use crate::{build_levels_reporting, hash_leaf, MerkleError, MerkleHasher, MerkleTree, TreeConfig};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Leaves hashed between reports while building from memory
+const REPORT_INTERVAL: usize = 4096;
+
+/// Receives progress from long-running operations
+///
+/// Every method defaults to doing nothing.
+pub trait Progress: Sync {
+    /// `count` more leaves have been hashed
+    fn leaves_hashed(&self, _count: u64) {}
+
+    /// Level `level` above the leaves is complete with `nodes` nodes
+    fn level_built(&self, _level: usize, _nodes: usize) {}
+
+    /// `bytes` more bytes of input have been consumed
+    fn bytes_read(&self, _bytes: u64) {}
+}
+
+impl Progress for () {}
+
+impl MerkleTree {
+    /// Builds a tree like [`MerkleTree::new`], reporting to `progress`
+    pub fn new_with_progress(data: Vec<Vec<u8>>, progress: &dyn Progress) -> Self {
+        let mut leaves = Vec::with_capacity(data.len());
+        for chunk in data.chunks(REPORT_INTERVAL) {
+            leaves.extend(chunk.iter().map(|item| hash_leaf(item)));
+            progress.bytes_read(chunk.iter().map(|item| item.len() as u64).sum());
+            progress.leaves_hashed(chunk.len() as u64);
+        }
+
+        MerkleTree {
+            config: TreeConfig::default(),
+            levels: build_levels_reporting(leaves, &TreeConfig::default(), progress),
+        }
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Builds a tree hashed by `config`, a [`TreeConfig`] or any other
+    /// [`MerkleHasher`], over a file split into `chunk_size`-byte leaves,
+    /// the last one possibly shorter
+    pub fn from_file(
+        path: impl AsRef<Path>,
+        chunk_size: usize,
+        config: &H,
+    ) -> Result<Self, MerkleError> {
+        Self::from_file_with_progress(path, chunk_size, config, &())
+    }
+
+    /// Builds a tree like [`MerkleTree::from_file`], reporting to `progress`
+    pub fn from_file_with_progress(
+        path: impl AsRef<Path>,
+        chunk_size: usize,
+        config: &H,
+        progress: &dyn Progress,
+    ) -> Result<Self, MerkleError> {
+        if chunk_size == 0 {
+            return Err(MerkleError::Encode(
+                "chunk size must be non-zero".to_string(),
+            ));
+        }
+
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut leaves = Vec::new();
+        let mut chunk = Vec::with_capacity(chunk_size);
+        loop {
+            chunk.clear();
+            let read = reader
+                .by_ref()
+                .take(chunk_size as u64)
+                .read_to_end(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+
+            leaves.push(config.hash_leaf(&chunk));
+            progress.bytes_read(read as u64);
+            progress.leaves_hashed(1);
+        }
+
+        Ok(MerkleTree {
+            config: config.clone(),
+            levels: build_levels_reporting(leaves, config, progress),
+        })
+    }
+}
@@ -0,0 +1,179 @@
+//! Accepting a root only when independent sources agree on it.
+//!
+//! A light client that takes its roots from the log it verifies against
+//! can be shown a private view of the log. Asking several sources instead
+//! (gossip peers, a DNS record, an on-chain anchor) and
+//! requiring a [`Quorum`] of them to report the same root for a tree size
+//! means one equivocating server can't get a proof accepted on its own.
+//!
+//! Sources reporting another root are listed in the [`QuorumReport`] as
+//! dissenting rather than failing the check, so a single bad source can't
+//! block verification either; callers decide whether dissent is worth an
+//! alert. A source that reports two different roots for one size counts
+//! as dissenting whichever root it also backs.
+
+use crate::{BoundProof, MembershipProof, MerkleError, MerkleHasher, RootRecord};
+
+/// A root as one source reported it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReportedRoot {
+    /// Name of the source, each counted once however often it reports
+    pub source: String,
+    pub tree_size: u64,
+    pub root_hash: Vec<u8>,
+}
+
+/// How many sources must agree on a root
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quorum {
+    pub threshold: usize,
+}
+
+/// The root a quorum agreed on, and who agreed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuorumReport {
+    pub tree_size: u64,
+    pub root_hash: Vec<u8>,
+    pub agreeing: Vec<String>,
+    /// Sources that reported a different root for the same size
+    pub dissenting: Vec<String>,
+}
+
+impl Quorum {
+    /// Requires `threshold` sources to agree
+    pub fn new(threshold: usize) -> Self {
+        Quorum { threshold }
+    }
+
+    /// Returns the root for `tree_size` that the most sources report, if
+    /// enough of them do
+    ///
+    /// Reports for other sizes are ignored. Fails with
+    /// [`MerkleError::NoQuorum`] if no root has `threshold` sources behind
+    /// it, or if two roots tie for the most; a threshold of zero still
+    /// needs one source.
+    pub fn agreed_root(
+        &self,
+        tree_size: u64,
+        reports: &[ReportedRoot],
+    ) -> Result<QuorumReport, MerkleError> {
+        // Every distinct root with the sources reporting it, in report order
+        let mut roots: Vec<(&[u8], Vec<&str>)> = Vec::new();
+        for report in reports.iter().filter(|r| r.tree_size == tree_size) {
+            let index = match roots.iter().position(|(root, _)| *root == report.root_hash) {
+                Some(index) => index,
+                None => {
+                    roots.push((&report.root_hash, Vec::new()));
+                    roots.len() - 1
+                }
+            };
+            let sources = &mut roots[index].1;
+            if !sources.contains(&report.source.as_str()) {
+                sources.push(&report.source);
+            }
+        }
+
+        // A source backing more than one root is equivocating itself
+        let equivocating: Vec<&str> = roots
+            .iter()
+            .flat_map(|(_, sources)| sources)
+            .copied()
+            .filter(|source| {
+                roots
+                    .iter()
+                    .filter(|(_, sources)| sources.contains(source))
+                    .count()
+                    > 1
+            })
+            .collect();
+        let support = |sources: &[&str]| {
+            sources
+                .iter()
+                .filter(|source| !equivocating.contains(source))
+                .count()
+        };
+
+        let required = self.threshold.max(1);
+        let best = roots
+            .iter()
+            .map(|(_, sources)| support(sources))
+            .max()
+            .unwrap_or(0);
+        if best < required {
+            return Err(MerkleError::NoQuorum {
+                agreeing: best,
+                required,
+            });
+        }
+        let mut winners = roots.iter().filter(|(_, sources)| support(sources) == best);
+        let (root_hash, sources) = winners.next().unwrap();
+        if winners.next().is_some() {
+            // Breaking the tie takes one more source
+            return Err(MerkleError::NoQuorum {
+                agreeing: best,
+                required: best + 1,
+            });
+        }
+
+        let agreeing: Vec<String> = sources
+            .iter()
+            .filter(|source| !equivocating.contains(source))
+            .map(|source| source.to_string())
+            .collect();
+        let mut dissenting: Vec<String> = Vec::new();
+        for (_, sources) in roots.iter().filter(|(root, _)| root != root_hash) {
+            for source in sources {
+                if !agreeing.iter().any(|agreed| agreed == source)
+                    && !dissenting.iter().any(|listed| listed == source)
+                {
+                    dissenting.push(source.to_string());
+                }
+            }
+        }
+
+        Ok(QuorumReport {
+            tree_size,
+            root_hash: root_hash.to_vec(),
+            agreeing,
+            dissenting,
+        })
+    }
+}
+
+impl QuorumReport {
+    fn record(&self) -> RootRecord {
+        RootRecord {
+            timestamp: 0,
+            tree_size: self.tree_size,
+            root_hash: self.root_hash.clone(),
+        }
+    }
+}
+
+impl<H: MerkleHasher> BoundProof<H> {
+    /// Checks the proof against the root a quorum of `reports` agrees on
+    /// for its tree size
+    pub fn verify_with_quorum(
+        &self,
+        reports: &[ReportedRoot],
+        quorum: &Quorum,
+    ) -> Result<QuorumReport, MerkleError> {
+        let report = quorum.agreed_root(self.tree_size(), reports)?;
+        self.verify_against(&report.record())?;
+        Ok(report)
+    }
+}
+
+impl MembershipProof {
+    /// Checks the proof against the root a quorum of `reports` agrees on
+    /// for its tree size
+    pub fn verify_with_quorum(
+        &self,
+        reports: &[ReportedRoot],
+        quorum: &Quorum,
+    ) -> Result<QuorumReport, MerkleError> {
+        let report = quorum.agreed_root(self.tree_size(), reports)?;
+        self.verify_against(&report.record())?;
+        Ok(report)
+    }
+}
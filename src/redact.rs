@@ -0,0 +1,154 @@
+//! Publishing a tree with some leaf payloads withheld.
+//!
+//! A [`RedactedTree`] holds each leaf either as its payload or, once
+//! redacted, as just its leaf hash. The root is the same either way, so a
+//! reader holding the original root can check that the published version
+//! still commits to it and that every revealed payload is the one that was
+//! there. Redacting an already redacted tree works the same, letting each
+//! holder withhold more before passing it on.
+//!
+//! A withheld hash only hides a payload that can't be guessed; salt short
+//! or predictable payloads before building the tree.
+//!
+//! The export is text, one leaf per line in order:
+//!
+//! ```text
+//! revealed <hex payload>
+//! withheld <hex leaf hash>
+//! ```
+
+use crate::{hash_leaf, BoundProof, MerkleError, MerkleTree};
+use std::io::{BufRead, Write};
+
+/// One leaf of a [`RedactedTree`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Disclosure {
+    /// The payload itself
+    Revealed(Vec<u8>),
+    /// Only the leaf hash of a withheld payload
+    Withheld(Vec<u8>),
+}
+
+impl Disclosure {
+    fn leaf_hash(&self) -> Vec<u8> {
+        match self {
+            Disclosure::Revealed(payload) => hash_leaf(payload),
+            Disclosure::Withheld(hash) => hash.clone(),
+        }
+    }
+}
+
+/// Leaves of a tree, each revealed or withheld
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RedactedTree {
+    leaves: Vec<Disclosure>,
+}
+
+impl RedactedTree {
+    /// Creates a tree with every payload revealed
+    pub fn new(data: Vec<Vec<u8>>) -> Self {
+        RedactedTree {
+            leaves: data.into_iter().map(Disclosure::Revealed).collect(),
+        }
+    }
+
+    /// Returns a copy with the payloads at `indices` withheld
+    pub fn redact(&self, indices: &[usize]) -> Result<Self, MerkleError> {
+        let mut leaves = self.leaves.clone();
+        for &index in indices {
+            let leaf = leaves.get_mut(index).ok_or(MerkleError::IndexOutOfBounds {
+                index,
+                len: self.leaves.len(),
+            })?;
+            if let Disclosure::Revealed(payload) = leaf {
+                *leaf = Disclosure::Withheld(hash_leaf(payload));
+            }
+        }
+
+        Ok(RedactedTree { leaves })
+    }
+
+    /// Returns every leaf in order
+    pub fn leaves(&self) -> &[Disclosure] {
+        &self.leaves
+    }
+
+    /// Returns the revealed payloads with their indices
+    pub fn revealed(&self) -> impl Iterator<Item = (usize, &[u8])> {
+        self.leaves
+            .iter()
+            .enumerate()
+            .filter_map(|(index, leaf)| match leaf {
+                Disclosure::Revealed(payload) => Some((index, payload.as_slice())),
+                Disclosure::Withheld(_) => None,
+            })
+    }
+
+    /// Rebuilds the tree, which has the same root as the unredacted one
+    pub fn tree(&self) -> MerkleTree {
+        MerkleTree::from_leaf_hashes(self.leaves.iter().map(Disclosure::leaf_hash).collect())
+    }
+
+    /// Returns true if the leaves still commit to `root_hash`
+    pub fn verify(&self, root_hash: &[u8]) -> bool {
+        self.tree().root_hash().as_deref() == Some(root_hash)
+    }
+
+    /// Proves the revealed payload at `index` is unchanged at that index
+    ///
+    /// Returns `None` if the leaf is withheld or out of bounds.
+    pub fn prove_revealed(&self, index: usize) -> Option<BoundProof> {
+        match self.leaves.get(index)? {
+            Disclosure::Revealed(_) => self.tree().generate_bound_proof(index),
+            Disclosure::Withheld(_) => None,
+        }
+    }
+
+    /// Writes the export described in the module docs
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), MerkleError> {
+        for leaf in &self.leaves {
+            match leaf {
+                Disclosure::Revealed(payload) => {
+                    writeln!(writer, "revealed {}", hex::encode(payload))?
+                }
+                Disclosure::Withheld(hash) => writeln!(writer, "withheld {}", hex::encode(hash))?,
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads an export written by [`RedactedTree::write_to`]
+    pub fn read_from<R: BufRead>(reader: R) -> Result<Self, MerkleError> {
+        let mut leaves = Vec::new();
+
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let error = |message: &str| {
+                MerkleError::Parse(format!("line {}: {}", line_number + 1, message))
+            };
+            // An empty payload leaves no hex after the keyword
+            let mut fields = line.split_whitespace();
+            let (Some(kind), value, None) =
+                (fields.next(), fields.next().unwrap_or(""), fields.next())
+            else {
+                return Err(error("expected `revealed <hex>` or `withheld <hex>`"));
+            };
+            let value = hex::decode(value).map_err(|_| error("invalid hex"))?;
+
+            leaves.push(match kind {
+                "revealed" => Disclosure::Revealed(value),
+                "withheld" if value.len() == 32 => Disclosure::Withheld(value),
+                "withheld" => return Err(error("withheld leaf hash must be 32 bytes")),
+                _ => return Err(error("unknown leaf kind")),
+            });
+        }
+
+        Ok(RedactedTree { leaves })
+    }
+}
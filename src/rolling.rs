@@ -0,0 +1,234 @@
+//! A tree over a sliding window of the most recent leaves.
+//!
+//! Leaves sit in a ring of slots: the leaf with sequence number `s` goes in
+//! slot `s % slots`, over the slot a long-expired leaf used. Pushing or
+//! expiring a leaf rewrites one slot and rehashes its path, so each costs
+//! O(log N) rather than a rebuild. Empty and expired slots hold zero bytes,
+//! as many as a hash has, which no leaf hash can equal. The ring doubles
+//! when an age window outgrows it, and a count window grows up to its
+//! count; only then is the tree rebuilt.
+
+use crate::{build_levels_reporting, MerkleHasher, MerkleProof, MerkleTree, TreeConfig};
+use std::collections::VecDeque;
+
+/// How long a leaf stays in a [`RollingTree`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// Keep at most this many of the newest leaves
+    Count(usize),
+    /// Keep leaves whose timestamp is within this many time units of the newest
+    Age(u64),
+}
+
+/// A retained leaf and where it sits in the overall stream
+struct Entry {
+    sequence: u64,
+    timestamp: u64,
+}
+
+/// A Merkle tree that only commits to leaves inside its window
+///
+/// Every pushed leaf gets a sequence number that stays stable while the
+/// leaf is retained, and a slot that stays stable until the ring grows.
+/// Hashing follows `H`, a [`TreeConfig`] unless another [`MerkleHasher`] is
+/// given.
+pub struct RollingTree<H: MerkleHasher = TreeConfig> {
+    window: Window,
+    entries: VecDeque<Entry>,
+    next_sequence: u64,
+    /// Hash held by a slot with no retained leaf
+    empty_slot: Vec<u8>,
+    /// Tree over every slot of the ring, retained or not
+    tree: MerkleTree<H>,
+}
+
+impl RollingTree {
+    /// Creates a rolling tree that keeps the last `count` leaves
+    pub fn with_capacity(count: usize) -> Self {
+        Self::new(Window::Count(count))
+    }
+
+    /// Creates a rolling tree that keeps leaves younger than `max_age`
+    pub fn with_max_age(max_age: u64) -> Self {
+        Self::new(Window::Age(max_age))
+    }
+
+    /// Creates an empty rolling tree with the given window
+    pub fn new(window: Window) -> Self {
+        Self::new_with_hasher(window, TreeConfig::default())
+    }
+}
+
+impl<H: MerkleHasher> RollingTree<H> {
+    /// Creates an empty rolling tree whose hashes are derived by `hasher`,
+    /// a [`TreeConfig`] or any other [`MerkleHasher`]
+    pub fn new_with_hasher(window: Window, hasher: H) -> Self {
+        RollingTree {
+            window,
+            entries: VecDeque::new(),
+            next_sequence: 0,
+            empty_slot: vec![0; hasher.empty_hash().len()],
+            tree: MerkleTree {
+                config: hasher,
+                levels: Vec::new(),
+            },
+        }
+    }
+
+    /// Appends a leaf observed at `timestamp` and returns its sequence number
+    ///
+    /// Count windows ignore the timestamp; age windows expect timestamps to
+    /// be non-decreasing.
+    pub fn push(&mut self, data: &[u8], timestamp: u64) -> u64 {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        // Make room in a full count window before growing the ring for it
+        if let Window::Count(count) = self.window {
+            while self.entries.len() >= count.max(1) {
+                self.pop_oldest();
+            }
+        }
+        if self.entries.len() == self.slots() {
+            self.grow();
+        }
+
+        let leaf_hash = self.tree.config.hash_leaf(data);
+        self.set_slot(self.slot(sequence), leaf_hash);
+        self.entries.push_back(Entry {
+            sequence,
+            timestamp,
+        });
+        self.evict(timestamp);
+
+        sequence
+    }
+
+    /// Drops leaves that have aged out by `now` without adding a new one
+    ///
+    /// Returns the number of leaves that expired.
+    pub fn expire(&mut self, now: u64) -> usize {
+        let before = self.entries.len();
+        self.evict(now);
+        before - self.entries.len()
+    }
+
+    /// Returns the window this tree was created with
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Returns the tree over the ring, one leaf per slot
+    pub fn tree(&self) -> &MerkleTree<H> {
+        &self.tree
+    }
+
+    /// Returns the configuration the tree hashes with
+    pub fn config(&self) -> &H {
+        &self.tree.config
+    }
+
+    /// Returns the number of retained leaves
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no leaves are retained
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the sequence numbers of the oldest and newest retained leaves
+    pub fn retained(&self) -> Option<(u64, u64)> {
+        Some((
+            self.entries.front()?.sequence,
+            self.entries.back()?.sequence,
+        ))
+    }
+
+    /// Returns the current root hash, if any leaves are retained
+    ///
+    /// Once every leaf has expired the ring holds only empty slots, which
+    /// commit to nothing, so there is no root.
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.tree.root_hash()
+    }
+
+    /// Returns the current root hash as a hex string
+    pub fn root_hash_hex(&self) -> Option<String> {
+        self.root_hash().map(hex::encode)
+    }
+
+    /// Generates a proof for retained data against the current root
+    pub fn generate_proof(&self, data: &[u8]) -> Option<MerkleProof<H>> {
+        self.tree.generate_proof(data)
+    }
+
+    /// Generates a proof for the retained leaf with the given sequence number
+    pub fn generate_proof_for(&self, sequence: u64) -> Option<MerkleProof<H>> {
+        let (oldest, newest) = self.retained()?;
+        if !(oldest..=newest).contains(&sequence) {
+            return None;
+        }
+
+        self.tree.generate_proof_at(self.slot(sequence))
+    }
+
+    /// Removes leaves that fall outside the window as of `now`
+    fn evict(&mut self, now: u64) {
+        match self.window {
+            Window::Count(count) => {
+                while self.entries.len() > count {
+                    self.pop_oldest();
+                }
+            }
+            Window::Age(max_age) => {
+                while let Some(oldest) = self.entries.front() {
+                    if now.saturating_sub(oldest.timestamp) < max_age {
+                        break;
+                    }
+                    self.pop_oldest();
+                }
+            }
+        }
+    }
+
+    fn pop_oldest(&mut self) {
+        if let Some(oldest) = self.entries.pop_front() {
+            self.set_slot(self.slot(oldest.sequence), self.empty_slot.clone());
+        }
+    }
+
+    fn slots(&self) -> usize {
+        self.tree.len()
+    }
+
+    fn slot(&self, sequence: u64) -> usize {
+        (sequence % self.slots() as u64) as usize
+    }
+
+    /// Writes a slot's leaf hash and rehashes the path above it
+    fn set_slot(&mut self, slot: usize, leaf_hash: Vec<u8>) {
+        self.tree.levels[0][slot] = leaf_hash;
+        self.tree.update_path(slot);
+    }
+
+    /// Doubles the ring, capped at a count window's count, and moves the
+    /// retained leaves to their new slots
+    fn grow(&mut self) {
+        let mut slots = (self.slots() * 2).max(1);
+        if let Window::Count(count) = self.window {
+            slots = slots.min(count.max(1));
+        }
+
+        let mut leaves = vec![self.empty_slot.clone(); slots];
+        for entry in &self.entries {
+            let leaf = &self.tree.levels[0][self.slot(entry.sequence)];
+            leaves[(entry.sequence % slots as u64) as usize] = leaf.clone();
+        }
+        self.tree.levels = build_levels_reporting(leaves, &self.tree.config, &());
+    }
+}
@@ -0,0 +1,295 @@
+//! A persisted record of every root a [`HistoryTree`] has published.
+//!
+//! Each publish appends `<timestamp> <tree size> <hex root>` to the log
+//! file, so the history survives restarts and can be read with ordinary
+//! tools. Timestamps are whatever the caller uses, typically Unix seconds;
+//! both they and tree sizes must never decrease, which lets lookups by
+//! either binary search, and a size recorded again must have the same
+//! root.
+//!
+//! Every line is written whole and synced, so a last line without its
+//! newline was cut short by a crash during an append. Opening the log
+//! drops such a line and truncates the file back to the last complete
+//! one; the root it was recording was never confirmed as published.
+//!
+//! A third-party timestamp for a record is appended as
+//! `@ <record index> <authority> <time> <radius> <hex evidence>`; see
+//! [`crate::timestamp`].
+
+use crate::timestamp::valid_authority_id;
+use crate::{
+    HistoryTree, IncrementalProof, MerkleError, TimestampAuthority, TimestampToken,
+    TimestampVerifier,
+};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::Path;
+
+/// One published root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootRecord {
+    pub timestamp: u64,
+    pub tree_size: u64,
+    pub root_hash: Vec<u8>,
+}
+
+/// Published roots in order, optionally backed by a log file
+#[derive(Debug, Default)]
+pub struct RootHistory {
+    records: Vec<RootRecord>,
+    /// Third-party timestamps, each with the index of its record
+    timestamps: Vec<(usize, TimestampToken)>,
+    log: Option<File>,
+}
+
+impl RootHistory {
+    /// Creates an empty history kept only in memory
+    pub fn new() -> Self {
+        RootHistory::default()
+    }
+
+    /// Opens the log at `path`, creating it if needed, and loads its records
+    ///
+    /// An incomplete last line is removed from the file, as the module docs
+    /// describe.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MerkleError> {
+        let log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut text = Vec::new();
+        (&log).read_to_end(&mut text)?;
+
+        let mut history = RootHistory::new();
+        let mut complete = 0;
+        for (line_number, line) in text.split_inclusive(|&byte| byte == b'\n').enumerate() {
+            let Some(line) = line.strip_suffix(b"\n") else {
+                break;
+            };
+            complete += line.len() + 1;
+            let error = || MerkleError::Parse(format!("root log line {}", line_number + 1));
+            let line = std::str::from_utf8(line).map_err(|_| error())?;
+
+            if let Some(fields) = line.strip_prefix("@ ") {
+                let (index, token) = parse_timestamp(fields).ok_or_else(error)?;
+                if index >= history.records.len() {
+                    return Err(error());
+                }
+                history.timestamps.push((index, token));
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let timestamp = fields
+                .next()
+                .and_then(|f| f.parse().ok())
+                .ok_or_else(error)?;
+            let tree_size = fields
+                .next()
+                .and_then(|f| f.parse().ok())
+                .ok_or_else(error)?;
+            let root_hash = fields
+                .next()
+                .and_then(|f| hex::decode(f).ok())
+                .ok_or_else(error)?;
+            if fields.next().is_some() {
+                return Err(error());
+            }
+
+            history.push(RootRecord {
+                timestamp,
+                tree_size,
+                root_hash,
+            })?;
+        }
+
+        if complete < text.len() {
+            log.set_len(complete as u64)?;
+        }
+        history.log = Some(log);
+        Ok(history)
+    }
+
+    /// Records the current root of `tree` as published at `timestamp`
+    pub fn publish(
+        &mut self,
+        tree: &HistoryTree,
+        timestamp: u64,
+    ) -> Result<&RootRecord, MerkleError> {
+        let record = RootRecord {
+            timestamp,
+            tree_size: tree.len(),
+            root_hash: tree.root_hash(),
+        };
+
+        self.check_order(&record)?;
+        if let Some(log) = &mut self.log {
+            writeln!(
+                log,
+                "{} {} {}",
+                record.timestamp,
+                record.tree_size,
+                hex::encode(&record.root_hash)
+            )?;
+            log.sync_data()?;
+        }
+        self.records.push(record);
+
+        Ok(self.records.last().unwrap())
+    }
+
+    /// Returns every record, oldest first
+    pub fn records(&self) -> &[RootRecord] {
+        &self.records
+    }
+
+    /// Has `authority` timestamp record `index` and stores the token
+    pub fn anchor(
+        &mut self,
+        index: usize,
+        authority: &dyn TimestampAuthority,
+    ) -> Result<&TimestampToken, MerkleError> {
+        let record = self
+            .records
+            .get(index)
+            .ok_or_else(|| MerkleError::Encode(format!("no root record {}", index)))?;
+        let token = authority.stamp(&record.timestamp_nonce())?;
+        if !valid_authority_id(&token.authority) {
+            return Err(MerkleError::Encode(format!(
+                "invalid timestamp authority {:?}",
+                token.authority
+            )));
+        }
+
+        if let Some(log) = &mut self.log {
+            writeln!(
+                log,
+                "@ {} {} {} {} {}",
+                index,
+                token.authority,
+                token.time,
+                token.radius,
+                hex::encode(&token.evidence)
+            )?;
+            log.sync_data()?;
+        }
+        self.timestamps.push((index, token));
+
+        Ok(&self.timestamps.last().unwrap().1)
+    }
+
+    /// Returns the timestamps stored for record `index`
+    pub fn timestamps(&self, index: usize) -> impl Iterator<Item = &TimestampToken> {
+        self.timestamps
+            .iter()
+            .filter(move |(record, _)| *record == index)
+            .map(|(_, token)| token)
+    }
+
+    /// Returns the earliest time by which record `index`'s root provably
+    /// existed, going by the valid timestamps from `verifiers`
+    pub fn existed_by(&self, index: usize, verifiers: &[&dyn TimestampVerifier]) -> Option<u64> {
+        let record = self.records.get(index)?;
+        self.timestamps(index)
+            .filter(|token| {
+                verifiers
+                    .iter()
+                    .any(|verifier| record.verify_timestamp(token, *verifier))
+            })
+            .map(TimestampToken::latest)
+            .min()
+    }
+
+    /// Returns the root that was current at `timestamp`: the latest one
+    /// published at or before it
+    pub fn at_time(&self, timestamp: u64) -> Option<&RootRecord> {
+        let end = self
+            .records
+            .partition_point(|record| record.timestamp <= timestamp);
+        end.checked_sub(1).map(|index| &self.records[index])
+    }
+
+    /// Returns the latest root published for a tree of at most `tree_size`
+    /// leaves
+    pub fn at_size(&self, tree_size: u64) -> Option<&RootRecord> {
+        let end = self
+            .records
+            .partition_point(|record| record.tree_size <= tree_size);
+        end.checked_sub(1).map(|index| &self.records[index])
+    }
+
+    /// Proves that `newer` extends `older` using the tree both came from
+    ///
+    /// Fails if either record's root does not match `tree`, which means the
+    /// tree is not the one this history was published from.
+    pub fn prove_consistency(
+        &self,
+        tree: &HistoryTree,
+        older: &RootRecord,
+        newer: &RootRecord,
+    ) -> Result<IncrementalProof, MerkleError> {
+        for record in [older, newer] {
+            if tree.root_at(record.tree_size).as_ref() != Some(&record.root_hash) {
+                return Err(MerkleError::Parse(format!(
+                    "tree does not match the root published at size {}",
+                    record.tree_size
+                )));
+            }
+        }
+
+        tree.prove_incremental(older.tree_size, newer.tree_size)
+            .ok_or_else(|| MerkleError::Parse("older record is larger than newer".to_string()))
+    }
+
+    fn push(&mut self, record: RootRecord) -> Result<(), MerkleError> {
+        self.check_order(&record)?;
+        self.records.push(record);
+        Ok(())
+    }
+
+    fn check_order(&self, record: &RootRecord) -> Result<(), MerkleError> {
+        match self.records.last() {
+            Some(last)
+                if record.timestamp < last.timestamp || record.tree_size < last.tree_size =>
+            {
+                Err(MerkleError::Parse(format!(
+                    "root at time {} size {} is older than the last one recorded",
+                    record.timestamp, record.tree_size
+                )))
+            }
+            Some(last)
+                if record.tree_size == last.tree_size && record.root_hash != last.root_hash =>
+            {
+                Err(MerkleError::Parse(format!(
+                    "root at time {} differs from the one already recorded for size {}",
+                    record.timestamp, record.tree_size
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parses the fields of a timestamp line after its `@`
+fn parse_timestamp(fields: &str) -> Option<(usize, TimestampToken)> {
+    let mut fields = fields.split(' ');
+    let (Some(index), Some(authority), Some(time), Some(radius), Some(evidence), None) = (
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+        fields.next(),
+    ) else {
+        return None;
+    };
+
+    let token = TimestampToken {
+        authority: valid_authority_id(authority).then(|| authority.to_string())?,
+        time: time.parse().ok()?,
+        radius: radius.parse().ok()?,
+        evidence: hex::decode(evidence).ok()?,
+    };
+    Some((index.parse().ok()?, token))
+}
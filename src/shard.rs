@@ -0,0 +1,245 @@
+//! Trees split into shards under a root of roots.
+//!
+//! A [`ShardedTree`] keeps each shard as its own [`MerkleTree`] and commits
+//! to all of them with a top tree whose leaves are the shard roots, taken
+//! as they are rather than hashed again. Shards can be rebuilt or served
+//! independently while clients track a single super-root.
+//!
+//! A [`ComposedProof`] stitches a leaf's proof within its shard to the
+//! shard's proof within the top tree and verifies against the super-root
+//! in one call. Its encoding is big-endian:
+//!
+//! ```text
+//! "SMTC" | version: u8 | shard: u64 | leaf hash: [u8; 32]
+//!        | leaf depth: u8 | (side: u8, hash: [u8; 32]) * leaf depth
+//!        | shard root: [u8; 32]
+//!        | shard depth: u8 | (side: u8, hash: [u8; 32]) * shard depth
+//!        | root: [u8; 32]
+//! ```
+//!
+//! where a side of 0 is left and 1 is right. As with packed proofs, the
+//! domain tag is not encoded, so proofs read back verify as untagged.
+
+use crate::{build_levels_reporting, MerkleError, MerkleProof, MerkleTree, Side};
+use std::io::{Read, Write};
+
+pub(crate) const MAGIC: &[u8; 4] = b"SMTC";
+pub(crate) const FORMAT_VERSION: u8 = 1;
+const HASH_LEN: usize = 32;
+
+/// Shards committed to by one root over their roots
+pub struct ShardedTree {
+    shards: Vec<MerkleTree>,
+    /// Tree whose leaves are the shard roots
+    top: MerkleTree,
+}
+
+impl ShardedTree {
+    /// Commits to `shards` in order
+    ///
+    /// Fails if a shard is empty, or with [`MerkleError::ConfigMismatch`]
+    /// if the shards don't share a configuration, which the top tree is
+    /// built with.
+    pub fn new(shards: Vec<MerkleTree>) -> Result<Self, MerkleError> {
+        let config = shards
+            .first()
+            .map(|shard| shard.config.clone())
+            .unwrap_or_default();
+
+        let mut roots = Vec::with_capacity(shards.len());
+        for (position, shard) in shards.iter().enumerate() {
+            if shard.config != config {
+                return Err(MerkleError::ConfigMismatch);
+            }
+            let root = shard
+                .root_hash()
+                .ok_or_else(|| MerkleError::Encode(format!("shard {} is empty", position)))?;
+            roots.push(root);
+        }
+
+        let levels = build_levels_reporting(roots, &config, &());
+        Ok(ShardedTree {
+            shards,
+            top: MerkleTree { config, levels },
+        })
+    }
+
+    /// Returns the super-root, or `None` if there are no shards
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.top.root_hash()
+    }
+
+    /// Returns the shards in order
+    pub fn shards(&self) -> &[MerkleTree] {
+        &self.shards
+    }
+
+    /// Returns the number of leaves across all shards
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(MerkleTree::len).sum()
+    }
+
+    /// Returns true if there are no shards
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// Generates a proof for leaf `index` of shard `shard`
+    pub fn generate_proof_at(&self, shard: usize, index: usize) -> Option<ComposedProof> {
+        Some(ComposedProof {
+            shard: shard as u64,
+            leaf_proof: self.shards.get(shard)?.generate_proof_at(index)?,
+            shard_proof: self.top.generate_proof_at(shard)?,
+        })
+    }
+
+    /// Generates a proof for the first leaf holding `data`, in any shard
+    pub fn generate_proof(&self, data: &[u8]) -> Option<ComposedProof> {
+        self.shards.iter().enumerate().find_map(|(shard, tree)| {
+            Some(ComposedProof {
+                shard: shard as u64,
+                leaf_proof: tree.generate_proof(data)?,
+                shard_proof: self.top.generate_proof_at(shard)?,
+            })
+        })
+    }
+}
+
+/// A leaf's proof within its shard joined to the shard's proof within the
+/// top tree
+pub struct ComposedProof {
+    shard: u64,
+    leaf_proof: MerkleProof,
+    shard_proof: MerkleProof,
+}
+
+impl ComposedProof {
+    /// Returns the position of the leaf's shard
+    pub fn shard(&self) -> u64 {
+        self.shard
+    }
+
+    /// Returns the proof from the leaf to its shard root
+    pub fn leaf_proof(&self) -> &MerkleProof {
+        &self.leaf_proof
+    }
+
+    /// Returns the proof from the shard root to the super-root
+    pub fn shard_proof(&self) -> &MerkleProof {
+        &self.shard_proof
+    }
+
+    /// Returns the hash of the proven leaf
+    pub fn leaf_hash(&self) -> &[u8] {
+        self.leaf_proof.leaf_hash()
+    }
+
+    /// Returns the super-root the proof was generated against
+    pub fn root_hash(&self) -> &[u8] {
+        self.shard_proof.root_hash()
+    }
+
+    /// Verifies the leaf up through its shard to `root_hash`
+    pub fn verify(&self, root_hash: &[u8]) -> bool {
+        let shard_root = self.shard_proof.leaf_hash();
+        self.leaf_proof.verify(shard_root) && self.shard_proof.verify(root_hash)
+    }
+
+    /// Writes the encoding described in the module docs
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), MerkleError> {
+        if self.root_hash().len() != HASH_LEN {
+            return Err(MerkleError::Encode(
+                "proof hashes must be 32 bytes".to_string(),
+            ));
+        }
+
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION])?;
+        writer.write_all(&self.shard.to_be_bytes())?;
+        write_proof(&mut writer, &self.leaf_proof)?;
+        write_proof(&mut writer, &self.shard_proof)?;
+        writer.write_all(self.root_hash())?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a proof written by [`ComposedProof::write_to`]
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, MerkleError> {
+        let mut header = [0u8; 5];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != FORMAT_VERSION {
+            return Err(parse_error("not a supported composed proof"));
+        }
+
+        let mut shard = [0u8; 8];
+        reader.read_exact(&mut shard)?;
+        let leaf_hash = read_hash(&mut reader)?;
+        let leaf_siblings = read_siblings(&mut reader)?;
+        let shard_root = read_hash(&mut reader)?;
+        let shard_siblings = read_siblings(&mut reader)?;
+        let root = read_hash(&mut reader)?;
+
+        if reader.read(&mut [0u8])? != 0 {
+            return Err(parse_error("trailing bytes"));
+        }
+
+        Ok(ComposedProof {
+            shard: u64::from_be_bytes(shard),
+            leaf_proof: MerkleProof::new(leaf_hash, leaf_siblings, shard_root.clone()),
+            shard_proof: MerkleProof::new(shard_root, shard_siblings, root),
+        })
+    }
+}
+
+fn parse_error(message: &str) -> MerkleError {
+    MerkleError::Parse(format!("composed proof: {}", message))
+}
+
+/// Writes a proof's leaf hash and siblings, leaving out its root
+fn write_proof<W: Write>(writer: &mut W, proof: &MerkleProof) -> Result<(), MerkleError> {
+    let depth = u8::try_from(proof.siblings().len())
+        .map_err(|_| MerkleError::Encode("proof is deeper than 255 levels".to_string()))?;
+    if proof.leaf_hash().len() != HASH_LEN
+        || proof
+            .siblings()
+            .iter()
+            .any(|(hash, _)| hash.len() != HASH_LEN)
+    {
+        return Err(MerkleError::Encode(
+            "proof hashes must be 32 bytes".to_string(),
+        ));
+    }
+
+    writer.write_all(proof.leaf_hash())?;
+    writer.write_all(&[depth])?;
+    for (hash, side) in proof.siblings() {
+        writer.write_all(&[u8::from(*side == Side::Right)])?;
+        writer.write_all(hash)?;
+    }
+    Ok(())
+}
+
+fn read_siblings<R: Read>(reader: &mut R) -> Result<Vec<(Vec<u8>, Side)>, MerkleError> {
+    let mut depth = [0u8];
+    reader.read_exact(&mut depth)?;
+
+    (0..depth[0])
+        .map(|_| {
+            let mut side = [0u8];
+            reader.read_exact(&mut side)?;
+            let side = match side[0] {
+                0 => Side::Left,
+                1 => Side::Right,
+                _ => return Err(parse_error("invalid sibling side")),
+            };
+            Ok((read_hash(reader)?, side))
+        })
+        .collect()
+}
+
+fn read_hash<R: Read>(reader: &mut R) -> Result<Vec<u8>, MerkleError> {
+    let mut hash = vec![0u8; HASH_LEN];
+    reader.read_exact(&mut hash)?;
+    Ok(hash)
+}
@@ -0,0 +1,169 @@
+//! Trees whose leaf order is a secret permutation of the input.
+//!
+//! A proof's sibling sides give away its leaf's position, so a published
+//! claims file tells anyone who reads it where each claimant sat in the
+//! input: sign-up order, say, or an alphabetical list of names. A
+//! [`ShuffledTree`] places the leaves in an order drawn from a 32-byte
+//! seed, and keeps the mapping both ways so callers still ask for proofs
+//! by input index. The permutation is Fisher-Yates over SHA-256 in counter
+//! mode, so the same seed and leaf count always give the same order.
+//!
+//! The seed is as sensitive as the order it hides. Keep it private, or
+//! store it encrypted next to the input; rebuilding from the seed is the
+//! only way back to the mapping once the tree is dropped.
+
+use crate::{MerkleHasher, MerkleProof, MerkleTree, PackedProofs, TreeConfig};
+use sha2::{Digest, Sha256};
+
+const SHUFFLE_TAG: &[u8] = b"simple-merkle-tree shuffle v1";
+
+/// Deterministic random words from a seed
+struct SeedStream {
+    seed: [u8; 32],
+    counter: u64,
+    block: [u8; 32],
+    used: usize,
+}
+
+impl SeedStream {
+    fn new(seed: &[u8; 32]) -> Self {
+        SeedStream {
+            seed: *seed,
+            counter: 0,
+            block: [0; 32],
+            used: 32,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        if self.used == self.block.len() {
+            let mut hasher = Sha256::new();
+            hasher.update(SHUFFLE_TAG);
+            hasher.update(self.seed);
+            hasher.update(self.counter.to_be_bytes());
+            self.block = hasher.finalize().into();
+            self.counter += 1;
+            self.used = 0;
+        }
+        let word = &self.block[self.used..self.used + 8];
+        self.used += 8;
+        u64::from_be_bytes(word.try_into().unwrap())
+    }
+
+    /// Returns a uniform value below `bound`, rejecting the words that
+    /// would bias it
+    fn below(&mut self, bound: u64) -> u64 {
+        let zone = u64::MAX - u64::MAX % bound;
+        loop {
+            let word = self.next_u64();
+            if word < zone {
+                return word % bound;
+            }
+        }
+    }
+}
+
+/// Returns the input index placed at each position when `len` leaves are
+/// shuffled under `seed`
+pub fn permutation(seed: &[u8; 32], len: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..len).collect();
+    let mut stream = SeedStream::new(seed);
+    for i in (1..len).rev() {
+        let j = stream.below(i as u64 + 1) as usize;
+        order.swap(i, j);
+    }
+    order
+}
+
+/// A tree built from its input in a seeded random order
+pub struct ShuffledTree<H: MerkleHasher = TreeConfig> {
+    tree: MerkleTree<H>,
+    /// Input index of the leaf at each position
+    order: Vec<usize>,
+    /// Position of each input index
+    positions: Vec<usize>,
+}
+
+impl<H: MerkleHasher> ShuffledTree<H> {
+    /// Shuffles `data` under `seed` and builds a tree with `hasher`
+    pub fn new_with_hasher(data: Vec<Vec<u8>>, seed: &[u8; 32], hasher: H) -> Self {
+        let order = permutation(seed, data.len());
+        let mut positions = vec![0; order.len()];
+        for (position, &index) in order.iter().enumerate() {
+            positions[index] = position;
+        }
+
+        let mut data: Vec<Option<Vec<u8>>> = data.into_iter().map(Some).collect();
+        let shuffled = order
+            .iter()
+            .map(|&index| data[index].take().unwrap())
+            .collect();
+        ShuffledTree {
+            tree: MerkleTree::hashed_by(shuffled, hasher),
+            order,
+            positions,
+        }
+    }
+
+    /// Returns the tree in its shuffled order
+    pub fn tree(&self) -> &MerkleTree<H> {
+        &self.tree
+    }
+
+    /// Returns the number of leaves
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Returns true if the tree has no leaves
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Returns the root hash, the same as [`MerkleTree::root_hash`] of
+    /// [`ShuffledTree::tree`]
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.tree.root_hash()
+    }
+
+    /// Returns where the leaf for input `index` ended up
+    pub fn position(&self, index: usize) -> Option<usize> {
+        self.positions.get(index).copied()
+    }
+
+    /// Returns the input index of the leaf at `position`
+    pub fn input_index(&self, position: usize) -> Option<usize> {
+        self.order.get(position).copied()
+    }
+
+    /// Generates a proof for the leaf built from input `index`
+    pub fn generate_proof(&self, index: usize) -> Option<MerkleProof<H>> {
+        self.tree.generate_proof_at(self.position(index)?)
+    }
+
+    /// Drops the mapping and returns the shuffled tree
+    pub fn into_tree(self) -> MerkleTree<H> {
+        self.tree
+    }
+
+    /// Packs proofs for the given input indices, as
+    /// [`MerkleTree::pack_proofs`] does for positions
+    ///
+    /// The packed indices are positions, so a claims file written from it
+    /// reveals only the shuffled order. Returns `None` if any index is out
+    /// of range.
+    pub fn pack_proofs(&self, indices: &[usize]) -> Option<PackedProofs<H>> {
+        let positions = indices
+            .iter()
+            .map(|&index| self.position(index))
+            .collect::<Option<Vec<_>>>()?;
+        self.tree.pack_proofs(&positions)
+    }
+}
+
+impl ShuffledTree {
+    /// Shuffles `data` under `seed` and builds an untagged tree
+    pub fn new(data: Vec<Vec<u8>>, seed: &[u8; 32]) -> Self {
+        Self::new_with_hasher(data, seed, TreeConfig::default())
+    }
+}
@@ -0,0 +1,325 @@
+//! Commitments to directory snapshots, compared like git trees.
+//!
+//! A [`Snapshot`] holds `(path, mode, blob hash)` entries and arranges them
+//! into directories. Each directory commits to its children, sorted by
+//! name, with a [`MerkleTree`] whose leaves are
+//!
+//! ```text
+//! kind: u8 | name_len: u32 | name | mode: u32 | hash_len: u32 | hash
+//! ```
+//!
+//! big-endian, where `kind` is `f` for a file, whose hash is its blob hash,
+//! and `d` for a directory, whose hash is its own root. The root of the
+//! top-level directory commits to the whole snapshot, so two machines agree
+//! on a snapshot by comparing one hash, and [`Snapshot::diff`] only
+//! descends into directories whose hashes differ.
+
+use crate::{MerkleError, MerkleTree};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::io::BufRead;
+
+/// Mode recorded for directories, as in git
+pub const DIRECTORY_MODE: u32 = 0o040000;
+
+/// One file of a snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotEntry {
+    /// Slash-separated path relative to the snapshot root
+    pub path: String,
+    pub mode: u32,
+    /// Hash of the file contents, in whatever scheme the caller uses
+    pub blob: Vec<u8>,
+}
+
+/// How a path differs between two snapshots
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// The mode or blob hash changed
+    Modified,
+}
+
+/// A file that differs between two snapshots
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathChange {
+    pub path: String,
+    pub kind: ChangeKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    File { mode: u32, blob: Vec<u8> },
+    Directory(Directory),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Directory {
+    hash: Vec<u8>,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn record(&self, name: &str) -> Vec<u8> {
+        let (kind, mode, hash) = match self {
+            Node::File { mode, blob } => (b'f', *mode, blob),
+            Node::Directory(directory) => (b'd', DIRECTORY_MODE, &directory.hash),
+        };
+
+        let mut record = vec![kind];
+        record.extend_from_slice(&(name.len() as u32).to_be_bytes());
+        record.extend_from_slice(name.as_bytes());
+        record.extend_from_slice(&mode.to_be_bytes());
+        record.extend_from_slice(&(hash.len() as u32).to_be_bytes());
+        record.extend_from_slice(hash);
+        record
+    }
+}
+
+impl Directory {
+    fn insert(&mut self, components: &[&str], node: Node) -> Result<(), &'static str> {
+        let (name, rest) = components.split_first().unwrap();
+        if rest.is_empty() {
+            if self.children.contains_key(*name) {
+                return Err("path appears twice or is also a directory");
+            }
+            self.children.insert(name.to_string(), node);
+            return Ok(());
+        }
+
+        let child = self
+            .children
+            .entry(name.to_string())
+            .or_insert_with(|| Node::Directory(Directory::default()));
+        match child {
+            Node::Directory(directory) => directory.insert(rest, node),
+            Node::File { .. } => Err("path is inside a file"),
+        }
+    }
+
+    /// Fills in the hash of this directory and every one below it
+    fn seal(&mut self) {
+        for child in self.children.values_mut() {
+            if let Node::Directory(directory) = child {
+                directory.seal();
+            }
+        }
+
+        let records = self
+            .children
+            .iter()
+            .map(|(name, child)| child.record(name))
+            .collect();
+        self.hash = MerkleTree::new(records).root_hash().unwrap_or_default();
+    }
+
+    fn files<'a>(&'a self, prefix: &str, out: &mut Vec<(String, &'a Node)>) {
+        for (name, child) in &self.children {
+            let path = join(prefix, name);
+            match child {
+                Node::File { .. } => out.push((path, child)),
+                Node::Directory(directory) => directory.files(&path, out),
+            }
+        }
+    }
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
+
+/// Every file under `node` as a change of `kind`
+fn all_files(prefix: &str, node: &Node, kind: ChangeKind, out: &mut Vec<PathChange>) {
+    match node {
+        Node::File { .. } => out.push(PathChange {
+            path: prefix.to_string(),
+            kind,
+        }),
+        Node::Directory(directory) => {
+            let mut files = Vec::new();
+            directory.files(prefix, &mut files);
+            out.extend(files.into_iter().map(|(path, _)| PathChange { path, kind }));
+        }
+    }
+}
+
+fn diff_directories(prefix: &str, old: &Directory, new: &Directory, out: &mut Vec<PathChange>) {
+    if old.hash == new.hash {
+        return;
+    }
+
+    // Merge the two sorted child lists
+    let mut old_children = old.children.iter().peekable();
+    let mut new_children = new.children.iter().peekable();
+    loop {
+        let order = match (old_children.peek(), new_children.peek()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some((old_name, _)), Some((new_name, _))) => old_name.cmp(new_name),
+        };
+
+        match order {
+            Ordering::Less => {
+                let (name, node) = old_children.next().unwrap();
+                all_files(&join(prefix, name), node, ChangeKind::Removed, out);
+            }
+            Ordering::Greater => {
+                let (name, node) = new_children.next().unwrap();
+                all_files(&join(prefix, name), node, ChangeKind::Added, out);
+            }
+            Ordering::Equal => {
+                let (name, old_node) = old_children.next().unwrap();
+                let (_, new_node) = new_children.next().unwrap();
+                let path = join(prefix, name);
+                match (old_node, new_node) {
+                    (Node::Directory(old), Node::Directory(new)) => {
+                        diff_directories(&path, old, new, out)
+                    }
+                    (Node::File { .. }, Node::File { .. }) if old_node != new_node => {
+                        out.push(PathChange {
+                            path,
+                            kind: ChangeKind::Modified,
+                        })
+                    }
+                    (Node::File { .. }, Node::File { .. }) => {}
+                    // A file replaced by a directory or the other way round
+                    _ => {
+                        all_files(&path, old_node, ChangeKind::Removed, out);
+                        all_files(&path, new_node, ChangeKind::Added, out);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A committed set of files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    root: Directory,
+    len: usize,
+}
+
+impl Snapshot {
+    /// Builds a snapshot from entries in any order
+    ///
+    /// Fails if a path is empty, has an empty component, appears twice, or
+    /// lies inside another entry.
+    pub fn new(entries: impl IntoIterator<Item = SnapshotEntry>) -> Result<Self, MerkleError> {
+        let mut root = Directory::default();
+        let mut len = 0;
+        for entry in entries {
+            let error =
+                |message: &str| MerkleError::Encode(format!("path {:?}: {}", entry.path, message));
+            let components: Vec<&str> = entry.path.split('/').collect();
+            if components.iter().any(|component| component.is_empty()) {
+                return Err(error("empty path component"));
+            }
+
+            let file = Node::File {
+                mode: entry.mode,
+                blob: entry.blob.clone(),
+            };
+            root.insert(&components, file).map_err(error)?;
+            len += 1;
+        }
+
+        root.seal();
+        Ok(Snapshot { root, len })
+    }
+
+    /// Reads the output of `git ls-tree -r <tree>`
+    ///
+    /// Each line is `<mode> <type> <object>\t<path>`; entries of any type
+    /// other than `blob` (submodule commits, for instance) are kept too,
+    /// with their object id as the hash.
+    pub fn read_ls_tree<R: BufRead>(reader: R) -> Result<Self, MerkleError> {
+        let mut entries = Vec::new();
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = |message: &str| {
+                MerkleError::Parse(format!("line {}: {}", line_number + 1, message))
+            };
+            let (header, path) = line
+                .split_once('\t')
+                .ok_or_else(|| error("expected a tab before the path"))?;
+            let mut fields = header.split(' ');
+            let (Some(mode), Some(_kind), Some(object), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(error("expected `<mode> <type> <object>`"));
+            };
+
+            entries.push(SnapshotEntry {
+                path: path.to_string(),
+                mode: u32::from_str_radix(mode, 8).map_err(|_| error("invalid mode"))?,
+                blob: hex::decode(object).map_err(|_| error("invalid object id"))?,
+            });
+        }
+
+        Self::new(entries)
+    }
+
+    /// Returns the number of files
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the hash committing to every path, mode and blob hash, or
+    /// `None` for an empty snapshot
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        (!self.is_empty()).then(|| self.root.hash.clone())
+    }
+
+    /// Returns the hash of the directory at `path`, `""` being the root
+    pub fn directory_hash(&self, path: &str) -> Option<&[u8]> {
+        let mut directory = &self.root;
+        for component in path.split('/').filter(|component| !component.is_empty()) {
+            match directory.children.get(component)? {
+                Node::Directory(child) => directory = child,
+                Node::File { .. } => return None,
+            }
+        }
+        Some(&directory.hash)
+    }
+
+    /// Returns every entry, sorted by path component
+    pub fn entries(&self) -> Vec<SnapshotEntry> {
+        let mut files = Vec::new();
+        self.root.files("", &mut files);
+        files
+            .into_iter()
+            .map(|(path, node)| match node {
+                Node::File { mode, blob } => SnapshotEntry {
+                    path,
+                    mode: *mode,
+                    blob: blob.clone(),
+                },
+                Node::Directory(_) => unreachable!("files only lists files"),
+            })
+            .collect()
+    }
+
+    /// Lists the files that differ from `self` to `other`, in path order
+    ///
+    /// Directories with the same hash on both sides are skipped whole.
+    pub fn diff(&self, other: &Snapshot) -> Vec<PathChange> {
+        let mut changes = Vec::new();
+        diff_directories("", &self.root, &other.root, &mut changes);
+        changes
+    }
+}
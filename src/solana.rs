@@ -0,0 +1,402 @@
+//! Off-chain model of Solana's concurrent Merkle tree.
+//!
+//! `spl-account-compression` keeps a fixed-depth Keccak-256 tree on chain,
+//! with 32-byte leaves, zero leaves for unused slots and parents hashed as
+//! `keccak(left || right)`. Alongside the root it keeps a ring buffer of
+//! [`ChangeLog`]s, one per write, so a write whose proof was made against
+//! any root still in the buffer can be fast-forwarded and applied.
+//! [`ConcurrentMerkleTree`] follows the same rules and also keeps every
+//! leaf, so it can hand out the proofs an indexer would and check or replay
+//! writes exactly as the program does.
+//!
+//! The account can also store the top `canopy_depth` levels of the tree,
+//! letting transactions send only the lower `max_depth - canopy_depth`
+//! proof nodes. [`ConcurrentMerkleTree::canopy`] lays those levels out the
+//! way the account does and [`fill_in_proof_from_canopy`] completes a
+//! truncated proof from them.
+
+use crate::MerkleError;
+use sha3::{Digest, Keccak256};
+use std::collections::VecDeque;
+
+/// A 32-byte leaf or internal node
+pub type Node = [u8; 32];
+
+/// The value of every unused leaf
+pub const EMPTY_NODE: Node = [0; 32];
+
+/// Deepest tree the program supports
+pub const MAX_SUPPORTED_DEPTH: usize = 30;
+
+fn keccak_pair(left: &Node, right: &Node) -> Node {
+    let mut hasher = Keccak256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Returns the root of an all-empty subtree `level` levels tall
+pub fn empty_node(level: u32) -> Node {
+    (0..level).fold(EMPTY_NODE, |node, _| keccak_pair(&node, &node))
+}
+
+/// Returns the root reached from `leaf` at `index` through `proof`, leaf
+/// level first
+pub fn recompute(leaf: Node, proof: &[Node], index: u32) -> Node {
+    proof
+        .iter()
+        .enumerate()
+        .fold(leaf, |node, (level, sibling)| {
+            if (index >> level) & 1 == 0 {
+                keccak_pair(&node, sibling)
+            } else {
+                keccak_pair(sibling, &node)
+            }
+        })
+}
+
+/// Returns the depth of the canopy stored in `canopy`, if its length is one
+/// a canopy can have
+fn canopy_depth(canopy: &[Node]) -> Option<usize> {
+    let slots = canopy.len() + 2;
+    slots
+        .is_power_of_two()
+        .then(|| slots.trailing_zeros() as usize - 1)
+}
+
+/// Extends a proof truncated for a canopy back to the full depth
+///
+/// Empty canopy slots stand for empty subtrees, as they do on chain.
+pub fn fill_in_proof_from_canopy(
+    max_depth: usize,
+    canopy: &[Node],
+    index: u32,
+    proof: &mut Vec<Node>,
+) -> Result<(), MerkleError> {
+    let depth = canopy_depth(canopy)
+        .filter(|&depth| depth <= max_depth)
+        .ok_or_else(|| MerkleError::Parse(format!("{} nodes is not a canopy", canopy.len())))?;
+
+    // Heap position of the path node at the bottom of the canopy, with the
+    // root at 1 and the canopy stored from position 2 on
+    let mut position = ((1u64 << max_depth) + u64::from(index)) >> (max_depth - depth);
+    let mut inferred = Vec::new();
+    while position > 1 {
+        let sibling = (position ^ 1) as usize - 2;
+        let level = max_depth as u32 - position.ilog2();
+        inferred.push(match canopy[sibling] {
+            EMPTY_NODE => empty_node(level),
+            node => node,
+        });
+        position >>= 1;
+    }
+
+    let overlap = (proof.len() + inferred.len()).saturating_sub(max_depth);
+    proof.extend(inferred.into_iter().skip(overlap));
+    Ok(())
+}
+
+/// The nodes one write changed, recorded so older proofs can catch up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeLog {
+    /// Root after the write
+    pub root: Node,
+    /// The new leaf and every node above it, leaf first, root excluded
+    pub path: Vec<Node>,
+    /// Index of the leaf written
+    pub index: u32,
+}
+
+impl ChangeLog {
+    /// Updates a proof for leaf `index` made before this write
+    ///
+    /// If this write replaced that leaf, `leaf` becomes the value it wrote.
+    fn fast_forward(&self, index: u32, leaf: &mut Node, proof: &mut [Node]) {
+        if index == self.index {
+            *leaf = self.path[0];
+            return;
+        }
+
+        // Below the level where the two paths meet nothing changed for
+        // `index`; at that level the changed node is its sibling
+        let meet = (index ^ self.index).ilog2() as usize;
+        proof[meet] = self.path[meet];
+    }
+}
+
+/// A concurrent Merkle tree as kept by `spl-account-compression`
+#[derive(Debug, Clone)]
+pub struct ConcurrentMerkleTree {
+    max_depth: usize,
+    max_buffer_size: usize,
+    sequence_number: u64,
+    /// Oldest first; the newest holds the current root
+    change_logs: VecDeque<ChangeLog>,
+    /// `levels[l]` holds level `l` nodes up to the rightmost written leaf
+    levels: Vec<Vec<Node>>,
+    /// `empty[l]` is the root of an empty subtree `l` levels tall
+    empty: Vec<Node>,
+}
+
+impl ConcurrentMerkleTree {
+    /// Creates an empty tree, like the program's `init_empty_merkle_tree`
+    ///
+    /// `max_depth` may be at most [`MAX_SUPPORTED_DEPTH`] and
+    /// `max_buffer_size` must be a power of two.
+    pub fn new(max_depth: usize, max_buffer_size: usize) -> Result<Self, MerkleError> {
+        if max_depth == 0 || max_depth > MAX_SUPPORTED_DEPTH || !max_buffer_size.is_power_of_two() {
+            return Err(MerkleError::Parse(format!(
+                "unsupported concurrent tree shape: depth {}, buffer {}",
+                max_depth, max_buffer_size
+            )));
+        }
+
+        let empty: Vec<Node> = (0..=max_depth as u32).map(empty_node).collect();
+        let initial = ChangeLog {
+            root: empty[max_depth],
+            path: empty[..max_depth].to_vec(),
+            index: 0,
+        };
+
+        Ok(ConcurrentMerkleTree {
+            max_depth,
+            max_buffer_size,
+            sequence_number: 0,
+            change_logs: VecDeque::from([initial]),
+            levels: vec![Vec::new(); max_depth],
+            empty,
+        })
+    }
+
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    pub fn max_buffer_size(&self) -> usize {
+        self.max_buffer_size
+    }
+
+    /// Returns the number of writes applied so far
+    pub fn sequence_number(&self) -> u64 {
+        self.sequence_number
+    }
+
+    /// Returns the number of leaf slots in use, the index the next append
+    /// writes to
+    pub fn len(&self) -> usize {
+        self.levels[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels[0].is_empty()
+    }
+
+    /// Returns the current root
+    pub fn root(&self) -> Node {
+        self.change_logs.back().unwrap().root
+    }
+
+    /// Returns the leaf at `index`; unused slots are [`EMPTY_NODE`]
+    pub fn leaf(&self, index: u32) -> Option<Node> {
+        self.node(0, index as usize)
+    }
+
+    /// Returns the buffered change logs, oldest first
+    pub fn change_logs(&self) -> impl Iterator<Item = &ChangeLog> {
+        self.change_logs.iter()
+    }
+
+    /// Appends `leaf` in the next unused slot and returns the new root
+    pub fn append(&mut self, leaf: Node) -> Result<Node, MerkleError> {
+        if leaf == EMPTY_NODE {
+            return Err(MerkleError::Encode(
+                "cannot append the empty node".to_string(),
+            ));
+        }
+        if self.len() >= 1 << self.max_depth {
+            return Err(MerkleError::TreeFull {
+                capacity: 1 << self.max_depth,
+            });
+        }
+
+        let index = self.len() as u32;
+        Ok(self.write(index, leaf))
+    }
+
+    /// Replaces `previous_leaf` at `index` with `new_leaf`, like the
+    /// program's `replace_leaf`
+    ///
+    /// `proof` may be truncated, with missing nodes taken as empty, and may
+    /// have been made against `current_root` or any root still in the
+    /// buffer; it is fast-forwarded through the writes since. It fails if
+    /// one of those writes replaced this same leaf.
+    pub fn set_leaf(
+        &mut self,
+        current_root: Node,
+        previous_leaf: Node,
+        new_leaf: Node,
+        proof: &[Node],
+        index: u32,
+    ) -> Result<Node, MerkleError> {
+        if index as usize > self.len() || index as u64 >= 1 << self.max_depth {
+            return Err(MerkleError::IndexOutOfBounds {
+                index: index as usize,
+                len: self.len(),
+            });
+        }
+
+        let mut proof = self.fill_in_proof(proof);
+        self.check_valid_leaf(current_root, previous_leaf, &mut proof, index)?;
+        Ok(self.write(index, new_leaf))
+    }
+
+    /// Proves `leaf` is at `index` in the current tree, like the program's
+    /// `verify_leaf`, fast-forwarding `proof` as [`set_leaf`] does
+    ///
+    /// [`set_leaf`]: ConcurrentMerkleTree::set_leaf
+    pub fn prove_leaf(
+        &self,
+        current_root: Node,
+        leaf: Node,
+        proof: &[Node],
+        index: u32,
+    ) -> Result<(), MerkleError> {
+        if index as usize > self.len() {
+            return Err(MerkleError::IndexOutOfBounds {
+                index: index as usize,
+                len: self.len(),
+            });
+        }
+
+        let mut proof = self.fill_in_proof(proof);
+        self.check_valid_leaf(current_root, leaf, &mut proof, index)
+    }
+
+    /// Returns the full-depth proof for the slot at `index`
+    pub fn proof(&self, index: u32) -> Option<Vec<Node>> {
+        let index = index as usize;
+        if index >= 1 << self.max_depth {
+            return None;
+        }
+
+        Some(
+            (0..self.max_depth)
+                .map(|level| self.node(level, (index >> level) ^ 1).unwrap())
+                .collect(),
+        )
+    }
+
+    /// Returns the proof for `index` without the nodes a canopy of
+    /// `canopy_depth` levels supplies
+    pub fn proof_for_canopy(&self, index: u32, canopy_depth: usize) -> Option<Vec<Node>> {
+        let mut proof = self.proof(index)?;
+        proof.truncate(self.max_depth.checked_sub(canopy_depth)?);
+        Some(proof)
+    }
+
+    /// Returns the top `canopy_depth` levels below the root in the order the
+    /// account stores them, with untouched subtrees left empty
+    pub fn canopy(&self, canopy_depth: usize) -> Vec<Node> {
+        let canopy_depth = canopy_depth.min(self.max_depth);
+        (2..1usize << (canopy_depth + 1))
+            .map(|position| {
+                let level = self.max_depth - position.ilog2() as usize;
+                let offset = position - (1 << position.ilog2());
+                self.levels[level]
+                    .get(offset)
+                    .copied()
+                    .unwrap_or(EMPTY_NODE)
+            })
+            .collect()
+    }
+
+    /// Returns the node below the root at `level`, filling in empty
+    /// subtrees, or `None` past the edge of the tree
+    fn node(&self, level: usize, position: usize) -> Option<Node> {
+        if position >= 1 << (self.max_depth - level) {
+            return None;
+        }
+
+        Some(
+            self.levels[level]
+                .get(position)
+                .copied()
+                .unwrap_or(self.empty[level]),
+        )
+    }
+
+    /// Pads a truncated proof with empty subtrees up to the full depth
+    fn fill_in_proof(&self, proof: &[Node]) -> Vec<Node> {
+        let mut proof = proof[..proof.len().min(self.max_depth)].to_vec();
+        proof.extend_from_slice(&self.empty[proof.len()..self.max_depth]);
+        proof
+    }
+
+    /// Fast-forwards `proof` to the current root and checks it
+    ///
+    /// If `current_root` has left the buffer the proof is replayed through
+    /// every buffered write after the oldest, as the program does.
+    fn check_valid_leaf(
+        &self,
+        current_root: Node,
+        leaf: Node,
+        proof: &mut [Node],
+        index: u32,
+    ) -> Result<(), MerkleError> {
+        let start = self
+            .change_logs
+            .iter()
+            .rposition(|change_log| change_log.root == current_root)
+            .map_or(1, |position| position + 1);
+
+        let mut current_leaf = leaf;
+        for change_log in self.change_logs.range(start..) {
+            change_log.fast_forward(index, &mut current_leaf, proof);
+        }
+        if current_leaf != leaf {
+            return Err(MerkleError::LeafModified { index });
+        }
+
+        if recompute(leaf, proof, index) != self.root() {
+            return Err(MerkleError::InvalidProof);
+        }
+        Ok(())
+    }
+
+    /// Writes `leaf` at `index`, which must be in use or the next free slot,
+    /// and records the change
+    fn write(&mut self, index: u32, leaf: Node) -> Node {
+        let mut path = Vec::with_capacity(self.max_depth);
+        let mut node = leaf;
+        let mut position = index as usize;
+
+        for level in 0..self.max_depth {
+            let nodes = &mut self.levels[level];
+            if position == nodes.len() {
+                nodes.push(node);
+            } else {
+                nodes[position] = node;
+            }
+            path.push(node);
+
+            let sibling = self.node(level, position ^ 1).unwrap();
+            node = if position & 1 == 0 {
+                keccak_pair(&node, &sibling)
+            } else {
+                keccak_pair(&sibling, &node)
+            };
+            position >>= 1;
+        }
+
+        if self.change_logs.len() == self.max_buffer_size {
+            self.change_logs.pop_front();
+        }
+        self.change_logs.push_back(ChangeLog {
+            root: node,
+            path,
+            index,
+        });
+        self.sequence_number += 1;
+        node
+    }
+}
@@ -0,0 +1,520 @@
+//! A sparse Merkle tree over 256-bit keys, backed by an async node store.
+//!
+//! Keys are hashed with SHA-256 and the bits of the hash, most significant
+//! first, pick the path from the root. A subtree holding a single entry is
+//! stored as just that leaf, so paths are about `log2(n)` long rather than
+//! 256. Hashes are domain separated:
+//!
+//! - empty subtree: 32 zero bytes
+//! - leaf: `H(0x00 || key_hash || H(value))`
+//! - internal node: `H(0x01 || left || right)`
+//!
+//! Nodes are content addressed, so any key-value database can implement
+//! [`NodeStore`]; RocksDB and sled map onto it directly with a column or
+//! tree keyed by node hash. Writes go to a buffer that is flushed to the
+//! store in one batch once it reaches [`SparseMerkleTree::with_buffer_limit`]
+//! nodes, or on [`SparseMerkleTree::flush`]. Replaced nodes are not
+//! deleted, so every earlier root stays readable until
+//! [`compact`](crate::compact()) drops the ones no longer retained.
+
+use crate::MerkleError;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+
+/// Hash of a sparse tree node
+pub type NodeHash = [u8; 32];
+
+/// The hash standing in for any subtree with no entries
+pub const EMPTY_HASH: NodeHash = [0; 32];
+
+/// Number of buffered nodes that triggers a flush unless configured otherwise
+const DEFAULT_BUFFER_LIMIT: usize = 1024;
+
+fn hash_key(key: &[u8]) -> NodeHash {
+    Sha256::digest(key).into()
+}
+
+fn hash_sparse_leaf(key_hash: &NodeHash, value_hash: &NodeHash) -> NodeHash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(key_hash);
+    hasher.update(value_hash);
+    hasher.finalize().into()
+}
+
+pub(crate) fn hash_sparse_node(left: &NodeHash, right: &NodeHash) -> NodeHash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Returns true if bit `depth` of `key` is set, counting from the top
+pub(crate) fn bit(key: &NodeHash, depth: usize) -> bool {
+    key[depth / 8] & (0x80 >> (depth % 8)) != 0
+}
+
+/// A stored node of a sparse tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SparseNode {
+    Internal { left: NodeHash, right: NodeHash },
+    Leaf { key_hash: NodeHash, value: Vec<u8> },
+}
+
+impl SparseNode {
+    /// Returns the hash the node is stored under
+    pub fn hash(&self) -> NodeHash {
+        match self {
+            SparseNode::Internal { left, right } => hash_sparse_node(left, right),
+            SparseNode::Leaf { key_hash, value } => {
+                hash_sparse_leaf(key_hash, &Sha256::digest(value).into())
+            }
+        }
+    }
+}
+
+/// Content-addressed storage for sparse tree nodes
+///
+/// Both methods take batches; the tree reads one level of a multi-key
+/// lookup per call and writes a whole buffer per call.
+pub trait NodeStore: Sync {
+    /// Looks up nodes by hash, returning `None` for any that are absent
+    fn get_nodes(
+        &self,
+        hashes: &[NodeHash],
+    ) -> impl Future<Output = Result<Vec<Option<SparseNode>>, MerkleError>> + Send;
+
+    /// Stores nodes under their hashes
+    fn put_nodes(
+        &self,
+        nodes: Vec<(NodeHash, SparseNode)>,
+    ) -> impl Future<Output = Result<(), MerkleError>> + Send;
+}
+
+/// A [`NodeStore`] held in memory, for tests and small deployments
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    pub(crate) nodes: Mutex<HashMap<NodeHash, SparseNode>>,
+}
+
+impl MemoryStore {
+    /// Creates an empty store
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Returns the number of stored nodes
+    pub fn len(&self) -> usize {
+        self.nodes.lock().unwrap().len()
+    }
+
+    /// Returns true if nothing has been stored
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl NodeStore for MemoryStore {
+    async fn get_nodes(&self, hashes: &[NodeHash]) -> Result<Vec<Option<SparseNode>>, MerkleError> {
+        let nodes = self.nodes.lock().unwrap();
+        Ok(hashes.iter().map(|hash| nodes.get(hash).cloned()).collect())
+    }
+
+    async fn put_nodes(&self, nodes: Vec<(NodeHash, SparseNode)>) -> Result<(), MerkleError> {
+        self.nodes.lock().unwrap().extend(nodes);
+        Ok(())
+    }
+}
+
+/// A proof that a key holds a value, or holds nothing, under a root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseProof {
+    /// Sibling hashes from the root down
+    siblings: Vec<NodeHash>,
+    /// Key and value hashes of the leaf the path ends at, if any
+    leaf: Option<(NodeHash, NodeHash)>,
+}
+
+impl SparseProof {
+    /// Returns the sibling hashes from the root down
+    pub fn siblings(&self) -> &[NodeHash] {
+        &self.siblings
+    }
+
+    /// Verifies that `key` maps to `value` under `root`
+    ///
+    /// With `value` set to `None` this checks that the key is absent: the
+    /// path ends at an empty subtree or at a leaf for a different key.
+    pub fn verify(&self, root: &NodeHash, key: &[u8], value: Option<&[u8]>) -> bool {
+        let key_hash = hash_key(key);
+        if self.siblings.len() > 256 {
+            return false;
+        }
+
+        let mut hash = match (&self.leaf, value) {
+            (None, None) => EMPTY_HASH,
+            (Some((leaf_key, value_hash)), None) => {
+                let shares_path = (0..self.siblings.len())
+                    .all(|depth| bit(leaf_key, depth) == bit(&key_hash, depth));
+                if *leaf_key == key_hash || !shares_path {
+                    return false;
+                }
+                hash_sparse_leaf(leaf_key, value_hash)
+            }
+            (Some((leaf_key, value_hash)), Some(value)) => {
+                if *leaf_key != key_hash || *value_hash != <NodeHash>::from(Sha256::digest(value)) {
+                    return false;
+                }
+                hash_sparse_leaf(leaf_key, value_hash)
+            }
+            (None, Some(_)) => return false,
+        };
+
+        for (depth, sibling) in self.siblings.iter().enumerate().rev() {
+            hash = if bit(&key_hash, depth) {
+                hash_sparse_node(sibling, &hash)
+            } else {
+                hash_sparse_node(&hash, sibling)
+            };
+        }
+
+        hash == *root
+    }
+}
+
+/// A sparse Merkle tree whose nodes live in a [`NodeStore`]
+pub struct SparseMerkleTree<S> {
+    store: S,
+    root: NodeHash,
+    /// Nodes written since the last flush, consulted before the store
+    buffer: HashMap<NodeHash, SparseNode>,
+    buffer_limit: usize,
+}
+
+impl<S: NodeStore> SparseMerkleTree<S> {
+    /// Creates an empty tree in `store`
+    pub fn new(store: S) -> Self {
+        Self::open(store, EMPTY_HASH)
+    }
+
+    /// Opens the tree with root `root` from nodes already in `store`
+    pub fn open(store: S, root: NodeHash) -> Self {
+        SparseMerkleTree {
+            store,
+            root,
+            buffer: HashMap::new(),
+            buffer_limit: DEFAULT_BUFFER_LIMIT,
+        }
+    }
+
+    /// Sets how many buffered nodes trigger a flush
+    pub fn with_buffer_limit(mut self, nodes: usize) -> Self {
+        self.buffer_limit = nodes;
+        self
+    }
+
+    /// Returns the current root hash
+    ///
+    /// Nodes under the root may still be buffered; flush before persisting
+    /// the root anywhere.
+    pub fn root_hash(&self) -> NodeHash {
+        self.root
+    }
+
+    /// Returns the underlying store
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the number of nodes waiting to be flushed
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Looks up the value stored under `key`
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, MerkleError> {
+        Ok(self.get_many(&[key]).await?.pop().unwrap())
+    }
+
+    /// Looks up several keys, reading each tree level in one batch
+    pub async fn get_many(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>, MerkleError> {
+        let key_hashes: Vec<NodeHash> = keys.iter().map(|key| hash_key(key)).collect();
+        let mut values = vec![None; keys.len()];
+        let mut walks: Vec<(usize, NodeHash)> = (0..keys.len()).map(|i| (i, self.root)).collect();
+        let mut depth = 0;
+
+        while !walks.is_empty() {
+            let hashes: Vec<NodeHash> = walks.iter().map(|(_, hash)| *hash).collect();
+            let nodes = self.nodes(&hashes).await?;
+
+            let mut next = Vec::new();
+            for ((i, _), node) in walks.into_iter().zip(nodes) {
+                match node {
+                    Some(SparseNode::Internal { left, right }) => {
+                        next.push((
+                            i,
+                            if bit(&key_hashes[i], depth) {
+                                right
+                            } else {
+                                left
+                            },
+                        ));
+                    }
+                    Some(SparseNode::Leaf { key_hash, value }) if key_hash == key_hashes[i] => {
+                        values[i] = Some(value);
+                    }
+                    _ => {}
+                }
+            }
+
+            walks = next;
+            depth += 1;
+        }
+
+        Ok(values)
+    }
+
+    /// Looks up `key` along with a proof of the answer against the root
+    pub async fn get_with_proof(
+        &self,
+        key: &[u8],
+    ) -> Result<(Option<Vec<u8>>, SparseProof), MerkleError> {
+        let key_hash = hash_key(key);
+        let (siblings, terminal) = self.walk(&key_hash).await?;
+
+        let (value, leaf) = match terminal {
+            Some(SparseNode::Leaf {
+                key_hash: leaf_key,
+                value,
+            }) => {
+                let value_hash = Sha256::digest(&value).into();
+                (
+                    (leaf_key == key_hash).then_some(value),
+                    Some((leaf_key, value_hash)),
+                )
+            }
+            _ => (None, None),
+        };
+
+        Ok((value, SparseProof { siblings, leaf }))
+    }
+
+    /// Stores `value` under `key`, replacing any earlier value
+    pub async fn insert(&mut self, key: &[u8], value: Vec<u8>) -> Result<(), MerkleError> {
+        self.insert_unflushed(key, value).await?;
+
+        if self.buffer.len() >= self.buffer_limit {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Inserts every entry, then flushes once
+    pub async fn insert_batch(
+        &mut self,
+        entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>,
+    ) -> Result<(), MerkleError> {
+        for (key, value) in entries {
+            self.insert_unflushed(&key, value).await?;
+        }
+
+        self.flush().await
+    }
+
+    /// Removes `key`, returning the value it held
+    pub async fn remove(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, MerkleError> {
+        let key_hash = hash_key(key);
+        let (siblings, terminal) = self.walk(&key_hash).await?;
+
+        let value = match terminal {
+            Some(SparseNode::Leaf {
+                key_hash: leaf_key,
+                value,
+            }) if leaf_key == key_hash => value,
+            _ => return Ok(None),
+        };
+
+        // A leaf left alone in its subtree moves up to keep paths short
+        let mut hash = EMPTY_HASH;
+        let mut depth = siblings.len();
+        while depth > 0 {
+            let sibling = siblings[depth - 1];
+            let lift = if hash == EMPTY_HASH {
+                matches!(self.node(&sibling).await?, Some(SparseNode::Leaf { .. }))
+            } else {
+                sibling == EMPTY_HASH
+            };
+            if !lift {
+                break;
+            }
+
+            if hash == EMPTY_HASH {
+                hash = sibling;
+            }
+            depth -= 1;
+        }
+
+        self.root = self.rehash_path(&key_hash, &siblings[..depth], hash);
+        if self.buffer.len() >= self.buffer_limit {
+            self.flush().await?;
+        }
+        Ok(Some(value))
+    }
+
+    /// Writes every buffered node to the store in one batch
+    pub async fn flush(&mut self) -> Result<(), MerkleError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let nodes = self
+            .buffer
+            .iter()
+            .map(|(hash, node)| (*hash, node.clone()))
+            .collect();
+        self.store.put_nodes(nodes).await?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    async fn insert_unflushed(&mut self, key: &[u8], value: Vec<u8>) -> Result<(), MerkleError> {
+        let key_hash = hash_key(key);
+        let (siblings, terminal) = self.walk(&key_hash).await?;
+        let depth = siblings.len();
+
+        let mut hash = self.buffer_node(SparseNode::Leaf { key_hash, value });
+
+        // Another key's leaf sits on the path: push both down to where they part
+        if let Some(
+            other @ SparseNode::Leaf {
+                key_hash: other_key,
+                ..
+            },
+        ) = &terminal
+        {
+            if *other_key != key_hash {
+                let split = (depth..256)
+                    .find(|&d| bit(&key_hash, d) != bit(other_key, d))
+                    .unwrap();
+                let other_hash = other.hash();
+
+                hash = if bit(&key_hash, split) {
+                    self.buffer_internal(other_hash, hash)
+                } else {
+                    self.buffer_internal(hash, other_hash)
+                };
+                for d in (depth..split).rev() {
+                    hash = if bit(&key_hash, d) {
+                        self.buffer_internal(EMPTY_HASH, hash)
+                    } else {
+                        self.buffer_internal(hash, EMPTY_HASH)
+                    };
+                }
+            }
+        }
+
+        self.root = self.rehash_path(&key_hash, &siblings, hash);
+        Ok(())
+    }
+
+    /// Walks from the root towards `key_hash`, returning the siblings passed
+    /// on the way down and the leaf the walk ended at, if any
+    async fn walk(
+        &self,
+        key_hash: &NodeHash,
+    ) -> Result<(Vec<NodeHash>, Option<SparseNode>), MerkleError> {
+        let mut siblings = Vec::new();
+        let mut hash = self.root;
+
+        loop {
+            match self.node(&hash).await? {
+                Some(SparseNode::Internal { left, right }) => {
+                    if siblings.len() == 256 {
+                        return Err(MerkleError::Parse(
+                            "sparse tree is deeper than its keys".to_string(),
+                        ));
+                    }
+                    let (next, sibling) = if bit(key_hash, siblings.len()) {
+                        (right, left)
+                    } else {
+                        (left, right)
+                    };
+                    siblings.push(sibling);
+                    hash = next;
+                }
+                terminal => return Ok((siblings, terminal)),
+            }
+        }
+    }
+
+    /// Hashes `hash` back up to the root along `key_hash`'s path
+    fn rehash_path(
+        &mut self,
+        key_hash: &NodeHash,
+        siblings: &[NodeHash],
+        mut hash: NodeHash,
+    ) -> NodeHash {
+        for (depth, sibling) in siblings.iter().enumerate().rev() {
+            hash = if bit(key_hash, depth) {
+                self.buffer_internal(*sibling, hash)
+            } else {
+                self.buffer_internal(hash, *sibling)
+            };
+        }
+        hash
+    }
+
+    fn buffer_internal(&mut self, left: NodeHash, right: NodeHash) -> NodeHash {
+        self.buffer_node(SparseNode::Internal { left, right })
+    }
+
+    pub(crate) fn buffer_node(&mut self, node: SparseNode) -> NodeHash {
+        let hash = node.hash();
+        self.buffer.insert(hash, node);
+        hash
+    }
+
+    async fn node(&self, hash: &NodeHash) -> Result<Option<SparseNode>, MerkleError> {
+        Ok(self.nodes(std::slice::from_ref(hash)).await?.pop().unwrap())
+    }
+
+    /// Fetches nodes from the buffer, then the store in one batch
+    ///
+    /// The empty hash yields `None`; any other hash must resolve.
+    pub(crate) async fn nodes(
+        &self,
+        hashes: &[NodeHash],
+    ) -> Result<Vec<Option<SparseNode>>, MerkleError> {
+        let mut nodes: Vec<Option<SparseNode>> = hashes
+            .iter()
+            .map(|hash| self.buffer.get(hash).cloned())
+            .collect();
+
+        let missing: Vec<NodeHash> = hashes
+            .iter()
+            .zip(&nodes)
+            .filter(|(hash, node)| node.is_none() && **hash != EMPTY_HASH)
+            .map(|(hash, _)| *hash)
+            .collect();
+        if missing.is_empty() {
+            return Ok(nodes);
+        }
+
+        let mut fetched = self.store.get_nodes(&missing).await?.into_iter();
+        for (hash, node) in hashes.iter().zip(&mut nodes) {
+            if node.is_none() && *hash != EMPTY_HASH {
+                *node = Some(
+                    fetched
+                        .next()
+                        .flatten()
+                        .ok_or(MerkleError::MissingNode(*hash))?,
+                );
+            }
+        }
+
+        Ok(nodes)
+    }
+}
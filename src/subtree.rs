@@ -0,0 +1,119 @@
+//! Inclusion proofs for internal nodes.
+//!
+//! Level 0 holds the leaf hashes and the last level holds the root, the same
+//! numbering [`MerkleTree::export_levels`] uses. Node `index` of `level`
+//! is the root of the subtree over leaves `index * 2^level` up to the next
+//! multiple of `2^level`, clipped to the tree size.
+//!
+//! Leaves and internal nodes are hashed the same way, so a proof does not by
+//! itself say which level it starts at. [`SubtreeProof::verify`] therefore
+//! takes the tree size and the level and leaf range the verifier expects,
+//! and checks the number of proof hashes, `depth - level`, and the side of
+//! each one against them.
+
+use crate::view::level_lengths;
+use crate::{MerkleHasher, MerkleProof, MerkleTree, Side, TreeConfig};
+use std::ops::Range;
+
+/// A proof that an internal hash is the root of one subtree of the tree
+pub struct SubtreeProof<H: MerkleHasher = TreeConfig> {
+    level: usize,
+    index: usize,
+    leaves: Range<usize>,
+    proof: MerkleProof<H>,
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Proves that node `index` of `level` is part of the tree
+    ///
+    /// Returns `None` if there is no such node. Level 0 gives the same proof
+    /// as [`MerkleTree::generate_proof_at`], and the root level gives a proof
+    /// with no hashes.
+    pub fn prove_subtree(&self, level: usize, index: usize) -> Option<SubtreeProof<H>> {
+        if index >= self.levels.get(level)?.len() {
+            return None;
+        }
+
+        let start = index << level;
+        let end = ((index + 1) << level).min(self.len());
+
+        Some(SubtreeProof {
+            level,
+            index,
+            leaves: start..end,
+            proof: self.build_proof_from(level, index),
+        })
+    }
+
+    /// Verifies a subtree proof against this tree's root
+    pub fn verify_subtree_proof(&self, proof: &SubtreeProof<H>) -> bool {
+        match self.root_hash() {
+            Some(root) => proof.verify(&root, self.len(), proof.level, proof.leaves()),
+            None => false,
+        }
+    }
+}
+
+impl<H: MerkleHasher> SubtreeProof<H> {
+    /// Returns the level of the proven node, 0 being the leaves
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Returns the position of the proven node within its level
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the leaf indices the proven node covers
+    pub fn leaves(&self) -> Range<usize> {
+        self.leaves.clone()
+    }
+
+    /// Returns the hash of the proven node
+    pub fn node_hash(&self) -> &[u8] {
+        self.proof.leaf_hash()
+    }
+
+    /// Returns the root hash of the tree the proof was generated from
+    pub fn root_hash(&self) -> &[u8] {
+        self.proof.root_hash()
+    }
+
+    /// Verifies that the proof shows the node of `level` over `leaves` is
+    /// part of the tree of `tree_size` leaves with the given root hash
+    ///
+    /// Fails if `leaves` isn't exactly the range one node of `level`
+    /// covers, or if the proof's path doesn't climb from that node to the
+    /// root of a tree of that size.
+    pub fn verify(
+        &self,
+        root_hash: &[u8],
+        tree_size: usize,
+        level: usize,
+        leaves: Range<usize>,
+    ) -> bool {
+        let lengths: Vec<u64> = level_lengths(tree_size as u64).collect();
+        let Some(&level_len) = lengths.get(level) else {
+            return false;
+        };
+        let index = leaves.start >> level;
+        let covered = (index << level)..((index + 1) << level).min(tree_size);
+        if self.level != level
+            || self.index != index
+            || self.leaves != leaves
+            || covered != leaves
+            || index as u64 >= level_len
+        {
+            return false;
+        }
+
+        let path = &self.proof.proof_hashes;
+        path.len() == lengths.len() - 1 - level
+            && path
+                .iter()
+                .enumerate()
+                .all(|(height, (_, side))| *side == Side::of_sibling((index >> height) as u64))
+            && self.proof.verify(root_hash)
+    }
+}
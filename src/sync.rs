@@ -0,0 +1,293 @@
+//! State sync for sparse trees from untrusted peers.
+//!
+//! [`SparseMerkleTree::export_chunks`] cuts a tree into the subtrees found
+//! at a split depth, in key order, and ships each as a [`SyncChunk`]: every
+//! node of the subtree plus the siblings from the root down to it. A new
+//! node opens an empty store at a root it trusts with
+//! [`SparseMerkleTree::open`] and applies chunks as they arrive, from any
+//! peer and in any order. Each is checked against the root before anything
+//! is written, and once every chunk is in, the tree is complete; the nodes
+//! above the split depth are rebuilt from the chunks' sibling paths.
+//!
+//! A chunk's encoding is big-endian:
+//!
+//! ```text
+//! "SMTS" | version: u8 | depth: u16 | prefix: [u8; 32] | sibling: [u8; 32] * depth
+//!        | count: u64 | node * count
+//! ```
+//!
+//! where the first `depth` bits of `prefix` are the path to the subtree and
+//! a node is either `0x01 | left: [u8; 32] | right: [u8; 32]` or
+//! `0x00 | key hash: [u8; 32] | value_len: u32 | value`.
+
+use crate::sparse::{bit, hash_sparse_node, NodeHash, SparseNode, EMPTY_HASH};
+use crate::{MerkleError, NodeStore, SparseMerkleTree};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+pub(crate) const MAGIC: &[u8; 4] = b"SMTS";
+pub(crate) const FORMAT_VERSION: u8 = 1;
+
+/// One subtree of a sparse tree with the path proving where it sits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncChunk {
+    /// Path from the root to the subtree, in the top `siblings.len()` bits
+    prefix: NodeHash,
+    /// Sibling hashes from the root down to the subtree
+    siblings: Vec<NodeHash>,
+    /// Every node of the subtree, its top first
+    nodes: Vec<SparseNode>,
+}
+
+impl SyncChunk {
+    /// Returns how far below the root the subtree sits
+    pub fn depth(&self) -> usize {
+        self.siblings.len()
+    }
+
+    /// Returns the nodes of the subtree, its top first
+    pub fn nodes(&self) -> &[SparseNode] {
+        &self.nodes
+    }
+
+    /// Returns true if the chunk holds exactly one whole subtree and its
+    /// path leads to `root`
+    pub fn verify(&self, root: &NodeHash) -> bool {
+        let Some(top) = self.nodes.first() else {
+            return false;
+        };
+        if self.siblings.len() > 256 {
+            return false;
+        }
+
+        // Every child must be in the chunk and every node reachable from the top
+        let by_hash: HashMap<NodeHash, &SparseNode> =
+            self.nodes.iter().map(|node| (node.hash(), node)).collect();
+        let mut reached = 0;
+        let mut pending = vec![top.hash()];
+        while let Some(hash) = pending.pop() {
+            match by_hash.get(&hash) {
+                Some(SparseNode::Internal { left, right }) => pending.extend(
+                    [left, right]
+                        .into_iter()
+                        .filter(|&&child| child != EMPTY_HASH),
+                ),
+                Some(SparseNode::Leaf { .. }) => {}
+                None => return false,
+            }
+            reached += 1;
+        }
+        if reached != self.nodes.len() {
+            return false;
+        }
+
+        let mut hash = top.hash();
+        for (depth, sibling) in self.siblings.iter().enumerate().rev() {
+            hash = if bit(&self.prefix, depth) {
+                hash_sparse_node(sibling, &hash)
+            } else {
+                hash_sparse_node(&hash, sibling)
+            };
+        }
+        hash == *root
+    }
+
+    /// Writes the encoding described in the module docs
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), MerkleError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[FORMAT_VERSION])?;
+        writer.write_all(&(self.siblings.len() as u16).to_be_bytes())?;
+        writer.write_all(&self.prefix)?;
+        for sibling in &self.siblings {
+            writer.write_all(sibling)?;
+        }
+
+        writer.write_all(&(self.nodes.len() as u64).to_be_bytes())?;
+        for node in &self.nodes {
+            match node {
+                SparseNode::Internal { left, right } => {
+                    writer.write_all(&[0x01])?;
+                    writer.write_all(left)?;
+                    writer.write_all(right)?;
+                }
+                SparseNode::Leaf { key_hash, value } => {
+                    let len = u32::try_from(value.len()).map_err(|_| {
+                        MerkleError::Encode("value is longer than 4 GiB".to_string())
+                    })?;
+                    writer.write_all(&[0x00])?;
+                    writer.write_all(key_hash)?;
+                    writer.write_all(&len.to_be_bytes())?;
+                    writer.write_all(value)?;
+                }
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a chunk written by [`SyncChunk::write_to`]
+    ///
+    /// This only checks the encoding; call [`SyncChunk::verify`] or apply
+    /// the chunk to find out whether it belongs to a root.
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, MerkleError> {
+        let error = |message: &str| MerkleError::Parse(format!("sync chunk: {}", message));
+
+        let mut header = [0u8; 7];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != FORMAT_VERSION {
+            return Err(error("not a supported sync chunk"));
+        }
+        let depth = u16::from_be_bytes([header[5], header[6]]);
+        if depth > 256 {
+            return Err(error("deeper than the keys"));
+        }
+
+        let prefix = read_hash(&mut reader)?;
+        let siblings = (0..depth)
+            .map(|_| read_hash(&mut reader))
+            .collect::<Result<_, _>>()?;
+
+        let mut count = [0u8; 8];
+        reader.read_exact(&mut count)?;
+        let mut nodes = Vec::new();
+        for _ in 0..u64::from_be_bytes(count) {
+            let mut tag = [0u8];
+            reader.read_exact(&mut tag)?;
+            nodes.push(match tag[0] {
+                0x01 => SparseNode::Internal {
+                    left: read_hash(&mut reader)?,
+                    right: read_hash(&mut reader)?,
+                },
+                0x00 => {
+                    let key_hash = read_hash(&mut reader)?;
+                    let mut len = [0u8; 4];
+                    reader.read_exact(&mut len)?;
+                    let len = u32::from_be_bytes(len) as u64;
+
+                    // Read through a limit so a bogus length can't force a huge allocation
+                    let mut value = Vec::new();
+                    if (&mut reader).take(len).read_to_end(&mut value)? as u64 != len {
+                        return Err(error("truncated value"));
+                    }
+                    SparseNode::Leaf { key_hash, value }
+                }
+                _ => return Err(error("invalid node tag")),
+            });
+        }
+
+        if reader.read(&mut [0u8])? != 0 {
+            return Err(error("trailing bytes"));
+        }
+
+        Ok(SyncChunk {
+            prefix,
+            siblings,
+            nodes,
+        })
+    }
+}
+
+fn read_hash<R: Read>(reader: &mut R) -> Result<NodeHash, MerkleError> {
+    let mut hash = [0u8; 32];
+    reader.read_exact(&mut hash)?;
+    Ok(hash)
+}
+
+impl<S: NodeStore> SparseMerkleTree<S> {
+    /// Cuts the tree into one chunk per non-empty subtree at `split_depth`,
+    /// in key order
+    ///
+    /// A leaf that sits above the split depth is a chunk of its own. Up to
+    /// `2^split_depth` chunks come out; pick the depth so each holds a
+    /// comfortable amount of state.
+    pub async fn export_chunks(&self, split_depth: usize) -> Result<Vec<SyncChunk>, MerkleError> {
+        let mut chunks = Vec::new();
+
+        // Subtrees still to visit, leftmost on top
+        let mut pending = vec![(self.root_hash(), EMPTY_HASH, Vec::new())];
+        while let Some((hash, prefix, siblings)) = pending.pop() {
+            let Some(node) = self.nodes(&[hash]).await?.pop().flatten() else {
+                continue;
+            };
+            let depth = siblings.len();
+
+            match node {
+                SparseNode::Internal { left, right } if depth < split_depth => {
+                    let mut right_prefix = prefix;
+                    right_prefix[depth / 8] |= 0x80 >> (depth % 8);
+                    let mut right_siblings = siblings.clone();
+                    right_siblings.push(left);
+                    pending.push((right, right_prefix, right_siblings));
+
+                    let mut left_siblings = siblings;
+                    left_siblings.push(right);
+                    pending.push((left, prefix, left_siblings));
+                }
+                node => chunks.push(SyncChunk {
+                    prefix,
+                    siblings,
+                    nodes: self.subtree_nodes(node).await?,
+                }),
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// Checks `chunk` against the current root, then stores its nodes and
+    /// the nodes on its path in one flush
+    ///
+    /// Fails with [`MerkleError::InvalidProof`], writing nothing, if the
+    /// chunk does not belong to the root.
+    pub async fn apply_chunk(&mut self, chunk: &SyncChunk) -> Result<(), MerkleError> {
+        if !chunk.verify(&self.root_hash()) {
+            return Err(MerkleError::InvalidProof);
+        }
+
+        let mut hash = chunk.nodes[0].hash();
+        for node in &chunk.nodes {
+            self.buffer_node(node.clone());
+        }
+
+        // The nodes above the subtree come with no chunk of their own
+        for (depth, sibling) in chunk.siblings.iter().enumerate().rev() {
+            hash = self.buffer_node(if bit(&chunk.prefix, depth) {
+                SparseNode::Internal {
+                    left: *sibling,
+                    right: hash,
+                }
+            } else {
+                SparseNode::Internal {
+                    left: hash,
+                    right: *sibling,
+                }
+            });
+        }
+
+        self.flush().await
+    }
+
+    /// Collects every node under `top`, one store read per level
+    async fn subtree_nodes(&self, top: SparseNode) -> Result<Vec<SparseNode>, MerkleError> {
+        let mut nodes = Vec::new();
+        let mut level = vec![top];
+
+        while !level.is_empty() {
+            let children: Vec<NodeHash> = level
+                .iter()
+                .filter_map(|node| match node {
+                    SparseNode::Internal { left, right } => Some([*left, *right]),
+                    SparseNode::Leaf { .. } => None,
+                })
+                .flatten()
+                .filter(|hash| *hash != EMPTY_HASH)
+                .collect();
+
+            nodes.extend(level);
+            level = self.nodes(&children).await?.into_iter().flatten().collect();
+        }
+
+        Ok(nodes)
+    }
+}
@@ -0,0 +1,233 @@
+//! Test doubles for code that consumes proofs.
+//!
+//! A downstream test of, say, a claims endpoint needs proofs that verify
+//! and proofs that don't, but not a real dataset hashed into a real tree.
+//! A [`MockTree`] stands for a tree of any size in which every leaf holds
+//! [`FILLER_LEAF`] unless a test scripts it with [`MockTree::with_leaf`].
+//! Runs of filler leaves hash alike, so the root and each proof cost a few
+//! hashes per scripted leaf and level, however large the tree.
+//!
+//! The proofs are ordinary [`MerkleProof`]s, identical to what a
+//! [`MerkleTree`](crate::MerkleTree) over the same leaves would produce, so
+//! the code under test needs no changes. [`MockTree::invalid_proof`]
+//! breaks one on purpose in a chosen way.
+
+use crate::{MerkleHasher, MerkleProof, Side, TreeConfig};
+use std::collections::BTreeMap;
+
+/// Data of every leaf a test hasn't scripted
+pub const FILLER_LEAF: &[u8] = b"simple-merkle-tree mock leaf";
+
+/// How [`MockTree::invalid_proof`] breaks a proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The leaf hash has a bit flipped
+    LeafHash,
+    /// The sibling at `level`, counting from the leaf, has a bit flipped
+    Sibling { level: usize },
+    /// The sibling at `level` is on the wrong side
+    Side { level: usize },
+    /// The topmost sibling is missing
+    MissingLevel,
+    /// An extra sibling sits above the topmost one
+    ExtraLevel,
+}
+
+/// A proof from a [`MockTree`], with what verifying it should give
+pub struct MockProof<H: MerkleHasher = TreeConfig> {
+    /// Position of the proven leaf
+    pub index: u64,
+    pub proof: MerkleProof<H>,
+    /// How the proof was broken, `None` if it is valid
+    pub fault: Option<Fault>,
+}
+
+impl<H: MerkleHasher> MockProof<H> {
+    /// Returns true if the proof should verify against the tree's root
+    pub fn is_valid(&self) -> bool {
+        self.fault.is_none()
+    }
+
+    /// Returns the proof to hand to the code under test
+    pub fn into_proof(self) -> MerkleProof<H> {
+        self.proof
+    }
+}
+
+/// A virtual tree of filler leaves with a few scripted ones
+#[derive(Debug, Clone)]
+pub struct MockTree<H: MerkleHasher = TreeConfig> {
+    hasher: H,
+    size: u64,
+    /// Hash of a node over nothing but filler leaves, per level
+    filler: Vec<Vec<u8>>,
+    /// Nodes that aren't filler: those above a scripted leaf and those on
+    /// the right edge, whose subtrees may be partial
+    nodes: Vec<BTreeMap<u64, Vec<u8>>>,
+    /// Number of nodes on each level
+    widths: Vec<u64>,
+}
+
+impl MockTree {
+    /// Stands in for an untagged tree of `size` leaves
+    pub fn new(size: u64) -> Self {
+        Self::new_with_hasher(size, TreeConfig::default())
+    }
+}
+
+impl<H: MerkleHasher> MockTree<H> {
+    /// Stands in for a tree of `size` leaves hashed by `hasher`
+    pub fn new_with_hasher(size: u64, hasher: H) -> Self {
+        // Level widths as a real tree would build them, hashing a lone leaf once
+        let mut widths = Vec::new();
+        if size > 0 {
+            widths.push(size);
+            while widths.len() == 1 || widths[widths.len() - 1] > 1 {
+                widths.push(widths[widths.len() - 1].div_ceil(2));
+            }
+        }
+
+        let mut filler = vec![hasher.hash_leaf(FILLER_LEAF)];
+        while filler.len() < widths.len() {
+            let below = &filler[filler.len() - 1];
+            filler.push(hasher.hash_node(below, below));
+        }
+
+        let mut tree = MockTree {
+            hasher,
+            size,
+            filler,
+            nodes: vec![BTreeMap::new(); widths.len()],
+            widths,
+        };
+        if size > 0 {
+            let leaf = tree.filler[0].clone();
+            tree.set_leaf(size - 1, leaf);
+        }
+        tree
+    }
+
+    /// Scripts the leaf at `index` to hold `data`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is past the end of the tree.
+    pub fn with_leaf(mut self, index: u64, data: &[u8]) -> Self {
+        assert!(index < self.size, "leaf {} of {}", index, self.size);
+        let leaf = self.hasher.hash_leaf(data);
+        self.set_leaf(index, leaf);
+        self
+    }
+
+    /// Returns the number of leaves the tree stands for
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns true if the tree stands for no leaves
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns the root a real tree over the same leaves would have
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.nodes.last()?.get(&0).cloned()
+    }
+
+    /// Returns a valid proof for the leaf at `index`
+    pub fn proof(&self, index: u64) -> Option<MockProof<H>> {
+        if index >= self.size {
+            return None;
+        }
+
+        let mut siblings = Vec::new();
+        let mut position = index;
+        for level in 0..self.widths.len() - 1 {
+            siblings.push((self.sibling(level, position), Side::of_sibling(position)));
+            position /= 2;
+        }
+
+        let proof = MerkleProof {
+            proof_hashes: siblings,
+            leaf_hash: self.node(0, index),
+            root_hash: self.root_hash()?,
+            config: self.hasher.clone(),
+        };
+        Some(MockProof {
+            index,
+            proof,
+            fault: None,
+        })
+    }
+
+    /// Returns a proof for the leaf at `index` broken by `fault`
+    ///
+    /// Returns `None` if `index` is out of range, if `fault` names a level
+    /// the proof doesn't have, or if it wouldn't break this proof: moving a
+    /// sibling that equals its node to the other side changes nothing.
+    pub fn invalid_proof(&self, index: u64, fault: Fault) -> Option<MockProof<H>> {
+        let mut mock = self.proof(index)?;
+        let proof = &mut mock.proof;
+        match fault {
+            Fault::LeafHash => flip_bit(&mut proof.leaf_hash),
+            Fault::Sibling { level } => flip_bit(&mut proof.proof_hashes.get_mut(level)?.0),
+            Fault::Side { level } => {
+                let (sibling, side) = proof.proof_hashes.get_mut(level)?;
+                if *sibling == self.node(level, index >> level) {
+                    return None;
+                }
+                *side = match side {
+                    Side::Left => Side::Right,
+                    Side::Right => Side::Left,
+                };
+            }
+            Fault::MissingLevel => {
+                proof.proof_hashes.pop();
+            }
+            Fault::ExtraLevel => {
+                let top = self.filler[self.filler.len() - 1].clone();
+                proof.proof_hashes.push((top, Side::Right));
+            }
+        }
+        mock.fault = Some(fault);
+        Some(mock)
+    }
+
+    /// Returns the hash of node `position` on `level`
+    fn node(&self, level: usize, position: u64) -> Vec<u8> {
+        self.nodes[level]
+            .get(&position)
+            .unwrap_or(&self.filler[level])
+            .clone()
+    }
+
+    /// Returns the hash node `position` on `level` is paired with
+    fn sibling(&self, level: usize, position: u64) -> Vec<u8> {
+        if position ^ 1 < self.widths[level] {
+            self.node(level, position ^ 1)
+        } else {
+            // The last node of an odd-width level pairs with its own padding
+            self.hasher.pad(&self.node(level, position))
+        }
+    }
+
+    /// Sets a leaf hash and rehashes the path above it
+    fn set_leaf(&mut self, index: u64, leaf: Vec<u8>) {
+        self.nodes[0].insert(index, leaf);
+        let mut position = index;
+        for level in 0..self.widths.len() - 1 {
+            let left = position & !1;
+            let parent = self
+                .hasher
+                .hash_node(&self.node(level, left), &self.sibling(level, left));
+            position /= 2;
+            self.nodes[level + 1].insert(position, parent);
+        }
+    }
+}
+
+fn flip_bit(hash: &mut [u8]) {
+    if let Some(byte) = hash.first_mut() {
+        *byte ^= 1;
+    }
+}
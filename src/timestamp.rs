@@ -0,0 +1,91 @@
+//! Third-party timestamps for published roots.
+//!
+//! A [`RootRecord`]'s own timestamp is only the publisher's clock. A
+//! [`TimestampAuthority`] — a Roughtime server, an RFC 3161 service, or
+//! anything else that signs times — can vouch that a root existed by a
+//! given time: the authority is asked to sign
+//! [`RootRecord::timestamp_nonce`], which commits to the tree size and
+//! root hash, and the answer is kept as a [`TimestampToken`]. For
+//! Roughtime the nonce is the request nonce and the evidence is the signed
+//! response; for RFC 3161 the nonce is the message imprint and the evidence
+//! is the token.
+//!
+//! Checking the evidence is up to a [`TimestampVerifier`] for the same
+//! authority, holding its public key. Tokens can be stored in a
+//! [`RootHistory`](crate::RootHistory) next to the records they vouch for.
+
+use crate::{MerkleError, RootRecord};
+use sha2::{Digest, Sha256};
+
+/// Prefix of every timestamp nonce, so it can't be mistaken for another hash
+const NONCE_CONTEXT: &[u8] = b"simple-merkle-tree root timestamp v1";
+
+/// A time an authority signed for a nonce
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimestampToken {
+    /// Identifies the authority; must be non-empty and without whitespace
+    pub authority: String,
+    /// Signed time, in Unix seconds
+    pub time: u64,
+    /// Uncertainty the authority claims, in seconds either side of `time`
+    pub radius: u64,
+    /// The authority's signed answer, in its own format
+    pub evidence: Vec<u8>,
+}
+
+impl TimestampToken {
+    /// Returns the latest time the token allows, by which the nonce must
+    /// have existed
+    pub fn latest(&self) -> u64 {
+        self.time.saturating_add(self.radius)
+    }
+}
+
+/// Obtains signed timestamps
+pub trait TimestampAuthority {
+    /// Returns the name the matching [`TimestampVerifier`] is known by
+    fn authority_id(&self) -> &str;
+
+    /// Asks the authority to sign the current time over `nonce`
+    fn stamp(&self, nonce: &[u8; 32]) -> Result<TimestampToken, MerkleError>;
+}
+
+/// Checks timestamps from one authority
+pub trait TimestampVerifier {
+    /// Returns the name of the authority, matched against
+    /// [`TimestampToken::authority`]
+    fn authority_id(&self) -> &str;
+
+    /// Returns true if `token.evidence` is the authority's valid signature
+    /// of `token.time` and `token.radius` over `nonce`
+    fn verify(&self, nonce: &[u8; 32], token: &TimestampToken) -> bool;
+}
+
+impl RootRecord {
+    /// Returns the value an authority signs to timestamp this root
+    ///
+    /// It commits to the tree size and root hash but not the publisher's
+    /// timestamp, which the authority's time is meant to replace.
+    pub fn timestamp_nonce(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(NONCE_CONTEXT);
+        hasher.update(self.tree_size.to_be_bytes());
+        hasher.update(&self.root_hash);
+        hasher.finalize().into()
+    }
+
+    /// Returns true if `token` is `verifier`'s valid timestamp of this root
+    pub fn verify_timestamp(
+        &self,
+        token: &TimestampToken,
+        verifier: &dyn TimestampVerifier,
+    ) -> bool {
+        token.authority == verifier.authority_id()
+            && verifier.verify(&self.timestamp_nonce(), token)
+    }
+}
+
+/// Returns true if `id` can name an authority in a root log
+pub(crate) fn valid_authority_id(id: &str) -> bool {
+    !id.is_empty() && !id.contains(char::is_whitespace)
+}
@@ -0,0 +1,75 @@
+//! Voiding leaves in place.
+//!
+//! [`MerkleTree::delete_leaf`] overwrites a leaf hash with [`TOMBSTONE`],
+//! which no data hashes to, and rehashes its path. Trees with shorter
+//! hashes use as many zero bytes as their hashes have. Every other leaf keeps
+//! its index, so proofs and references by position stay meaningful, and a
+//! [`BoundProof`] of the tombstone shows that a given index was deleted.
+
+use crate::{BoundProof, MerkleError, MerkleHasher, MerkleProof, MerkleTree, RootRecord};
+
+/// The leaf hash marking a deleted leaf
+pub const TOMBSTONE: [u8; 32] = [0; 32];
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Replaces the leaf at `index` with [`TOMBSTONE`] and returns the new
+    /// root
+    ///
+    /// Deleting a leaf that is already deleted changes nothing.
+    pub fn delete_leaf(&mut self, index: usize) -> Result<Vec<u8>, MerkleError> {
+        if index >= self.len() {
+            return Err(MerkleError::IndexOutOfBounds {
+                index,
+                len: self.len(),
+            });
+        }
+
+        let leaf = &mut self.levels[0][index];
+        *leaf = vec![0; leaf.len()];
+        self.update_path(index);
+        Ok(self.root_hash().unwrap())
+    }
+
+    /// Returns whether the leaf at `index` is deleted, or `None` if it is
+    /// out of bounds
+    pub fn is_deleted(&self, index: usize) -> Option<bool> {
+        self.levels
+            .first()?
+            .get(index)
+            .map(|hash| is_tombstone(hash))
+    }
+
+    /// Proves that the leaf at `index` is deleted
+    ///
+    /// Returns `None` unless it is.
+    pub fn prove_deleted(&self, index: usize) -> Option<BoundProof<H>> {
+        if !self.is_deleted(index)? {
+            return None;
+        }
+        self.generate_bound_proof(index)
+    }
+}
+
+impl<H: MerkleHasher> MerkleProof<H> {
+    /// Returns true if the proof is for a deleted leaf
+    pub fn is_tombstone(&self) -> bool {
+        is_tombstone(&self.leaf_hash)
+    }
+}
+
+impl<H: MerkleHasher> BoundProof<H> {
+    /// Checks that the proof shows leaf `self.index()` deleted in the tree
+    /// `entry` was published for
+    pub fn verify_deleted_against(&self, entry: &RootRecord) -> Result<(), MerkleError> {
+        self.verify_against(entry)?;
+        if !self.proof().is_tombstone() {
+            return Err(MerkleError::InvalidProof);
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if `hash` is all zero bytes, as a deleted leaf is
+fn is_tombstone(hash: &[u8]) -> bool {
+    !hash.is_empty() && hash.iter().all(|&byte| byte == 0)
+}
@@ -0,0 +1,155 @@
+//! Trees over domain objects instead of raw byte vectors.
+//!
+//! A [`TypedMerkleTree<T>`] encodes each item exactly once through
+//! [`LeafEncode`], and its proofs can only be checked against a `&T`. Callers
+//! never handle the encoded bytes themselves, so they can't hash an
+//! already-encoded value a second time by accident.
+
+use crate::{MerkleHasher, MerkleProof, MerkleTree, TreeConfig};
+use std::marker::PhantomData;
+
+/// Types that know the bytes their leaf is hashed from
+pub trait LeafEncode {
+    /// Returns the bytes hashed into this value's leaf
+    fn encode_leaf(&self) -> Vec<u8>;
+}
+
+impl LeafEncode for [u8] {
+    fn encode_leaf(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl<const N: usize> LeafEncode for [u8; N] {
+    fn encode_leaf(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+impl LeafEncode for Vec<u8> {
+    fn encode_leaf(&self) -> Vec<u8> {
+        self.clone()
+    }
+}
+
+impl LeafEncode for str {
+    fn encode_leaf(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl LeafEncode for String {
+    fn encode_leaf(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+}
+
+impl<T: LeafEncode + ?Sized> LeafEncode for &T {
+    fn encode_leaf(&self) -> Vec<u8> {
+        (**self).encode_leaf()
+    }
+}
+
+/// A Merkle tree whose leaves are `T` values
+///
+/// Hashing follows `H`, a [`TreeConfig`] unless another [`MerkleHasher`] is
+/// given.
+pub struct TypedMerkleTree<T: LeafEncode, H: MerkleHasher = TreeConfig> {
+    tree: MerkleTree<H>,
+    items: PhantomData<fn(&T)>,
+}
+
+impl<T: LeafEncode> TypedMerkleTree<T> {
+    /// Creates a new tree from a list of items
+    pub fn new(items: &[T]) -> Self {
+        Self::new_with_hasher(items, TreeConfig::default())
+    }
+}
+
+impl<T: LeafEncode, H: MerkleHasher> TypedMerkleTree<T, H> {
+    /// Creates a new tree from a list of items, hashed by `hasher`
+    pub fn new_with_hasher(items: &[T], hasher: H) -> Self {
+        let data = items.iter().map(LeafEncode::encode_leaf).collect();
+
+        TypedMerkleTree {
+            tree: MerkleTree::hashed_by(data, hasher),
+            items: PhantomData,
+        }
+    }
+
+    /// Returns the underlying byte-level tree
+    pub fn tree(&self) -> &MerkleTree<H> {
+        &self.tree
+    }
+
+    /// Returns the number of items in the tree
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns true if the tree has no items
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the Merkle root hash, if it exists
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.tree.root_hash()
+    }
+
+    /// Returns the Merkle root hash as a hex string
+    pub fn root_hash_hex(&self) -> Option<String> {
+        self.tree.root_hash_hex()
+    }
+
+    /// Generates a proof that `item` is in the tree
+    pub fn generate_proof(&self, item: &T) -> Option<TypedProof<T, H>> {
+        self.tree
+            .generate_proof(&item.encode_leaf())
+            .map(TypedProof::new)
+    }
+
+    /// Generates a proof for the item at `index`
+    pub fn generate_proof_at(&self, index: usize) -> Option<TypedProof<T, H>> {
+        self.tree.generate_proof_at(index).map(TypedProof::new)
+    }
+
+    /// Verifies that `proof` shows `item` is in this tree
+    pub fn verify_proof(&self, item: &T, proof: &TypedProof<T, H>) -> bool {
+        match self.tree.root_hash() {
+            Some(root) => proof.verify(item, &root),
+            None => false,
+        }
+    }
+}
+
+/// A proof that a particular `T` value is in a [`TypedMerkleTree`]
+pub struct TypedProof<T: LeafEncode, H: MerkleHasher = TreeConfig> {
+    proof: MerkleProof<H>,
+    items: PhantomData<fn(&T)>,
+}
+
+impl<T: LeafEncode, H: MerkleHasher> TypedProof<T, H> {
+    fn new(proof: MerkleProof<H>) -> Self {
+        TypedProof {
+            proof,
+            items: PhantomData,
+        }
+    }
+
+    /// Returns the untyped proof
+    pub fn proof(&self) -> &MerkleProof<H> {
+        &self.proof
+    }
+
+    /// Unwraps the untyped proof
+    pub fn into_proof(self) -> MerkleProof<H> {
+        self.proof
+    }
+
+    /// Verifies the proof is for `item` and leads to the given root hash
+    pub fn verify(&self, item: &T, root_hash: &[u8]) -> bool {
+        let leaf_hash = self.proof.merkle_hasher().hash_leaf(&item.encode_leaf());
+        self.proof.leaf_hash() == leaf_hash.as_slice() && self.proof.verify(root_hash)
+    }
+}
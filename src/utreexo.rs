@@ -0,0 +1,514 @@
+//! A Utreexo-style dynamic accumulator: a forest of perfect trees that
+//! supports deletion.
+//!
+//! Adding works like incrementing a binary counter. The new leaf is a tree
+//! of height 0, and while a tree of the same height exists the two merge
+//! under a new parent, so there is at most one root per height. Deleting a
+//! leaf moves its sibling up into their parent's place; deleting both
+//! children of a node deletes the node, and a deleted root leaves its
+//! height empty. Leaves are `H(0x00 || element)` and parents
+//! `H(0x01 || left || right)`, so no element can pass for an internal node
+//! and take the whole subtree under it with it when deleted.
+//!
+//! A [`Stump`] keeps only the roots, one hash per height. It can add on its
+//! own, and given a [`BatchProof`] from a [`Forest`] it can check that the
+//! elements are present and delete them, ending with the same roots the
+//! forest has. A batch proof is the part of each affected tree the deletion
+//! needs, so hashes shared between the proven leaves are sent once.
+//!
+//! [`BatchProof::to_bytes`] encodes a proof big-endian, each tree in
+//! pre-order:
+//!
+//! ```text
+//! "SMTU" | version: u8 | count: u32 | (height: u32 | node) * count
+//! node = 0x00 | hash: [u8; 32]    a subtree known by its hash
+//!      | 0x01 | hash: [u8; 32]    a leaf being deleted
+//!      | 0x02 | node | node       a branch, left first
+//! ```
+
+use crate::sparse::NodeHash;
+use crate::MerkleError;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+
+pub(crate) const MAGIC: &[u8; 4] = b"SMTU";
+pub(crate) const FORMAT_VERSION: u8 = 1;
+
+const KNOWN: u8 = 0x00;
+const TARGET: u8 = 0x01;
+const BRANCH: u8 = 0x02;
+
+fn leaf_hash(element: &[u8]) -> NodeHash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x00]);
+    hasher.update(element);
+    hasher.finalize().into()
+}
+
+fn parent_hash(left: &NodeHash, right: &NodeHash) -> NodeHash {
+    let mut hasher = Sha256::new();
+    hasher.update([0x01]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// The part of one tree a batch of deletions touches
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PartialTree {
+    /// A subtree with nothing to delete, known only by its hash
+    Known(NodeHash),
+    /// A leaf being deleted
+    Target(NodeHash),
+    Branch(Box<PartialTree>, Box<PartialTree>),
+}
+
+impl PartialTree {
+    fn hash(&self) -> NodeHash {
+        match self {
+            PartialTree::Known(hash) | PartialTree::Target(hash) => *hash,
+            PartialTree::Branch(left, right) => parent_hash(&left.hash(), &right.hash()),
+        }
+    }
+
+    /// Returns the hash of what is left after deleting every target, or
+    /// `None` if nothing is
+    fn delete(&self) -> Option<NodeHash> {
+        match self {
+            PartialTree::Known(hash) => Some(*hash),
+            PartialTree::Target(_) => None,
+            PartialTree::Branch(left, right) => match (left.delete(), right.delete()) {
+                (Some(left), Some(right)) => Some(parent_hash(&left, &right)),
+                (Some(survivor), None) | (None, Some(survivor)) => Some(survivor),
+                (None, None) => None,
+            },
+        }
+    }
+
+    fn targets(&self, out: &mut Vec<NodeHash>) {
+        match self {
+            PartialTree::Known(_) => {}
+            PartialTree::Target(hash) => out.push(*hash),
+            PartialTree::Branch(left, right) => {
+                left.targets(out);
+                right.targets(out);
+            }
+        }
+    }
+
+    fn hash_count(&self) -> usize {
+        match self {
+            PartialTree::Known(_) | PartialTree::Target(_) => 1,
+            PartialTree::Branch(left, right) => left.hash_count() + right.hash_count(),
+        }
+    }
+
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            PartialTree::Known(hash) => {
+                out.push(KNOWN);
+                out.extend_from_slice(hash);
+            }
+            PartialTree::Target(hash) => {
+                out.push(TARGET);
+                out.extend_from_slice(hash);
+            }
+            PartialTree::Branch(left, right) => {
+                out.push(BRANCH);
+                left.write(out);
+                right.write(out);
+            }
+        }
+    }
+
+    /// Reads a tree no deeper than `height` off the front of `bytes`
+    fn read(bytes: &mut &[u8], height: u32) -> Option<PartialTree> {
+        let (&tag, rest) = bytes.split_first()?;
+        *bytes = rest;
+        match tag {
+            KNOWN | TARGET => {
+                let hash = bytes.get(..32)?.try_into().ok()?;
+                *bytes = &bytes[32..];
+                Some(match tag {
+                    KNOWN => PartialTree::Known(hash),
+                    _ => PartialTree::Target(hash),
+                })
+            }
+            BRANCH if height > 0 => {
+                let left = PartialTree::read(bytes, height - 1)?;
+                let right = PartialTree::read(bytes, height - 1)?;
+                Some(PartialTree::Branch(Box::new(left), Box::new(right)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Proof that a batch of elements is in the accumulator, enough to delete
+/// them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchProof {
+    /// Each affected tree with the height of its root slot
+    trees: Vec<(u32, PartialTree)>,
+}
+
+impl BatchProof {
+    /// Returns the leaf hashes the proof covers
+    pub fn targets(&self) -> Vec<NodeHash> {
+        let mut targets = Vec::new();
+        for (_, tree) in &self.trees {
+            tree.targets(&mut targets);
+        }
+        targets
+    }
+
+    /// Returns the number of hashes the proof carries, leaves included
+    pub fn hash_count(&self) -> usize {
+        self.trees.iter().map(|(_, tree)| tree.hash_count()).sum()
+    }
+
+    /// Encodes the proof as described in the module docs
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&(self.trees.len() as u32).to_be_bytes());
+        for (height, tree) in &self.trees {
+            bytes.extend_from_slice(&height.to_be_bytes());
+            tree.write(&mut bytes);
+        }
+        bytes
+    }
+
+    /// Decodes a proof written by [`BatchProof::to_bytes`]
+    ///
+    /// Trees must be listed by increasing height and be no deeper than
+    /// their height.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleError> {
+        let error = || MerkleError::Parse("batch proof: malformed encoding".to_string());
+
+        let header = bytes.get(..9).ok_or_else(error)?;
+        if &header[..4] != MAGIC || header[4] != FORMAT_VERSION {
+            return Err(MerkleError::Parse(
+                "batch proof: not a supported batch proof".to_string(),
+            ));
+        }
+        let count = u32::from_be_bytes(header[5..9].try_into().unwrap());
+
+        let mut rest = &bytes[9..];
+        let mut trees: Vec<(u32, PartialTree)> = Vec::new();
+        for _ in 0..count {
+            let height = u32::from_be_bytes(rest.get(..4).ok_or_else(error)?.try_into().unwrap());
+            rest = &rest[4..];
+            if height >= u64::BITS || trees.last().is_some_and(|&(last, _)| height <= last) {
+                return Err(error());
+            }
+            trees.push((
+                height,
+                PartialTree::read(&mut rest, height).ok_or_else(error)?,
+            ));
+        }
+
+        if !rest.is_empty() {
+            return Err(error());
+        }
+        Ok(BatchProof { trees })
+    }
+}
+
+/// The roots of the accumulator and nothing else
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Stump {
+    leaves: u64,
+    /// `roots[h]` is the root of height `h`; `None` where all of its
+    /// leaves were deleted or there is no tree of that height
+    roots: Vec<Option<NodeHash>>,
+}
+
+impl Stump {
+    /// Creates an empty accumulator
+    pub fn new() -> Self {
+        Stump::default()
+    }
+
+    /// Returns the number of elements ever added, deleted ones included
+    pub fn leaves(&self) -> u64 {
+        self.leaves
+    }
+
+    /// Returns the root for each height, lowest first
+    pub fn roots(&self) -> &[Option<NodeHash>] {
+        &self.roots
+    }
+
+    /// Adds `element`
+    pub fn add(&mut self, element: &[u8]) {
+        let mut node = leaf_hash(element);
+        // Trees of every height below the first gap merge with the new leaf
+        let height = self.leaves.trailing_ones() as usize;
+        if self.roots.len() <= height {
+            self.roots.resize(height + 1, None);
+        }
+
+        // An empty root contributes nothing, so the tree moves up as is
+        for root in &mut self.roots[..height] {
+            if let Some(root) = root.take() {
+                node = parent_hash(&root, &node);
+            }
+        }
+        self.roots[height] = Some(node);
+        self.leaves += 1;
+    }
+
+    /// Returns true if `proof` shows every one of `elements` is present
+    pub fn verify(&self, proof: &BatchProof, elements: &[&[u8]]) -> bool {
+        let mut expected: Vec<NodeHash> =
+            elements.iter().map(|element| leaf_hash(element)).collect();
+        let mut targets = proof.targets();
+        expected.sort_unstable();
+        targets.sort_unstable();
+        if expected != targets {
+            return false;
+        }
+
+        // Each root may be covered once
+        proof.trees.windows(2).all(|pair| pair[0].0 < pair[1].0)
+            && proof
+                .trees
+                .iter()
+                .all(|(height, tree)| self.roots.get(*height as usize) == Some(&Some(tree.hash())))
+    }
+
+    /// Deletes `elements` after checking `proof` for them
+    pub fn delete(&mut self, proof: &BatchProof, elements: &[&[u8]]) -> Result<(), MerkleError> {
+        if !self.verify(proof, elements) {
+            return Err(MerkleError::InvalidProof);
+        }
+
+        for (height, tree) in &proof.trees {
+            self.roots[*height as usize] = tree.delete();
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ForestNode {
+    hash: NodeHash,
+    parent: Option<usize>,
+    children: Option<[usize; 2]>,
+}
+
+/// The full accumulator, able to prove any element it holds
+#[derive(Debug, Clone, Default)]
+pub struct Forest {
+    leaves: u64,
+    nodes: Vec<ForestNode>,
+    /// Slots of deleted nodes, reused before `nodes` grows
+    free: Vec<usize>,
+    /// Root node of each height, as in [`Stump`]
+    roots: Vec<Option<usize>>,
+    /// Live leaves by hash; an element added twice has two
+    positions: HashMap<NodeHash, Vec<usize>>,
+}
+
+impl Forest {
+    /// Creates an empty accumulator
+    pub fn new() -> Self {
+        Forest::default()
+    }
+
+    /// Returns the number of elements present
+    pub fn len(&self) -> usize {
+        self.positions.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Returns true if `element` is present
+    pub fn contains(&self, element: &[u8]) -> bool {
+        self.positions.contains_key(&leaf_hash(element))
+    }
+
+    /// Returns the roots alone, for handing to light clients
+    pub fn stump(&self) -> Stump {
+        Stump {
+            leaves: self.leaves,
+            roots: self
+                .roots
+                .iter()
+                .map(|root| root.map(|id| self.nodes[id].hash))
+                .collect(),
+        }
+    }
+
+    /// Adds `element`
+    pub fn add(&mut self, element: &[u8]) {
+        let hash = leaf_hash(element);
+        let mut node = self.alloc(hash, None);
+        self.positions.entry(hash).or_default().push(node);
+
+        // Trees of every height below the first gap merge with the new leaf
+        let height = self.leaves.trailing_ones() as usize;
+        if self.roots.len() <= height {
+            self.roots.resize(height + 1, None);
+        }
+        for slot in 0..height {
+            if let Some(root) = self.roots[slot].take() {
+                let hash = parent_hash(&self.nodes[root].hash, &self.nodes[node].hash);
+                let parent = self.alloc(hash, Some([root, node]));
+                self.nodes[root].parent = Some(parent);
+                self.nodes[node].parent = Some(parent);
+                node = parent;
+            }
+        }
+        self.roots[height] = Some(node);
+        self.leaves += 1;
+    }
+
+    /// Proves that all of `elements` are present
+    ///
+    /// Returns `None` if one is missing. An element listed twice must have
+    /// been added twice.
+    pub fn prove(&self, elements: &[&[u8]]) -> Option<BatchProof> {
+        let targets = self.targets(elements)?;
+
+        // Every node on a path from a target to its root
+        let mut on_path = HashSet::new();
+        for &target in &targets {
+            let mut node = Some(target);
+            while let Some(id) = node {
+                if !on_path.insert(id) {
+                    break;
+                }
+                node = self.nodes[id].parent;
+            }
+        }
+
+        let trees = self
+            .roots
+            .iter()
+            .enumerate()
+            .filter_map(|(height, root)| {
+                let root = (*root)?;
+                on_path
+                    .contains(&root)
+                    .then(|| (height as u32, self.partial(root, &on_path)))
+            })
+            .collect();
+
+        Some(BatchProof { trees })
+    }
+
+    /// Deletes all of `elements`, returning false and changing nothing if
+    /// one is missing
+    pub fn delete(&mut self, elements: &[&[u8]]) -> bool {
+        let Some(targets) = self.targets(elements) else {
+            return false;
+        };
+
+        for target in targets {
+            let hash = self.nodes[target].hash;
+            let positions = self.positions.get_mut(&hash).unwrap();
+            positions.retain(|&id| id != target);
+            if positions.is_empty() {
+                self.positions.remove(&hash);
+            }
+            self.remove(target);
+        }
+        true
+    }
+
+    /// Picks a distinct live leaf for each element
+    fn targets(&self, elements: &[&[u8]]) -> Option<Vec<usize>> {
+        let mut used: HashMap<NodeHash, usize> = HashMap::new();
+        elements
+            .iter()
+            .map(|element| {
+                let hash = leaf_hash(element);
+                let count = used.entry(hash).or_default();
+                let id = *self.positions.get(&hash)?.get(*count)?;
+                *count += 1;
+                Some(id)
+            })
+            .collect()
+    }
+
+    fn partial(&self, id: usize, on_path: &HashSet<usize>) -> PartialTree {
+        let node = &self.nodes[id];
+        match node.children {
+            _ if !on_path.contains(&id) => PartialTree::Known(node.hash),
+            None => PartialTree::Target(node.hash),
+            Some([left, right]) => PartialTree::Branch(
+                Box::new(self.partial(left, on_path)),
+                Box::new(self.partial(right, on_path)),
+            ),
+        }
+    }
+
+    /// Removes leaf `id`, moving its sibling into their parent's place
+    fn remove(&mut self, id: usize) {
+        let Some(parent) = self.nodes[id].parent else {
+            let slot = self
+                .roots
+                .iter()
+                .position(|&root| root == Some(id))
+                .unwrap();
+            self.roots[slot] = None;
+            self.free.push(id);
+            return;
+        };
+
+        let [left, right] = self.nodes[parent].children.unwrap();
+        let sibling = if left == id { right } else { left };
+        let grandparent = self.nodes[parent].parent;
+        self.nodes[sibling].parent = grandparent;
+
+        match grandparent {
+            Some(grandparent) => {
+                let children = self.nodes[grandparent].children.as_mut().unwrap();
+                let slot = children.iter().position(|&child| child == parent).unwrap();
+                children[slot] = sibling;
+                self.rehash(grandparent);
+            }
+            None => {
+                let slot = self
+                    .roots
+                    .iter()
+                    .position(|&root| root == Some(parent))
+                    .unwrap();
+                self.roots[slot] = Some(sibling);
+            }
+        }
+        self.free.extend([id, parent]);
+    }
+
+    /// Recomputes hashes from `id` up to its root
+    fn rehash(&mut self, id: usize) {
+        let mut node = Some(id);
+        while let Some(id) = node {
+            let [left, right] = self.nodes[id].children.unwrap();
+            self.nodes[id].hash = parent_hash(&self.nodes[left].hash, &self.nodes[right].hash);
+            node = self.nodes[id].parent;
+        }
+    }
+
+    fn alloc(&mut self, hash: NodeHash, children: Option<[usize; 2]>) -> usize {
+        let node = ForestNode {
+            hash,
+            parent: None,
+            children,
+        };
+        match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+}
@@ -0,0 +1,550 @@
+//! Machine-readable test vectors for implementations in other languages.
+//!
+//! A vector file is plain text, one record per line, with every byte string
+//! hex encoded:
+//!
+//! ```text
+//! vector rfc6962
+//! leaf 6c6561662d30
+//! root 6e340b9c...
+//! inclusion 0 <sibling> <sibling> ...
+//! consistency 1 3 <hash> <hash> ...
+//! end
+//! ```
+//!
+//! `inclusion` lines list a leaf index followed by the proof hashes from the
+//! leaf up; every direction can be derived from the index (and, for
+//! `rfc6962` and `tendermint`, the tree size), and `openzeppelin` proofs
+//! need none. Wide trees list each level's `arity - 1` siblings in order
+//! before moving up a level. `consistency` lines only appear for `rfc6962`
+//! and give the old size, the new size and the proof. Lines starting with
+//! `#` are comments.
+//!
+//! The supported modes are:
+//!
+//! - `dense`: [`MerkleTree`], SHA-256 with the last node duplicated
+//! - `wide4`, `wide16`, `wide256`: [`WideMerkleTree`] at that arity
+//! - `rfc6962`: [`HistoryTree`]
+//! - `tendermint`: Tendermint's simple tree, hashed as `rfc6962` but
+//!   without consistency proofs
+//! - `bitcoin`: a block's transaction tree, double SHA-256 with the last
+//!   node duplicated; each leaf is a whole transaction, so the leaf hashes
+//!   are txids in internal byte order, and a lone transaction's txid is
+//!   the root. [`check_mainnet`] holds this mode to real block roots.
+//! - `openzeppelin`, with the `openzeppelin` feature: a
+//!   `StandardMerkleTree` with each leaf a single `bytes` value, proofs
+//!   indexed by the order the leaves are given in
+
+use crate::{Arity, HistoryTree, MerkleError, MerkleHasher, MerkleTree, WideMerkleTree};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+/// Tree construction a vector was produced with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorMode {
+    Dense,
+    Wide(Arity),
+    Rfc6962,
+    Tendermint,
+    Bitcoin,
+    #[cfg(feature = "openzeppelin")]
+    OpenZeppelin,
+}
+
+impl VectorMode {
+    /// Every mode vectors can be generated for
+    pub const ALL: &'static [VectorMode] = &[
+        VectorMode::Dense,
+        VectorMode::Wide(Arity::Four),
+        VectorMode::Wide(Arity::Sixteen),
+        VectorMode::Wide(Arity::TwoFiftySix),
+        VectorMode::Rfc6962,
+        VectorMode::Tendermint,
+        VectorMode::Bitcoin,
+        #[cfg(feature = "openzeppelin")]
+        VectorMode::OpenZeppelin,
+    ];
+}
+
+impl fmt::Display for VectorMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VectorMode::Dense | VectorMode::Wide(Arity::Binary) => write!(f, "dense"),
+            VectorMode::Wide(arity) => write!(f, "wide{}", arity.children()),
+            VectorMode::Rfc6962 => write!(f, "rfc6962"),
+            VectorMode::Tendermint => write!(f, "tendermint"),
+            VectorMode::Bitcoin => write!(f, "bitcoin"),
+            #[cfg(feature = "openzeppelin")]
+            VectorMode::OpenZeppelin => write!(f, "openzeppelin"),
+        }
+    }
+}
+
+impl FromStr for VectorMode {
+    type Err = MerkleError;
+
+    fn from_str(name: &str) -> Result<Self, MerkleError> {
+        match name {
+            "dense" => Ok(VectorMode::Dense),
+            "wide4" => Ok(VectorMode::Wide(Arity::Four)),
+            "wide16" => Ok(VectorMode::Wide(Arity::Sixteen)),
+            "wide256" => Ok(VectorMode::Wide(Arity::TwoFiftySix)),
+            "rfc6962" => Ok(VectorMode::Rfc6962),
+            "tendermint" => Ok(VectorMode::Tendermint),
+            "bitcoin" => Ok(VectorMode::Bitcoin),
+            #[cfg(feature = "openzeppelin")]
+            "openzeppelin" => Ok(VectorMode::OpenZeppelin),
+            _ => Err(MerkleError::Parse(format!(
+                "unknown vector mode {:?}",
+                name
+            ))),
+        }
+    }
+}
+
+/// A proof listed in a vector: leaf index and proof hashes from the leaf up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InclusionVector {
+    pub index: u64,
+    pub path: Vec<Vec<u8>>,
+}
+
+/// A consistency proof listed in an `rfc6962` vector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConsistencyVector {
+    pub old_size: u64,
+    pub new_size: u64,
+    pub path: Vec<Vec<u8>>,
+}
+
+/// Leaves plus everything an implementation should derive from them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestVector {
+    pub mode: VectorMode,
+    pub leaves: Vec<Vec<u8>>,
+    pub root: Vec<u8>,
+    pub inclusion: Vec<InclusionVector>,
+    pub consistency: Vec<ConsistencyVector>,
+}
+
+/// Bitcoin's double SHA-256 over transactions and pairs of txids
+#[derive(Clone)]
+struct Bitcoin;
+
+impl MerkleHasher for Bitcoin {
+    fn hash_leaf(&self, data: &[u8]) -> Vec<u8> {
+        Sha256::digest(Sha256::digest(data)).to_vec()
+    }
+
+    fn hash_node(&self, left: &[u8], right: &[u8]) -> Vec<u8> {
+        self.hash_leaf(&[left, right].concat())
+    }
+
+    fn empty_hash(&self) -> Vec<u8> {
+        // Every block has a coinbase, so no tree is ever empty
+        vec![0; 32]
+    }
+}
+
+/// Root and every proof of a binary tree, by leaf index
+fn binary_vectors<H: MerkleHasher>(
+    tree: &MerkleTree<H>,
+) -> Option<(Vec<u8>, Vec<InclusionVector>)> {
+    let inclusion = (0..tree.len())
+        .map(|index| InclusionVector {
+            index: index as u64,
+            path: tree
+                .build_proof(index)
+                .proof_hashes
+                .into_iter()
+                .map(|(hash, _)| hash)
+                .collect(),
+        })
+        .collect();
+
+    Some((tree.root_hash()?, inclusion))
+}
+
+/// Root and every proof of a block's transaction tree over `txids`
+///
+/// A block with a single transaction has that txid as its root and an
+/// empty path; only nodes with a level to pair in are hashed with
+/// themselves.
+fn bitcoin_vectors(txids: Vec<Vec<u8>>) -> Option<(Vec<u8>, Vec<InclusionVector>)> {
+    if let [txid] = &txids[..] {
+        let proof = InclusionVector {
+            index: 0,
+            path: Vec::new(),
+        };
+        return Some((txid.clone(), vec![proof]));
+    }
+
+    binary_vectors(&MerkleTree::from_leaf_hashes_by(txids, Bitcoin))
+}
+
+/// The genesis block's coinbase transaction, the block's only one
+const GENESIS_COINBASE: &str = "\
+    01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff\
+    001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e\
+    6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104\
+    678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51e\
+    c112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+
+/// Mainnet blocks pinned by [`check_mainnet`]: height, txids and merkle
+/// root, all in the byte-reversed order block explorers show
+const MAINNET_BLOCKS: &[(u64, &[&str], &str)] = &[
+    (
+        0,
+        &["4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"],
+        "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b",
+    ),
+    (
+        170,
+        &[
+            "b1fea52486ce0c62bb442b530a3f0132b826c74e473d1f2c220bfa78111c5082",
+            "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16",
+        ],
+        "7dac2c5666815c17a3b36427de37bb9d2e2c5ccec3f8633eb91a4205cb4c10ff",
+    ),
+    (
+        100_000,
+        &[
+            "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+            "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+            "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+            "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+        ],
+        "f3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766",
+    ),
+];
+
+/// Decodes a hash in the byte-reversed order block explorers show
+fn reversed_hex(text: &str) -> Vec<u8> {
+    let mut bytes = hex::decode(text).expect("pinned hashes are valid hex");
+    bytes.reverse();
+    bytes
+}
+
+/// Checks the `bitcoin` mode against the merkle roots of real mainnet
+/// blocks, so vectors are held to something outside this crate
+///
+/// The genesis coinbase is hashed from the raw transaction, checking the
+/// leaf hashing too; the other blocks start from their txids.
+pub fn check_mainnet() -> Result<(), MerkleError> {
+    let mismatch = |height: u64, expected: &str| {
+        Err(MerkleError::VectorMismatch(format!(
+            "bitcoin block {}: merkle root should be {}",
+            height, expected
+        )))
+    };
+
+    let coinbase = hex::decode(GENESIS_COINBASE).expect("the coinbase is valid hex");
+    let genesis = generate(VectorMode::Bitcoin, vec![coinbase]).expect("one leaf has a root");
+    let (_, _, genesis_root) = MAINNET_BLOCKS[0];
+    if genesis.root != reversed_hex(genesis_root) {
+        return mismatch(0, genesis_root);
+    }
+
+    for &(height, txids, root) in MAINNET_BLOCKS {
+        let txids = txids.iter().map(|txid| reversed_hex(txid)).collect();
+        let (computed, _) = bitcoin_vectors(txids).expect("every block has a transaction");
+        if computed != reversed_hex(root) {
+            return mismatch(height, root);
+        }
+    }
+
+    Ok(())
+}
+
+/// Leaf counts covered by [`generate_all`], chosen to hit odd levels and
+/// power-of-two boundaries
+const LEAF_COUNTS: [usize; 10] = [1, 2, 3, 4, 5, 7, 8, 16, 17, 33];
+
+/// Derives the vector for `leaves` under `mode`
+///
+/// Returns `None` for modes without a root over zero leaves.
+pub fn generate(mode: VectorMode, leaves: Vec<Vec<u8>>) -> Option<TestVector> {
+    let (root, inclusion, consistency) = match mode {
+        VectorMode::Dense | VectorMode::Wide(Arity::Binary) => {
+            let (root, inclusion) = binary_vectors(&MerkleTree::new(leaves.clone()))?;
+            (root, inclusion, Vec::new())
+        }
+        VectorMode::Bitcoin => {
+            let txids = leaves.iter().map(|leaf| Bitcoin.hash_leaf(leaf)).collect();
+            let (root, inclusion) = bitcoin_vectors(txids)?;
+            (root, inclusion, Vec::new())
+        }
+        VectorMode::Wide(arity) => {
+            let tree = WideMerkleTree::new(leaves.clone(), arity);
+            let inclusion = (0..tree.len())
+                .map(|index| InclusionVector {
+                    index: index as u64,
+                    path: tree
+                        .generate_proof_at(index)
+                        .unwrap()
+                        .steps()
+                        .iter()
+                        .flat_map(|step| step.siblings.clone())
+                        .collect(),
+                })
+                .collect();
+
+            (tree.root_hash()?, inclusion, Vec::new())
+        }
+        VectorMode::Rfc6962 | VectorMode::Tendermint => {
+            let mut tree = HistoryTree::new();
+            for leaf in &leaves {
+                tree.append(leaf);
+            }
+
+            let size = tree.len();
+            let inclusion = (0..size)
+                .map(|index| InclusionVector {
+                    index,
+                    path: tree.prove_membership(index, size).unwrap().path().to_vec(),
+                })
+                .collect();
+            let consistency_sizes = match mode {
+                VectorMode::Rfc6962 => 1..size,
+                _ => 0..0,
+            };
+            let consistency = consistency_sizes
+                .map(|old_size| ConsistencyVector {
+                    old_size,
+                    new_size: size,
+                    path: tree
+                        .prove_incremental(old_size, size)
+                        .unwrap()
+                        .path()
+                        .to_vec(),
+                })
+                .collect();
+
+            (tree.root_hash(), inclusion, consistency)
+        }
+        #[cfg(feature = "openzeppelin")]
+        VectorMode::OpenZeppelin => {
+            use crate::openzeppelin::StandardMerkleTree;
+
+            let values = leaves
+                .iter()
+                .map(|leaf| vec![format!("0x{}", hex::encode(leaf)).into()])
+                .collect();
+            let tree = StandardMerkleTree::of(values, &["bytes"]).ok()?;
+            let inclusion = (0..tree.len())
+                .map(|index| InclusionVector {
+                    index: index as u64,
+                    path: tree
+                        .proof(index)
+                        .unwrap()
+                        .iter()
+                        .map(|hash| hash.to_vec())
+                        .collect(),
+                })
+                .collect();
+
+            (tree.root().to_vec(), inclusion, Vec::new())
+        }
+    };
+
+    Some(TestVector {
+        mode,
+        leaves,
+        root,
+        inclusion,
+        consistency,
+    })
+}
+
+/// Generates the standard fixture set for every supported mode
+pub fn generate_all() -> Vec<TestVector> {
+    let mut vectors = Vec::new();
+
+    for &mode in VectorMode::ALL {
+        // The empty RFC 6962 tree has a well-defined root worth pinning down
+        if matches!(mode, VectorMode::Rfc6962 | VectorMode::Tendermint) {
+            vectors.extend(generate(mode, Vec::new()));
+        }
+        for count in LEAF_COUNTS {
+            let leaves = (0..count)
+                .map(|i| format!("leaf-{}", i).into_bytes())
+                .collect();
+            vectors.extend(generate(mode, leaves));
+        }
+    }
+
+    vectors
+}
+
+/// Recomputes a vector and reports the first disagreement
+pub fn check(vector: &TestVector) -> Result<(), MerkleError> {
+    let mismatch = |what: String| {
+        Err(MerkleError::VectorMismatch(format!(
+            "{} ({} leaves, {}): {}",
+            vector.mode,
+            vector.leaves.len(),
+            hex::encode(&vector.root),
+            what
+        )))
+    };
+
+    let Some(expected) = generate(vector.mode, vector.leaves.clone()) else {
+        return mismatch("mode has no root for zero leaves".to_string());
+    };
+
+    if expected.root != vector.root {
+        return mismatch(format!("root should be {}", hex::encode(&expected.root)));
+    }
+    // Every proof must be listed, in order, so a vector can't pass by omission
+    if vector.inclusion.len() != expected.inclusion.len() {
+        return mismatch(format!(
+            "{} inclusion proofs listed, {} expected",
+            vector.inclusion.len(),
+            expected.inclusion.len()
+        ));
+    }
+    for (proof, expected) in vector.inclusion.iter().zip(&expected.inclusion) {
+        if proof != expected {
+            return mismatch(format!(
+                "inclusion proof for leaf {} should be for leaf {} with path {}",
+                proof.index,
+                expected.index,
+                hex_path(&expected.path)
+            ));
+        }
+    }
+    if vector.consistency.len() != expected.consistency.len() {
+        return mismatch(format!(
+            "{} consistency proofs listed, {} expected",
+            vector.consistency.len(),
+            expected.consistency.len()
+        ));
+    }
+    for (proof, expected) in vector.consistency.iter().zip(&expected.consistency) {
+        if proof != expected {
+            return mismatch(format!(
+                "consistency proof from {} to {} should be from {} to {} with path {}",
+                proof.old_size,
+                proof.new_size,
+                expected.old_size,
+                expected.new_size,
+                hex_path(&expected.path)
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn hex_path(path: &[Vec<u8>]) -> String {
+    let hashes: Vec<String> = path.iter().map(hex::encode).collect();
+    format!("[{}]", hashes.join(" "))
+}
+
+/// Writes vectors in the text format described in the module docs
+pub fn write_vectors<W: Write>(mut writer: W, vectors: &[TestVector]) -> Result<(), MerkleError> {
+    writeln!(writer, "# simple-merkle-tree test vectors")?;
+
+    for vector in vectors {
+        writeln!(writer, "vector {}", vector.mode)?;
+        for leaf in &vector.leaves {
+            writeln!(writer, "leaf {}", hex::encode(leaf))?;
+        }
+        writeln!(writer, "root {}", hex::encode(&vector.root))?;
+        for proof in &vector.inclusion {
+            write!(writer, "inclusion {}", proof.index)?;
+            write_path(&mut writer, &proof.path)?;
+        }
+        for proof in &vector.consistency {
+            write!(writer, "consistency {} {}", proof.old_size, proof.new_size)?;
+            write_path(&mut writer, &proof.path)?;
+        }
+        writeln!(writer, "end")?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_path<W: Write>(writer: &mut W, path: &[Vec<u8>]) -> Result<(), MerkleError> {
+    for hash in path {
+        write!(writer, " {}", hex::encode(hash))?;
+    }
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Reads vectors written by [`write_vectors`]
+pub fn read_vectors<R: BufRead>(reader: R) -> Result<Vec<TestVector>, MerkleError> {
+    let mut vectors = Vec::new();
+    let mut current: Option<TestVector> = None;
+
+    for (line_number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let error =
+            |message: &str| MerkleError::Parse(format!("line {}: {}", line_number + 1, message));
+        let hash = |text: &str| hex::decode(text).map_err(|_| error("invalid hex"));
+        let number = |text: Option<&str>| -> Result<u64, MerkleError> {
+            text.and_then(|text| text.parse().ok())
+                .ok_or_else(|| error("invalid number"))
+        };
+
+        let mut fields = line.split_whitespace();
+        let keyword = fields.next().unwrap();
+
+        if keyword == "vector" {
+            if current.is_some() {
+                return Err(error("vector started before the previous one ended"));
+            }
+            let mode = fields
+                .next()
+                .ok_or_else(|| error("missing mode"))?
+                .parse()?;
+            current = Some(TestVector {
+                mode,
+                leaves: Vec::new(),
+                root: Vec::new(),
+                inclusion: Vec::new(),
+                consistency: Vec::new(),
+            });
+            continue;
+        }
+
+        let vector = current
+            .as_mut()
+            .ok_or_else(|| error("record outside a vector"))?;
+        match keyword {
+            "leaf" => vector.leaves.push(hash(fields.next().unwrap_or(""))?),
+            "root" => vector.root = hash(fields.next().ok_or_else(|| error("missing root"))?)?,
+            "inclusion" => {
+                let index = number(fields.next())?;
+                let path = fields.map(hash).collect::<Result<_, _>>()?;
+                vector.inclusion.push(InclusionVector { index, path });
+            }
+            "consistency" => {
+                let old_size = number(fields.next())?;
+                let new_size = number(fields.next())?;
+                let path = fields.map(hash).collect::<Result<_, _>>()?;
+                vector.consistency.push(ConsistencyVector {
+                    old_size,
+                    new_size,
+                    path,
+                });
+            }
+            "end" => vectors.push(current.take().unwrap()),
+            _ => return Err(error("unknown record")),
+        }
+    }
+
+    if current.is_some() {
+        return Err(MerkleError::Parse("last vector has no `end`".to_string()));
+    }
+    Ok(vectors)
+}
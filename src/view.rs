@@ -0,0 +1,194 @@
+//! Tree files that proofs can be served from without decoding.
+//!
+//! The layout is a 32-byte header followed by every node as a fixed 32-byte
+//! record, level by level from the leaves up:
+//!
+//! ```text
+//! "SMTV" | version: u8 | reserved: [u8; 3] | leaves: u64 | nodes: u64
+//!        | reserved: [u8; 8] | node hash: [u8; 32] * nodes
+//! ```
+//!
+//! Integers are big-endian and reserved bytes are zero. Every record starts
+//! at a multiple of 32 bytes, so the node area can also be cast to
+//! `[[u8; 32]]` with `bytemuck` or `zerocopy`. [`TreeView`] checks the
+//! header and the buffer length once, then reads hashes straight out of
+//! the buffer; it does not allocate and works without `std`.
+//!
+//! A view does not recompute hashes, so a buffer from an untrusted source
+//! only yields proofs that are as good as its root. Domain tags are not
+//! recorded and views always prove as untagged, so only trees hashed by
+//! the default [`TreeConfig`](crate::TreeConfig) can be written in this
+//! layout.
+
+use crate::Side;
+
+#[cfg(feature = "std")]
+use crate::{MerkleError, MerkleHasher, MerkleProof, MerkleTree, TreeConfig};
+
+pub(crate) const MAGIC: &[u8; 4] = b"SMTV";
+pub(crate) const FORMAT_VERSION: u8 = 1;
+const HEADER_LEN: usize = 32;
+const HASH_LEN: usize = 32;
+
+/// Returns the number of nodes on each level of a tree with `leaves` leaves
+pub(crate) fn level_lengths(leaves: u64) -> impl Iterator<Item = u64> {
+    // A single leaf is still paired with itself, so there are two levels
+    let mut next = (leaves > 0).then_some(leaves);
+    let mut first = true;
+    core::iter::from_fn(move || {
+        let len = next?;
+        next = (len > 1 || first).then(|| len.div_ceil(2));
+        first = false;
+        Some(len)
+    })
+}
+
+/// A serialized tree read in place
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeView<'a> {
+    leaves: u64,
+    nodes: &'a [u8],
+}
+
+impl<'a> TreeView<'a> {
+    /// Checks the header and length, returning `None` if the buffer is not a
+    /// well-formed tree file
+    pub fn parse(bytes: &'a [u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN {
+            return None;
+        }
+        let (header, nodes) = bytes.split_at(HEADER_LEN);
+
+        let leaves = u64::from_be_bytes(header[8..16].try_into().ok()?);
+        let node_count = u64::from_be_bytes(header[16..24].try_into().ok()?);
+        if &header[..4] != MAGIC
+            || header[4] != FORMAT_VERSION
+            || header[5..8] != [0; 3]
+            || header[24..] != [0; 8]
+        {
+            return None;
+        }
+
+        let expected = level_lengths(leaves).try_fold(0u64, |total, len| total.checked_add(len))?;
+        let expected_len = usize::try_from(expected).ok()?.checked_mul(HASH_LEN)?;
+        if node_count != expected || nodes.len() != expected_len {
+            return None;
+        }
+
+        Some(TreeView { leaves, nodes })
+    }
+
+    /// Returns the number of leaves
+    pub fn len(&self) -> u64 {
+        self.leaves
+    }
+
+    /// Returns true if the tree has no leaves
+    pub fn is_empty(&self) -> bool {
+        self.leaves == 0
+    }
+
+    /// Returns the number of levels, leaves and root included
+    pub fn levels(&self) -> usize {
+        level_lengths(self.leaves).count()
+    }
+
+    /// Returns node `index` of `level`, level 0 being the leaves
+    pub fn node(&self, level: usize, index: u64) -> Option<&'a [u8; 32]> {
+        let mut offset = 0;
+        let mut lengths = level_lengths(self.leaves);
+        for _ in 0..level {
+            offset += lengths.next()?;
+        }
+        if index >= lengths.next()? {
+            return None;
+        }
+
+        // Bounds were checked against the buffer length in parse
+        let start = (offset + index) as usize * HASH_LEN;
+        self.nodes[start..start + HASH_LEN].try_into().ok()
+    }
+
+    /// Returns the hash of leaf `index`
+    pub fn leaf(&self, index: u64) -> Option<&'a [u8; 32]> {
+        self.node(0, index)
+    }
+
+    /// Returns the root hash, if the tree has leaves
+    pub fn root_hash(&self) -> Option<&'a [u8; 32]> {
+        self.node(self.levels().checked_sub(1)?, 0)
+    }
+
+    /// Returns the siblings proving leaf `index`, from the leaf up, each with
+    /// the side it sits on
+    ///
+    /// The hashes borrow from the buffer, so this is a proof in the form
+    /// [`verify_proof_in_place`](crate::verify_proof_in_place) takes,
+    /// without copying.
+    pub fn siblings(&self, index: u64) -> Option<impl Iterator<Item = (&'a [u8; 32], Side)> + 'a> {
+        if index >= self.leaves {
+            return None;
+        }
+
+        let view = *self;
+        let levels = self.levels() - 1;
+        Some((0..levels).map(move |level| {
+            let position = index >> level;
+            // A missing sibling means the node was paired with itself
+            let sibling = view
+                .node(level, position ^ 1)
+                .or_else(|| view.node(level, position))
+                .unwrap();
+            (sibling, Side::of_sibling(position))
+        }))
+    }
+
+    /// Copies out the proof for leaf `index`
+    #[cfg(feature = "std")]
+    pub fn proof(&self, index: u64) -> Option<MerkleProof> {
+        let siblings = self
+            .siblings(index)?
+            .map(|(hash, side)| (hash.to_vec(), side))
+            .collect();
+        Some(MerkleProof::new(
+            self.leaf(index)?.to_vec(),
+            siblings,
+            self.root_hash()?.to_vec(),
+        ))
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H: MerkleHasher> MerkleTree<H> {
+    /// Encodes the tree in the layout [`TreeView`] reads
+    ///
+    /// Fails with [`MerkleError::ConfigMismatch`] unless the tree hashes as
+    /// the default [`TreeConfig`], since the layout has no room for a
+    /// domain tag or another hasher, and with
+    /// [`MerkleError::InvalidHashLength`] if its hashes are not 32 bytes, as
+    /// can happen for trees imported from levels.
+    pub fn to_view_bytes(&self) -> Result<Vec<u8>, MerkleError> {
+        if self.config.config_id() != TreeConfig::default().config_id() {
+            return Err(MerkleError::ConfigMismatch);
+        }
+
+        let nodes: usize = self.levels.iter().map(Vec::len).sum();
+        let mut bytes = Vec::with_capacity(HEADER_LEN + nodes * HASH_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[FORMAT_VERSION, 0, 0, 0]);
+        bytes.extend_from_slice(&(self.len() as u64).to_be_bytes());
+        bytes.extend_from_slice(&(nodes as u64).to_be_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+
+        for hash in self.levels.iter().flatten() {
+            if hash.len() != HASH_LEN {
+                return Err(MerkleError::InvalidHashLength {
+                    expected: HASH_LEN,
+                    actual: hash.len(),
+                });
+            }
+            bytes.extend_from_slice(hash);
+        }
+        Ok(bytes)
+    }
+}
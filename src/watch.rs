@@ -0,0 +1,137 @@
+//! Change notifications for growing trees.
+//!
+//! [`Watched`] wraps any [`Accumulator`] and tells subscribers the new size
+//! and root after every change, so publishers such as gossip or on-chain
+//! anchoring react to new roots instead of polling for them. Subscribers
+//! can register a callback with [`Watched::on_change`], take a
+//! [`std::sync::mpsc`] receiver from [`Watched::subscribe`], or, with the
+//! `async` feature, a tokio `watch` receiver from [`Watched::watch`] that
+//! always holds the latest state.
+//!
+//! Reads go straight through to the wrapped accumulator. Writes go through
+//! [`Watched::add`] or [`Watched::modify`], and a change that leaves the
+//! size and root as they were notifies nobody.
+
+use crate::Accumulator;
+use std::ops::Deref;
+use std::sync::mpsc;
+
+/// State of a watched accumulator after a change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeUpdate {
+    pub size: u64,
+    /// The new commitment, `None` if the accumulator has none
+    pub root: Option<Vec<u8>>,
+}
+
+type Callback = Box<dyn FnMut(&TreeUpdate) + Send>;
+
+/// An accumulator that notifies subscribers whenever it changes
+pub struct Watched<A: Accumulator> {
+    inner: A,
+    callbacks: Vec<Callback>,
+    senders: Vec<mpsc::Sender<TreeUpdate>>,
+    #[cfg(feature = "async")]
+    watch: Option<tokio::sync::watch::Sender<TreeUpdate>>,
+}
+
+impl<A: Accumulator> Watched<A> {
+    /// Starts watching `inner`; nobody is subscribed yet
+    pub fn new(inner: A) -> Self {
+        Watched {
+            inner,
+            callbacks: Vec::new(),
+            senders: Vec::new(),
+            #[cfg(feature = "async")]
+            watch: None,
+        }
+    }
+
+    /// Returns the current size and root
+    pub fn state(&self) -> TreeUpdate {
+        TreeUpdate {
+            size: self.inner.size(),
+            root: self.inner.commitment(),
+        }
+    }
+
+    /// Calls `callback` with every update, on the thread making the change
+    pub fn on_change(&mut self, callback: impl FnMut(&TreeUpdate) + Send + 'static) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    /// Returns a receiver that gets every update
+    ///
+    /// The sender is dropped once the receiver is, at the next update.
+    pub fn subscribe(&mut self) -> mpsc::Receiver<TreeUpdate> {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    /// Returns a tokio `watch` receiver holding the latest state
+    ///
+    /// Slow readers skip straight to the newest update rather than queue
+    /// every one.
+    #[cfg(feature = "async")]
+    pub fn watch(&mut self) -> tokio::sync::watch::Receiver<TreeUpdate> {
+        match &self.watch {
+            Some(sender) => sender.subscribe(),
+            None => {
+                let (sender, receiver) = tokio::sync::watch::channel(self.state());
+                self.watch = Some(sender);
+                receiver
+            }
+        }
+    }
+
+    /// Adds an element and notifies subscribers
+    pub fn add(&mut self, element: &[u8]) -> A::Key {
+        let key = self.inner.add(element);
+        self.notify();
+        key
+    }
+
+    /// Runs `change` against the accumulator, then notifies subscribers if
+    /// its size or root moved
+    pub fn modify<T>(&mut self, change: impl FnOnce(&mut A) -> T) -> T {
+        let before = self.state();
+        let result = change(&mut self.inner);
+        if self.state() != before {
+            self.notify();
+        }
+        result
+    }
+
+    /// Stops watching and returns the accumulator
+    pub fn into_inner(self) -> A {
+        self.inner
+    }
+
+    fn notify(&mut self) {
+        let update = self.state();
+        for callback in &mut self.callbacks {
+            callback(&update);
+        }
+        self.senders
+            .retain(|sender| sender.send(update.clone()).is_ok());
+        #[cfg(feature = "async")]
+        if let Some(sender) = &self.watch {
+            sender.send_replace(update);
+        }
+    }
+}
+
+impl<A: Accumulator> Deref for Watched<A> {
+    type Target = A;
+
+    fn deref(&self) -> &A {
+        &self.inner
+    }
+}
+
+impl<A: Accumulator> From<A> for Watched<A> {
+    fn from(inner: A) -> Self {
+        Watched::new(inner)
+    }
+}
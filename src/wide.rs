@@ -0,0 +1,259 @@
+//! Dense trees with more than two children per node.
+//!
+//! Each internal node hashes all of its children with
+//! [`MerkleHasher::hash_children`], so a wider tree is shallower but its
+//! proofs carry every sibling in each group. A group that runs past the end
+//! of a level is filled with the hasher's padding for its last node, which
+//! makes a binary [`WideMerkleTree`] produce the same root as a
+//! [`MerkleTree`](crate::MerkleTree) with the same hasher.
+
+use crate::{MerkleHasher, TreeConfig};
+use std::borrow::Cow;
+
+/// Number of children per internal node
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arity {
+    #[default]
+    Binary,
+    Four,
+    Sixteen,
+    TwoFiftySix,
+}
+
+impl Arity {
+    /// Returns the number of children per internal node
+    pub fn children(self) -> usize {
+        match self {
+            Arity::Binary => 2,
+            Arity::Four => 4,
+            Arity::Sixteen => 16,
+            Arity::TwoFiftySix => 256,
+        }
+    }
+}
+
+/// Returns node `index` of a level when grouping for a parent, padding the
+/// last node for positions past the end
+fn padded<'a, H: MerkleHasher>(hasher: &H, nodes: &'a [Vec<u8>], index: usize) -> Cow<'a, [u8]> {
+    match nodes.get(index) {
+        Some(node) => Cow::Borrowed(node),
+        None => Cow::Owned(hasher.pad(nodes.last().unwrap())),
+    }
+}
+
+/// A Merkle tree with a configurable number of children per node
+///
+/// Hashing follows `H`, a [`TreeConfig`] unless another [`MerkleHasher`] is
+/// given.
+pub struct WideMerkleTree<H: MerkleHasher = TreeConfig> {
+    arity: Arity,
+    config: H,
+    /// Hashes of every level, leaves first; the last level holds the root
+    levels: Vec<Vec<Vec<u8>>>,
+}
+
+impl WideMerkleTree {
+    /// Creates a new tree from a list of data items
+    pub fn new(data: Vec<Vec<u8>>, arity: Arity) -> Self {
+        Self::new_with_hasher(data, arity, TreeConfig::default())
+    }
+}
+
+impl<H: MerkleHasher> WideMerkleTree<H> {
+    /// Creates a new tree from a list of data items, hashed by `hasher`
+    pub fn new_with_hasher(data: Vec<Vec<u8>>, arity: Arity, hasher: H) -> Self {
+        let width = arity.children();
+        let mut levels = Vec::new();
+
+        if !data.is_empty() {
+            levels.push(
+                data.iter()
+                    .map(|item| hasher.hash_leaf(item))
+                    .collect::<Vec<_>>(),
+            );
+
+            // A single leaf is still grouped with its padding
+            while levels.len() == 1 || levels.last().unwrap().len() > 1 {
+                let nodes = levels.last().unwrap();
+                let next_level = (0..nodes.len())
+                    .step_by(width)
+                    .map(|start| {
+                        let group: Vec<_> = (start..start + width)
+                            .map(|i| padded(&hasher, nodes, i))
+                            .collect();
+                        let children: Vec<&[u8]> = group.iter().map(|child| &**child).collect();
+                        hasher.hash_children(&children)
+                    })
+                    .collect();
+
+                levels.push(next_level);
+            }
+        }
+
+        WideMerkleTree {
+            arity,
+            config: hasher,
+            levels,
+        }
+    }
+
+    /// Returns the number of children per internal node
+    pub fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    /// Returns the number of leaves in the tree
+    pub fn len(&self) -> usize {
+        self.levels.first().map_or(0, |leaves| leaves.len())
+    }
+
+    /// Returns true if the tree has no leaves
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of levels between the leaves and the root
+    pub fn depth(&self) -> usize {
+        self.levels.len().saturating_sub(1)
+    }
+
+    /// Returns the Merkle root hash, if it exists
+    pub fn root_hash(&self) -> Option<Vec<u8>> {
+        self.levels.last().map(|root| root[0].clone())
+    }
+
+    /// Returns the Merkle root hash as a hex string
+    pub fn root_hash_hex(&self) -> Option<String> {
+        self.root_hash().map(hex::encode)
+    }
+
+    /// Returns the hasher the tree was built with
+    pub fn merkle_hasher(&self) -> &H {
+        &self.config
+    }
+
+    /// Generates a proof that a leaf with given data exists in the tree
+    pub fn generate_proof(&self, data: &[u8]) -> Option<WideProof<H>> {
+        let leaf_hash = self.config.hash_leaf(data);
+        let index = self
+            .levels
+            .first()?
+            .iter()
+            .position(|leaf| *leaf == leaf_hash)?;
+
+        self.generate_proof_at(index)
+    }
+
+    /// Generates a proof for the leaf at `index`
+    pub fn generate_proof_at(&self, index: usize) -> Option<WideProof<H>> {
+        if index >= self.len() {
+            return None;
+        }
+
+        let width = self.arity.children();
+        let mut steps = Vec::new();
+        let mut position = index;
+
+        for nodes in &self.levels[..self.levels.len() - 1] {
+            let start = position - position % width;
+            let siblings = (start..start + width)
+                .filter(|&i| i != position)
+                .map(|i| padded(&self.config, nodes, i).into_owned())
+                .collect();
+
+            steps.push(WideProofStep {
+                position: position % width,
+                siblings,
+            });
+            position /= width;
+        }
+
+        Some(WideProof {
+            arity: self.arity,
+            config: self.config.clone(),
+            leaf_hash: self.levels[0][index].clone(),
+            steps,
+            root_hash: self.root_hash().unwrap(),
+        })
+    }
+
+    /// Verifies whether data is included in the tree using a proof
+    pub fn verify_proof(&self, proof: &WideProof<H>) -> bool {
+        match self.root_hash() {
+            Some(root) => proof.arity == self.arity && proof.verify(&root),
+            None => false,
+        }
+    }
+}
+
+/// One level of a [`WideProof`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WideProofStep {
+    /// Where the running hash sits among its siblings
+    pub position: usize,
+    /// The other children of the parent, in order, without the running hash
+    pub siblings: Vec<Vec<u8>>,
+}
+
+/// A proof that a particular data item is in a [`WideMerkleTree`]
+///
+/// The proof carries the hasher of the tree it came from and verifies with
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WideProof<H: MerkleHasher = TreeConfig> {
+    arity: Arity,
+    config: H,
+    leaf_hash: Vec<u8>,
+    steps: Vec<WideProofStep>,
+    root_hash: Vec<u8>,
+}
+
+impl<H: MerkleHasher> WideProof<H> {
+    /// Returns the arity of the tree the proof was generated from
+    pub fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    /// Returns the hasher the proof verifies with
+    pub fn merkle_hasher(&self) -> &H {
+        &self.config
+    }
+
+    /// Returns the hash of the leaf the proof starts from
+    pub fn leaf_hash(&self) -> &[u8] {
+        &self.leaf_hash
+    }
+
+    /// Returns the sibling sets from the leaf level up
+    pub fn steps(&self) -> &[WideProofStep] {
+        &self.steps
+    }
+
+    /// Returns the root hash of the tree the proof was generated from
+    pub fn root_hash(&self) -> &[u8] {
+        &self.root_hash
+    }
+
+    /// Verifies the proof against the given root hash
+    pub fn verify(&self, root_hash: &[u8]) -> bool {
+        let width = self.arity.children();
+        let mut current_hash = self.leaf_hash.clone();
+
+        for step in &self.steps {
+            if step.siblings.len() != width - 1 || step.position >= width {
+                return false;
+            }
+
+            let (before, after) = step.siblings.split_at(step.position);
+            let children = before
+                .iter()
+                .map(Vec::as_slice)
+                .chain(std::iter::once(current_hash.as_slice()))
+                .chain(after.iter().map(Vec::as_slice));
+
+            current_hash = self.config.hash_children(&children.collect::<Vec<_>>());
+        }
+
+        current_hash == root_hash
+    }
+}
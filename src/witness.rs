@@ -0,0 +1,276 @@
+//! Witness cosignatures over published tree heads.
+//!
+//! A log publishes a [`TreeHead`] and independent witnesses countersign it
+//! once they have checked it against what they saw before. A verifier then
+//! only accepts a head that enough of the witnesses it trusts have signed,
+//! so a log can't show different views to different clients without the
+//! witnesses colluding.
+//!
+//! Tree heads are signed in the checkpoint body format used by transparency
+//! log witnesses: the origin line, the decimal tree size and the base64 root
+//! hash, each followed by a newline. The log signs the body itself. A
+//! witness signs it behind a `cosignature/v1` header and the time it
+//! cosigned, as in the C2SP witness protocol, so a witness's signature can
+//! never pass for the log's or the other way round. Signature schemes are
+//! pluggable through [`Signer`] and [`Verifier`].
+
+use crate::{base64, HistoryTree, MerkleError};
+
+/// Starts every line carrying a signature in the crate's text formats
+pub(crate) const SIGNATURE_PREFIX: &str = "— ";
+
+/// A tree size and root hash published by a log
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeHead {
+    /// Identifies the log, e.g. `example.com/log`
+    pub origin: String,
+    /// Number of leaves the root commits to
+    pub size: u64,
+    /// Root hash at that size
+    pub root_hash: Vec<u8>,
+}
+
+impl TreeHead {
+    /// Creates a tree head
+    pub fn new(origin: impl Into<String>, size: u64, root_hash: Vec<u8>) -> Self {
+        TreeHead {
+            origin: origin.into(),
+            size,
+            root_hash,
+        }
+    }
+
+    /// Creates the tree head for the current version of a history tree
+    pub fn from_history(origin: impl Into<String>, tree: &HistoryTree) -> Self {
+        Self::new(origin, tree.len(), tree.root_hash())
+    }
+
+    /// Returns the checkpoint body that signers sign
+    pub fn to_checkpoint(&self) -> String {
+        format!(
+            "{}\n{}\n{}\n",
+            self.origin,
+            self.size,
+            base64::encode(&self.root_hash)
+        )
+    }
+
+    /// Parses a checkpoint body written by [`TreeHead::to_checkpoint`]
+    ///
+    /// Extension lines after the root hash aren't supported.
+    pub fn parse_checkpoint(text: &str) -> Result<Self, MerkleError> {
+        let error = |message: &str| MerkleError::Parse(format!("checkpoint: {}", message));
+
+        let body = text
+            .strip_suffix('\n')
+            .ok_or_else(|| error("missing final newline"))?;
+        let lines: Vec<&str> = body.split('\n').collect();
+        let [origin, size, root_hash] = lines[..] else {
+            return Err(error("expected origin, size and root hash lines"));
+        };
+
+        if origin.is_empty() {
+            return Err(error("empty origin"));
+        }
+        // Reject non-canonical sizes such as `+5` or `07`, which would sign differently
+        let size: u64 = size.parse().map_err(|_| error("invalid tree size"))?;
+        if size.to_string() != lines[1] {
+            return Err(error("invalid tree size"));
+        }
+        let root_hash = base64::decode(root_hash).ok_or_else(|| error("invalid root hash"))?;
+
+        Ok(TreeHead::new(origin, size, root_hash))
+    }
+}
+
+/// Produces signatures for one key
+pub trait Signer {
+    /// Returns the name the matching [`Verifier`] is known by
+    fn key_id(&self) -> &str;
+
+    /// Signs `message`
+    fn sign(&self, message: &[u8]) -> Vec<u8>;
+}
+
+/// Checks signatures for one key
+pub trait Verifier {
+    /// Returns the name of the key, matched against [`Cosignature::key_id`]
+    fn key_id(&self) -> &str;
+
+    /// Returns true if `signature` is a valid signature of `message`
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool;
+}
+
+/// One party's signature over a tree head: the log's, or a witness's made
+/// at `timestamp`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cosignature {
+    pub key_id: String,
+    /// Seconds since the Unix epoch at which a witness cosigned; `None` for
+    /// the log's own signature
+    pub timestamp: Option<u64>,
+    pub signature: Vec<u8>,
+}
+
+impl Cosignature {
+    /// Signs a tree head as the log that published it
+    pub fn sign(head: &TreeHead, log: &dyn Signer) -> Self {
+        Cosignature {
+            key_id: log.key_id().to_string(),
+            timestamp: None,
+            signature: log.sign(head.to_checkpoint().as_bytes()),
+        }
+    }
+
+    /// Cosigns a tree head as a witness, at `timestamp`
+    pub fn cosign(head: &TreeHead, witness: &dyn Signer, timestamp: u64) -> Self {
+        Cosignature {
+            key_id: witness.key_id().to_string(),
+            timestamp: Some(timestamp),
+            signature: witness.sign(&cosigned_message(head, timestamp)),
+        }
+    }
+
+    /// Returns true if this is the log `log`'s valid signature over `head`
+    pub fn verify_log(&self, head: &TreeHead, log: &dyn Verifier) -> bool {
+        self.timestamp.is_none()
+            && self.key_id == log.key_id()
+            && log.verify(head.to_checkpoint().as_bytes(), &self.signature)
+    }
+
+    /// Returns true if this is `witness`'s valid cosignature over `head`
+    pub fn verify_witness(&self, head: &TreeHead, witness: &dyn Verifier) -> bool {
+        match self.timestamp {
+            Some(timestamp) => {
+                self.key_id == witness.key_id()
+                    && witness.verify(&cosigned_message(head, timestamp), &self.signature)
+            }
+            None => false,
+        }
+    }
+
+    /// Writes the signature line of the bundle and evidence formats:
+    /// `— <key id> [<timestamp>] <base64 signature>`
+    ///
+    /// Returns `None` if the key id is empty or contains whitespace.
+    pub(crate) fn to_line(&self) -> Option<String> {
+        if self.key_id.is_empty() || self.key_id.contains(char::is_whitespace) {
+            return None;
+        }
+        let timestamp = self
+            .timestamp
+            .map_or(String::new(), |timestamp| format!("{} ", timestamp));
+        Some(format!(
+            "{}{} {}{}",
+            SIGNATURE_PREFIX,
+            self.key_id,
+            timestamp,
+            base64::encode(&self.signature)
+        ))
+    }
+
+    /// Parses a line written by [`Cosignature::to_line`]
+    pub(crate) fn parse_line(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.strip_prefix(SIGNATURE_PREFIX)?.split(' ').collect();
+        let (key_id, timestamp, signature) = match fields[..] {
+            [key_id, signature] => (key_id, None, signature),
+            [key_id, timestamp, signature] => {
+                let parsed: u64 = timestamp.parse().ok()?;
+                // Timestamps are signed, so only one spelling of each is accepted
+                if parsed.to_string() != timestamp {
+                    return None;
+                }
+                (key_id, Some(parsed), signature)
+            }
+            _ => return None,
+        };
+        if key_id.is_empty() {
+            return None;
+        }
+        Some(Cosignature {
+            key_id: key_id.to_string(),
+            timestamp,
+            signature: base64::decode(signature)?,
+        })
+    }
+}
+
+/// Returns the message a witness signs to cosign `head` at `timestamp`
+fn cosigned_message(head: &TreeHead, timestamp: u64) -> Vec<u8> {
+    format!(
+        "cosignature/v1\ntime {}\n{}",
+        timestamp,
+        head.to_checkpoint()
+    )
+    .into_bytes()
+}
+
+/// A tree head together with the cosignatures collected for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WitnessedHead {
+    pub head: TreeHead,
+    pub cosignatures: Vec<Cosignature>,
+}
+
+impl WitnessedHead {
+    /// Starts collecting cosignatures for a tree head
+    pub fn new(head: TreeHead) -> Self {
+        WitnessedHead {
+            head,
+            cosignatures: Vec::new(),
+        }
+    }
+
+    /// Adds the log's signature
+    pub fn sign(&mut self, log: &dyn Signer) {
+        self.cosignatures.push(Cosignature::sign(&self.head, log));
+    }
+
+    /// Adds `witness`'s cosignature, made at `timestamp`
+    pub fn cosign(&mut self, witness: &dyn Signer, timestamp: u64) {
+        self.cosignatures
+            .push(Cosignature::cosign(&self.head, witness, timestamp));
+    }
+
+    /// Returns how many of `witnesses` have validly cosigned the head
+    ///
+    /// Each witness counts once no matter how many of its signatures are
+    /// attached, and cosignatures from keys not in `witnesses` are ignored.
+    pub fn valid_witnesses(&self, witnesses: &[&dyn Verifier]) -> usize {
+        let mut counted: Vec<&str> = Vec::new();
+
+        for witness in witnesses {
+            if counted.contains(&witness.key_id()) {
+                continue;
+            }
+            if self
+                .cosignatures
+                .iter()
+                .any(|cosignature| cosignature.verify_witness(&self.head, *witness))
+            {
+                counted.push(witness.key_id());
+            }
+        }
+
+        counted.len()
+    }
+
+    /// Checks that at least `threshold` of `witnesses` have cosigned the head
+    ///
+    /// Returns the number of valid witnesses on success.
+    pub fn verify(
+        &self,
+        witnesses: &[&dyn Verifier],
+        threshold: usize,
+    ) -> Result<usize, MerkleError> {
+        let valid = self.valid_witnesses(witnesses);
+
+        if valid < threshold {
+            return Err(MerkleError::InsufficientCosignatures {
+                valid,
+                required: threshold,
+            });
+        }
+        Ok(valid)
+    }
+}
@@ -0,0 +1,227 @@
+//! The alternative ways of building and checking a tree agree with
+//! `MerkleTree::new` on arbitrary input.
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use simple_merkle_tree::{pipelined_root_with, MerkleTree, PipelineConfig};
+#[cfg(any(feature = "arena", feature = "testing"))]
+use simple_merkle_tree::{MerkleProof, Side};
+
+/// Everything a proof holds: leaf hash, siblings, root hash and domain
+#[cfg(any(feature = "arena", feature = "testing"))]
+type Parts<'a> = (&'a [u8], &'a [(Vec<u8>, Side)], &'a [u8], Option<&'a [u8]>);
+
+/// Splits a proof into its parts, for comparing proofs
+#[cfg(any(feature = "arena", feature = "testing"))]
+fn parts(proof: &MerkleProof) -> Parts<'_> {
+    (
+        proof.leaf_hash(),
+        proof.siblings(),
+        proof.root_hash(),
+        proof.domain(),
+    )
+}
+
+fn data() -> impl Strategy<Value = Vec<Vec<u8>>> {
+    vec(vec(any::<u8>(), 0..40), 0..70)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn proofs_verify_against_the_root(data in data()) {
+        let tree = MerkleTree::new(data.clone());
+        prop_assert_eq!(tree.root_hash().is_none(), data.is_empty());
+        for index in 0..data.len() {
+            let proof = tree.generate_proof_at(index).unwrap();
+            prop_assert!(tree.verify_proof(&proof));
+            prop_assert!(proof.verify(&tree.root_hash().unwrap()));
+        }
+        prop_assert!(tree.generate_proof_at(data.len()).is_none());
+    }
+
+    #[test]
+    fn updates_match_a_rebuild(
+        mut data in data(),
+        updates in vec((any::<prop::sample::Index>(), vec(any::<u8>(), 0..40)), 1..5),
+    ) {
+        prop_assume!(!data.is_empty());
+        let mut tree = MerkleTree::new(data.clone());
+        let before = tree.root_hash();
+        let len = data.len();
+
+        // A transaction that fails part way changes nothing
+        let failed = tree.transaction(|txn| {
+            txn.update(0, b"staged")?;
+            txn.update(len, b"past the end")
+        });
+        prop_assert!(failed.is_err());
+        prop_assert_eq!(tree.root_hash(), before);
+
+        let root = tree
+            .transaction(|txn| {
+                for (index, leaf) in &updates {
+                    txn.update(index.index(len), leaf)?;
+                }
+                Ok::<_, simple_merkle_tree::MerkleError>(())
+            })
+            .unwrap();
+        for (index, leaf) in updates {
+            data[index.index(len)] = leaf;
+        }
+        let rebuilt = MerkleTree::new(data);
+        prop_assert_eq!(root, rebuilt.root_hash());
+        prop_assert!(tree == rebuilt);
+    }
+
+    #[test]
+    fn explained_checks_agree_with_owns_proof(
+        ours in data(),
+        theirs in data(),
+        pick in any::<prop::sample::Index>(),
+    ) {
+        prop_assume!(!ours.is_empty() && !theirs.is_empty());
+        let tree = MerkleTree::new(ours);
+        let other = MerkleTree::new(theirs.clone());
+        let proof = other.generate_proof_at(pick.index(theirs.len())).unwrap();
+
+        // Explaining a rejection never blames less than ownership does
+        let owned = tree.owns_proof(&proof);
+        let explained = tree.verify_proof_explain(&proof);
+        if owned.is_err() {
+            prop_assert!(explained.is_err());
+        }
+        prop_assert_eq!(explained.is_ok(), tree.verify_proof(&proof));
+    }
+
+    #[test]
+    fn pushes_match_a_rebuild(data in data()) {
+        let mut tree = MerkleTree::new(Vec::new());
+        for item in &data {
+            tree.push(item);
+        }
+        prop_assert_eq!(tree.root_hash(), MerkleTree::new(data).root_hash());
+    }
+
+    #[test]
+    fn pipelined_root_matches(
+        data in data(),
+        workers in 1usize..4,
+        batch_size in 1usize..9,
+        queue_depth in 1usize..3,
+    ) {
+        let config = PipelineConfig { workers, batch_size, queue_depth };
+        prop_assert_eq!(
+            pipelined_root_with(data.clone(), &config),
+            MerkleTree::new(data).root_hash()
+        );
+    }
+}
+
+#[cfg(feature = "arena")]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn arena_tree_matches(data in data(), domain in proptest::option::of(vec(any::<u8>(), 1..8))) {
+        use bumpalo::Bump;
+        use simple_merkle_tree::arena::ArenaTree;
+        use simple_merkle_tree::TreeConfig;
+
+        let (config, tree) = match domain {
+            Some(domain) => (
+                TreeConfig::with_domain(domain.clone()),
+                MerkleTree::builder()
+                    .domain(domain)
+                    .build(data.clone())
+                    .unwrap()
+                    .into_config_tree()
+                    .unwrap(),
+            ),
+            None => (TreeConfig::default(), MerkleTree::new(data.clone())),
+        };
+
+        let bump = Bump::new();
+        let arena = ArenaTree::new_in(&data, &config, &bump);
+        prop_assert_eq!(arena.len(), data.len());
+        prop_assert_eq!(arena.root_hash().map(|root| root.to_vec()), tree.root_hash());
+        for index in 0..data.len() {
+            let (ours, theirs) = (arena.generate_proof_at(index), tree.generate_proof_at(index));
+            prop_assert_eq!(ours.as_ref().map(parts), theirs.as_ref().map(parts));
+        }
+        prop_assert!(arena.to_tree() == tree);
+    }
+}
+
+#[cfg(feature = "testing")]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn mock_tree_matches(
+        size in 1u64..150,
+        scripted in vec((any::<prop::sample::Index>(), vec(any::<u8>(), 0..16)), 0..6),
+    ) {
+        use simple_merkle_tree::testing::{Fault, MockTree, FILLER_LEAF};
+
+        let mut data = vec![FILLER_LEAF.to_vec(); size as usize];
+        let mut mock = MockTree::new(size);
+        for (index, leaf) in &scripted {
+            let index = index.index(size as usize);
+            mock = mock.with_leaf(index as u64, leaf);
+            data[index] = leaf.clone();
+        }
+
+        let tree = MerkleTree::new(data);
+        let root = tree.root_hash().unwrap();
+        prop_assert_eq!(mock.root_hash(), Some(root.clone()));
+        for index in 0..size {
+            let proof = mock.proof(index).unwrap();
+            prop_assert!(proof.is_valid());
+            let proof = proof.into_proof();
+            let expected = tree.generate_proof_at(index as usize).unwrap();
+            prop_assert_eq!(parts(&proof), parts(&expected));
+
+            let broken = mock.invalid_proof(index, Fault::LeafHash).unwrap();
+            prop_assert!(!broken.is_valid());
+            prop_assert!(!broken.into_proof().verify(&root));
+        }
+        prop_assert!(mock.proof(size).is_none());
+    }
+}
+
+#[cfg(feature = "parallel")]
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn verify_parallel_matches(
+        data in vec(vec(any::<u8>(), 0..16), 1..90),
+        picks in vec(any::<prop::sample::Index>(), 1..12),
+        flip in any::<prop::sample::Index>(),
+    ) {
+        use simple_merkle_tree::PackedProofs;
+
+        let tree = MerkleTree::new(data.clone());
+        let root = tree.root_hash().unwrap();
+        let mut indices: Vec<usize> = picks.iter().map(|pick| pick.index(data.len())).collect();
+        indices.sort_unstable();
+        indices.dedup();
+
+        let packed = tree.pack_proofs(&indices).unwrap();
+        prop_assert!(packed.verify_all(&root));
+        prop_assert!(packed.verify_parallel(&root));
+        prop_assert!(!packed.verify_parallel(&[0; 32]));
+
+        // Flipping any hash byte after the header makes both say no
+        let mut bytes = Vec::new();
+        packed.write_to(&mut bytes).unwrap();
+        let hashes_start = 54 + 8 * indices.len();
+        let position = hashes_start + flip.index(bytes.len() - hashes_start);
+        bytes[position] ^= 1;
+        let altered = PackedProofs::read_from(&bytes[..]).unwrap();
+        prop_assert_eq!(altered.verify_parallel(&root), altered.verify_all(&root));
+        prop_assert!(!altered.verify_parallel(&root));
+    }
+}
@@ -0,0 +1,609 @@
+//! Every encoding the crate writes reads back to what was written, and
+//! malformed or mismatched input is turned away.
+
+use sha2::{Digest, Sha256};
+use simple_merkle_tree::history::HistoryTree;
+use simple_merkle_tree::redact::RedactedTree;
+use simple_merkle_tree::roots::RootHistory;
+use simple_merkle_tree::shard::{ComposedProof, ShardedTree};
+use simple_merkle_tree::utreexo::{BatchProof, Forest};
+use simple_merkle_tree::vectors::{self, VectorMode};
+use simple_merkle_tree::{
+    ExportFormat, FirmwareManifest, ManifestView, MerkleError, MerkleProof, MerkleTree,
+    PackedProofs, ProofBundle, Side, Signer, TreeHead, TreeView, Verifier,
+};
+use std::path::PathBuf;
+
+fn leaves(count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| format!("leaf-{}", i).into_bytes())
+        .collect()
+}
+
+fn tagged_tree(count: usize) -> MerkleTree {
+    MerkleTree::builder()
+        .domain(b"formats".to_vec())
+        .build(leaves(count))
+        .unwrap()
+        .into_config_tree()
+        .unwrap()
+}
+
+/// Everything a proof holds: leaf hash, siblings, root hash and domain
+type Parts<'a> = (&'a [u8], &'a [(Vec<u8>, Side)], &'a [u8], Option<&'a [u8]>);
+
+/// Splits a proof into its parts, for comparing proofs
+fn parts(proof: &MerkleProof) -> Parts<'_> {
+    (
+        proof.leaf_hash(),
+        proof.siblings(),
+        proof.root_hash(),
+        proof.domain(),
+    )
+}
+
+fn same_proofs(a: Option<MerkleProof>, b: Option<MerkleProof>) -> bool {
+    a.as_ref().map(parts) == b.as_ref().map(parts)
+}
+
+fn is_parse_error<T>(result: Result<T, MerkleError>) -> bool {
+    matches!(result, Err(MerkleError::Parse(_)))
+}
+
+/// Signs with the SHA-256 of a secret and the message; enough to tell
+/// keys and messages apart
+struct TestKey(&'static str);
+
+impl TestKey {
+    fn signature(&self, message: &[u8]) -> Vec<u8> {
+        Sha256::new()
+            .chain_update(self.0)
+            .chain_update(message)
+            .finalize()
+            .to_vec()
+    }
+}
+
+impl Signer for TestKey {
+    fn key_id(&self) -> &str {
+        self.0
+    }
+
+    fn sign(&self, message: &[u8]) -> Vec<u8> {
+        self.signature(message)
+    }
+}
+
+impl Verifier for TestKey {
+    fn key_id(&self) -> &str {
+        self.0
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        self.signature(message) == signature
+    }
+}
+
+/// A path in the temporary directory no other test uses
+fn scratch_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "simple-merkle-tree-{}-{}",
+        std::process::id(),
+        name
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn packed_proofs_round_trip() {
+    for tree in [MerkleTree::new(leaves(13)), tagged_tree(13)] {
+        let packed = tree.pack_proofs(&[0, 3, 4, 12]).unwrap();
+        let mut bytes = Vec::new();
+        packed.write_to(&mut bytes).unwrap();
+
+        let read = PackedProofs::read_from(&bytes[..]).unwrap();
+        assert_eq!(read, packed);
+        assert!(read.verify_all(&tree.root_hash().unwrap()));
+        assert!(same_proofs(read.proof(3), tree.generate_proof_at(3)));
+    }
+}
+
+#[test]
+fn packed_proofs_reject_malformed_input() {
+    let tree = MerkleTree::new(leaves(13));
+    let mut bytes = Vec::new();
+    tree.pack_proofs(&[2, 5])
+        .unwrap()
+        .write_to(&mut bytes)
+        .unwrap();
+
+    let mut magic = bytes.clone();
+    magic[0] = b'X';
+    assert!(is_parse_error(PackedProofs::read_from(&magic[..])));
+
+    // Untagged: magic, version, flag, size and root come before the indices
+    let mut swapped = bytes.clone();
+    swapped[54..70].rotate_left(8);
+    assert!(is_parse_error(PackedProofs::read_from(&swapped[..])));
+
+    let mut flag = bytes.clone();
+    flag[5] = 2;
+    assert!(is_parse_error(PackedProofs::read_from(&flag[..])));
+
+    assert!(PackedProofs::read_from(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn tree_view_round_trip() {
+    for count in [1, 2, 7, 16] {
+        let tree = MerkleTree::new(leaves(count));
+        let bytes = tree.to_view_bytes().unwrap();
+        let view = TreeView::parse(&bytes).unwrap();
+
+        assert_eq!(view.len(), count as u64);
+        assert_eq!(view.root_hash().map(|root| root.to_vec()), tree.root_hash());
+        for index in 0..count {
+            assert!(same_proofs(
+                view.proof(index as u64),
+                tree.generate_proof_at(index)
+            ));
+        }
+    }
+}
+
+#[test]
+fn tree_view_rejects_malformed_input() {
+    let bytes = MerkleTree::new(leaves(5)).to_view_bytes().unwrap();
+    assert!(TreeView::parse(&bytes[..bytes.len() - 32]).is_none());
+    assert!(TreeView::parse(&bytes[..16]).is_none());
+
+    let mut reserved = bytes.clone();
+    reserved[6] = 1;
+    assert!(TreeView::parse(&reserved).is_none());
+
+    let mut leaves_field = bytes.clone();
+    leaves_field[15] += 1;
+    assert!(TreeView::parse(&leaves_field).is_none());
+
+    // The layout has no room for a domain tag
+    assert_eq!(
+        tagged_tree(5).to_view_bytes(),
+        Err(MerkleError::ConfigMismatch)
+    );
+}
+
+fn exported(tree: &MerkleTree, format: ExportFormat) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    tree.export_levels(&mut bytes, format).unwrap();
+    bytes
+}
+
+#[test]
+fn level_export_round_trip() {
+    let mut formats = vec![ExportFormat::Lines];
+    if cfg!(feature = "json") {
+        formats.push(ExportFormat::Json);
+    }
+
+    for format in formats {
+        for tree in [MerkleTree::new(leaves(9)), tagged_tree(9)] {
+            let bytes = exported(&tree, format);
+            let imported = MerkleTree::import_levels(&bytes[..], format).unwrap();
+            assert!(imported == tree, "{:?}", format);
+        }
+    }
+}
+
+#[test]
+fn level_export_rejects_malformed_input() {
+    let tree = tagged_tree(6);
+    let text = String::from_utf8(exported(&tree, ExportFormat::Lines)).unwrap();
+    let import = |text: &str| MerkleTree::import_levels(text.as_bytes(), ExportFormat::Lines);
+
+    let newer = text.replacen("smt-levels 3", "smt-levels 4", 1);
+    assert!(is_parse_error(import(&newer)));
+
+    let config_line = text.lines().nth(2).unwrap();
+    assert!(config_line.starts_with("config "));
+    let without_config = text.replacen(&format!("{}\n", config_line), "", 1);
+    assert!(is_parse_error(import(&without_config)));
+
+    let mut lines: Vec<&str> = text.lines().collect();
+    lines.swap(3, 4);
+    assert!(is_parse_error(import(&lines.join("\n"))));
+
+    // A file from a tagged tree can't be read as another hasher's
+    let untagged = MerkleTree::new(leaves(6));
+    let tagged_bytes = text.as_bytes();
+    assert!(matches!(
+        MerkleTree::import_levels_with_hasher(
+            tagged_bytes,
+            ExportFormat::Lines,
+            untagged.config().clone()
+        ),
+        Err(MerkleError::ConfigMismatch)
+    ));
+}
+
+#[test]
+fn level_export_reads_version_one() {
+    let tree = MerkleTree::new(leaves(4));
+    let text = String::from_utf8(exported(&tree, ExportFormat::Lines)).unwrap();
+    // Version 1 had only the node lines
+    let nodes: String = text
+        .lines()
+        .skip(2)
+        .map(|line| format!("{}\n", line))
+        .collect();
+
+    let imported = MerkleTree::import_levels(nodes.as_bytes(), ExportFormat::Lines).unwrap();
+    assert!(imported == tree);
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+#[test]
+fn compressed_exports_round_trip() {
+    use simple_merkle_tree::{compress, decompress, decompress_with_limit, Compression};
+
+    let mut compressions = Vec::new();
+    if cfg!(feature = "zstd") {
+        compressions.push(Compression::Zstd);
+    }
+    if cfg!(feature = "lz4") {
+        compressions.push(Compression::Lz4);
+    }
+
+    let tree = tagged_tree(40);
+    let plain = exported(&tree, ExportFormat::Lines);
+    for compression in compressions {
+        let mut bytes = Vec::new();
+        tree.export_levels_compressed(&mut bytes, ExportFormat::Lines, compression)
+            .unwrap();
+        assert_eq!(Compression::detect(&bytes), compression);
+        assert!(MerkleTree::import_levels(&bytes[..], ExportFormat::Lines).unwrap() == tree);
+
+        let packed = compress(&plain, compression).unwrap();
+        assert_eq!(decompress(&packed).unwrap(), plain);
+        assert_eq!(
+            decompress_with_limit(&packed, plain.len() - 1),
+            Err(MerkleError::DecompressedTooLarge {
+                limit: plain.len() - 1
+            })
+        );
+        assert_eq!(decompress_with_limit(&packed, plain.len()).unwrap(), plain);
+
+        // A frame cut short is an error, not a shorter file
+        assert!(decompress(&packed[..packed.len() / 2]).is_err());
+    }
+}
+
+#[test]
+fn indexed_proofs_round_trip() {
+    let tree = tagged_tree(11);
+    let root = tree.root_hash().unwrap();
+    for index in 0..tree.len() {
+        let proof = tree.generate_proof_at(index).unwrap();
+        let indexed = proof.to_indexed().unwrap();
+        assert_eq!(indexed.index, index as u64);
+        assert!(indexed.verify(&root));
+        assert!(same_proofs(indexed.to_proof().ok(), Some(proof)));
+    }
+
+    // Bits above the depth can't be turned into sides
+    let mut indexed = tree.generate_proof_at(3).unwrap().to_indexed().unwrap();
+    indexed.index |= 1 << indexed.siblings.len();
+    assert!(matches!(indexed.to_proof(), Err(MerkleError::InvalidProof)));
+    assert!(!indexed.verify(&root));
+}
+
+fn signed_log() -> (HistoryTree, TestKey) {
+    let mut tree = HistoryTree::new();
+    for leaf in leaves(10) {
+        tree.append(&leaf);
+    }
+    (tree, TestKey("log"))
+}
+
+#[test]
+fn proof_bundles_round_trip() {
+    let (tree, log) = signed_log();
+    let witness = TestKey("witness");
+    let mut bundle = tree.bundle_proof(4, "example.com/log", &log).unwrap();
+    bundle.head.cosign(&witness, 1_700_000_000);
+
+    let text = bundle.to_text().unwrap();
+    let parsed = ProofBundle::parse(&text).unwrap();
+    assert_eq!(parsed, bundle);
+    assert_eq!(parsed.verify(&log, &[&witness], 1), Ok(1));
+
+    assert!(parsed.verify(&witness, &[], 0).is_err());
+    assert!(is_parse_error(ProofBundle::parse(
+        &text.replacen("v1", "v9", 1)
+    )));
+    assert!(is_parse_error(ProofBundle::parse(
+        &text.replacen("index 4", "index x", 1)
+    )));
+}
+
+#[cfg(any(feature = "zstd", feature = "lz4"))]
+#[test]
+fn compressed_bundles_round_trip() {
+    use simple_merkle_tree::Compression;
+
+    let (tree, log) = signed_log();
+    let bundle = tree.bundle_proof(7, "example.com/log", &log).unwrap();
+    let compression = if cfg!(feature = "zstd") {
+        Compression::Zstd
+    } else {
+        Compression::Lz4
+    };
+    let bytes = bundle.to_bytes(compression).unwrap();
+    assert_eq!(ProofBundle::from_bytes(&bytes).unwrap(), bundle);
+    assert_eq!(
+        ProofBundle::from_bytes(bundle.to_text().unwrap().as_bytes()).unwrap(),
+        bundle
+    );
+}
+
+#[test]
+fn checkpoints_round_trip() {
+    let (tree, _) = signed_log();
+    let head = TreeHead::from_history("example.com/log", &tree);
+    let text = head.to_checkpoint();
+    assert_eq!(TreeHead::parse_checkpoint(&text), Ok(head));
+
+    assert!(is_parse_error(TreeHead::parse_checkpoint(text.trim_end())));
+    assert!(is_parse_error(TreeHead::parse_checkpoint(
+        &text.replacen("\n10\n", "\n010\n", 1)
+    )));
+    assert!(is_parse_error(TreeHead::parse_checkpoint(&format!(
+        "{}extension\n",
+        text
+    ))));
+}
+
+#[test]
+fn redacted_trees_round_trip() {
+    let tree = RedactedTree::new(leaves(6)).redact(&[1, 4]).unwrap();
+    let mut text = Vec::new();
+    tree.write_to(&mut text).unwrap();
+
+    let read = RedactedTree::read_from(&text[..]).unwrap();
+    assert_eq!(read, tree);
+    assert!(read.verify(&MerkleTree::new(leaves(6)).root_hash().unwrap()));
+
+    let text = String::from_utf8(text).unwrap();
+    let short = text.replacen("withheld ", "withheld 00", 1);
+    let short = short
+        .lines()
+        .map(|line| match line.strip_prefix("withheld 00") {
+            Some(hash) => format!("withheld {}", &hash[..62]),
+            None => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert!(is_parse_error(RedactedTree::read_from(short.as_bytes())));
+    assert!(is_parse_error(RedactedTree::read_from(&b"hidden 00\n"[..])));
+}
+
+#[test]
+fn composed_proofs_round_trip() {
+    let shards = (0..3)
+        .map(|shard| MerkleTree::new(leaves(4 + shard)))
+        .collect();
+    let tree = ShardedTree::new(shards).unwrap();
+    let root = tree.root_hash().unwrap();
+
+    let proof = tree.generate_proof_at(2, 5).unwrap();
+    let mut bytes = Vec::new();
+    proof.write_to(&mut bytes).unwrap();
+    let read = ComposedProof::read_from(&bytes[..]).unwrap();
+    assert_eq!(read.shard(), 2);
+    assert_eq!(read.leaf_hash(), proof.leaf_hash());
+    assert!(read.verify(&root));
+
+    let mut magic = bytes.clone();
+    magic[3] = b'X';
+    assert!(ComposedProof::read_from(&magic[..]).is_err());
+    assert!(ComposedProof::read_from(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn batch_proofs_round_trip() {
+    let mut forest = Forest::new();
+    let elements = leaves(11);
+    for element in &elements {
+        forest.add(element);
+    }
+    let targets: Vec<&[u8]> = vec![&elements[2], &elements[3], &elements[9]];
+
+    let proof = forest.prove(&targets).unwrap();
+    let bytes = proof.to_bytes();
+    let read = BatchProof::from_bytes(&bytes).unwrap();
+    assert_eq!(read, proof);
+    assert!(forest.stump().verify(&read, &targets));
+
+    assert!(BatchProof::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    assert!(BatchProof::from_bytes(&trailing).is_err());
+}
+
+#[test]
+fn firmware_manifests_round_trip() {
+    let image: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+    let key = TestKey("vendor");
+    let mut manifest = FirmwareManifest::build(&image, 128, b"v1.2.3").unwrap();
+    manifest.sign(&key).unwrap();
+    let bytes = manifest.to_bytes();
+
+    let view = ManifestView::parse(&bytes).unwrap();
+    assert_eq!(view.metadata(), b"v1.2.3");
+    assert_eq!(view.chunk_count(), 8);
+    assert!(view.verify_signature(|message, signature| key.verify(message, signature)));
+    for (index, chunk) in image.chunks(128).enumerate() {
+        let siblings = manifest.chunk_proof(index as u64).unwrap();
+        assert!(view.verify_chunk(index as u64, chunk, &siblings));
+        assert!(!view.verify_chunk(index as u64, &chunk[1..], &siblings));
+    }
+
+    assert!(ManifestView::parse(&bytes[..bytes.len() - 1]).is_none());
+    let mut magic = bytes.clone();
+    magic[0] = 0;
+    assert!(ManifestView::parse(&magic).is_none());
+}
+
+#[test]
+fn vector_files_round_trip() {
+    let all = vectors::generate_all();
+    let mut text = Vec::new();
+    vectors::write_vectors(&mut text, &all).unwrap();
+
+    let read = vectors::read_vectors(&text[..]).unwrap();
+    assert_eq!(read, all);
+    for vector in &read {
+        vectors::check(vector).unwrap();
+    }
+
+    let text = String::from_utf8(text).unwrap();
+    assert!(
+        vectors::read_vectors(text.replacen("vector dense", "vector nope", 1).as_bytes()).is_err()
+    );
+    let unterminated = text.trim_end().strip_suffix("end").unwrap();
+    assert!(vectors::read_vectors(unterminated.as_bytes()).is_err());
+
+    // A vector whose root was altered is caught by `check`
+    let mut altered = vectors::generate(VectorMode::Dense, leaves(5)).unwrap();
+    altered.root[0] ^= 1;
+    assert!(matches!(
+        vectors::check(&altered),
+        Err(MerkleError::VectorMismatch(_))
+    ));
+}
+
+#[cfg(feature = "openzeppelin")]
+#[test]
+fn openzeppelin_dumps_round_trip() {
+    use serde_json::{json, Value};
+    use simple_merkle_tree::openzeppelin::StandardMerkleTree;
+
+    let values = (0..5)
+        .map(|i| vec![json!(format!("0x{:040x}", i + 1)), json!(i * 100)])
+        .collect();
+    let tree = StandardMerkleTree::of(values, &["address", "uint256"]).unwrap();
+    let dump = tree.dump();
+    assert_eq!(StandardMerkleTree::load(&dump).unwrap(), tree);
+
+    // Every node is re-derived, so an altered one is caught
+    let mut document: Value = serde_json::from_str(&dump).unwrap();
+    document["tree"][0] = json!(format!("0x{}", "00".repeat(32)));
+    assert!(is_parse_error(StandardMerkleTree::load(
+        &document.to_string()
+    )));
+    assert!(is_parse_error(StandardMerkleTree::load(&dump.replacen(
+        "standard-v1",
+        "standard-v2",
+        1
+    ))));
+}
+
+#[cfg(feature = "anchor")]
+#[test]
+fn anchor_calldata_round_trip() {
+    use simple_merkle_tree::anchor::Anchor;
+
+    let anchor = Anchor {
+        tree_size: 1234,
+        root: [7; 32],
+    };
+    let calldata = anchor.calldata();
+    assert_eq!(calldata.len(), 4 + 64);
+    assert_eq!(Anchor::decode_calldata(&calldata), Ok(anchor));
+
+    assert!(is_parse_error(Anchor::decode_calldata(
+        &calldata[..calldata.len() - 1]
+    )));
+    let mut selector = calldata.clone();
+    selector[0] ^= 1;
+    assert!(is_parse_error(Anchor::decode_calldata(&selector)));
+    // The size word must fit a u64
+    let mut size = calldata.clone();
+    size[4] = 1;
+    assert!(is_parse_error(Anchor::decode_calldata(&size)));
+}
+
+#[test]
+fn root_logs_reopen_with_every_record() {
+    let path = scratch_path("roots-reopen");
+    let (mut tree, _) = signed_log();
+    {
+        let mut history = RootHistory::open(&path).unwrap();
+        history.publish(&tree, 100).unwrap();
+        tree.append(b"one more");
+        history.publish(&tree, 200).unwrap();
+    }
+
+    let history = RootHistory::open(&path).unwrap();
+    assert_eq!(history.records().len(), 2);
+    assert_eq!(history.at_size(11).unwrap().root_hash, tree.root_hash());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn root_logs_drop_a_torn_last_line() {
+    let path = scratch_path("roots-torn");
+    let (tree, _) = signed_log();
+    RootHistory::open(&path)
+        .unwrap()
+        .publish(&tree, 100)
+        .unwrap();
+    let complete = std::fs::read(&path).unwrap();
+
+    // A crash mid-write leaves part of a line
+    let mut torn = complete.clone();
+    torn.extend_from_slice(b"200 11 abc");
+    std::fs::write(&path, &torn).unwrap();
+
+    let mut history = RootHistory::open(&path).unwrap();
+    assert_eq!(history.records().len(), 1);
+    assert_eq!(std::fs::read(&path).unwrap(), complete);
+
+    // And the log carries on after it
+    let mut tree = tree;
+    tree.append(b"one more");
+    history.publish(&tree, 200).unwrap();
+    drop(history);
+    assert_eq!(RootHistory::open(&path).unwrap().records().len(), 2);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn root_logs_reject_bad_records() {
+    let path = scratch_path("roots-bad");
+    let (tree, _) = signed_log();
+    let root = hex::encode(tree.root_hash());
+
+    // Two roots for one size
+    let other = hex::encode([0u8; 32]);
+    std::fs::write(&path, format!("100 10 {}\n200 10 {}\n", root, other)).unwrap();
+    assert!(is_parse_error(RootHistory::open(&path)));
+
+    // The same root twice is only a repeated publish
+    std::fs::write(&path, format!("100 10 {}\n200 10 {}\n", root, root)).unwrap();
+    assert!(RootHistory::open(&path).is_ok());
+
+    std::fs::write(&path, "100 ten 00\n").unwrap();
+    assert!(is_parse_error(RootHistory::open(&path)));
+    std::fs::write(&path, format!("100 10 {} extra\n", root)).unwrap();
+    assert!(is_parse_error(RootHistory::open(&path)));
+    std::fs::write(&path, format!("200 10 {}\n100 11 {}\n", root, root)).unwrap();
+    assert!(is_parse_error(RootHistory::open(&path)));
+    std::fs::remove_file(&path).unwrap();
+
+    let mut history = RootHistory::new();
+    history.publish(&tree, 100).unwrap();
+    let mut smaller = HistoryTree::new();
+    smaller.append(b"x");
+    assert!(history.publish(&smaller, 200).is_err());
+}
@@ -0,0 +1,309 @@
+//! Roots and proofs pinned to values published by, or derived from the
+//! specification of, the implementations each mode is compatible with.
+
+use sha2::{Digest, Sha256};
+use simple_merkle_tree::vectors::{self, VectorMode};
+use simple_merkle_tree::HistoryTree;
+
+fn unhex(text: &str) -> Vec<u8> {
+    hex::decode(text).unwrap()
+}
+
+#[cfg(any(feature = "openzeppelin", feature = "solana", feature = "bittorrent"))]
+fn unhex32(text: &str) -> [u8; 32] {
+    unhex(text).try_into().unwrap()
+}
+
+/// The leaves of the RFC 6962 test vectors used by certificate-transparency
+/// and Trillian
+const RFC6962_LEAVES: [&str; 8] = [
+    "",
+    "00",
+    "10",
+    "2021",
+    "3031",
+    "40414243",
+    "5051525354555657",
+    "606162636465666768696a6b6c6d6e6f",
+];
+
+/// Root over the first `i + 1` leaves
+const RFC6962_ROOTS: [&str; 8] = [
+    "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+    "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+    "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+    "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+    "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+    "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+    "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+    "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+];
+
+/// (leaf index, tree size, audit path)
+const RFC6962_INCLUSION: &[(u64, u64, &[&str])] = &[
+    (0, 1, &[]),
+    (
+        0,
+        8,
+        &[
+            "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+        ],
+    ),
+    (
+        5,
+        8,
+        &[
+            "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+            "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        ],
+    ),
+    (
+        2,
+        3,
+        &["fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125"],
+    ),
+    (
+        1,
+        5,
+        &[
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+        ],
+    ),
+];
+
+/// (old size, new size, consistency proof)
+const RFC6962_CONSISTENCY: &[(u64, u64, &[&str])] = &[
+    (1, 1, &[]),
+    (
+        1,
+        8,
+        &[
+            "96a296d224f285c67bee93c30f8a309157f0daa35dc5b87e410b78630a09cfc7",
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "6b47aaf29ee3c2af9af889bc1fb9254dabd31177f16232dd6aab035ca39bf6e4",
+        ],
+    ),
+    (
+        6,
+        8,
+        &[
+            "0ebc5d3437fbe2db158b9f126a1d118e308181031d0a949f8dededebc558ef6a",
+            "ca854ea128ed050b41b35ffc1b87b8eb2bde461e9e3b5596ece6b9d5975a0ae0",
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        ],
+    ),
+    (
+        2,
+        5,
+        &[
+            "5f083f0a1a33ca076a95279832580db3e0ef4584bdff1f54c8a360f50de3031e",
+            "bc1a0643b12e4d2d7c77918f44e0f4f79a838b6cf9ec5b5c283e1f4d88599e6b",
+        ],
+    ),
+];
+
+fn rfc6962_tree() -> HistoryTree {
+    let mut tree = HistoryTree::new();
+    for leaf in RFC6962_LEAVES {
+        tree.append(&unhex(leaf));
+    }
+    tree
+}
+
+#[test]
+fn rfc6962_roots() {
+    let tree = rfc6962_tree();
+    for (size, root) in (1..).zip(RFC6962_ROOTS) {
+        assert_eq!(tree.root_at(size), Some(unhex(root)), "size {}", size);
+    }
+
+    // The empty tree's root is the hash of nothing
+    assert_eq!(
+        HistoryTree::new().root_hash(),
+        unhex("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+    );
+}
+
+#[test]
+fn rfc6962_inclusion_proofs() {
+    let tree = rfc6962_tree();
+    for &(index, size, path) in RFC6962_INCLUSION {
+        let proof = tree.prove_membership(index, size).unwrap();
+        let expected: Vec<Vec<u8>> = path.iter().map(|hash| unhex(hash)).collect();
+        assert_eq!(proof.path(), expected, "leaf {} of {}", index, size);
+
+        let root = unhex(RFC6962_ROOTS[size as usize - 1]);
+        assert!(proof.verify(&root));
+        // A path for the wrong version doesn't verify
+        assert!(!proof.verify(&unhex(RFC6962_ROOTS[size as usize % 8])));
+    }
+}
+
+#[test]
+fn rfc6962_consistency_proofs() {
+    let tree = rfc6962_tree();
+    for &(old_size, new_size, path) in RFC6962_CONSISTENCY {
+        let proof = tree.prove_incremental(old_size, new_size).unwrap();
+        let expected: Vec<Vec<u8>> = path.iter().map(|hash| unhex(hash)).collect();
+        assert_eq!(proof.path(), expected, "{} to {}", old_size, new_size);
+
+        let old_root = unhex(RFC6962_ROOTS[old_size as usize - 1]);
+        let new_root = unhex(RFC6962_ROOTS[new_size as usize - 1]);
+        assert!(proof.verify(&old_root, &new_root));
+        if old_size < new_size {
+            assert!(!proof.verify(&new_root, &old_root));
+        }
+    }
+}
+
+#[test]
+fn rfc6962_and_tendermint_vectors_use_the_same_roots() {
+    let leaves: Vec<Vec<u8>> = RFC6962_LEAVES.iter().map(|leaf| unhex(leaf)).collect();
+    for mode in [VectorMode::Rfc6962, VectorMode::Tendermint] {
+        let vector = vectors::generate(mode, leaves.clone()).unwrap();
+        assert_eq!(vector.root, unhex(RFC6962_ROOTS[7]), "{}", mode);
+        vectors::check(&vector).unwrap();
+    }
+}
+
+#[test]
+fn bitcoin_mode_matches_mainnet() {
+    vectors::check_mainnet().unwrap();
+}
+
+#[test]
+fn bitcoin_single_transaction_is_its_own_root() {
+    let transaction = b"any lone transaction".to_vec();
+    let txid = Sha256::digest(Sha256::digest(&transaction)).to_vec();
+    let vector = vectors::generate(VectorMode::Bitcoin, vec![transaction]).unwrap();
+
+    assert_eq!(vector.root, txid);
+    assert_eq!(vector.inclusion.len(), 1);
+    assert!(vector.inclusion[0].path.is_empty());
+}
+
+/// The example tree from the `@openzeppelin/merkle-tree` README
+#[cfg(feature = "openzeppelin")]
+#[test]
+fn openzeppelin_readme_tree() {
+    use serde_json::json;
+    use simple_merkle_tree::openzeppelin::{self, StandardMerkleTree};
+
+    let values = vec![
+        vec![
+            json!("0x1111111111111111111111111111111111111111"),
+            json!("5000000000000000000"),
+        ],
+        vec![
+            json!("0x2222222222222222222222222222222222222222"),
+            json!("2500000000000000000"),
+        ],
+    ];
+    let tree = StandardMerkleTree::of(values, &["address", "uint256"]).unwrap();
+
+    let root = unhex32("d4dee0beab2d53f2cc83e567171bd2820e49898130a22622b10ead383e90bd77");
+    assert_eq!(tree.root(), root);
+    let first = unhex32("eb02c421cfa48976e66dfb29120745909ea3a0f843456c263cf8f1253483e283");
+    let second = unhex32("b92c48e9d7abe27fd8dfd6b5dfdbfb1c9a463f80c712b66f3a5180a090cccafc");
+    assert_eq!(tree.leaf(0), Some(first));
+    assert_eq!(tree.leaf(1), Some(second));
+    assert_eq!(tree.proof(0), Some(vec![second]));
+    assert!(openzeppelin::verify(&root, &first, &[second]));
+    assert!(!openzeppelin::verify(&root, &first, &[first]));
+}
+
+/// Keccak-256 roots of empty and partly filled trees as
+/// `spl-concurrent-merkle-tree` initializes and appends to them
+#[cfg(feature = "solana")]
+#[test]
+fn solana_concurrent_tree() {
+    use simple_merkle_tree::solana::{self, ConcurrentMerkleTree};
+
+    // keccak(0^32 || 0^32) and one level above it
+    assert_eq!(
+        solana::empty_node(1),
+        unhex32("ad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5")
+    );
+    assert_eq!(
+        solana::empty_node(2),
+        unhex32("b4c11951957c6f8f642c4af61cd6b24640fec6dc7fc607ee8206a99e92410d30")
+    );
+    let empty = ConcurrentMerkleTree::new(14, 64).unwrap();
+    assert_eq!(
+        empty.root(),
+        unhex32("5c67add7c6caf302256adedf7ab114da0acfe870d449a3a489f781d659e8becc")
+    );
+
+    let mut tree = ConcurrentMerkleTree::new(3, 8).unwrap();
+    for byte in 1..=3 {
+        tree.append([byte; 32]).unwrap();
+    }
+    let root = unhex32("4c834c12bef45cc9c49eb03fd150a4bf6e9b7da512fd08a74847367aaba086d3");
+    assert_eq!(tree.root(), root);
+    let proof = tree.proof(2).unwrap();
+    assert_eq!(
+        proof,
+        vec![
+            [0; 32],
+            unhex32("346d8c96a2454213fcc0daff3c96ad0398148181b9fa6488f7ae2c0af5b20aa0"),
+            solana::empty_node(2),
+        ]
+    );
+    assert_eq!(solana::recompute([3; 32], &proof, 2), root);
+    tree.prove_leaf(root, [3; 32], &proof, 2).unwrap();
+}
+
+/// A three-block file hashed as BEP 52 describes: block hashes padded with
+/// zero hashes to a power of two
+#[cfg(feature = "bittorrent")]
+#[test]
+fn bittorrent_pieces_root() {
+    use simple_merkle_tree::bittorrent::{self, PieceLayers, TorrentFile, BLOCK_SIZE};
+
+    let data: Vec<u8> = (0..40_000u32).map(|i| (i % 251) as u8).collect();
+    let file = TorrentFile::new(&data);
+    assert_eq!(file.blocks(), 3);
+    let root = unhex32("ab671631a9fa97a1fdac651fff6c68773b9acf0735b9c7f6ecdd54cbf1bf5dc2");
+    assert_eq!(file.pieces_root(), Some(root));
+    assert_eq!(
+        TorrentFile::from_reader(&data[..]).unwrap().pieces_root(),
+        Some(root)
+    );
+
+    let layer = file.piece_layer(32 * 1024).unwrap();
+    assert_eq!(
+        layer,
+        vec![
+            unhex32("d9e13d0b676ad681164ef0b7b5910d1328ea83a047cad57e619d76bbe3a08525"),
+            unhex32("c878da4f6d2bc3d9e59af3c6ef3aaf72b248998c30a4b77a4e7de79a899daf72"),
+        ]
+    );
+    assert!(bittorrent::verify_piece_layer(&root, data.len() as u64, 32 * 1024, &layer).unwrap());
+    assert!(bittorrent::verify_piece(&layer[1], &data[32 * 1024..], 32 * 1024).unwrap());
+    assert!(!bittorrent::verify_piece(&layer[0], &data[32 * 1024..], 32 * 1024).unwrap());
+
+    for index in 0..file.blocks() {
+        let block = &data[index * BLOCK_SIZE..data.len().min((index + 1) * BLOCK_SIZE)];
+        let proof = file.block_proof(index).unwrap();
+        assert!(bittorrent::verify_block(&root, index, block, &proof));
+    }
+
+    // A file of one block is rooted at the block's own hash and has no layer
+    let small = TorrentFile::new(&data[..1000]);
+    assert_eq!(
+        small.pieces_root(),
+        Some(unhex32(
+            "4e4c294b331f7a2099a379bec34b9f9fc03dc46ab465d998f4d683da53487e6d"
+        ))
+    );
+    let mut layers = PieceLayers::new(32 * 1024).unwrap();
+    layers.add(&small);
+    layers.add(&file);
+    assert_eq!(layers.len(), 1);
+    assert_eq!(layers.get(&root), Some(&layer[..]));
+}
@@ -0,0 +1,172 @@
+//! Configs and inputs the crate must refuse, next to ones it must accept.
+
+use simple_merkle_tree::{
+    LimitsConfig, MerkleError, MerkleTree, RequestGuard, RollingTree, RootRecord, VerifyPolicy,
+};
+use std::time::Instant;
+
+fn leaves(count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| format!("leaf {}", i).into_bytes())
+        .collect()
+}
+
+fn record(tree: &MerkleTree) -> RootRecord {
+    RootRecord {
+        timestamp: 1,
+        tree_size: tree.len() as u64,
+        root_hash: tree.root_hash().unwrap(),
+    }
+}
+
+#[test]
+fn limits_need_a_burst_that_admits_a_full_request() {
+    assert!(LimitsConfig::default().validate().is_ok());
+
+    for text in ["burst = 0", "burst = 10\nmax_proofs_per_request = 11"] {
+        assert!(matches!(
+            LimitsConfig::parse(text),
+            Err(MerkleError::InvalidLimit(_))
+        ));
+    }
+
+    let config = LimitsConfig {
+        burst: 4,
+        max_proofs_per_request: 5,
+        ..LimitsConfig::default()
+    };
+    assert!(matches!(
+        RequestGuard::<u32>::new(config),
+        Err(MerkleError::InvalidLimit(_))
+    ));
+
+    let config = LimitsConfig::parse("burst = 5\nmax_proofs_per_request = 5").unwrap();
+    let mut guard = RequestGuard::new(config).unwrap();
+    let now = Instant::now();
+    assert!(guard.admit(&1u32, 10, 5, now).is_ok());
+    assert!(matches!(
+        guard.admit(&1u32, 10, 6, now),
+        Err(MerkleError::RequestTooLarge { .. })
+    ));
+}
+
+#[test]
+fn policies_derive_the_algorithm_from_the_hasher() {
+    let tree = MerkleTree::new(leaves(5));
+    let proof = tree.generate_bound_proof(3).unwrap();
+    let entry = record(&tree);
+
+    // The defaults want a signed root, which a root record never is
+    assert!(matches!(
+        proof.verify_with_policy(&entry, &VerifyPolicy::default()),
+        Err(MerkleError::PolicyViolation(_))
+    ));
+
+    let unsigned = VerifyPolicy::parse("require_signed_root = false").unwrap();
+    assert!(proof.verify_with_policy(&entry, &unsigned).is_ok());
+
+    let keccak_only =
+        VerifyPolicy::parse("require_signed_root = false\nallowed_hashes = keccak256").unwrap();
+    assert!(matches!(
+        proof.verify_with_policy(&entry, &keccak_only),
+        Err(MerkleError::PolicyViolation(_))
+    ));
+
+    let witnessed = VerifyPolicy::parse("require_signed_root = false\nmin_witnesses = 1").unwrap();
+    assert!(proof.verify_with_policy(&entry, &witnessed).is_err());
+
+    let too_small = VerifyPolicy::parse("require_signed_root = false\nmin_tree_size = 6").unwrap();
+    assert!(proof.verify_with_policy(&entry, &too_small).is_err());
+
+    // Sorted pairs hash unlike any TreeConfig, so the policy can't name them
+    let sorted = MerkleTree::builder()
+        .sorted_pairs(true)
+        .build(leaves(5))
+        .unwrap();
+    let sorted_entry = RootRecord {
+        timestamp: 1,
+        tree_size: 5,
+        root_hash: sorted.root_hash().unwrap(),
+    };
+    let sorted_proof = sorted.generate_bound_proof(3).unwrap();
+    assert!(sorted_proof.verify_against(&sorted_entry).is_ok());
+    assert!(matches!(
+        sorted_proof.verify_with_policy(&sorted_entry, &unsigned),
+        Err(MerkleError::PolicyViolation(_))
+    ));
+
+    assert!(VerifyPolicy::parse("allowed_hashes = md5").is_err());
+    assert!(VerifyPolicy::parse("require_signed_root").is_err());
+}
+
+#[test]
+fn bound_proofs_reject_other_sizes() {
+    let tree = MerkleTree::new(leaves(5));
+    let proof = tree.generate_bound_proof(1).unwrap();
+    let mut entry = record(&MerkleTree::new(leaves(6)));
+    assert!(matches!(
+        proof.verify_against(&entry),
+        Err(MerkleError::StaleProof {
+            proof_size: 5,
+            root_size: 6
+        })
+    ));
+
+    entry.tree_size = 5;
+    assert!(proof.verify_against(&entry).is_err());
+    assert!(proof.verify_against(&record(&tree)).is_ok());
+}
+
+#[test]
+fn rolling_trees_have_no_root_once_everything_expires() {
+    let mut rolling = RollingTree::with_max_age(10);
+    assert!(rolling.root_hash().is_none());
+
+    rolling.push(b"first", 0);
+    rolling.push(b"second", 5);
+    assert!(rolling.root_hash().is_some());
+    assert_eq!(rolling.retained(), Some((0, 1)));
+
+    assert_eq!(rolling.expire(12), 1);
+    assert!(rolling.root_hash().is_some());
+    assert!(rolling.generate_proof(b"first").is_none());
+    let proof = rolling.generate_proof(b"second").unwrap();
+    assert!(proof.verify(&rolling.root_hash().unwrap()));
+
+    assert_eq!(rolling.expire(100), 1);
+    assert!(rolling.root_hash().is_none());
+    assert!(rolling.root_hash_hex().is_none());
+    assert!(rolling.retained().is_none());
+
+    rolling.push(b"third", 100);
+    assert!(rolling.root_hash().is_some());
+}
+
+#[cfg(feature = "canonical")]
+#[test]
+fn canonical_encodings_refuse_non_finite_floats() {
+    use simple_merkle_tree::canonical::{encode, Encoding};
+    use std::collections::{BTreeMap, HashMap};
+
+    for encoding in [Encoding::Json, Encoding::Binary] {
+        for value in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(
+                encode(&value, encoding),
+                Err(MerkleError::Encode(_))
+            ));
+            assert!(matches!(
+                encode(&vec![1.0, value], encoding),
+                Err(MerkleError::Encode(_))
+            ));
+        }
+        assert!(encode(&1.5f64, encoding).is_ok());
+
+        // Map order doesn't change the encoding
+        let forward: HashMap<_, _> = (0..20).map(|i| (i, i * 2)).collect();
+        let backward: HashMap<_, _> = (0..20).rev().map(|i| (i, i * 2)).collect();
+        let sorted: BTreeMap<_, _> = forward.iter().map(|(k, v)| (*k, *v)).collect();
+        let expected = encode(&sorted, encoding).unwrap();
+        assert_eq!(encode(&forward, encoding).unwrap(), expected);
+        assert_eq!(encode(&backward, encoding).unwrap(), expected);
+    }
+}